@@ -0,0 +1,432 @@
+//! Proofs of beacon chain state anchored to the execution chain: since Cancun (EIP-4788) each execution block
+//! header commits to the root of the parent beacon block in `parent_beacon_block_root`.
+//!
+//! [`EthBeaconStateCircuit`] proves that an execution block hash is in the merkle mountain range of an
+//! [`EthBlockHeaderChainCircuit`](crate::block_header::EthBlockHeaderChainCircuit), that the `parent_beacon_block_root`
+//! of its header is the SSZ root of a beacon block header, and that a leaf is in the beacon state with the
+//! `state_root` of that beacon block header, e.g. the withdrawal credentials of a validator or the chunk of
+//! `balances` containing its balance, see [`BeaconStateLayout`].
+use crate::{
+    block_header::{
+        fields::BlockHeaderField,
+        merkle::{BlockHashMerkleProof, EthBlockHashMerkleChip},
+        EthBlockHeaderChip, EthBlockHeaderTraceWitness, MAINNET_BLOCK_HEADER_RLP_MAX_BYTES,
+    },
+    halo2_proofs::{
+        circuit::{Layouter, SimpleFloorPlanner, Value},
+        plonk::{Circuit, ConstraintSystem, Error},
+    },
+    sha256::{
+        ssz::{sha256_merkle_root, uint64_chunk, uint64_to_chunk},
+        Sha256Chip,
+    },
+    util::{bytes_be_to_u128, encode_h256_to_field, AssignedH256, EthConfigParams},
+    EthChip, EthConfig, Field, Network,
+};
+#[cfg(feature = "display")]
+use ark_std::{end_timer, start_timer};
+use ethers_core::{types::H256, utils::keccak256};
+use halo2_base::{gates::GateInstructions, AssignedValue, Context, ContextParams, SKIP_FIRST_PASS};
+use itertools::Itertools;
+use rlp::Rlp;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use snark_verifier_sdk::CircuitExt;
+use std::marker::PhantomData;
+
+#[cfg(test)]
+mod tests;
+
+/// `BeaconBlockHeader` of the consensus specs
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BeaconBlockHeader {
+    pub slot: u64,
+    pub proposer_index: u64,
+    pub parent_root: H256,
+    pub state_root: H256,
+    pub body_root: H256,
+}
+
+/// Index of `state_root` in the SSZ merkle tree of a [`BeaconBlockHeader`]
+const BEACON_HEADER_STATE_ROOT_IDX: usize = 3;
+
+impl BeaconBlockHeader {
+    /// The leaves of the SSZ merkle tree of the header, padded to a power of two
+    pub fn chunks(&self) -> [[u8; 32]; 8] {
+        [
+            uint64_to_chunk(self.slot),
+            uint64_to_chunk(self.proposer_index),
+            self.parent_root.0,
+            self.state_root.0,
+            self.body_root.0,
+            [0; 32],
+            [0; 32],
+            [0; 32],
+        ]
+    }
+
+    pub fn hash_tree_root(&self) -> H256 {
+        H256(sha256_merkle_root(&self.chunks()))
+    }
+}
+
+/// Positions of leaves in the SSZ merkle tree of the beacon state, as `(index, depth)` below the state root, where
+/// the generalized index of the leaf is `2^depth + index`.
+///
+/// Lists are merkleized up to their limit and their length is mixed in, so the depth of a list element is
+/// `1 + log2(limit)` below the list field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BeaconStateLayout {
+    /// Depth of the merkle tree of the fields of `BeaconState`
+    pub state_depth: usize,
+    pub validators_field_idx: u64,
+    pub balances_field_idx: u64,
+    /// `VALIDATOR_REGISTRY_LIMIT = 2^validator_registry_depth`
+    pub validator_registry_depth: usize,
+}
+
+/// Depth of the merkle tree of the 8 fields of `Validator`
+const VALIDATOR_DEPTH: usize = 3;
+const VALIDATOR_WITHDRAWAL_CREDENTIALS_IDX: u64 = 1;
+/// `uint64` balances are packed 4 to a chunk
+const BALANCES_PER_CHUNK_LOG2: usize = 2;
+
+impl BeaconStateLayout {
+    /// The layout of the Deneb beacon state, with 28 fields
+    pub fn deneb() -> Self {
+        Self {
+            state_depth: 5,
+            validators_field_idx: 11,
+            balances_field_idx: 12,
+            validator_registry_depth: 40,
+        }
+    }
+
+    /// The layout of the Electra beacon state, with 37 fields
+    pub fn electra() -> Self {
+        Self { state_depth: 6, ..Self::deneb() }
+    }
+
+    fn list_element(&self, field_idx: u64, limit_depth: usize, element_idx: u64) -> (u64, usize) {
+        assert!(field_idx < 1 << self.state_depth && element_idx < 1 << limit_depth);
+        // the list data is the left child of the list root, the length the right child
+        ((field_idx << 1 << limit_depth) | element_idx, self.state_depth + 1 + limit_depth)
+    }
+
+    /// Position of the `withdrawal_credentials` of the validator `validator_idx`
+    pub fn validator_withdrawal_credentials(&self, validator_idx: u64) -> (u64, usize) {
+        let (validator, depth) = self.list_element(
+            self.validators_field_idx,
+            self.validator_registry_depth,
+            validator_idx,
+        );
+        (
+            (validator << VALIDATOR_DEPTH) | VALIDATOR_WITHDRAWAL_CREDENTIALS_IDX,
+            depth + VALIDATOR_DEPTH,
+        )
+    }
+
+    /// Position of the chunk of `balances` containing the balance of the validator `validator_idx`, see
+    /// [`balance_from_chunk`]
+    pub fn validator_balance_chunk(&self, validator_idx: u64) -> (u64, usize) {
+        self.list_element(
+            self.balances_field_idx,
+            self.validator_registry_depth - BALANCES_PER_CHUNK_LOG2,
+            validator_idx >> BALANCES_PER_CHUNK_LOG2,
+        )
+    }
+}
+
+/// The balance of the validator `validator_idx` in its chunk of `balances`
+pub fn balance_from_chunk(chunk: &H256, validator_idx: u64) -> u64 {
+    let offset = 8 * (validator_idx as usize % (1 << BALANCES_PER_CHUNK_LOG2));
+    u64::from_le_bytes(chunk[offset..offset + 8].try_into().unwrap())
+}
+
+/// SSZ merkle proof of a leaf of the beacon state
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BeaconStateProof {
+    pub leaf: H256,
+    /// Index of the leaf at depth `branch.len()` below the state root
+    pub index: u64,
+    /// Sibling nodes from the leaf up to the state root
+    pub branch: Vec<H256>,
+}
+
+impl BeaconStateProof {
+    /// The state root computed from the proof
+    pub fn root(&self) -> H256 {
+        self.branch.iter().enumerate().fold(self.leaf, |node, (depth, sibling)| {
+            let pair = if (self.index >> depth) & 1 == 1 {
+                [sibling.as_bytes(), node.as_bytes()]
+            } else {
+                [node.as_bytes(), sibling.as_bytes()]
+            };
+            H256(Sha256::digest(pair.concat()).into())
+        })
+    }
+}
+
+/// The `parent_beacon_block_root` of the RLP encoded block header `header`
+pub fn get_parent_beacon_block_root(header: &[u8]) -> H256 {
+    let rlp = Rlp::new(header);
+    let root = rlp
+        .at(BlockHeaderField::ParentBeaconBlockRoot.idx())
+        .and_then(|item| item.data().map(H256::from_slice));
+    root.expect("block header has no parent_beacon_block_root")
+}
+
+/// Proves a leaf of the beacon state committed to by an execution block in a header chain.
+///
+/// Public instances, with H256 encoded as hi-lo (u128, u128):
+/// - the execution block hash and the `max_depth + 1` merkle roots of the header chain, as in
+/// [`EthBlockHashMerkleCircuit`](crate::block_header::merkle::EthBlockHashMerkleCircuit)
+/// - the slot of the beacon block whose root is the `parent_beacon_block_root` of the execution block
+/// - the index of the leaf in the beacon state, at depth `state_proof.branch.len()`, and the leaf
+#[derive(Clone, Debug)]
+pub struct EthBeaconStateCircuit<F> {
+    /// RLP encoded execution block header, Cancun or later
+    pub header_rlp: Vec<u8>,
+    pub block_hash_proof: BlockHashMerkleProof,
+    pub merkle_roots: Vec<H256>,
+    pub beacon_header: BeaconBlockHeader,
+    pub state_proof: BeaconStateProof,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> EthBeaconStateCircuit<F> {
+    pub fn new(
+        header_rlp: Vec<u8>,
+        block_hash_proof: BlockHashMerkleProof,
+        merkle_roots: Vec<H256>,
+        beacon_header: BeaconBlockHeader,
+        state_proof: BeaconStateProof,
+    ) -> Self {
+        assert!(header_rlp.len() <= MAINNET_BLOCK_HEADER_RLP_MAX_BYTES, "block header is too long");
+        assert_eq!(
+            H256(keccak256(&header_rlp)),
+            block_hash_proof.block_hash,
+            "block hash mismatch"
+        );
+        assert!(
+            block_hash_proof.verify(&merkle_roots),
+            "block is not in the merkle mountain range"
+        );
+        assert_eq!(
+            get_parent_beacon_block_root(&header_rlp),
+            beacon_header.hash_tree_root(),
+            "parent_beacon_block_root mismatch"
+        );
+        assert_eq!(state_proof.root(), beacon_header.state_root, "beacon state root mismatch");
+        Self {
+            header_rlp,
+            block_hash_proof,
+            merkle_roots,
+            beacon_header,
+            state_proof,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn max_depth(&self) -> usize {
+        self.merkle_roots.len() - 1
+    }
+
+    pub fn instance(&self) -> Vec<F> {
+        let block_hash = encode_h256_to_field::<F>(&self.block_hash_proof.block_hash);
+        let merkle_roots = self.merkle_roots.iter().flat_map(encode_h256_to_field);
+        let leaf = encode_h256_to_field::<F>(&self.state_proof.leaf);
+        block_hash
+            .into_iter()
+            .chain(merkle_roots)
+            .chain([F::from(self.beacon_header.slot), F::from(self.state_proof.index)])
+            .chain(leaf)
+            .collect()
+    }
+}
+
+/// Assigned inputs of [`EthBeaconStateCircuit`] after `FirstPhase`
+struct EthBeaconStateWitness<'v, F: Field> {
+    header_witness: EthBlockHeaderTraceWitness<'v, F>,
+    block_hash: AssignedH256<'v, F>,
+    merkle_roots: Vec<AssignedH256<'v, F>>,
+    slot: AssignedValue<'v, F>,
+    index: AssignedValue<'v, F>,
+    leaf: AssignedH256<'v, F>,
+}
+
+impl<F: Field> EthBeaconStateCircuit<F> {
+    /// Constrains all the proofs. This MUST be done in `FirstPhase`; the header RLP decoding is finalized in
+    /// `SecondPhase` with `decompose_block_header_phase1`.
+    fn assign_phase0<'v>(
+        &self,
+        ctx: &mut Context<'v, F>,
+        chip: &mut EthChip<'v, F>,
+        sha256: &mut Sha256Chip<'v, F>,
+    ) -> EthBeaconStateWitness<'v, F> {
+        let gate = chip.gate().clone();
+        let load_bytes = |ctx: &mut Context<'v, F>, bytes: &[u8]| {
+            gate.assign_witnesses(ctx, bytes.iter().map(|byte| Value::known(F::from(*byte as u64))))
+        };
+
+        // ==== execution block header and its hash ====
+        let mut header = self.header_rlp.clone();
+        header.resize(MAINNET_BLOCK_HEADER_RLP_MAX_BYTES, 0);
+        // decoded as the headers of the header chain, so the block hash is that of the header chain
+        let header_witness = chip.decompose_block_header_phase0(ctx, &header, Network::Mainnet);
+        let proof = self.block_hash_proof.assign(ctx, &gate, self.max_depth());
+        for (byte0, byte1) in chip.keccak().var_len_queries[header_witness.block_hash_query_idx]
+            .output_assigned
+            .iter()
+            .zip(proof.block_hash.iter())
+        {
+            ctx.constrain_equal(byte0, byte1);
+        }
+
+        // ==== block hash in the header chain ====
+        let merkle_roots = self
+            .merkle_roots
+            .iter()
+            .map(|root| {
+                let root = encode_h256_to_field::<F>(root).map(Value::known);
+                gate.assign_witnesses(ctx, root).try_into().unwrap()
+            })
+            .collect_vec();
+        let block_hash = chip.verify_block_hash_merkle_proof(ctx, &proof, &merkle_roots);
+
+        // ==== beacon block header ====
+        let parent_beacon_block_root = header_witness.get(BlockHeaderField::ParentBeaconBlockRoot);
+        // also constrains that the header has at least the Cancun fields
+        gate.assert_is_const(ctx, &parent_beacon_block_root.field_len, F::from(32));
+        let [slot, proposer_index] = [self.beacon_header.slot, self.beacon_header.proposer_index]
+            .map(|value| gate.load_witness(ctx, Value::known(F::from(value))));
+        let zero = gate.load_zero(ctx);
+        let mut beacon_leaves =
+            vec![uint64_chunk(ctx, &gate, &slot), uint64_chunk(ctx, &gate, &proposer_index)];
+        for root in [
+            &self.beacon_header.parent_root,
+            &self.beacon_header.state_root,
+            &self.beacon_header.body_root,
+        ] {
+            beacon_leaves.push(load_bytes(ctx, root.as_bytes()));
+        }
+        beacon_leaves.resize(8, vec![zero; 32]);
+        let beacon_block_root = sha256.merkle_tree_root(ctx, &gate, &beacon_leaves);
+        for (byte0, byte1) in
+            beacon_block_root.iter().zip_eq(parent_beacon_block_root.field_cells.iter())
+        {
+            ctx.constrain_equal(byte0, byte1);
+        }
+
+        // ==== leaf in the beacon state ====
+        let leaf = load_bytes(ctx, self.state_proof.leaf.as_bytes());
+        let branch = self
+            .state_proof
+            .branch
+            .iter()
+            .map(|node| load_bytes(ctx, node.as_bytes()))
+            .collect_vec();
+        let index = gate.load_witness(ctx, Value::known(F::from(self.state_proof.index)));
+        let state_root = &beacon_leaves[BEACON_HEADER_STATE_ROOT_IDX];
+        sha256.verify_merkle_branch(ctx, &gate, leaf.clone(), &branch, &index, state_root);
+        let leaf = bytes_be_to_u128(ctx, &gate, &leaf).try_into().unwrap();
+
+        EthBeaconStateWitness { header_witness, block_hash, merkle_roots, slot, index, leaf }
+    }
+}
+
+impl<F: Field> Circuit<F> for EthBeaconStateCircuit<F> {
+    type Config = EthConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        self.clone()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        let params = EthConfigParams::get_beacon_state();
+        EthConfig::configure(meta, params, 0)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        #[cfg(feature = "display")]
+        let witness_gen = start_timer!(|| "synthesize");
+
+        let gamma = layouter.get_challenge(config.rlc().gamma);
+        config.range().load_lookup_table(&mut layouter).expect("load range lookup table");
+        config.keccak().load_aux_tables(&mut layouter).expect("load keccak lookup tables");
+
+        let mut first_pass = SKIP_FIRST_PASS;
+        let mut instance = vec![];
+        layouter
+            .assign_region(
+                || "beacon state anchored to the header chain",
+                |region| {
+                    if first_pass {
+                        first_pass = false;
+                        return Ok(());
+                    }
+                    let mut chip = EthChip::new(config.clone(), gamma);
+                    let mut sha256 = Sha256Chip::new();
+                    let mut aux = Context::new(
+                        region,
+                        ContextParams {
+                            max_rows: chip.gate().max_rows,
+                            num_context_ids: 2,
+                            fixed_columns: chip.gate().constants.clone(),
+                        },
+                    );
+                    let ctx = &mut aux;
+
+                    // ================= FIRST PHASE ================
+                    let witness = self.assign_phase0(ctx, &mut chip, &mut sha256);
+                    chip.assign_phase0(ctx);
+                    ctx.next_phase();
+
+                    // ================= SECOND PHASE ================
+                    chip.get_challenge(ctx);
+                    chip.keccak_assign_phase1(ctx);
+                    chip.decompose_block_header_phase1(ctx, witness.header_witness);
+                    chip.range().finalize(ctx);
+
+                    instance.extend(
+                        witness
+                            .block_hash
+                            .iter()
+                            .chain(witness.merkle_roots.iter().flatten())
+                            .chain([&witness.slot, &witness.index])
+                            .chain(witness.leaf.iter())
+                            .map(|acell| acell.cell().clone()),
+                    );
+
+                    #[cfg(feature = "display")]
+                    {
+                        println!("Number of SHA-256 blocks: {}", sha256.num_blocks());
+                        ctx.print_stats(&["Range", "RLC"]);
+                    }
+                    Ok(())
+                },
+            )
+            .unwrap();
+        for (i, cell) in instance.into_iter().enumerate() {
+            layouter.constrain_instance(cell, config.instance, i);
+        }
+        #[cfg(feature = "display")]
+        end_timer!(witness_gen);
+        Ok(())
+    }
+}
+
+impl<F: Field> CircuitExt<F> for EthBeaconStateCircuit<F> {
+    fn num_instance(&self) -> Vec<usize> {
+        vec![2 * (self.max_depth() + 2) + 4]
+    }
+
+    fn instances(&self) -> Vec<Vec<F>> {
+        vec![self.instance()]
+    }
+}
@@ -0,0 +1,237 @@
+use super::*;
+use crate::{
+    block_header::EthBlockHeaderChainCircuit,
+    halo2_proofs::{dev::MockProver, halo2curves::bn256::Fr},
+    sha256::ssz::sha256_merkle_branch,
+    util::get_merkle_mountain_range,
+};
+use rlp::RlpStream;
+use std::env::set_var;
+
+const MAX_DEPTH: usize = 4;
+
+/// A beacon state with 4 fields, of which `validators` and `balances` have limit 4
+const TEST_LAYOUT: BeaconStateLayout = BeaconStateLayout {
+    state_depth: 2,
+    validators_field_idx: 1,
+    balances_field_idx: 2,
+    validator_registry_depth: 2,
+};
+const NUM_VALIDATORS: u64 = 3;
+
+fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    Sha256::digest([&left[..], &right[..]].concat()).into()
+}
+
+fn mock_validator(idx: u64) -> [[u8; 32]; 8] {
+    let mut withdrawal_credentials = [idx as u8; 32];
+    withdrawal_credentials[..12].copy_from_slice(&[1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    [
+        [0xaa; 32],
+        withdrawal_credentials,
+        uint64_to_chunk(32_000_000_000),
+        uint64_to_chunk(0),
+        uint64_to_chunk(100),
+        uint64_to_chunk(200),
+        uint64_to_chunk(u64::MAX),
+        uint64_to_chunk(u64::MAX),
+    ]
+}
+
+fn mock_balance(idx: u64) -> u64 {
+    32_000_000_000 + idx * 1_000_000
+}
+
+/// The state root and the proofs of the withdrawal credentials and balance chunk of validator `idx` in a mock beacon
+/// state with layout `TEST_LAYOUT`
+fn mock_beacon_state(idx: u64) -> (H256, BeaconStateProof, BeaconStateProof) {
+    let validators = (0..NUM_VALIDATORS).map(mock_validator).collect_vec();
+    let mut validator_roots =
+        validators.iter().map(|chunks| sha256_merkle_root(chunks)).collect_vec();
+    validator_roots.resize(1 << TEST_LAYOUT.validator_registry_depth, [0; 32]);
+    let length = uint64_to_chunk(NUM_VALIDATORS);
+    let validators_root = hash_pair(&sha256_merkle_root(&validator_roots), &length);
+
+    let mut balances = [0u8; 32];
+    for i in 0..NUM_VALIDATORS as usize {
+        balances[8 * i..8 * i + 8].copy_from_slice(&mock_balance(i as u64).to_le_bytes());
+    }
+    let balances_root = hash_pair(&balances, &length);
+
+    let fields = [uint64_to_chunk(1606824023), validators_root, balances_root, [0; 32]];
+    let state_root = H256(sha256_merkle_root(&fields));
+
+    let withdrawal_credentials = {
+        let (index, depth) = TEST_LAYOUT.validator_withdrawal_credentials(idx);
+        let branch = [
+            sha256_merkle_branch(&validators[idx as usize], 1),
+            sha256_merkle_branch(&validator_roots, idx as usize),
+            vec![length],
+            sha256_merkle_branch(&fields, TEST_LAYOUT.validators_field_idx as usize),
+        ]
+        .concat();
+        assert_eq!(branch.len(), depth);
+        let branch = branch.into_iter().map(H256).collect();
+        BeaconStateProof { leaf: H256(validators[idx as usize][1]), index, branch }
+    };
+    let balance = {
+        let (index, depth) = TEST_LAYOUT.validator_balance_chunk(idx);
+        let branch =
+            [vec![length], sha256_merkle_branch(&fields, TEST_LAYOUT.balances_field_idx as usize)]
+                .concat();
+        assert_eq!(branch.len(), depth);
+        let branch = branch.into_iter().map(H256).collect();
+        BeaconStateProof { leaf: H256(balances), index, branch }
+    };
+    (state_root, withdrawal_credentials, balance)
+}
+
+/// A Cancun execution block header of block `number` with `parent_hash` and `parent_beacon_block_root`
+fn mock_cancun_header(parent_hash: H256, number: u64, parent_beacon_block_root: H256) -> Vec<u8> {
+    let mut stream = RlpStream::new_list(20);
+    stream
+        .append(&parent_hash.as_bytes().to_vec())
+        .append(&keccak256([0xc0]).to_vec())
+        .append(&vec![2u8; 20])
+        .append(&H256::repeat_byte(3).as_bytes().to_vec())
+        .append(&H256::repeat_byte(4).as_bytes().to_vec())
+        .append(&H256::repeat_byte(5).as_bytes().to_vec())
+        .append(&vec![0u8; 256])
+        .append(&0u64)
+        .append(&number)
+        .append(&30_000_000u64)
+        .append(&12_345_678u64)
+        .append(&1_710_338_135u64)
+        .append(&b"beacon".to_vec())
+        .append(&H256::repeat_byte(6).as_bytes().to_vec())
+        .append(&vec![0u8; 8])
+        .append(&7_000_000_000u64)
+        .append(&H256::repeat_byte(7).as_bytes().to_vec())
+        .append(&131_072u64)
+        .append(&0u64)
+        .append(&parent_beacon_block_root.as_bytes().to_vec());
+    stream.out().to_vec()
+}
+
+fn mock_beacon_header(state_root: H256) -> BeaconBlockHeader {
+    BeaconBlockHeader {
+        slot: 8_626_176,
+        proposer_index: 1_234,
+        parent_root: H256::repeat_byte(8),
+        state_root,
+        body_root: H256::repeat_byte(9),
+    }
+}
+
+fn get_test_circuit(state_proof: BeaconStateProof, state_root: H256) -> EthBeaconStateCircuit<Fr> {
+    let beacon_header = mock_beacon_header(state_root);
+    let header_rlp =
+        mock_cancun_header(H256::repeat_byte(1), 19_426_587, beacon_header.hash_tree_root());
+    let mut block_hashes = (0..11).map(|i: u64| H256(keccak256(i.to_be_bytes()))).collect_vec();
+    block_hashes[5] = H256(keccak256(&header_rlp));
+    let merkle_roots = get_merkle_mountain_range(&block_hashes, MAX_DEPTH);
+    let block_hash_proof = BlockHashMerkleProof::new(&block_hashes, 5);
+    EthBeaconStateCircuit::new(
+        header_rlp,
+        block_hash_proof,
+        merkle_roots,
+        beacon_header,
+        state_proof,
+    )
+}
+
+#[test]
+pub fn test_beacon_state_layout() {
+    let layout = BeaconStateLayout::deneb();
+    assert_eq!(layout.validator_withdrawal_credentials(0), ((11 << 44) | 1, 49));
+    assert_eq!(layout.validator_balance_chunk(5), ((12 << 39) | 1, 44));
+    // the fields of the Electra beacon state no longer fit in a tree of depth 5
+    let layout = BeaconStateLayout::electra();
+    assert_eq!(layout.validator_withdrawal_credentials(0), ((11 << 44) | 1, 50));
+    assert_eq!(layout.validator_balance_chunk(5), ((12 << 39) | 1, 45));
+
+    for idx in 0..NUM_VALIDATORS {
+        let (state_root, withdrawal_credentials, balance) = mock_beacon_state(idx);
+        assert_eq!(withdrawal_credentials.root(), state_root);
+        assert_eq!(balance.root(), state_root);
+        assert_eq!(balance_from_chunk(&balance.leaf, idx), mock_balance(idx));
+    }
+}
+
+#[test]
+pub fn test_parent_beacon_block_root() {
+    let root = H256::repeat_byte(0xbe);
+    let header = mock_cancun_header(H256::repeat_byte(1), 19_426_587, root);
+    assert!(header.len() <= MAINNET_BLOCK_HEADER_RLP_MAX_BYTES);
+    assert_eq!(get_parent_beacon_block_root(&header), root);
+}
+
+#[test]
+pub fn test_mock_beacon_state_withdrawal_credentials() {
+    let k = EthConfigParams::get_beacon_state().degree;
+    let (state_root, withdrawal_credentials, _) = mock_beacon_state(2);
+    let circuit = get_test_circuit(withdrawal_credentials, state_root);
+    MockProver::run(k, &circuit, circuit.instances()).unwrap().assert_satisfied();
+}
+
+#[test]
+pub fn test_mock_beacon_state_wrong_balance() {
+    let k = EthConfigParams::get_beacon_state().degree;
+    let (state_root, _, balance) = mock_beacon_state(1);
+    let circuit = get_test_circuit(balance, state_root);
+    let mut instances = circuit.instances();
+    // the lo half of the leaf
+    *instances[0].last_mut().unwrap() += Fr::one();
+    assert!(MockProver::run(k, &circuit, instances).unwrap().verify().is_err());
+}
+
+#[test]
+#[should_panic = "beacon state root mismatch"]
+pub fn test_beacon_state_wrong_state_root() {
+    let (_, withdrawal_credentials, _) = mock_beacon_state(0);
+    get_test_circuit(withdrawal_credentials, H256::repeat_byte(0xff));
+}
+
+#[test]
+pub fn test_mock_beacon_state_anchored_to_header_chain() {
+    set_var("BLOCK_HEADER_CONFIG", "configs/tests/multi_block.json");
+    let max_depth = 3;
+    let (state_root, withdrawal_credentials, _) = mock_beacon_state(1);
+    let beacon_header = mock_beacon_header(state_root);
+    // a chain of 5 Cancun blocks, of which block 3 commits to the beacon block
+    let mut headers: Vec<Vec<u8>> = vec![];
+    let mut parent_hash = H256::repeat_byte(1);
+    for idx in 0..5u8 {
+        let root = match idx {
+            3 => beacon_header.hash_tree_root(),
+            _ => H256::repeat_byte(0x10 + idx),
+        };
+        let header = mock_cancun_header(parent_hash, 19_426_587 + idx as u64, root);
+        parent_hash = H256(keccak256(&header));
+        headers.push(header);
+    }
+    let block_hashes = headers.iter().map(|header| H256(keccak256(header))).collect_vec();
+    let header_chain = EthBlockHeaderChainCircuit::<Fr>::from_headers(
+        headers.clone(),
+        Network::Mainnet,
+        max_depth,
+    );
+    let header_chain_instance = header_chain.instance.to_instance();
+    let k = EthConfigParams::get_header().degree;
+    MockProver::run(k, &header_chain, vec![header_chain_instance.clone()])
+        .unwrap()
+        .assert_satisfied();
+
+    let circuit = EthBeaconStateCircuit::new(
+        headers[3].clone(),
+        BlockHashMerkleProof::new(&block_hashes, 3),
+        header_chain.instance.merkle_mountain_range.clone(),
+        beacon_header,
+        withdrawal_credentials,
+    );
+    // the merkle roots are public instances of both circuits
+    let num_roots = 2 * (max_depth + 1);
+    assert_eq!(circuit.instance()[2..2 + num_roots], header_chain_instance[5..5 + num_roots]);
+    let k = EthConfigParams::get_beacon_state().degree;
+    MockProver::run(k, &circuit, circuit.instances()).unwrap().assert_satisfied();
+}
@@ -0,0 +1,86 @@
+use axiom_eth::{
+    block_header::{
+        helpers::{CircuitType, Finality, Sequencer, Task},
+        scheduler::SchedulerConfig,
+    },
+    Network,
+};
+use clap::Parser;
+use clap_num::maybe_hex;
+use ethers_providers::{Http, Provider};
+
+/// Proves the block header chain between `start` and `end`, aggregating snarks up to `max_depth`
+#[derive(Parser, Debug)]
+struct Cli {
+    #[arg(long, value_enum, default_value_t = Network::Mainnet)]
+    network: Network,
+    #[arg(long, value_parser = maybe_hex::<u32>)]
+    start: u32,
+    #[arg(long, value_parser = maybe_hex::<u32>)]
+    end: u32,
+    #[arg(long)]
+    max_depth: usize,
+    #[arg(long)]
+    initial_depth: Option<usize>,
+    /// Produce a single final snark with the merkle mountain range as output
+    #[arg(long)]
+    final_merkle: bool,
+    /// Number of rounds of SNARK verification for the EVM after the final snark
+    #[arg(long)]
+    evm_rounds: Option<usize>,
+    /// JSON-RPC URL of a node to fetch blocks from. Defaults to `{NETWORK}_RPC_URL`, then Infura with
+    /// `INFURA_ID`. Not needed if all initial snarks are on disk.
+    #[arg(long)]
+    rpc_url: Option<String>,
+    /// Continue the run recorded in the journal instead of starting a new one
+    #[arg(long)]
+    resume: bool,
+    #[arg(long)]
+    num_workers: Option<usize>,
+    /// Memory budget in GiB for the proofs generated at the same time
+    #[arg(long)]
+    memory_budget: Option<u64>,
+    /// Aggregate up to `2^max_fan_in_log2` snarks per aggregation circuit, as many as fit within the memory
+    /// budget and have a configuration in `configs/headers`, or one derived from fan-in 2
+    #[arg(long)]
+    max_fan_in_log2: Option<usize>,
+}
+
+fn main() {
+    let args = Cli::parse();
+    let mut sequencer = match args.rpc_url {
+        Some(url) => {
+            let provider = Provider::<Http>::try_from(url.as_str())
+                .expect("could not instantiate HTTP Provider");
+            Sequencer::with_provider(args.network, Some(provider))
+        }
+        None => Sequencer::new(args.network),
+    };
+    let mut config = SchedulerConfig::default();
+    if let Some(num_workers) = args.num_workers {
+        config.num_workers = num_workers;
+    }
+    config.memory_budget = args.memory_budget.map(|gib| gib << 30);
+
+    if args.resume {
+        sequencer.resume(&config).expect("no journal to resume from");
+        return;
+    }
+    let finality = match (args.evm_rounds, args.final_merkle) {
+        (Some(rounds), _) => {
+            Finality::Evm(rounds.checked_sub(1).expect("evm rounds must be positive"))
+        }
+        (None, true) => Finality::Merkle,
+        (None, false) => Finality::None,
+    };
+    let initial_depth = args.initial_depth.unwrap_or(args.max_depth);
+    let mut circuit_type = CircuitType::new(args.max_depth, initial_depth, finality);
+    if let Some(max_fan_in_log2) = args.max_fan_in_log2 {
+        circuit_type = sequencer.choose_fan_in(circuit_type, max_fan_in_log2, &config);
+        println!("Aggregating up to {} snarks at a time", circuit_type.fan_in());
+    }
+    for start in (args.start..=args.end).step_by(1 << args.max_depth) {
+        let end = std::cmp::min(start + (1 << args.max_depth) - 1, args.end);
+        sequencer.get_snark_parallel(Task::new(start, end, circuit_type), &config);
+    }
+}
@@ -0,0 +1,70 @@
+use axiom_eth::keccak::planner::{num_keccak_f, CircuitStats, KeccakPlanner};
+use clap::Parser;
+use std::{fs::File, path::PathBuf};
+
+/// Finds the smallest circuit degree, with its keccak rows per round and column counts, that fits the cells and
+/// keccak queries of a circuit
+#[derive(Parser, Debug)]
+struct Cli {
+    /// JSON file with the cells of the circuit, as `CircuitStats`. The other options are added to it.
+    #[arg(long)]
+    stats: Option<PathBuf>,
+    #[arg(long, default_value_t = 0)]
+    num_keccak_f: usize,
+    /// Maximum input byte lengths of the keccak queries, comma separated
+    #[arg(long, value_delimiter = ',')]
+    keccak_queries: Vec<usize>,
+    /// Advice cells without lookup in each phase, comma separated
+    #[arg(long, value_delimiter = ',')]
+    advice_cells: Vec<usize>,
+    /// Cells to range check by lookup in each phase, comma separated
+    #[arg(long, value_delimiter = ',')]
+    lookup_cells: Vec<usize>,
+    #[arg(long, default_value_t = 0)]
+    rlc_cells: usize,
+    #[arg(long, default_value_t = 0)]
+    fixed_cells: usize,
+    #[arg(long)]
+    min_degree: Option<u32>,
+    #[arg(long)]
+    max_degree: Option<u32>,
+    #[arg(long)]
+    max_advice_columns: Option<usize>,
+    /// Write the config params of the plan to this file, e.g. `configs/storage.json`
+    #[arg(long)]
+    out: Option<PathBuf>,
+}
+
+fn main() {
+    let args = Cli::parse();
+    let mut stats: CircuitStats = match &args.stats {
+        Some(path) => serde_json::from_reader(File::open(path).expect("stats file does not exist"))
+            .expect("invalid stats file"),
+        None => CircuitStats::default(),
+    };
+    stats.num_keccak_f += args.num_keccak_f + num_keccak_f(args.keccak_queries);
+    let add_per_phase = |cells: &mut Vec<usize>, extra: &[usize]| {
+        cells.resize(cells.len().max(extra.len()), 0);
+        cells.iter_mut().zip(extra).for_each(|(cells, extra)| *cells += extra);
+    };
+    add_per_phase(&mut stats.advice_cells, &args.advice_cells);
+    add_per_phase(&mut stats.lookup_cells, &args.lookup_cells);
+    stats.rlc_cells += args.rlc_cells;
+    stats.fixed_cells += args.fixed_cells;
+
+    let mut planner = KeccakPlanner::default();
+    planner.min_degree = args.min_degree.unwrap_or(planner.min_degree);
+    planner.max_degree = args.max_degree.unwrap_or(planner.max_degree);
+    planner.max_advice_columns = args.max_advice_columns.unwrap_or(planner.max_advice_columns);
+
+    let plan = planner.plan(&stats).unwrap_or_else(|| {
+        panic!(
+            "no degree in [{}, {}] fits {stats:?} with at most {} advice columns",
+            planner.min_degree, planner.max_degree, planner.max_advice_columns
+        )
+    });
+    println!("{}", serde_json::to_string_pretty(&plan).unwrap());
+    if let Some(out) = args.out {
+        serde_json::to_writer_pretty(File::create(out).unwrap(), &plan.params).unwrap();
+    }
+}
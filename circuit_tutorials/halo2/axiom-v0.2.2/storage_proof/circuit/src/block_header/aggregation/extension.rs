@@ -0,0 +1,407 @@
+use super::{
+    finalize_merkle_mountain_range, split_u64_into_u32s, AggregationWithKeccakConfig,
+    AggregationWithKeccakConfigParams, EthBlockHeaderChainAggregationCircuit,
+};
+use crate::{block_header::EthBlockHeaderChainInstance, Field};
+#[cfg(feature = "display")]
+use ark_std::{end_timer, start_timer};
+use ethers_core::types::H256;
+use halo2_base::{
+    gates::{range::RangeConfig, GateInstructions, RangeInstructions},
+    halo2_proofs::{
+        circuit::{Layouter, Region, SimpleFloorPlanner},
+        halo2curves::bn256::{Bn256, Fr},
+        plonk::{Circuit, ConstraintSystem, Error},
+        poly::kzg::commitment::ParamsKZG,
+    },
+    utils::PrimeField,
+    AssignedValue, Context,
+    QuantumCell::{Constant, Existing},
+};
+use itertools::Itertools;
+use rand::Rng;
+use snark_verifier_sdk::{
+    halo2::aggregation::{AggregationCircuit, AggregationConfig, Halo2Loader},
+    Snark, LIMBS,
+};
+use std::rc::Rc;
+
+/// Extends a proven header chain with new blocks, without proving the old blocks again.
+///
+/// Aggregates the snark of a chain `[start, end]` of an `EthBlockHeaderChainAggregationCircuit` of depth
+/// `max_depth`, and the snark of the chain `[end + 1, new_end]` of an `EthBlockHeaderChainCircuit` or
+/// `EthBlockHeaderChainAggregationCircuit` of depth `new_depth <= max_depth`, with the same `initial_depth`.
+/// The number of blocks in `[start, end]` must be a multiple of `2^initial_depth`, so that the
+/// `2^{new_depth - initial_depth}` roots of the new chain can be placed right after the roots of the old chain.
+///
+/// The public instances are the same as those of an `EthBlockHeaderChainAggregationCircuit` of depth `max_depth`
+/// for the chain `[start, new_end]`, so the snark of an extension can be extended again or finalized like the
+/// snark of an aggregation circuit, see [`EthBlockHeaderChainFinalExtensionCircuit`]. The verifying keys of both
+/// snarks are part of the circuit, so each of these needs its own proving key.
+#[derive(Clone)]
+pub struct EthBlockHeaderChainExtensionCircuit {
+    pub inner: EthBlockHeaderChainAggregationCircuit,
+    pub new_depth: usize,
+}
+
+impl EthBlockHeaderChainExtensionCircuit {
+    pub fn new(
+        params: &ParamsKZG<Bn256>,
+        prev_snark: Snark,
+        new_snark: Snark,
+        rng: &mut (impl Rng + Send),
+        max_depth: usize,
+        new_depth: usize,
+        initial_depth: usize,
+    ) -> Self {
+        assert!(max_depth > initial_depth && max_depth >= new_depth && new_depth >= initial_depth);
+        let prev =
+            EthBlockHeaderChainInstance::from_instance(&prev_snark.instances[0][4 * LIMBS..]);
+        let new_instance_start_idx = usize::from(new_depth != initial_depth) * 4 * LIMBS;
+        let new = EthBlockHeaderChainInstance::from_instance(
+            &new_snark.instances[0][new_instance_start_idx..],
+        );
+        let chain_instance = extend_instances(&prev, &new, max_depth, new_depth, initial_depth);
+        let num_blocks = chain_instance.end_block_number - chain_instance.start_block_number + 1;
+        let aggregation = AggregationCircuit::new(params, vec![prev_snark, new_snark], rng);
+        let inner = EthBlockHeaderChainAggregationCircuit {
+            aggregation,
+            num_blocks,
+            chain_instance,
+            max_depth,
+            initial_depth,
+        };
+        Self { inner, new_depth }
+    }
+
+    /// Aggregates the snarks and extends the previous chain as in [`extend_previous_instances`]. Returns the
+    /// accumulator followed by the new instances, as in
+    /// [`EthBlockHeaderChainAggregationCircuit::aggregate_and_join_instances`].
+    pub fn aggregate_and_extend_instances<'v>(
+        &self,
+        config: &AggregationConfig,
+        region: Region<'v, Fr>,
+    ) -> (Vec<AssignedValue<'v, Fr>>, AssignedValue<'v, Fr>, Rc<Halo2Loader<'v>>) {
+        self.inner.aggregate_and_then(
+            config,
+            region,
+            |ctx, range, prev_instances, num_blocks_minus_one| {
+                extend_previous_instances(
+                    ctx,
+                    range,
+                    prev_instances,
+                    num_blocks_minus_one,
+                    self.inner.max_depth,
+                    self.new_depth,
+                    self.inner.initial_depth,
+                )
+            },
+        )
+    }
+
+    pub fn merkle_mountain_range(&self) -> Vec<H256> {
+        self.inner.merkle_mountain_range()
+    }
+
+    pub fn instance(&self) -> Vec<Fr> {
+        self.inner.instance()
+    }
+}
+
+impl Circuit<Fr> for EthBlockHeaderChainExtensionCircuit {
+    type Config = AggregationConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self { inner: self.inner.without_witnesses(), new_depth: self.new_depth }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+        AggregationCircuit::configure(meta)
+    }
+
+    fn synthesize(
+        &self,
+        config: AggregationConfig,
+        mut layouter: impl Layouter<Fr>,
+    ) -> Result<(), Error> {
+        #[cfg(feature = "display")]
+        let witness_time = start_timer!(|| format!(
+            "synthesize extension {:06x}-{:06x} {} {} {}",
+            self.inner.chain_instance.start_block_number,
+            self.inner.chain_instance.end_block_number,
+            self.inner.max_depth,
+            self.new_depth,
+            self.inner.initial_depth
+        ));
+        config.range().load_lookup_table(&mut layouter).expect("load range lookup table");
+        let mut first_pass = halo2_base::SKIP_FIRST_PASS;
+        let mut instances = Vec::new();
+        layouter
+            .assign_region(
+                || "Block header chain extension circuit",
+                |region| {
+                    if first_pass {
+                        first_pass = false;
+                        return Ok(());
+                    }
+                    let (new_instances, _, loader) =
+                        self.aggregate_and_extend_instances(&config, region);
+                    instances.extend(new_instances.iter().map(|assigned| assigned.cell()).cloned());
+                    let ctx = &mut loader.ctx_mut();
+                    config.base_field_config.finalize(ctx);
+
+                    #[cfg(feature = "display")]
+                    ctx.print_stats(&["Range"]);
+                    Ok(())
+                },
+            )
+            .unwrap();
+
+        for (i, cell) in instances.into_iter().enumerate() {
+            layouter.constrain_instance(cell, config.instance, i);
+        }
+        #[cfg(feature = "display")]
+        end_timer!(witness_time);
+        Ok(())
+    }
+}
+
+/// Same as `EthBlockHeaderChainExtensionCircuit` but uses Keccak chip to compute the final merkle mountain range,
+/// as in [`EthBlockHeaderChainFinalAggregationCircuit`](super::EthBlockHeaderChainFinalAggregationCircuit).
+/// It has the same public instances as the final aggregation circuit of depth `max_depth`, so its snark can be
+/// aggregated for the EVM like the snark of a `Finality::Merkle` task.
+#[derive(Clone)]
+pub struct EthBlockHeaderChainFinalExtensionCircuit(pub EthBlockHeaderChainExtensionCircuit);
+
+impl EthBlockHeaderChainFinalExtensionCircuit {
+    /// The snarks should be as in [`EthBlockHeaderChainExtensionCircuit::new`]
+    pub fn new(
+        params: &ParamsKZG<Bn256>,
+        prev_snark: Snark,
+        new_snark: Snark,
+        rng: &mut (impl Rng + Send),
+        max_depth: usize,
+        new_depth: usize,
+        initial_depth: usize,
+    ) -> Self {
+        let mut pre_circuit = EthBlockHeaderChainExtensionCircuit::new(
+            params,
+            prev_snark,
+            new_snark,
+            rng,
+            max_depth,
+            new_depth,
+            initial_depth,
+        );
+        pre_circuit.inner.chain_instance.merkle_mountain_range =
+            pre_circuit.merkle_mountain_range();
+        Self(pre_circuit)
+    }
+
+    pub fn instance(&self) -> Vec<Fr> {
+        self.0.instance()
+    }
+}
+
+impl Circuit<Fr> for EthBlockHeaderChainFinalExtensionCircuit {
+    type Config = AggregationWithKeccakConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self(self.0.without_witnesses())
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+        let params = AggregationWithKeccakConfigParams::get();
+        AggregationWithKeccakConfig::configure(meta, params)
+    }
+
+    fn synthesize(
+        &self,
+        config: AggregationWithKeccakConfig,
+        mut layouter: impl Layouter<Fr>,
+    ) -> Result<(), Error> {
+        #[cfg(feature = "display")]
+        let witness_time = start_timer!(|| format!(
+            "synthesize extension {:06x}-{:06x} {} {} {} with keccak",
+            self.0.inner.chain_instance.start_block_number,
+            self.0.inner.chain_instance.end_block_number,
+            self.0.inner.max_depth,
+            self.0.new_depth,
+            self.0.inner.initial_depth
+        ));
+        config.range().load_lookup_table(&mut layouter).expect("load range lookup table");
+        config.keccak.load_aux_tables(&mut layouter).expect("load keccak lookup table");
+        let gamma = layouter.get_challenge(config.rlc.gamma);
+        let mut first_pass = halo2_base::SKIP_FIRST_PASS;
+        let mut instances = Vec::new();
+        layouter
+            .assign_region(
+                || "Block header chain final extension circuit",
+                |region| {
+                    if first_pass {
+                        first_pass = false;
+                        return Ok(());
+                    }
+                    let (pre_instances, num_blocks_minus_one, loader) =
+                        self.0.aggregate_and_extend_instances(&config.aggregation, region);
+                    let ctx = &mut loader.ctx_mut();
+                    instances = finalize_merkle_mountain_range(
+                        &config,
+                        ctx,
+                        gamma,
+                        pre_instances,
+                        &num_blocks_minus_one,
+                        self.0.inner.max_depth,
+                        self.0.inner.initial_depth,
+                    );
+
+                    #[cfg(feature = "display")]
+                    ctx.print_stats(&["Range", "RLC"]);
+                    Ok(())
+                },
+            )
+            .unwrap();
+
+        for (i, cell) in instances.into_iter().enumerate() {
+            layouter.constrain_instance(cell, config.aggregation.instance, i);
+        }
+        #[cfg(feature = "display")]
+        end_timer!(witness_time);
+        Ok(())
+    }
+}
+
+/// Extends the chain `prev` of depth `max_depth` with the chain `new` of depth `new_depth` as in
+/// [`extend_previous_instances`], off-circuit
+pub(crate) fn extend_instances(
+    prev: &EthBlockHeaderChainInstance,
+    new: &EthBlockHeaderChainInstance,
+    max_depth: usize,
+    new_depth: usize,
+    initial_depth: usize,
+) -> EthBlockHeaderChainInstance {
+    assert_eq!(prev.end_hash, new.prev_hash, "new chain does not extend the previous chain");
+    assert_eq!(prev.end_block_number + 1, new.start_block_number);
+    let prev_num_blocks = prev.end_block_number - prev.start_block_number + 1;
+    assert_eq!(prev_num_blocks % (1 << initial_depth), 0, "previous chain ends mid-mountain");
+    let num_blocks = new.end_block_number - prev.start_block_number + 1;
+    assert!(num_blocks <= 1 << max_depth);
+
+    // the roots of the new chain replace the roots after the previous chain, and the new chain determines
+    // the `initial_depth` smallest mountains
+    let num_roots = 1 << (max_depth - initial_depth);
+    let num_new_roots = 1 << (new_depth - initial_depth);
+    let offset = prev_num_blocks as usize >> initial_depth;
+    let mut roots = prev.merkle_mountain_range[..num_roots].to_vec();
+    for (root, new_root) in roots[offset..].iter_mut().zip(new.merkle_mountain_range.iter()) {
+        *root = *new_root;
+    }
+    roots.extend_from_slice(&new.merkle_mountain_range[num_new_roots..]);
+    EthBlockHeaderChainInstance {
+        prev_hash: prev.prev_hash,
+        end_hash: new.end_hash,
+        start_block_number: prev.start_block_number,
+        end_block_number: new.end_block_number,
+        merkle_mountain_range: roots,
+    }
+}
+
+/// Takes the instances of an `EthBlockHeaderChainAggregationCircuit` of depth `max_depth` followed by the
+/// instances of a chain of depth `new_depth` (with accumulators if `new_depth != initial_depth`) and
+/// - checks that the second chain extends the first, which has a multiple of `2^initial_depth` blocks
+/// - updates the merkle mountain range:
+///     - replaces the `2^{new_depth - initial_depth}` roots after the roots of the first chain by the
+///     roots of the second chain
+///     - takes the last `initial_depth` roots for the smallest part of the range from the second chain
+///
+/// Returns the new instances for the depth `max_depth` circuit (without accumulators), as in
+/// [`join_previous_instances`](super::join_previous_instances)
+pub fn extend_previous_instances<'v, F: Field + PrimeField>(
+    ctx: &mut Context<'v, F>,
+    range: &RangeConfig<F>,
+    prev_instances: Vec<Vec<AssignedValue<'v, F>>>,
+    num_blocks_minus_one: &AssignedValue<'v, F>,
+    max_depth: usize,
+    new_depth: usize,
+    initial_depth: usize,
+) -> Vec<AssignedValue<'v, F>> {
+    let [prev_instance, new_instance]: [_; 2] = prev_instances.try_into().unwrap();
+    let num_instance =
+        EthBlockHeaderChainAggregationCircuit::get_num_instance(max_depth, initial_depth);
+    let new_start = if new_depth != initial_depth { 4 * LIMBS } else { 0 };
+    let new_num_instance =
+        EthBlockHeaderChainAggregationCircuit::get_num_instance(new_depth, initial_depth);
+    debug_assert_eq!(prev_instance.len(), 4 * LIMBS + num_instance);
+    debug_assert_eq!(new_instance.len(), new_start + new_num_instance);
+    let prev_instance = &prev_instance[4 * LIMBS..];
+    let new_instance = &new_instance[new_start..];
+
+    // the new chain starts right after the previous chain
+    for (a, b) in prev_instance[2..4].iter().zip(new_instance[..2].iter()) {
+        ctx.constrain_equal(a, b);
+    }
+    let (start_block_number, prev_end_block_number) =
+        split_u64_into_u32s(ctx, range, &prev_instance[4]);
+    let (new_start_block_number, end_block_number) =
+        split_u64_into_u32s(ctx, range, &new_instance[4]);
+    let next_block_number =
+        range.gate().add(ctx, Existing(&prev_end_block_number), Constant(F::one()));
+    ctx.constrain_equal(&next_block_number, &new_start_block_number);
+    let new_num_blocks_minus_one =
+        range.gate().sub(ctx, Existing(&end_block_number), Existing(&new_start_block_number));
+    range.check_less_than_safe(ctx, &new_num_blocks_minus_one, 1 << new_depth);
+    // check number of blocks is correct; also constrains `num_blocks <= 2^max_depth`
+    let boundary_num_diff =
+        range.gate().sub(ctx, Existing(&end_block_number), Existing(&start_block_number));
+    ctx.constrain_equal(&boundary_num_diff, num_blocks_minus_one);
+    range.check_less_than_safe(ctx, num_blocks_minus_one, 1 << max_depth);
+    let boundary_block_numbers = range.gate().mul_add(
+        ctx,
+        Constant(range.gate().pow_of_two()[32]),
+        Existing(&start_block_number),
+        Existing(&end_block_number),
+    );
+
+    // the previous chain fills `offset` mountains of depth `initial_depth`
+    let prev_num_blocks =
+        range.gate().sub(ctx, Existing(&new_start_block_number), Existing(&start_block_number));
+    let (offset, rem) =
+        range.div_mod(ctx, Existing(&prev_num_blocks), 1usize << initial_depth, max_depth);
+    range.gate().assert_is_const(ctx, &rem, F::zero());
+
+    // update merkle roots
+    let num_roots = 1 << (max_depth - initial_depth);
+    let num_new_roots = 1 << (new_depth - initial_depth);
+    let prev_roots = prev_instance[5..5 + 2 * num_roots].chunks(2).collect_vec();
+    let new_roots = new_instance[5..5 + 2 * num_new_roots].chunks(2).collect_vec();
+
+    let mut instances = Vec::with_capacity(num_instance);
+    instances.extend_from_slice(&prev_instance[..2]);
+    instances.extend_from_slice(&new_instance[2..4]);
+    instances.push(boundary_block_numbers);
+    for (i, prev_root) in prev_roots.into_iter().enumerate() {
+        // root `i` is the root `i - offset` of the new chain, if there is one
+        let idx = range.gate().sub(ctx, Constant(F::from(i as u64)), Existing(&offset));
+        let indicator = range.gate().idx_to_indicator(ctx, Existing(&idx), num_new_roots);
+        let is_new = range.gate().sum(ctx, indicator.iter().map(Existing));
+        for (j, prev) in prev_root.iter().enumerate() {
+            let new = range.gate().select_by_indicator(
+                ctx,
+                new_roots.iter().map(|root| Existing(&root[j])),
+                indicator.iter(),
+            );
+            instances.push(range.gate().select(
+                ctx,
+                Existing(&new),
+                Existing(prev),
+                Existing(&is_new),
+            ));
+        }
+    }
+    instances.extend_from_slice(&new_instance[5 + 2 * num_new_roots..]);
+    debug_assert_eq!(instances.len(), num_instance);
+
+    instances
+}
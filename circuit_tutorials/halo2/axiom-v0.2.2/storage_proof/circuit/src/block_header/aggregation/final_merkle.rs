@@ -3,18 +3,19 @@ use ark_std::{end_timer, start_timer};
 use halo2_base::{
     gates::{flex_gate::FlexGateConfig, range::RangeConfig, GateInstructions},
     halo2_proofs::{
-        circuit::{Layouter, SimpleFloorPlanner},
+        circuit::{Cell, Layouter, SimpleFloorPlanner, Value},
         halo2curves::bn256::{Bn256, Fr},
         plonk::{Circuit, ConstraintSystem, Error},
         poly::kzg::commitment::ParamsKZG,
     },
+    AssignedValue, Context,
     QuantumCell::{Constant, Existing},
 };
 
 use crate::{
     keccak::{KeccakChip, KeccakConfig},
     rlp::rlc::{RlcChip, RlcConfig},
-    util::{bytes_be_to_u128, get_merkle_mountain_range, num_to_bytes_be, NUM_BYTES_IN_U128},
+    util::{bytes_be_to_u128, num_to_bytes_be, NUM_BYTES_IN_U128},
 };
 use itertools::Itertools;
 use rand::Rng;
@@ -81,14 +82,12 @@ impl AggregationWithKeccakConfig {
     }
 }
 
-/// Same as `EthBlockHeaderChainAggregationCircuit` but uses Keccak chip to compute the final merkle mountain root. Specifically, it aggregates two snarks at `max_depth - 1` and then computes the keccaks to get the final merkle mountain root.
+/// Same as `EthBlockHeaderChainAggregationCircuit` but uses Keccak chip to compute the final merkle mountain root. Specifically, it aggregates `2^m` snarks at `max_depth - m` and then computes the keccaks to get the final merkle mountain root.
 #[derive(Clone)]
 pub struct EthBlockHeaderChainFinalAggregationCircuit(pub EthBlockHeaderChainAggregationCircuit);
 
 impl EthBlockHeaderChainFinalAggregationCircuit {
-    /// `snarks` should be exactly two snarks of either
-    /// - `EthBlockHeaderChainCircuit` if `max_depth == initial_depth + 1` or
-    /// - `EthBlockHeaderChainAggregationCircuit` otherwise
+    /// `snarks` should be as in [`EthBlockHeaderChainAggregationCircuit::new`]
     pub fn new(
         params: &ParamsKZG<Bn256>,
         snarks: Vec<Snark>,
@@ -105,13 +104,7 @@ impl EthBlockHeaderChainFinalAggregationCircuit {
             max_depth,
             initial_depth,
         );
-        let leaves = &pre_circuit.chain_instance.merkle_mountain_range
-            [..num_blocks as usize >> initial_depth];
-        let mut new_mmr = get_merkle_mountain_range(leaves, max_depth - initial_depth);
-        new_mmr.extend_from_slice(
-            &pre_circuit.chain_instance.merkle_mountain_range[1 << (max_depth - initial_depth)..],
-        );
-        pre_circuit.chain_instance.merkle_mountain_range = new_mmr;
+        pre_circuit.chain_instance.merkle_mountain_range = pre_circuit.merkle_mountain_range();
         Self(pre_circuit)
     }
 
@@ -164,77 +157,18 @@ impl Circuit<Fr> for EthBlockHeaderChainFinalAggregationCircuit {
                         first_pass = false;
                         return Ok(());
                     }
-                    let (mut pre_instances, num_blocks_minus_one, loader) =
+                    let (pre_instances, num_blocks_minus_one, loader) =
                         self.0.aggregate_and_join_instances(&config.aggregation, region);
                     let ctx = &mut loader.ctx_mut();
-                    // add RLC context
-                    ctx.advice_alloc.push((0, 0));
-
-                    // compute the keccaks that were delayed, to get the `max_depth - initial_depth + 1` biggest merkle mountain ranges
-                    let num_blocks = config.gate().add(
-                        ctx,
-                        Existing(&num_blocks_minus_one),
-                        Constant(Fr::one()),
-                    );
-                    let bits = config.gate().num_to_bits(ctx, &num_blocks, self.0.max_depth + 1);
-                    // bits is in little endian, we take the top `max_depth - initial_depth + 1` bits
-                    let num_leaves = 1 << (self.0.max_depth - self.0.initial_depth);
-                    let num_leaves_bits = &bits[self.0.initial_depth..];
-                    let start_idx = 4 * LIMBS + 5;
-                    // convert from u128 to bytes
-                    let leaves = &pre_instances[start_idx..]
-                        .chunks(2)
-                        .take(num_leaves)
-                        .map(|hash| {
-                            hash.iter()
-                                .flat_map(|hash_u128| {
-                                    num_to_bytes_be(
-                                        ctx,
-                                        config.range(),
-                                        hash_u128,
-                                        NUM_BYTES_IN_U128,
-                                    )
-                                })
-                                .collect_vec()
-                        })
-                        .collect_vec();
-
-                    let mut rlc_chip = RlcChip::new(config.rlc.clone(), gamma);
-                    let mut keccak_chip = KeccakChip::new(config.keccak.clone());
-                    let new_mmr = keccak_chip.merkle_mountain_range(
+                    instances = finalize_merkle_mountain_range(
+                        &config,
                         ctx,
-                        config.gate(),
-                        leaves,
-                        num_leaves_bits,
+                        gamma,
+                        pre_instances,
+                        &num_blocks_minus_one,
+                        self.0.max_depth,
+                        self.0.initial_depth,
                     );
-                    let new_mmr_len = new_mmr.len();
-                    debug_assert_eq!(new_mmr_len, self.0.max_depth - self.0.initial_depth + 1);
-                    // convert from bytes to u128
-                    for ((pair, hash_bytes), bit) in pre_instances[start_idx..]
-                        .chunks_mut(2)
-                        .zip(new_mmr.iter())
-                        .zip(num_leaves_bits.iter().rev())
-                    {
-                        let hash_u128s = bytes_be_to_u128(ctx, config.gate(), &hash_bytes[..]);
-                        debug_assert_eq!(hash_u128s.len(), 2);
-                        for (instance, hash_u128) in pair.iter_mut().zip(hash_u128s.into_iter()) {
-                            *instance = config.gate().mul(ctx, Existing(&hash_u128), Existing(bit));
-                        }
-                    }
-                    // TODO: maybe `copy_within` is better, after `AssignedValue` derives `Copy`?
-                    drop(
-                        pre_instances
-                            .drain(start_idx + 2 * new_mmr_len..start_idx + 2 * num_leaves),
-                    );
-                    instances.extend(pre_instances.iter().map(|assigned| assigned.cell()).cloned());
-                    keccak_chip.assign_phase0(&mut ctx.region);
-                    config.range().finalize(ctx);
-                    ctx.next_phase();
-
-                    // ============ SECOND PHASE ============
-                    rlc_chip.get_challenge(ctx);
-                    keccak_chip.assign_phase1(ctx, &mut rlc_chip, config.range());
-                    config.range().finalize(ctx);
 
                     #[cfg(feature = "display")]
                     {
@@ -253,3 +187,72 @@ impl Circuit<Fr> for EthBlockHeaderChainFinalAggregationCircuit {
         Ok(())
     }
 }
+
+/// Computes the keccaks delayed by the aggregation circuits: in `pre_instances`, the accumulator followed by the
+/// instances of a depth `max_depth` chain, replaces the `2^{max_depth - initial_depth}` roots by the
+/// `max_depth - initial_depth + 1` biggest mountains of the merkle mountain range.
+///
+/// Finalizes both phases of `ctx` and returns the cells of the new instances.
+pub(crate) fn finalize_merkle_mountain_range<'v>(
+    config: &AggregationWithKeccakConfig,
+    ctx: &mut Context<'v, Fr>,
+    gamma: Value<Fr>,
+    mut pre_instances: Vec<AssignedValue<'v, Fr>>,
+    num_blocks_minus_one: &AssignedValue<'v, Fr>,
+    max_depth: usize,
+    initial_depth: usize,
+) -> Vec<Cell> {
+    // add RLC context
+    ctx.advice_alloc.push((0, 0));
+
+    // compute the keccaks that were delayed, to get the `max_depth - initial_depth + 1` biggest merkle mountain ranges
+    let num_blocks = config.gate().add(ctx, Existing(num_blocks_minus_one), Constant(Fr::one()));
+    let bits = config.gate().num_to_bits(ctx, &num_blocks, max_depth + 1);
+    // bits is in little endian, we take the top `max_depth - initial_depth + 1` bits
+    let num_leaves = 1 << (max_depth - initial_depth);
+    let num_leaves_bits = &bits[initial_depth..];
+    let start_idx = 4 * LIMBS + 5;
+    // convert from u128 to bytes
+    let leaves = &pre_instances[start_idx..]
+        .chunks(2)
+        .take(num_leaves)
+        .map(|hash| {
+            hash.iter()
+                .flat_map(|hash_u128| {
+                    num_to_bytes_be(ctx, config.range(), hash_u128, NUM_BYTES_IN_U128)
+                })
+                .collect_vec()
+        })
+        .collect_vec();
+
+    let mut rlc_chip = RlcChip::new(config.rlc.clone(), gamma);
+    let mut keccak_chip = KeccakChip::new(config.keccak.clone());
+    let new_mmr = keccak_chip.merkle_mountain_range(ctx, config.gate(), leaves, num_leaves_bits);
+    let new_mmr_len = new_mmr.len();
+    debug_assert_eq!(new_mmr_len, max_depth - initial_depth + 1);
+    // convert from bytes to u128
+    for ((pair, hash_bytes), bit) in pre_instances[start_idx..]
+        .chunks_mut(2)
+        .zip(new_mmr.iter())
+        .zip(num_leaves_bits.iter().rev())
+    {
+        let hash_u128s = bytes_be_to_u128(ctx, config.gate(), &hash_bytes[..]);
+        debug_assert_eq!(hash_u128s.len(), 2);
+        for (instance, hash_u128) in pair.iter_mut().zip(hash_u128s.into_iter()) {
+            *instance = config.gate().mul(ctx, Existing(&hash_u128), Existing(bit));
+        }
+    }
+    // TODO: maybe `copy_within` is better, after `AssignedValue` derives `Copy`?
+    drop(pre_instances.drain(start_idx + 2 * new_mmr_len..start_idx + 2 * num_leaves));
+    let instances = pre_instances.iter().map(|assigned| assigned.cell()).cloned().collect();
+    keccak_chip.assign_phase0(&mut ctx.region);
+    config.range().finalize(ctx);
+    ctx.next_phase();
+
+    // ============ SECOND PHASE ============
+    rlc_chip.get_challenge(ctx);
+    keccak_chip.assign_phase1(ctx, &mut rlc_chip, config.range());
+    config.range().finalize(ctx);
+
+    instances
+}
@@ -1,7 +1,8 @@
 use super::EthBlockHeaderChainInstance;
-use crate::Field;
+use crate::{util::get_merkle_mountain_range, Field};
 #[cfg(feature = "display")]
 use ark_std::{end_timer, start_timer};
+use ethers_core::types::H256;
 use halo2_base::{
     gates::{range::RangeConfig, GateInstructions, RangeInstructions},
     halo2_proofs::{
@@ -25,9 +26,14 @@ use snark_verifier_sdk::{
 };
 use std::rc::Rc;
 
+mod extension;
 mod final_merkle;
+pub use extension::*;
 pub use final_merkle::*;
 
+#[cfg(test)]
+mod tests;
+
 #[derive(Clone)]
 pub struct EthBlockHeaderChainAggregationCircuit {
     // aggregation circuit with `instances` the accumulator (two G1 points) for delayed pairing verification
@@ -41,9 +47,11 @@ pub struct EthBlockHeaderChainAggregationCircuit {
 }
 
 impl EthBlockHeaderChainAggregationCircuit {
-    /// `snarks` should be exactly two snarks of either
-    /// - `EthBlockHeaderChainCircuit` if `max_depth == initial_depth + 1` or
-    /// - `EthBlockHeaderChainAggregationCircuit` otherwise
+    /// `snarks` should be `2^m` snarks, with `1 <= m <= max_depth - initial_depth`, of either
+    /// - `EthBlockHeaderChainCircuit` if `max_depth == initial_depth + m` or
+    /// - `EthBlockHeaderChainAggregationCircuit` of depth `max_depth - m` otherwise
+    ///
+    /// Snarks after the last one containing a block are only used as padding.
     pub fn new(
         params: &ParamsKZG<Bn256>,
         snarks: Vec<Snark>,
@@ -52,45 +60,22 @@ impl EthBlockHeaderChainAggregationCircuit {
         max_depth: usize,
         initial_depth: usize,
     ) -> Self {
-        assert_eq!(snarks.len(), 2);
-        assert!(max_depth > initial_depth);
+        assert!(snarks.len() >= 2 && snarks.len().is_power_of_two());
+        let fan_in_log2 = snarks.len().trailing_zeros() as usize;
+        assert!(max_depth >= initial_depth + fan_in_log2);
         assert!(num_blocks <= 1 << max_depth);
+        let prev_depth = max_depth - fan_in_log2;
 
-        let instance_start_idx = usize::from(initial_depth + 1 != max_depth) * 4 * LIMBS;
-        let [instance0, instance1] = [0, 1].map(|i| {
-            EthBlockHeaderChainInstance::from_instance(
-                &snarks[i].instances[0][instance_start_idx..],
-            )
-        });
-
-        let mut roots = Vec::with_capacity((1 << (max_depth - initial_depth)) + initial_depth);
-        let cutoff = 1 << (max_depth - initial_depth - 1);
-        roots.extend_from_slice(&instance0.merkle_mountain_range[..cutoff]);
-        roots.extend_from_slice(&instance1.merkle_mountain_range[..cutoff]);
-        if num_blocks <= 1 << (max_depth - 1) {
-            assert_eq!(instance0.end_block_number - instance0.start_block_number, num_blocks - 1);
-            roots.extend_from_slice(&instance0.merkle_mountain_range[cutoff..]);
-        } else {
-            assert_eq!(instance0.end_hash, instance1.prev_hash);
-            assert_eq!(
-                instance0.end_block_number - instance0.start_block_number,
-                (1 << (max_depth - 1)) - 1
-            );
-            assert_eq!(instance0.end_block_number, instance1.start_block_number - 1);
-            assert_eq!(instance1.end_block_number - instance0.start_block_number, num_blocks - 1);
-            roots.extend_from_slice(&instance1.merkle_mountain_range[cutoff..]);
-        };
-        let chain_instance = EthBlockHeaderChainInstance {
-            prev_hash: instance0.prev_hash,
-            end_hash: if num_blocks <= 1 << (max_depth - 1) {
-                instance0.end_hash
-            } else {
-                instance1.end_hash
-            },
-            start_block_number: instance0.start_block_number,
-            end_block_number: instance0.start_block_number + num_blocks - 1,
-            merkle_mountain_range: roots,
-        };
+        let instance_start_idx = usize::from(initial_depth != prev_depth) * 4 * LIMBS;
+        let instances = snarks
+            .iter()
+            .map(|snark| {
+                EthBlockHeaderChainInstance::from_instance(
+                    &snark.instances[0][instance_start_idx..],
+                )
+            })
+            .collect_vec();
+        let chain_instance = join_instances(&instances, num_blocks, max_depth, initial_depth);
         let aggregation = AggregationCircuit::new(params, snarks, rng);
 
         Self { aggregation, num_blocks, chain_instance, max_depth, initial_depth }
@@ -100,6 +85,35 @@ impl EthBlockHeaderChainAggregationCircuit {
         &self,
         config: &AggregationConfig,
         region: Region<'v, Fr>,
+    ) -> (Vec<AssignedValue<'v, Fr>>, AssignedValue<'v, Fr>, Rc<Halo2Loader<'v>>) {
+        self.aggregate_and_then(
+            config,
+            region,
+            |ctx, range, prev_instances, num_blocks_minus_one| {
+                join_previous_instances(
+                    ctx,
+                    range,
+                    prev_instances,
+                    num_blocks_minus_one,
+                    self.max_depth,
+                    self.initial_depth,
+                )
+            },
+        )
+    }
+
+    /// Aggregates the snarks and computes the new instances from the instances of the snarks with `join`,
+    /// which is also given `num_blocks - 1`. Returns the accumulator followed by the new instances.
+    pub(crate) fn aggregate_and_then<'v>(
+        &self,
+        config: &AggregationConfig,
+        region: Region<'v, Fr>,
+        join: impl FnOnce(
+            &mut Context<'v, Fr>,
+            &RangeConfig<Fr>,
+            Vec<Vec<AssignedValue<'v, Fr>>>,
+            &AssignedValue<'v, Fr>,
+        ) -> Vec<AssignedValue<'v, Fr>>,
     ) -> (Vec<AssignedValue<'v, Fr>>, AssignedValue<'v, Fr>, Rc<Halo2Loader<'v>>) {
         let ctx = Context::new(
             region,
@@ -126,19 +140,22 @@ impl EthBlockHeaderChainAggregationCircuit {
             &mut ctx,
             Value::known(config.gate().get_field_element(self.num_blocks as u64 - 1)),
         );
-        let new_instances = join_previous_instances(
-            &mut ctx,
-            config.range(),
-            prev_instances.try_into().unwrap(),
-            &num_blocks_minus_one,
-            self.max_depth,
-            self.initial_depth,
-        );
+        let new_instances = join(&mut ctx, config.range(), prev_instances, &num_blocks_minus_one);
 
         let new_instances = [flatten_accumulator(acc), new_instances].concat();
         (new_instances, num_blocks_minus_one, loader)
     }
 
+    /// The merkle mountain range of the chain, largest mountain first, computed from the `2^{max_depth -
+    /// initial_depth}` roots in `chain_instance` as in [`EthBlockHeaderChainFinalAggregationCircuit`]
+    pub fn merkle_mountain_range(&self) -> Vec<H256> {
+        let mmr = &self.chain_instance.merkle_mountain_range;
+        let leaves = &mmr[..self.num_blocks as usize >> self.initial_depth];
+        let mut new_mmr = get_merkle_mountain_range(leaves, self.max_depth - self.initial_depth);
+        new_mmr.extend_from_slice(&mmr[1 << (self.max_depth - self.initial_depth)..]);
+        new_mmr
+    }
+
     /// The number of instances NOT INCLUDING the accumulator
     pub fn get_num_instance(max_depth: usize, initial_depth: usize) -> usize {
         debug_assert!(max_depth >= initial_depth);
@@ -214,8 +231,40 @@ impl Circuit<Fr> for EthBlockHeaderChainAggregationCircuit {
     }
 }
 
-/// Takes the concatenated previous instances from two `EthBlockHeaderChainAggregationCircuit`s
-/// of max depth `max_depth - 1` and  
+/// Joins the instances of `2^m` chains of depth `max_depth - m` as in [`join_previous_instances`], off-circuit
+fn join_instances(
+    instances: &[EthBlockHeaderChainInstance],
+    num_blocks: u32,
+    max_depth: usize,
+    initial_depth: usize,
+) -> EthBlockHeaderChainInstance {
+    let prev_depth = max_depth - instances.len().trailing_zeros() as usize;
+    // index of the chain containing the last block
+    let last = (num_blocks as usize - 1) >> prev_depth;
+    for (instance, next) in instances[..=last].iter().tuple_windows() {
+        assert_eq!(instance.end_block_number - instance.start_block_number, (1 << prev_depth) - 1);
+        assert_eq!(instance.end_hash, next.prev_hash);
+        assert_eq!(instance.end_block_number + 1, next.start_block_number);
+    }
+    assert_eq!(instances[last].end_block_number - instances[0].start_block_number, num_blocks - 1);
+
+    let mut roots = Vec::with_capacity((1 << (max_depth - initial_depth)) + initial_depth);
+    let cutoff = 1 << (prev_depth - initial_depth);
+    for instance in instances {
+        roots.extend_from_slice(&instance.merkle_mountain_range[..cutoff]);
+    }
+    roots.extend_from_slice(&instances[last].merkle_mountain_range[cutoff..]);
+    EthBlockHeaderChainInstance {
+        prev_hash: instances[0].prev_hash,
+        end_hash: instances[last].end_hash,
+        start_block_number: instances[0].start_block_number,
+        end_block_number: instances[0].start_block_number + num_blocks - 1,
+        merkle_mountain_range: roots,
+    }
+}
+
+/// Takes the concatenated previous instances from `2^m` `EthBlockHeaderChainAggregationCircuit`s
+/// of max depth `max_depth - m` and
 /// - checks that they form a chain of `max_depth`
 /// - updates the merkle mountain range:
 ///     - stores the latest `2^{max_depth - initial_depth}` roots for keccak later
@@ -225,103 +274,121 @@ impl Circuit<Fr> for EthBlockHeaderChainAggregationCircuit {
 pub fn join_previous_instances<'v, F: Field + PrimeField>(
     ctx: &mut Context<'v, F>,
     range: &RangeConfig<F>,
-    prev_instances: [Vec<AssignedValue<'v, F>>; 2],
+    prev_instances: Vec<Vec<AssignedValue<'v, F>>>,
     num_blocks_minus_one: &AssignedValue<'v, F>,
     max_depth: usize,
     initial_depth: usize,
 ) -> Vec<AssignedValue<'v, F>> {
-    let prev_depth = max_depth - 1;
+    assert!(prev_instances.len() >= 2 && prev_instances.len().is_power_of_two());
+    let prev_depth = max_depth - prev_instances.len().trailing_zeros() as usize;
     let non_accumulator_start = if prev_depth != initial_depth { 4 * LIMBS } else { 0 };
     let num_instance =
         EthBlockHeaderChainAggregationCircuit::get_num_instance(prev_depth, initial_depth)
             + non_accumulator_start;
-    debug_assert_eq!(num_instance, prev_instances[0].len());
-    debug_assert_eq!(num_instance, prev_instances[1].len());
-
-    let instance0 = &prev_instances[0][non_accumulator_start..];
-    let instance1 = &prev_instances[1][non_accumulator_start..];
-    let mountain_selector = range.is_less_than_safe(ctx, num_blocks_minus_one, 1 << prev_depth);
+    let instances = prev_instances
+        .iter()
+        .map(|instance| {
+            debug_assert_eq!(num_instance, instance.len());
+            &instance[non_accumulator_start..]
+        })
+        .collect_vec();
+    range.check_less_than_safe(ctx, num_blocks_minus_one, 1 << max_depth);
+    // index of the previous chain containing the last block, less than `2^m` by the check above
+    let (last_idx, _) =
+        range.div_mod(ctx, Existing(num_blocks_minus_one), 1usize << prev_depth, max_depth);
 
-    // join block hashes
-    let prev_hash = &instance0[..2];
-    let intermed_hash0 = &instance0[2..4];
-    let intermed_hash1 = &instance1[..2];
-    let end_hash = &instance1[2..4];
-    for (a, b) in intermed_hash0.iter().zip(intermed_hash1.iter()) {
-        // a == b || num_blocks <= 2^prev_depth
-        let mut eq_check = range.gate().is_equal(ctx, Existing(a), Existing(b));
-        eq_check = range.gate().or(ctx, Existing(&eq_check), Existing(&mountain_selector));
-        range.gate().assert_is_const(ctx, &eq_check, F::one());
-    }
-    let end_hash = intermed_hash0
+    // sanitize block numbers
+    let block_numbers = instances
         .iter()
-        .zip(end_hash.iter())
-        .map(|(a, b)| {
-            range.gate().select(ctx, Existing(a), Existing(b), Existing(&mountain_selector))
+        .map(|instance| {
+            let (start, end) = split_u64_into_u32s(ctx, range, &instance[4]);
+            let num_blocks_minus_one = range.gate().sub(ctx, Existing(&end), Existing(&start));
+            range.check_less_than_safe(ctx, &num_blocks_minus_one, 1 << prev_depth);
+            (start, end, num_blocks_minus_one)
         })
         .collect_vec();
+    let prev_max_blocks = range.gate().pow_of_two()[prev_depth];
+    for (i, (instance, next)) in instances.iter().tuple_windows().enumerate() {
+        let (_, end_block_number, num_blocks_minus_one) = &block_numbers[i];
+        let (next_start_block_number, _, _) = &block_numbers[i + 1];
+        // previous chain `i + 1` is only used if `num_blocks > (i + 1) * 2^prev_depth`
+        let is_unused = range.is_less_than_safe(ctx, &last_idx, i as u64 + 1);
+        let mut checks = instance[2..4]
+            .iter()
+            .zip(next[..2].iter())
+            .map(|(a, b)| range.gate().is_equal(ctx, Existing(a), Existing(b)))
+            .collect_vec();
+        // make sure chains link up
+        let next_block_number =
+            range.gate().add(ctx, Existing(end_block_number), Constant(F::one()));
+        checks.push(range.gate().is_equal(
+            ctx,
+            Existing(&next_block_number),
+            Existing(next_start_block_number),
+        ));
+        // a previous chain followed by another must have 2^prev_depth blocks
+        checks.push(range.gate().is_equal(
+            ctx,
+            Existing(num_blocks_minus_one),
+            Constant(prev_max_blocks - F::one()),
+        ));
+        for check in checks {
+            let eq_check = range.gate().or(ctx, Existing(&check), Existing(&is_unused));
+            range.gate().assert_is_const(ctx, &eq_check, F::one());
+        }
+    }
 
-    // join & sanitize block numbers
-    let (start_block_number, intermed_block_num0) = split_u64_into_u32s(ctx, range, &instance0[4]);
-    let (intermed_block_num1, mut end_block_number) =
-        split_u64_into_u32s(ctx, range, &instance1[4]);
-    let num_blocks0_minus_one =
-        range.gate().sub(ctx, Existing(&intermed_block_num0), Existing(&start_block_number));
-    let num_blocks1_minus_one =
-        range.gate().sub(ctx, Existing(&end_block_number), Existing(&intermed_block_num1));
-    range.check_less_than_safe(ctx, &num_blocks0_minus_one, 1 << prev_depth);
-    range.check_less_than_safe(ctx, &num_blocks1_minus_one, 1 << prev_depth);
+    // join block hashes
+    let prev_hash = &instances[0][..2];
+    let end_hash = (2..4)
+        .map(|i| {
+            range.gate().select_from_idx(
+                ctx,
+                instances.iter().map(|instance| Existing(&instance[i])),
+                Existing(&last_idx),
+            )
+        })
+        .collect_vec();
 
-    end_block_number = range.gate().select(
+    // join block numbers
+    let start_block_number = &block_numbers[0].0;
+    let end_block_number = range.gate().select_from_idx(
         ctx,
-        Existing(&intermed_block_num0),
-        Existing(&end_block_number),
-        Existing(&mountain_selector),
+        block_numbers.iter().map(|(_, end, _)| Existing(end)),
+        Existing(&last_idx),
     );
-    // make sure chains link up
-    let next_block_num0 = range.gate().add(ctx, Existing(&intermed_block_num0), Constant(F::one()));
-    let mut eq_check =
-        range.gate().is_equal(ctx, Existing(&next_block_num0), Existing(&intermed_block_num1));
-    eq_check = range.gate().or(ctx, Existing(&eq_check), Existing(&mountain_selector));
-    range.gate().assert_is_const(ctx, &eq_check, F::one());
-    // if num_blocks > 2^prev_depth, then num_blocks0 must equal 2^prev_depth
-    let prev_max_blocks = range.gate().pow_of_two()[prev_depth];
-    let is_max_depth0 = range.gate().is_equal(
-        ctx,
-        Existing(&num_blocks0_minus_one),
-        Constant(prev_max_blocks - F::one()),
-    );
-    eq_check = range.gate().or(ctx, Existing(&is_max_depth0), Existing(&mountain_selector));
-    range.gate().assert_is_const(ctx, &eq_check, F::one());
     // check number of blocks is correct
     let boundary_num_diff =
-        range.gate().sub(ctx, Existing(&end_block_number), Existing(&start_block_number));
+        range.gate().sub(ctx, Existing(&end_block_number), Existing(start_block_number));
     ctx.constrain_equal(&boundary_num_diff, num_blocks_minus_one);
     // concatenate block numbers
     let boundary_block_numbers = range.gate().mul_add(
         ctx,
         Constant(range.gate().pow_of_two()[32]),
-        Existing(&start_block_number),
+        Existing(start_block_number),
         Existing(&end_block_number),
     );
 
-    // update merkle roots
-    let roots0 = &instance0[5..];
-    let roots1 = &instance1[5..];
-    let cutoff = 2 * (1 << (prev_depth - initial_depth));
-
     // join merkle mountain ranges
-    let mut instances = Vec::with_capacity(num_instance + cutoff);
-    instances.extend_from_slice(prev_hash);
-    instances.extend_from_slice(&end_hash);
-    instances.push(boundary_block_numbers);
-    instances.extend_from_slice(&roots0[..cutoff]);
-    instances.extend_from_slice(&roots1[..cutoff]);
-    instances.extend(roots0[cutoff..].iter().zip(roots1[cutoff..].iter()).map(|(a, b)| {
-        range.gate().select(ctx, Existing(a), Existing(b), Existing(&mountain_selector))
-    }));
+    let cutoff = 2 * (1 << (prev_depth - initial_depth));
+    let mut new_instances = Vec::with_capacity(
+        EthBlockHeaderChainAggregationCircuit::get_num_instance(max_depth, initial_depth),
+    );
+    new_instances.extend_from_slice(prev_hash);
+    new_instances.extend(end_hash);
+    new_instances.push(boundary_block_numbers);
+    for instance in &instances {
+        new_instances.extend_from_slice(&instance[5..5 + cutoff]);
+    }
+    for i in 5 + cutoff..num_instance - non_accumulator_start {
+        new_instances.push(range.gate().select_from_idx(
+            ctx,
+            instances.iter().map(|instance| Existing(&instance[i])),
+            Existing(&last_idx),
+        ));
+    }
 
-    instances
+    new_instances
 }
 
 fn split_u64_into_u32s<'v, F: ScalarField>(
@@ -0,0 +1,265 @@
+use super::*;
+use ethers_core::utils::keccak256;
+use halo2_base::{
+    gates::range::RangeStrategy,
+    halo2_proofs::{
+        dev::MockProver,
+        plonk::{Column, Instance},
+    },
+    SKIP_FIRST_PASS,
+};
+use std::cmp::min;
+
+const DEGREE: usize = 12;
+const START_BLOCK_NUMBER: u32 = 0x765fb3;
+
+/// Loads `prev_instances` as witnesses and exposes their join with [`join_previous_instances`] as public
+/// instances, without aggregating any snarks. With `new_depth`, they are joined with
+/// [`extend_previous_instances`] instead.
+#[derive(Clone)]
+struct JoinInstancesTestCircuit {
+    prev_instances: Vec<Vec<Fr>>,
+    num_blocks: u32,
+    max_depth: usize,
+    initial_depth: usize,
+    new_depth: Option<usize>,
+}
+
+impl Circuit<Fr> for JoinInstancesTestCircuit {
+    type Config = (RangeConfig<Fr>, Column<Instance>);
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        self.clone()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+        let mut range =
+            RangeConfig::configure(meta, RangeStrategy::Vertical, &[1], &[1], 1, 8, 0, DEGREE);
+        range.gate.max_rows = (1 << DEGREE) - meta.minimum_rows();
+        let instance = meta.instance_column();
+        meta.enable_equality(instance);
+        (range, instance)
+    }
+
+    fn synthesize(
+        &self,
+        (range, instance): Self::Config,
+        mut layouter: impl Layouter<Fr>,
+    ) -> Result<(), Error> {
+        range.load_lookup_table(&mut layouter).expect("load range lookup table");
+        let mut first_pass = SKIP_FIRST_PASS;
+        let mut cells = vec![];
+        layouter.assign_region(
+            || "Join instances test",
+            |region| {
+                if first_pass {
+                    first_pass = false;
+                    return Ok(());
+                }
+                let mut aux = Context::new(
+                    region,
+                    ContextParams {
+                        max_rows: range.gate.max_rows,
+                        num_context_ids: 1,
+                        fixed_columns: range.gate.constants.clone(),
+                    },
+                );
+                let ctx = &mut aux;
+                let prev_instances = self
+                    .prev_instances
+                    .iter()
+                    .map(|instance| {
+                        range.gate.assign_witnesses(ctx, instance.iter().map(|x| Value::known(*x)))
+                    })
+                    .collect_vec();
+                let num_blocks_minus_one = range
+                    .gate
+                    .load_witness(ctx, Value::known(Fr::from(self.num_blocks as u64 - 1)));
+                let new_instances = match self.new_depth {
+                    Some(new_depth) => extend_previous_instances(
+                        ctx,
+                        &range,
+                        prev_instances,
+                        &num_blocks_minus_one,
+                        self.max_depth,
+                        new_depth,
+                        self.initial_depth,
+                    ),
+                    None => join_previous_instances(
+                        ctx,
+                        &range,
+                        prev_instances,
+                        &num_blocks_minus_one,
+                        self.max_depth,
+                        self.initial_depth,
+                    ),
+                };
+                cells = new_instances.iter().map(|assigned| assigned.cell()).cloned().collect();
+                range.finalize(ctx);
+                Ok(())
+            },
+        )?;
+        for (i, cell) in cells.into_iter().enumerate() {
+            layouter.constrain_instance(cell, instance, i);
+        }
+        Ok(())
+    }
+}
+
+/// Hashes of the blocks `0..=num_blocks`, where the chains start at block 1
+fn mock_block_hashes(num_blocks: usize) -> Vec<H256> {
+    (0..=num_blocks as u64).map(|i| H256(keccak256(i.to_be_bytes()))).collect()
+}
+
+/// The instance of an `EthBlockHeaderChainCircuit` of depth `depth` for the blocks `start..=end`
+fn initial_instance(
+    block_hashes: &[H256],
+    start: usize,
+    end: usize,
+    depth: usize,
+) -> EthBlockHeaderChainInstance {
+    EthBlockHeaderChainInstance::new(
+        block_hashes[start - 1],
+        block_hashes[end],
+        START_BLOCK_NUMBER + start as u32,
+        START_BLOCK_NUMBER + end as u32,
+        get_merkle_mountain_range(&block_hashes[start..=end], depth),
+    )
+}
+
+/// The full merkle mountain range of a joined chain, as in
+/// [`EthBlockHeaderChainAggregationCircuit::merkle_mountain_range`]
+fn full_merkle_mountain_range(
+    instance: &EthBlockHeaderChainInstance,
+    num_blocks: usize,
+    max_depth: usize,
+    initial_depth: usize,
+) -> Vec<H256> {
+    let roots = &instance.merkle_mountain_range;
+    let mut mmr =
+        get_merkle_mountain_range(&roots[..num_blocks >> initial_depth], max_depth - initial_depth);
+    mmr.extend_from_slice(&roots[1 << (max_depth - initial_depth)..]);
+    mmr
+}
+
+#[test]
+pub fn test_mock_join_fan_in_4() {
+    // 4 chains of depth 1 joined into a chain of depth 3: 2 full chains, a chain with 1 block and a
+    // padding copy of it, as in `Sequencer::build_circuit`
+    let (num_blocks, max_depth, initial_depth) = (5, 3, 1);
+    let block_hashes = mock_block_hashes(num_blocks);
+    let mut chains = (1..=num_blocks)
+        .step_by(2)
+        .map(|start| initial_instance(&block_hashes, start, min(start + 1, num_blocks), 1))
+        .collect_vec();
+    chains.push(chains[2].clone());
+    let joined = join_instances(&chains, num_blocks as u32, max_depth, initial_depth);
+    // the same chain as proving all the blocks at once
+    let expected = initial_instance(&block_hashes, 1, num_blocks, max_depth);
+    assert_eq!(
+        EthBlockHeaderChainInstance {
+            merkle_mountain_range: full_merkle_mountain_range(
+                &joined,
+                num_blocks,
+                max_depth,
+                initial_depth
+            ),
+            ..joined.clone()
+        },
+        expected
+    );
+
+    let circuit = JoinInstancesTestCircuit {
+        prev_instances: chains.iter().map(|chain| chain.to_instance()).collect(),
+        num_blocks: num_blocks as u32,
+        max_depth,
+        initial_depth,
+        new_depth: None,
+    };
+    let instance = joined.to_instance::<Fr>();
+    assert_eq!(
+        instance.len(),
+        EthBlockHeaderChainAggregationCircuit::get_num_instance(max_depth, initial_depth)
+    );
+    let k = DEGREE as u32;
+    MockProver::run(k, &circuit, vec![instance.clone()]).unwrap().assert_satisfied();
+
+    // the padding chain does not need to link up
+    let mut padding = circuit.clone();
+    padding.prev_instances[3][1] += Fr::one();
+    MockProver::run(k, &padding, vec![instance.clone()]).unwrap().assert_satisfied();
+
+    // the last chain does not start at the end of the previous chain
+    let mut unlinked = circuit.clone();
+    unlinked.prev_instances[2][1] += Fr::one();
+    assert!(MockProver::run(k, &unlinked, vec![instance.clone()]).unwrap().verify().is_err());
+
+    // a wrong end hash
+    let mut wrong_instance = instance.clone();
+    wrong_instance[3] += Fr::one();
+    assert!(MockProver::run(k, &circuit, vec![wrong_instance]).unwrap().verify().is_err());
+
+    // more blocks than in the chains, and more blocks than fit in depth 3
+    for num_blocks in [6, 9] {
+        let circuit = JoinInstancesTestCircuit { num_blocks, ..circuit.clone() };
+        assert!(MockProver::run(k, &circuit, vec![instance.clone()]).unwrap().verify().is_err());
+    }
+}
+
+#[test]
+pub fn test_mock_extend() {
+    // a chain of depth 3 with 4 blocks, padded as in `Sequencer::build_circuit`, extended by a chain of
+    // depth 2 with 3 blocks
+    let (num_blocks, max_depth, new_depth, initial_depth) = (7, 3, 2, 1);
+    let block_hashes = mock_block_hashes(num_blocks);
+    let chains = [1, 3, 5, 7]
+        .map(|start| initial_instance(&block_hashes, start, min(start + 1, num_blocks), 1));
+    let first_half = join_instances(&chains[..2], 4, 2, initial_depth);
+    let prev = join_instances(&[first_half.clone(), first_half], 4, max_depth, initial_depth);
+    let new = join_instances(&chains[2..], 3, new_depth, initial_depth);
+    let extended = extend_instances(&prev, &new, max_depth, new_depth, initial_depth);
+    // the same chain as proving all the blocks at once
+    let expected = initial_instance(&block_hashes, 1, num_blocks, max_depth);
+    assert_eq!(
+        EthBlockHeaderChainInstance {
+            merkle_mountain_range: full_merkle_mountain_range(
+                &extended,
+                num_blocks,
+                max_depth,
+                initial_depth
+            ),
+            ..extended.clone()
+        },
+        expected
+    );
+
+    // both chains are aggregation circuits, with an accumulator
+    let with_accumulator = |chain: &EthBlockHeaderChainInstance| {
+        [vec![Fr::zero(); 4 * LIMBS], chain.to_instance()].concat()
+    };
+    let circuit = JoinInstancesTestCircuit {
+        prev_instances: vec![with_accumulator(&prev), with_accumulator(&new)],
+        num_blocks: num_blocks as u32,
+        max_depth,
+        initial_depth,
+        new_depth: Some(new_depth),
+    };
+    let instance = extended.to_instance::<Fr>();
+    let k = DEGREE as u32;
+    MockProver::run(k, &circuit, vec![instance.clone()]).unwrap().assert_satisfied();
+
+    // the new chain does not start at the end of the previous chain
+    let mut unlinked = circuit.clone();
+    unlinked.prev_instances[1][4 * LIMBS] += Fr::one();
+    assert!(MockProver::run(k, &unlinked, vec![instance.clone()]).unwrap().verify().is_err());
+
+    // a wrong root of the previous chain
+    let mut wrong_root = circuit.clone();
+    wrong_root.prev_instances[0][4 * LIMBS + 5] += Fr::one();
+    assert!(MockProver::run(k, &wrong_root, vec![instance.clone()]).unwrap().verify().is_err());
+
+    // a wrong number of blocks
+    let wrong_num_blocks = JoinInstancesTestCircuit { num_blocks: 8, ..circuit };
+    assert!(MockProver::run(k, &wrong_num_blocks, vec![instance]).unwrap().verify().is_err());
+}
@@ -0,0 +1,173 @@
+//! Solidity contract with a stable, typed ABI around the Yul verifier of a header chain snark for the EVM, i.e.
+//! the snark of a [`Task`](super::helpers::Task) with [`Finality::Evm`](super::helpers::Finality::Evm).
+//!
+//! The Yul verifier from `gen_evm_verifier_shplonk` takes raw calldata: the public instances as 32 byte big endian
+//! words, followed by the proof. The instances are the accumulator (`4 * LIMBS` words), then the chain instance:
+//! `prevHash` and `endHash` as hi-lo (u128, u128), `startBlockNumber << 32 | endBlockNumber`, and the
+//! `max_depth + 1` merkle mountain range roots as hi-lo. The wrapper instead exposes
+//! ```solidity
+//! function verifyHeaderChain(bytes32 prevHash, bytes32 endHash, uint32 start, uint32 end, bytes32[] mmr, bytes proof)
+//! ```
+//! where `proof` is the accumulator followed by the proof, and reverts if the proof is invalid.
+use ethers_core::{
+    abi::{self, Token},
+    utils::{hex, id},
+};
+use snark_verifier::loader::evm::{Address, ExecutorBuilder};
+use snark_verifier_sdk::LIMBS;
+use std::{
+    io::{self, Write},
+    process::{Command, Stdio},
+};
+
+pub const HEADER_CHAIN_VERIFIER_CONTRACT: &str = "HeaderChainVerifier";
+pub const VERIFY_HEADER_CHAIN_SIGNATURE: &str =
+    "verifyHeaderChain(bytes32,bytes32,uint32,uint32,bytes32[],bytes)";
+const ACCUMULATOR_BYTES: usize = 4 * LIMBS * 32;
+
+/// Solidity source of the contract [`HEADER_CHAIN_VERIFIER_CONTRACT`] wrapping the Yul verifier of the header chain
+/// snark of depth `max_depth`. Its constructor takes the address of the deployed Yul verifier.
+pub fn gen_header_chain_verifier_wrapper(max_depth: usize) -> String {
+    format!(
+        r#"// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.0;
+
+/// Typed interface to the verifier of the header chain snark of depth {max_depth}
+contract {HEADER_CHAIN_VERIFIER_CONTRACT} {{
+    uint256 private constant ACCUMULATOR_BYTES = {ACCUMULATOR_BYTES};
+    uint256 public constant MMR_LEN = {mmr_len};
+
+    /// The Yul verifier, which takes the public instances followed by the proof
+    address public immutable verifier;
+
+    constructor(address _verifier) {{
+        verifier = _verifier;
+    }}
+
+    /// Reverts unless `proof` proves the chain of blocks `start..=end`, where `prevHash` is the parent hash of
+    /// block `start`, `endHash` is the hash of block `end`, and `mmr` is the merkle mountain range of the block
+    /// hashes, largest mountain first. `proof` is the accumulator followed by the proof.
+    function verifyHeaderChain(
+        bytes32 prevHash,
+        bytes32 endHash,
+        uint32 start,
+        uint32 end,
+        bytes32[] calldata mmr,
+        bytes calldata proof
+    ) external view {{
+        require(mmr.length == MMR_LEN, "wrong merkle mountain range length");
+        require(proof.length >= ACCUMULATOR_BYTES, "proof too short");
+        bytes memory instances = abi.encodePacked(
+            hi(prevHash),
+            lo(prevHash),
+            hi(endHash),
+            lo(endHash),
+            (uint256(start) << 32) | uint256(end)
+        );
+        for (uint256 i = 0; i < MMR_LEN; i++) {{
+            instances = abi.encodePacked(instances, hi(mmr[i]), lo(mmr[i]));
+        }}
+        (bool success, ) = verifier.staticcall(
+            abi.encodePacked(proof[:ACCUMULATOR_BYTES], instances, proof[ACCUMULATOR_BYTES:])
+        );
+        require(success, "invalid proof");
+    }}
+
+    function hi(bytes32 hash) private pure returns (uint256) {{
+        return uint256(hash) >> 128;
+    }}
+
+    function lo(bytes32 hash) private pure returns (uint256) {{
+        return uint256(hash) & type(uint128).max;
+    }}
+}}
+"#,
+        mmr_len = max_depth + 1
+    )
+}
+
+/// Compiles the Solidity `source` with `solc` and returns the deployment code of `contract`. Returns an error if
+/// `solc` is not installed or does not output the binary of `contract`.
+pub fn compile_solidity(source: &str, contract: &str) -> io::Result<Vec<u8>> {
+    let mut cmd = Command::new("solc")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .arg("--bin")
+        .arg("-")
+        .spawn()?;
+    cmd.stdin.take().unwrap().write_all(source.as_bytes())?;
+    let output = cmd.wait_with_output()?;
+    let output = String::from_utf8_lossy(&output.stdout);
+    let header = format!(":{contract} =======");
+    let binary = output
+        .lines()
+        .skip_while(|line| !line.ends_with(&header))
+        .skip_while(|line| *line != "Binary:")
+        .nth(1)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Other,
+                format!("solc did not output the binary of {contract}"),
+            )
+        })?;
+    hex::decode(binary).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Converts the raw `calldata` of the Yul verifier of the header chain snark of depth `max_depth`, as returned by
+/// `Sequencer::get_calldata`, to calldata of `verifyHeaderChain`
+pub fn encode_verify_header_chain(calldata: &[u8], max_depth: usize) -> Vec<u8> {
+    let num_instance_bytes = 32 * (5 + 2 * (max_depth + 1));
+    assert!(calldata.len() >= ACCUMULATOR_BYTES + num_instance_bytes, "calldata too short");
+    let (accumulator, rest) = calldata.split_at(ACCUMULATOR_BYTES);
+    let (instances, proof) = rest.split_at(num_instance_bytes);
+    let words = instances.chunks(32).collect::<Vec<_>>();
+    // hi-lo (u128, u128) to bytes32
+    let hash = |i: usize| Token::FixedBytes([&words[i][16..], &words[i + 1][16..]].concat());
+    let start = u32::from_be_bytes(words[4][24..28].try_into().unwrap());
+    let end = u32::from_be_bytes(words[4][28..].try_into().unwrap());
+    let mmr = (0..=max_depth).map(|i| hash(5 + 2 * i)).collect();
+    let tokens = [
+        hash(0),
+        hash(2),
+        Token::Uint(start.into()),
+        Token::Uint(end.into()),
+        Token::Array(mmr),
+        Token::Bytes([accumulator, proof].concat()),
+    ];
+    [&id(VERIFY_HEADER_CHAIN_SIGNATURE)[..], &abi::encode(&tokens)].concat()
+}
+
+/// Deploys the Yul verifier and the wrapper from their deployment code in a local EVM (revm), and calls the
+/// wrapper with `calldata`. Returns the gas used, or `None` if the call reverted.
+pub fn evm_verify_header_chain(
+    verifier_code: Vec<u8>,
+    wrapper_code: Vec<u8>,
+    calldata: Vec<u8>,
+) -> Option<u64> {
+    let mut evm = ExecutorBuilder::default().with_gas_limit(u64::MAX.into()).build();
+    let caller = Address::from_low_u64_be(0xfe);
+    let verifier = evm.deploy(caller, verifier_code.into(), 0.into()).address;
+    let verifier = verifier.expect("verifier deployment should not fail");
+    // the constructor argument is the ABI encoded address of the verifier
+    let wrapper_code = [wrapper_code, vec![0; 12], verifier.as_bytes().to_vec()].concat();
+    let wrapper = evm.deploy(caller, wrapper_code.into(), 0.into()).address;
+    let wrapper = wrapper.expect("wrapper deployment should not fail");
+    let result = evm.call_raw(caller, wrapper, calldata.into(), 0.into());
+    (!result.reverted).then_some(result.gas_used)
+}
+
+/// Compiles the wrapper of the header chain snark of depth `max_depth` with `solc`, deploys it with the Yul verifier
+/// from its deployment code `verifier_code` as in [`evm_verify_header_chain`], and calls `verifyHeaderChain` with the
+/// raw `calldata` of the Yul verifier. Returns the gas used, or an error if `solc` is not installed or the call
+/// reverted.
+pub fn verify_with_header_chain_wrapper(
+    verifier_code: Vec<u8>,
+    calldata: &[u8],
+    max_depth: usize,
+) -> io::Result<u64> {
+    let wrapper = gen_header_chain_verifier_wrapper(max_depth);
+    let wrapper_code = compile_solidity(&wrapper, HEADER_CHAIN_VERIFIER_CONTRACT)?;
+    let calldata = encode_verify_header_chain(calldata, max_depth);
+    evm_verify_header_chain(verifier_code, wrapper_code, calldata)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "verifyHeaderChain reverted"))
+}
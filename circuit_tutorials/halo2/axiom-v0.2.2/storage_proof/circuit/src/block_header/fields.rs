@@ -0,0 +1,100 @@
+//! Selection of individual block header fields, e.g. to expose the timestamp or base fee of a block in a
+//! header chain as public instances.
+use super::{
+    EthBlockHeaderTraceWitness, GOERLI_HEADER_FIELDS_MAX_BYTES, MAINNET_HEADER_FIELDS_MAX_BYTES,
+};
+use crate::{
+    rlp::RlpFieldWitness,
+    util::{bytes_be_to_u128, bytes_be_var_to_fixed, AssignedH256},
+    Field, Network,
+};
+use ethers_core::types::H256;
+use halo2_base::{gates::GateInstructions, Context};
+use rlp::Rlp;
+use serde::{Deserialize, Serialize};
+
+/// The fields of a block header, in RLP order
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BlockHeaderField {
+    ParentHash,
+    OmmersHash,
+    Beneficiary,
+    StateRoot,
+    TransactionsRoot,
+    ReceiptsRoot,
+    LogsBloom,
+    Difficulty,
+    Number,
+    GasLimit,
+    GasUsed,
+    Timestamp,
+    ExtraData,
+    /// `prevrandao` after the merge
+    MixHash,
+    Nonce,
+    /// Only after London; 0 for earlier blocks
+    BaseFeePerGas,
+    /// Only after Shanghai; 0 for earlier blocks, as are the fields below for blocks before their fork
+    WithdrawalsRoot,
+    /// Only after Cancun
+    BlobGasUsed,
+    /// Only after Cancun
+    ExcessBlobGas,
+    /// Only after Cancun, see EIP-4788
+    ParentBeaconBlockRoot,
+    /// Only after Prague, see EIP-7685
+    RequestsHash,
+}
+
+impl BlockHeaderField {
+    /// Index of the field in the RLP list of the header
+    pub fn idx(&self) -> usize {
+        *self as usize
+    }
+
+    pub fn max_bytes(&self, network: Network) -> usize {
+        match network {
+            Network::Mainnet => MAINNET_HEADER_FIELDS_MAX_BYTES[self.idx()],
+            Network::Goerli => GOERLI_HEADER_FIELDS_MAX_BYTES[self.idx()],
+        }
+    }
+
+    /// Whether the field fits in an H256, so it can be exposed as hi-lo
+    pub fn is_h256(&self, network: Network) -> bool {
+        self.max_bytes(network) <= 32
+    }
+}
+
+impl<'v, F: Field> EthBlockHeaderTraceWitness<'v, F> {
+    pub fn get(&self, field: BlockHeaderField) -> &RlpFieldWitness<'v, F> {
+        &self.rlp_witness.field_witness[field.idx()]
+    }
+
+    /// The value of `field` left padded with zeros to 32 bytes, as H256 represented as two u128.
+    /// Integer fields are thus their value as U256.
+    ///
+    /// The field must be at most 32 bytes, see [`BlockHeaderField::is_h256`].
+    pub fn get_h256(
+        &self,
+        ctx: &mut Context<'_, F>,
+        gate: &impl GateInstructions<F>,
+        field: BlockHeaderField,
+    ) -> AssignedH256<'v, F> {
+        let witness = self.get(field);
+        assert!(witness.field_cells.len() <= 32, "{field:?} does not fit in 32 bytes");
+        let bytes = bytes_be_var_to_fixed(ctx, gate, &witness.field_cells, &witness.field_len, 32);
+        bytes_be_to_u128(ctx, gate, &bytes).try_into().unwrap()
+    }
+}
+
+/// Same as [`EthBlockHeaderTraceWitness::get_h256`] for the RLP encoded `header`, which may be zero padded.
+pub fn get_header_field_h256(header: &[u8], field: BlockHeaderField) -> H256 {
+    let rlp = Rlp::new(header);
+    let mut bytes = [0u8; 32];
+    if field.idx() < rlp.item_count().expect("invalid block header RLP") {
+        let value = rlp.at(field.idx()).and_then(|item| item.data()).unwrap();
+        assert!(value.len() <= 32, "{field:?} does not fit in 32 bytes");
+        bytes[32 - value.len()..].copy_from_slice(value);
+    }
+    H256(bytes)
+}
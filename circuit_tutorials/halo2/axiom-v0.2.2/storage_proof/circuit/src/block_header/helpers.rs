@@ -1,13 +1,14 @@
 use super::{
     aggregation::{
         AggregationWithKeccakConfigParams, EthBlockHeaderChainAggregationCircuit,
-        EthBlockHeaderChainFinalAggregationCircuit,
+        EthBlockHeaderChainExtensionCircuit, EthBlockHeaderChainFinalAggregationCircuit,
+        EthBlockHeaderChainFinalExtensionCircuit,
     },
     EthBlockHeaderChainCircuit,
 };
 use crate::{
-    providers::{GOERLI_PROVIDER_URL, MAINNET_PROVIDER_URL},
-    util::EthConfigParams,
+    providers::{provider_url_from_env, rpc_url_var},
+    util::{write_atomic, EthConfigParams},
     Field, Network,
 };
 use core::cmp::min;
@@ -22,22 +23,27 @@ use halo2_base::{
 };
 use rand::SeedableRng;
 use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Serialize};
 use snark_verifier_sdk::{
     gen_pk,
     halo2::{
-        aggregation::{load_verify_circuit_degree, PublicAggregationCircuit},
+        aggregation::{
+            load_verify_circuit_degree, AggregationConfigParams, PublicAggregationCircuit,
+        },
         gen_snark_shplonk, read_snark,
     },
     CircuitExt, Snark, LIMBS,
 };
 use std::{
     collections::HashMap,
-    env::{set_var, var},
+    env::set_var,
+    fs, io,
     path::Path,
+    sync::{Arc, RwLock},
     vec,
 };
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub enum Finality {
     /// Produces as many snarks as needed to fit the entire block number range, without any final processing.
     None,
@@ -50,42 +56,72 @@ pub enum Finality {
     Evm(usize),
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct CircuitType {
     pub depth: usize,
     pub initial_depth: usize,
     pub finality: Finality,
+    /// Each aggregation circuit aggregates up to `2^fan_in_log2` snarks of depth `depth - fan_in_log2`.
+    /// If `depth - initial_depth` is not a multiple of `fan_in_log2`, the aggregation circuits right above
+    /// the initial depth have a smaller fan-in.
+    #[serde(default = "default_fan_in_log2")]
+    pub fan_in_log2: usize,
+}
+
+fn default_fan_in_log2() -> usize {
+    1
 }
 
 impl CircuitType {
     pub fn new(depth: usize, initial_depth: usize, finality: Finality) -> Self {
-        Self { depth, initial_depth, finality }
+        Self { depth, initial_depth, finality, fan_in_log2: default_fan_in_log2() }
+    }
+
+    pub fn with_fan_in_log2(self, fan_in_log2: usize) -> Self {
+        assert!(fan_in_log2 > 0, "fan-in must be at least 2");
+        Self { fan_in_log2, ..self }
+    }
+
+    /// This circuit aggregates up to `2^fan_in_log2()` snarks of depth `self.prev().depth`
+    pub fn fan_in_log2(&self) -> usize {
+        min(self.fan_in_log2, self.depth - self.initial_depth)
+    }
+
+    /// This circuit aggregates up to `fan_in()` snarks of depth `self.prev().depth`
+    pub fn fan_in(&self) -> usize {
+        1 << self.fan_in_log2()
     }
 
     pub fn prev(&self) -> Self {
         assert!(self.depth != self.initial_depth, "Trying to call prev on initial circuit");
         match self.finality {
             Finality::None | Finality::Merkle => {
-                Self::new(self.depth - 1, self.initial_depth, Finality::None)
+                Self { depth: self.depth - self.fan_in_log2(), finality: Finality::None, ..*self }
             }
             Finality::Evm(round) => {
                 if round == 0 {
-                    Self::new(self.depth, self.initial_depth, Finality::Merkle)
+                    Self { finality: Finality::Merkle, ..*self }
                 } else {
-                    Self::new(self.depth, self.initial_depth, Finality::Evm(round - 1))
+                    Self { finality: Finality::Evm(round - 1), ..*self }
                 }
             }
         }
     }
 
-    pub fn fname_prefix(&self, network: Network) -> String {
-        if self.depth == self.initial_depth {
-            format!("data/headers/{network}_{}", self.depth)
-        } else {
-            format!("data/headers/{network}_{}_{}", self.depth, self.initial_depth)
+    /// Identifies the circuit, and the circuits of the snarks it aggregates, in file names
+    pub fn name(&self, network: Network) -> String {
+        let Self { depth, initial_depth, .. } = *self;
+        match self.fan_in_log2() {
+            0 => format!("{network}_{depth}"),
+            1 => format!("{network}_{depth}_{initial_depth}"),
+            fan_in_log2 => format!("{network}_{depth}_{initial_depth}_fan{}", 1 << fan_in_log2),
         }
     }
 
+    pub fn fname_prefix(&self, network: Network) -> String {
+        format!("data/headers/{}", self.name(network))
+    }
+
     pub fn fname_suffix(&self) -> String {
         match self.finality {
             Finality::None => "".to_string(),
@@ -99,7 +135,54 @@ impl CircuitType {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+/// Derives the configuration of the aggregation circuit `circuit_type` from `config`, the configuration of the
+/// same circuit with fan-in 2. Verifying each snark takes about the same number of cells, so the degree and the
+/// lookup bits grow by `fan_in_log2 - 1` with the same columns. The circuits for the EVM only verify a single
+/// snark and keep the same configuration.
+pub fn derive_fan_in_config(circuit_type: CircuitType, config: &str) -> String {
+    let extra_bits = circuit_type.fan_in_log2() - 1;
+    let grow = |params: &mut AggregationConfigParams| {
+        params.degree += extra_bits as u32;
+        params.lookup_bits += extra_bits;
+    };
+    match circuit_type.finality {
+        Finality::None => {
+            let mut params = serde_json::from_str(config).expect("invalid aggregation config");
+            grow(&mut params);
+            serde_json::to_string_pretty(&params).unwrap()
+        }
+        Finality::Merkle => {
+            let mut params: AggregationWithKeccakConfigParams =
+                serde_json::from_str(config).expect("invalid final aggregation config");
+            grow(&mut params.aggregation);
+            serde_json::to_string_pretty(&params).unwrap()
+        }
+        Finality::Evm(_) => config.to_string(),
+    }
+}
+
+/// Sets the environmental var read by the aggregation circuits of `finality` to `config_path` and returns the degree
+/// of the configuration
+fn load_aggregation_degree(finality: Finality, config_path: String) -> u32 {
+    match finality {
+        Finality::None | Finality::Evm(_) => {
+            set_var("VERIFY_CONFIG", config_path);
+            load_verify_circuit_degree()
+        }
+        Finality::Merkle => {
+            set_var("FINAL_AGGREGATION_CONFIG", config_path);
+            AggregationWithKeccakConfigParams::get().aggregation.degree
+        }
+    }
+}
+
+/// Writes `snark` to `path` atomically, so an interrupted write never leaves a truncated snark behind.
+fn write_snark(path: impl AsRef<Path>, snark: &Snark) -> io::Result<()> {
+    let bytes = bincode::serialize(snark).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    write_atomic(path, bytes)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Task {
     pub start: u32,
     pub end: u32,
@@ -125,6 +208,27 @@ impl Task {
         assert!(self.end - self.start < 1 << self.circuit_type.depth);
         read_snark(self.snark_name(network))
     }
+
+    /// Writes `snark` to [`Task::snark_name`] atomically, so an interrupted write never leaves a truncated
+    /// snark behind.
+    pub fn write_snark(&self, network: Network, snark: &Snark) -> io::Result<()> {
+        write_snark(self.snark_name(network), snark)
+    }
+
+    /// The tasks whose snarks are aggregated to prove this task, in order of block number.
+    /// Empty if this task is at the initial depth.
+    pub fn dependencies(&self) -> Vec<Task> {
+        let Task { start, end, circuit_type } = *self;
+        if circuit_type.depth == circuit_type.initial_depth {
+            return vec![];
+        }
+        let prev_type = circuit_type.prev();
+        let prev_depth = prev_type.depth;
+        (start..=end)
+            .step_by(1 << prev_depth)
+            .map(|i| Task::new(i, min(end, i + (1 << prev_depth) - 1), prev_type))
+            .collect()
+    }
 }
 
 pub enum AnyCircuit {
@@ -135,61 +239,81 @@ pub enum AnyCircuit {
 }
 
 pub struct Sequencer {
-    pub pkeys: HashMap<CircuitType, ProvingKey<G1Affine>>,
+    /// Proving keys are shared between the threads proving tasks of the same circuit type
+    pub pkeys: RwLock<HashMap<CircuitType, Arc<ProvingKey<G1Affine>>>>,
     pub params_k: HashMap<CircuitType, u32>,
     pub params: HashMap<u32, ParamsKZG<Bn256>>,
     pub rng: ChaCha20Rng,
-    pub provider: Provider<Http>,
+    /// Only needed to fetch the block headers of initial tasks whose snarks are not on disk yet
+    pub provider: Option<Provider<Http>>,
     pub network: Network,
+    /// Directory of the circuit configurations, `configs/headers` by default
+    pub config_dir: String,
+    /// Directory of the files the sequencer derives besides task snarks: derived configurations, proving keys,
+    /// extension snarks and calldata, `data/headers` by default. Task snarks are at [`Task::snark_name`].
+    pub data_dir: String,
 }
 
 impl Sequencer {
+    /// Uses the JSON-RPC provider configured in the environment, see [`provider_url_from_env`].
+    /// If none is configured, only tasks whose initial snarks are already on disk can be proved.
     pub fn new(network: Network) -> Self {
-        let infura_id = var("INFURA_ID").expect("Infura ID not found");
-        let provider_url = match network {
-            Network::Mainnet => MAINNET_PROVIDER_URL,
-            Network::Goerli => GOERLI_PROVIDER_URL,
-        };
-        let provider = Provider::<Http>::try_from(format!("{provider_url}{infura_id}").as_str())
-            .expect("could not instantiate HTTP Provider");
+        let provider = provider_url_from_env(network).map(|url| {
+            Provider::<Http>::try_from(url.as_str()).expect("could not instantiate HTTP Provider")
+        });
+        Self::with_provider(network, provider)
+    }
 
+    pub fn with_provider(network: Network, provider: Option<Provider<Http>>) -> Self {
         Sequencer {
-            pkeys: HashMap::new(),
+            pkeys: RwLock::new(HashMap::new()),
             params_k: HashMap::new(),
             params: HashMap::new(),
             provider,
             network,
             rng: ChaCha20Rng::from_entropy(),
+            config_dir: "configs/headers".to_string(),
+            data_dir: "data/headers".to_string(),
         }
     }
 
-    pub fn get_params(&mut self, circuit_type: CircuitType) -> u32 {
-        let network = self.network;
-        let CircuitType { depth, initial_depth, finality } = circuit_type;
-        let fname_prefix = if depth == initial_depth {
-            format!("configs/headers/{network}_{depth}")
-        } else {
-            format!("configs/headers/{network}_{depth}_{initial_depth}")
+    /// The configuration file of `circuit_type`.
+    ///
+    /// An aggregation circuit with a fan-in above 2 and no configuration in `config_dir` uses a configuration
+    /// derived from that of the same circuit with fan-in 2, see [`derive_fan_in_config`], which is written to
+    /// `data_dir`.
+    pub fn config_path(&self, circuit_type: CircuitType) -> String {
+        let (config_dir, name) = (&self.config_dir, circuit_type.name(self.network));
+        if circuit_type.depth == circuit_type.initial_depth {
+            return format!("{config_dir}/{name}.json");
+        }
+        let path = format!("{config_dir}/{name}{}.json", circuit_type.fname_suffix());
+        if circuit_type.fan_in_log2() == 1 || Path::new(&path).exists() {
+            return path;
+        }
+        let Ok(config) = fs::read_to_string(self.config_path(circuit_type.with_fan_in_log2(1))) else {
+            return path;
         };
-        let k = if depth == initial_depth {
-            set_var("BLOCK_HEADER_CONFIG", format!("{fname_prefix}.json"));
+        let derived_path = format!("{}/{name}{}.json", self.data_dir, circuit_type.fname_suffix());
+        fs::create_dir_all(&self.data_dir).expect("create data dir should not fail");
+        write_atomic(&derived_path, derive_fan_in_config(circuit_type, &config))
+            .expect("write derived config should not fail");
+        derived_path
+    }
+
+    /// Sets the environmental vars with the configuration of `circuit_type` and returns its degree
+    pub fn get_degree(&self, circuit_type: CircuitType) -> u32 {
+        let config_path = self.config_path(circuit_type);
+        if circuit_type.depth == circuit_type.initial_depth {
+            set_var("BLOCK_HEADER_CONFIG", config_path);
             EthConfigParams::get_header().degree
         } else {
-            match finality {
-                Finality::None => {
-                    set_var("VERIFY_CONFIG", format!("{fname_prefix}.json"));
-                    load_verify_circuit_degree()
-                }
-                Finality::Merkle => {
-                    set_var("FINAL_AGGREGATION_CONFIG", format!("{fname_prefix}_final.json"));
-                    AggregationWithKeccakConfigParams::get().aggregation.degree
-                }
-                Finality::Evm(round) => {
-                    set_var("VERIFY_CONFIG", format!("{fname_prefix}_for_evm_{round}.json"));
-                    load_verify_circuit_degree()
-                }
-            }
-        };
+            load_aggregation_degree(circuit_type.finality, config_path)
+        }
+    }
+
+    pub fn get_params(&mut self, circuit_type: CircuitType) -> u32 {
+        let k = self.get_degree(circuit_type);
         self.params.entry(k).or_insert_with(|| gen_srs(k));
         self.params_k.insert(circuit_type, k);
         k
@@ -197,14 +321,28 @@ impl Sequencer {
 
     // recursively generates necessary snarks to create circuit
     pub fn get_circuit(&mut self, task: Task) -> AnyCircuit {
+        let snarks = task.dependencies().into_iter().map(|dep| self.get_snark(dep)).collect();
+        // set environmental vars
+        self.get_params(task.circuit_type);
+        self.build_circuit(task, snarks)
+    }
+
+    /// Creates the circuit for `task` from the snarks of `task.dependencies()`.
+    ///
+    /// Assumes `self.get_params(task.circuit_type)` was the last call to `get_params`, so the environmental vars are set.
+    pub fn build_circuit(&self, task: Task, mut snarks: Vec<Snark>) -> AnyCircuit {
         let Task { start, end, circuit_type } = task;
-        let CircuitType { depth, initial_depth, finality } = circuit_type;
+        let CircuitType { depth, initial_depth, finality, .. } = circuit_type;
         assert!(end - start < 1 << depth);
         if depth == initial_depth {
-            // set environmental vars
-            self.get_params(circuit_type);
+            let provider = self.provider.as_ref().unwrap_or_else(|| {
+                panic!(
+                    "No JSON-RPC provider to fetch blocks {start:#x}..={end:#x}: set {} or INFURA_ID",
+                    rpc_url_var(self.network)
+                )
+            });
             let circuit = EthBlockHeaderChainCircuit::from_provider(
-                &self.provider,
+                provider,
                 self.network,
                 start,
                 end - start + 1,
@@ -212,20 +350,12 @@ impl Sequencer {
             );
             AnyCircuit::Initial(circuit)
         } else {
-            let prev_type = circuit_type.prev();
-            let mut snarks: Vec<Snark>;
-            let prev_depth = prev_type.depth;
-            snarks = (start..=end)
-                .step_by(1 << prev_depth)
-                .map(|i| {
-                    self.get_snark(Task::new(i, min(end, i + (1 << prev_depth) - 1), prev_type))
-                })
-                .collect();
-            if (finality == Finality::None || finality == Finality::Merkle) && snarks.len() != 2 {
-                snarks.push(snarks[0].clone());
+            if finality == Finality::None || finality == Finality::Merkle {
+                // pad with the last snark, which is ignored by the circuit
+                let padding = snarks.last().unwrap().clone();
+                snarks.resize(1 << circuit_type.fan_in_log2(), padding);
             }
-            let k = self.get_params(circuit_type);
-            let params = self.params.get(&k).unwrap();
+            let params = &self.params[&self.params_k[&circuit_type]];
             let mut rng = self.rng.clone();
             match finality {
                 Finality::None => {
@@ -265,112 +395,321 @@ impl Sequencer {
             return snark;
         }
         let circuit = self.get_circuit(task);
-        let circuit_type = task.circuit_type;
+        self.load_pk(task.circuit_type, &circuit);
+        self.prove(task, circuit)
+    }
+
+    /// Proves the header chain from `prev.start` to `new.end` by extending the snark of `prev` with the snark of
+    /// `new`, see [`EthBlockHeaderChainExtensionCircuit`]. Both snarks are read from disk if they are there, so
+    /// the blocks of `prev` are not proved again to follow the chain head.
+    ///
+    /// `prev` and `new` must be `Finality::None` tasks with the same initial depth, where `prev` is an
+    /// aggregation task with a multiple of `2^initial_depth` blocks and `new` starts at `prev.end + 1`.
+    /// The snark has the same public instances as the snark of a task of the depth of `prev` and finality
+    /// `finality` for the whole chain: with `Finality::Merkle` the extension computes the final merkle mountain
+    /// range, see [`EthBlockHeaderChainFinalExtensionCircuit`], and with `Finality::Evm(round)` the snark of
+    /// finality `CircuitType::prev` is aggregated as for any other task.
+    ///
+    /// Uses the configuration `{config_dir}/{name}.json` of the extension if it exists, and otherwise the
+    /// configuration of the aggregation circuit of the depth of `prev` with fan-in 2, which also aggregates two
+    /// snarks.
+    pub fn extend_snark(&mut self, prev: Task, new: Task, finality: Finality) -> Snark {
+        let name = self.extension_name(prev, new, finality);
+        let snark_name =
+            format!("{}/{name}_{:06x}_{:06x}.snark", self.data_dir, prev.start, new.end);
+        if let Ok(snark) = read_snark(&snark_name) {
+            return snark;
+        }
+        let (prev_type, new_type) = (prev.circuit_type, new.circuit_type);
+        let snarks = match finality {
+            Finality::None | Finality::Merkle => vec![self.get_snark(prev), self.get_snark(new)],
+            Finality::Evm(_) => {
+                let circuit_type = CircuitType { finality, ..prev_type };
+                vec![self.extend_snark(prev, new, circuit_type.prev().finality)]
+            }
+        };
+        let k = self.get_extension_params(prev, new, finality);
+        let params = &self.params[&k];
+        let pk_path = format!("{}/{name}.pkey", self.data_dir);
+        let pk_path = Some(Path::new(&pk_path));
+        let mut rng = self.rng.clone();
+        let snark = match finality {
+            Finality::None => {
+                let [prev_snark, new_snark]: [_; 2] = snarks.try_into().unwrap();
+                let circuit = EthBlockHeaderChainExtensionCircuit::new(
+                    params,
+                    prev_snark,
+                    new_snark,
+                    &mut rng,
+                    prev_type.depth,
+                    new_type.depth,
+                    prev_type.initial_depth,
+                );
+                let pk = gen_pk(params, &circuit, pk_path);
+                gen_snark_shplonk(params, &pk, circuit, &mut rng, None::<&str>)
+            }
+            Finality::Merkle => {
+                let [prev_snark, new_snark]: [_; 2] = snarks.try_into().unwrap();
+                let circuit = EthBlockHeaderChainFinalExtensionCircuit::new(
+                    params,
+                    prev_snark,
+                    new_snark,
+                    &mut rng,
+                    prev_type.depth,
+                    new_type.depth,
+                    prev_type.initial_depth,
+                );
+                let pk = gen_pk(params, &circuit, pk_path);
+                gen_snark_shplonk(params, &pk, circuit, &mut rng, None::<&str>)
+            }
+            Finality::Evm(_) => {
+                let circuit = PublicAggregationCircuit::new(params, snarks, true, &mut rng);
+                let pk = gen_pk(params, &circuit, pk_path);
+                gen_snark_shplonk(params, &pk, circuit, &mut rng, None::<&str>)
+            }
+        };
+        write_snark(snark_name, &snark).expect("write snark should not fail");
+        snark
+    }
+
+    /// Identifies the circuit of [`Sequencer::extend_snark`], which depends on the verifying keys of both snarks,
+    /// in file names
+    fn extension_name(&self, prev: Task, new: Task, finality: Finality) -> String {
+        let network = self.network;
+        let (prev_type, new_type) = (prev.circuit_type, new.circuit_type);
+        assert!(prev_type.finality == Finality::None && new_type.finality == Finality::None);
+        assert_eq!(prev_type.initial_depth, new_type.initial_depth);
+        assert!(prev_type.depth > prev_type.initial_depth && new_type.depth <= prev_type.depth);
+        assert_eq!(prev.end + 1, new.start);
+        let new_name = new_type.name(network);
+        format!(
+            "{}_extend_{}{}",
+            prev_type.name(network),
+            &new_name[network.to_string().len() + 1..],
+            CircuitType { finality, ..prev_type }.fname_suffix()
+        )
+    }
+
+    /// Sets the environmental vars with the configuration of the circuit of [`Sequencer::extend_snark`], loads
+    /// the trusted setup and returns its degree
+    fn get_extension_params(&mut self, prev: Task, new: Task, finality: Finality) -> u32 {
+        let name = self.extension_name(prev, new, finality);
+        let mut config_path = format!("{}/{name}.json", self.config_dir);
+        if !Path::new(&config_path).exists() {
+            let circuit_type = CircuitType { finality, ..prev.circuit_type };
+            config_path = self.config_path(circuit_type.with_fan_in_log2(1));
+        }
+        let k = load_aggregation_degree(finality, config_path);
+        self.params.entry(k).or_insert_with(|| gen_srs(k));
+        k
+    }
+
+    /// Reads or generates the proving key for `circuit_type` if it is not loaded yet, using `circuit` for keygen.
+    pub fn load_pk(&self, circuit_type: CircuitType, circuit: &AnyCircuit) {
+        if self.pkeys.read().unwrap().contains_key(&circuit_type) {
+            return;
+        }
         let params = &self.params[&self.params_k[&circuit_type]];
-        let pk_name = circuit_type.pkey_name(network);
+        let pk_name = format!(
+            "{}/{}{}.pkey",
+            self.data_dir,
+            circuit_type.name(self.network),
+            circuit_type.fname_suffix()
+        );
         let pk_path = Some(Path::new(&pk_name));
-        let pk = self.pkeys.entry(circuit_type).or_insert_with(|| {
+        // another thread may have inserted the key while we waited for the lock
+        self.pkeys.write().unwrap().entry(circuit_type).or_insert_with(|| {
             // as you can see we do the same thing for each circuit, but because `Circuit` is
             // not an object-safe trait we can't put it in a `Box`
-            match &circuit {
+            Arc::new(match circuit {
                 AnyCircuit::Initial(circuit) => gen_pk(params, circuit, pk_path),
                 AnyCircuit::Intermediate(circuit) => gen_pk(params, circuit, pk_path),
                 AnyCircuit::Final(circuit) => gen_pk(params, circuit, pk_path),
                 AnyCircuit::ForEvm(circuit) => gen_pk(params, circuit, pk_path),
-            }
+            })
         });
-        let snark_path = Some(task.snark_name(network));
+    }
+
+    /// The proving key of `circuit_type`, which must already be loaded with `load_pk`. The lock on `pkeys` is
+    /// released before the key is used, so other threads can load keys while proofs are generated.
+    pub fn pk(&self, circuit_type: CircuitType) -> Arc<ProvingKey<G1Affine>> {
+        Arc::clone(&self.pkeys.read().unwrap()[&circuit_type])
+    }
+
+    /// Proves `circuit` for `task` and writes the snark to disk. The proving key must already be loaded with `load_pk`.
+    pub fn prove(&self, task: Task, circuit: AnyCircuit) -> Snark {
+        let circuit_type = task.circuit_type;
+        let snark = match circuit {
+            AnyCircuit::Initial(circuit) => self.prove_circuit(circuit_type, circuit),
+            AnyCircuit::Intermediate(circuit) => self.prove_circuit(circuit_type, circuit),
+            AnyCircuit::Final(circuit) => self.prove_circuit(circuit_type, circuit),
+            AnyCircuit::ForEvm(circuit) => self.prove_circuit(circuit_type, circuit),
+        };
+        task.write_snark(self.network, &snark).expect("write snark should not fail");
+        snark
+    }
+
+    /// Proves `circuit` with the proving key of `circuit_type`, which must already be loaded with `load_pk`
+    pub fn prove_circuit<C: CircuitExt<Fr>>(&self, circuit_type: CircuitType, circuit: C) -> Snark {
+        let params = &self.params[&self.params_k[&circuit_type]];
+        let pk = self.pk(circuit_type);
         let mut rng = self.rng.clone();
-        match circuit {
-            AnyCircuit::Initial(circuit) => {
-                gen_snark_shplonk(params, pk, circuit, &mut rng, snark_path)
-            }
-            AnyCircuit::Intermediate(circuit) => {
-                gen_snark_shplonk(params, pk, circuit, &mut rng, snark_path)
-            }
-            AnyCircuit::Final(circuit) => {
-                gen_snark_shplonk(params, pk, circuit, &mut rng, snark_path)
-            }
-            AnyCircuit::ForEvm(circuit) => {
-                gen_snark_shplonk(params, pk, circuit, &mut rng, snark_path)
-            }
-        }
+        gen_snark_shplonk(params, &pk, circuit, &mut rng, None::<&str>)
     }
 
     #[cfg(feature = "evm")]
     pub fn get_calldata(&mut self, task: Task, generate_smart_contract: bool) -> Vec<u8> {
-        let network = self.network;
+        let fname = self.calldata_path(task);
+        if let Ok(calldata) = std::fs::read_to_string(&fname) {
+            return hex::decode(calldata).expect("calldata should be hex");
+        }
+        self.write_calldata(task, generate_smart_contract).0
+    }
+
+    /// Same as `get_calldata(task, true)`, but always generates a new proof, and also checks it with the typed
+    /// Solidity wrapper around the Yul verifier, see [`super::evm`]. Returns the calldata of the Yul verifier
+    /// and the gas used by `verifyHeaderChain`.
+    ///
+    /// Unlike `get_calldata`, this needs `solc` to compile the wrapper, and returns an error if it is not
+    /// installed or the wrapper rejects the proof.
+    #[cfg(feature = "evm")]
+    pub fn get_calldata_with_wrapper(&mut self, task: Task) -> io::Result<(Vec<u8>, u64)> {
+        use super::evm::verify_with_header_chain_wrapper;
+
+        let (calldata, deployment_code) = self.write_calldata(task, true);
+        let deployment_code = deployment_code.expect("smart contract should be generated");
+        let gas =
+            verify_with_header_chain_wrapper(deployment_code, &calldata, task.circuit_type.depth)?;
+        Ok((calldata, gas))
+    }
+
+    #[cfg(feature = "evm")]
+    fn calldata_path(&self, task: Task) -> String {
         let circuit_type = task.circuit_type;
         assert!(matches!(circuit_type.finality, Finality::Evm(_)));
-        let fname = format!(
-            "data/headers/{}_{}_{}_{:06x}_{:06x}.calldata",
-            network, circuit_type.depth, circuit_type.initial_depth, task.start, task.end
-        );
-        if let Ok(calldata) = std::fs::read(&fname) {
-            return calldata;
-        }
+        format!(
+            "{}/{}_{:06x}_{:06x}.calldata",
+            self.data_dir,
+            circuit_type.name(self.network),
+            task.start,
+            task.end
+        )
+    }
 
+    /// Proves `task` for the EVM and writes the calldata to disk. Returns the calldata, and the deployment code of
+    /// the Yul verifier if `generate_smart_contract`.
+    #[cfg(feature = "evm")]
+    fn write_calldata(
+        &mut self,
+        task: Task,
+        generate_smart_contract: bool,
+    ) -> (Vec<u8>, Option<Vec<u8>>) {
+        let circuit_type = task.circuit_type;
+        let fname = self.calldata_path(task);
         let circuit = self.get_circuit(task);
-        match circuit {
-            AnyCircuit::ForEvm(circuit) => {
-                self.write_calldata_generic(circuit, circuit_type, &fname, generate_smart_contract)
-            }
-            _ => unreachable!(),
+        self.load_pk(circuit_type, &circuit);
+        let AnyCircuit::ForEvm(circuit) = circuit else { unreachable!() };
+        let params = &self.params[&self.params_k[&circuit_type]];
+        self.write_calldata_generic(
+            params,
+            &self.pk(circuit_type),
+            circuit,
+            &circuit_type.name(self.network),
+            circuit_type.depth,
+            &fname,
+            generate_smart_contract,
+        )
+    }
+
+    /// The calldata for the EVM verifier of the chain from `prev.start` to `new.end`, from the snark of
+    /// [`Sequencer::extend_snark`] with finality `Finality::Evm(round)`
+    #[cfg(feature = "evm")]
+    pub fn get_extension_calldata(
+        &mut self,
+        prev: Task,
+        new: Task,
+        round: usize,
+        generate_smart_contract: bool,
+    ) -> Vec<u8> {
+        let finality = Finality::Evm(round);
+        let name = self.extension_name(prev, new, finality);
+        let fname = format!("{}/{name}_{:06x}_{:06x}.calldata", self.data_dir, prev.start, new.end);
+        if let Ok(calldata) = std::fs::read_to_string(&fname) {
+            return hex::decode(calldata).expect("calldata should be hex");
         }
+        let circuit_type = CircuitType { finality, ..prev.circuit_type };
+        let snark = self.extend_snark(prev, new, circuit_type.prev().finality);
+        let k = self.get_extension_params(prev, new, finality);
+        let params = &self.params[&k];
+        let mut rng = self.rng.clone();
+        let circuit = PublicAggregationCircuit::new(params, vec![snark], true, &mut rng);
+        let pk_path = format!("{}/{name}.pkey", self.data_dir);
+        let pk = gen_pk(params, &circuit, Some(Path::new(&pk_path)));
+        self.write_calldata_generic(
+            params,
+            &pk,
+            circuit,
+            &name,
+            circuit_type.depth,
+            &fname,
+            generate_smart_contract,
+        )
+        .0
     }
 
+    /// Generates the EVM proof of `circuit` and writes its calldata to `path`. With `generate_smart_contract`,
+    /// also writes the Yul verifier and the source of its Solidity wrapper, for a chain of depth `depth`, to
+    /// `{data_dir}/{name}`, checks the proof with the Yul verifier and returns its deployment code.
     #[cfg(feature = "evm")]
+    #[allow(clippy::too_many_arguments)]
     fn write_calldata_generic<ConcreteCircuit: CircuitExt<Fr>>(
-        &mut self,
+        &self,
+        params: &ParamsKZG<Bn256>,
+        pk: &ProvingKey<G1Affine>,
         circuit: ConcreteCircuit,
-        circuit_type: CircuitType,
+        name: &str,
+        depth: usize,
         path: impl AsRef<Path>,
         generate_smart_contract: bool,
-    ) -> Vec<u8> {
-        #[allow(unused_imports)]
-        use ethers_core::utils::hex;
+    ) -> (Vec<u8>, Option<Vec<u8>>) {
+        use super::evm::gen_header_chain_verifier_wrapper;
         use snark_verifier::loader::evm::encode_calldata;
         use snark_verifier_sdk::evm::{
             evm_verify, gen_evm_proof_shplonk, gen_evm_verifier_shplonk,
         };
-        use std::fs;
 
-        let params = &self.params[&self.params_k[&circuit_type]];
-        let pk_name = circuit_type.pkey_name(self.network);
-        let pk_path = Some(Path::new(&pk_name));
-        let pk =
-            self.pkeys.entry(circuit_type).or_insert_with(|| gen_pk(params, &circuit, pk_path));
         let instances = circuit.instances();
         let mut rng = self.rng.clone();
         let proof = gen_evm_proof_shplonk(params, pk, circuit, instances.clone(), &mut rng);
         let calldata = encode_calldata(&instances, &proof);
-        fs::write(path, hex::encode(&calldata)).expect("write calldata should not fail");
-
-        if generate_smart_contract {
-            let num_instances = instances[0].len();
-            let deployment_code = gen_evm_verifier_shplonk::<ConcreteCircuit>(
-                params,
-                pk.get_vk(),
-                vec![num_instances],
-                Some(Path::new(&format!(
-                    "data/headers/{}_{}_{}.yul",
-                    self.network, circuit_type.depth, circuit_type.initial_depth
-                ))),
-            );
+        write_atomic(path, hex::encode(&calldata)).expect("write calldata should not fail");
 
-            evm_verify(deployment_code, instances, proof);
+        if !generate_smart_contract {
+            return (calldata, None);
         }
-        calldata
+        let num_instances = instances[0].len();
+        let deployment_code = gen_evm_verifier_shplonk::<ConcreteCircuit>(
+            params,
+            pk.get_vk(),
+            vec![num_instances],
+            Some(Path::new(&format!("{}/{name}.yul", self.data_dir))),
+        );
+        evm_verify(deployment_code.clone(), instances, proof);
+        // typed wrapper around the Yul verifier, compiled by `get_calldata_with_wrapper`
+        let wrapper = gen_header_chain_verifier_wrapper(depth);
+        write_atomic(format!("{}/{name}.sol", self.data_dir), wrapper)
+            .expect("write wrapper contract should not fail");
+        (calldata, Some(deployment_code))
     }
 }
 
 impl<F: Field + PrimeField> CircuitExt<F> for EthBlockHeaderChainCircuit<F> {
     fn num_instance(&self) -> Vec<usize> {
-        vec![Self::get_num_instance(self.max_depth)]
+        vec![Self::get_num_instance(self.max_depth) + 2 * self.field_queries.len()]
     }
 
     fn instances(&self) -> Vec<Vec<F>> {
-        vec![self.instance.to_instance()]
+        vec![[self.instance.to_instance(), self.field_instance()].concat()]
     }
 }
 
@@ -429,6 +768,16 @@ impl CircuitExt<Fr> for EthBlockHeaderChainAggregationCircuit {
 //
 // Note: we assume that `params` is the correct size for the circuit.
 
+impl CircuitExt<Fr> for EthBlockHeaderChainExtensionCircuit {
+    fn num_instance(&self) -> Vec<usize> {
+        self.inner.num_instance()
+    }
+
+    fn instances(&self) -> Vec<Vec<Fr>> {
+        vec![self.instance()]
+    }
+}
+
 impl CircuitExt<Fr> for EthBlockHeaderChainFinalAggregationCircuit {
     fn num_instance(&self) -> Vec<usize> {
         vec![4 * LIMBS + Self::get_num_instance(self.0.max_depth)]
@@ -438,3 +787,14 @@ impl CircuitExt<Fr> for EthBlockHeaderChainFinalAggregationCircuit {
         vec![self.instance()]
     }
 }
+
+impl CircuitExt<Fr> for EthBlockHeaderChainFinalExtensionCircuit {
+    fn num_instance(&self) -> Vec<usize> {
+        let max_depth = self.0.inner.max_depth;
+        vec![4 * LIMBS + EthBlockHeaderChainFinalAggregationCircuit::get_num_instance(max_depth)]
+    }
+
+    fn instances(&self) -> Vec<Vec<Fr>> {
+        vec![self.instance()]
+    }
+}
@@ -0,0 +1,72 @@
+//! On-disk record of the state of every task in a header chain aggregation run, so that an interrupted run
+//! can be resumed with [`Sequencer::resume`](super::helpers::Sequencer::resume).
+use super::helpers::Task;
+use crate::{util::write_atomic, Network};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs::File, io, path::Path};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TaskState {
+    /// Not proved yet
+    Pending,
+    /// Proving had started. If the run is no longer alive, it was interrupted before the snark was written.
+    Proving,
+    /// The snark is on disk
+    Done,
+    /// Proving panicked with the given message
+    Failed(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Journal {
+    /// The task whose dependency tree is being proved
+    pub root: Task,
+    /// State of each task, keyed by [`Task::snark_name`]
+    pub tasks: BTreeMap<String, TaskState>,
+}
+
+impl Journal {
+    pub fn new(root: Task) -> Self {
+        Self { root, tasks: BTreeMap::new() }
+    }
+
+    /// The default location of the journal for `network`
+    pub fn path(network: Network) -> String {
+        format!("data/headers/{network}_journal.json")
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::open(path)?;
+        serde_json::from_reader(file).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// The journal at `path` if it records a run for `root`, otherwise a new journal for `root`
+    pub fn read_for(path: impl AsRef<Path>, root: Task) -> Self {
+        match Self::read(path) {
+            Ok(journal) if journal.root == root => journal,
+            _ => Self::new(root),
+        }
+    }
+
+    /// Writes the journal atomically, so a crash while writing keeps the previous journal intact.
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let bytes = serde_json::to_vec_pretty(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        write_atomic(path, bytes)
+    }
+
+    pub fn state(&self, task: &Task, network: Network) -> Option<&TaskState> {
+        self.tasks.get(&task.snark_name(network))
+    }
+
+    pub fn set_state(&mut self, task: &Task, network: Network, state: TaskState) {
+        self.tasks.insert(task.snark_name(network), state);
+    }
+
+    /// Tasks that were being proved or had failed when the journal was last written
+    pub fn unfinished(&self) -> impl Iterator<Item = (&String, &TaskState)> {
+        self.tasks
+            .iter()
+            .filter(|(_, state)| matches!(state, TaskState::Proving | TaskState::Failed(_)))
+    }
+}
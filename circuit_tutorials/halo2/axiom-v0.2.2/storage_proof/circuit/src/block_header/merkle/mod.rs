@@ -0,0 +1,379 @@
+//! Merkle proofs that a block hash is a leaf of the merkle mountain range committed in
+//! [`EthBlockHeaderChainInstance::merkle_mountain_range`](super::EthBlockHeaderChainInstance).
+//!
+//! The mountain range of `block_hashes` has one mountain per 1 bit of `block_hashes.len()`, ordered largest first,
+//! and `merkle_roots[max_depth - depth]` is the root of the mountain of depth `depth` (or zero if there is none).
+//! A [`BlockHashMerkleProof`] is a path from a block hash up to the root of the mountain containing it.
+use crate::{
+    halo2_proofs::{
+        circuit::{Layouter, SimpleFloorPlanner, Value},
+        plonk::{Circuit, ConstraintSystem, Error},
+    },
+    keccak::KeccakChip,
+    mpt::AssignedBytes,
+    util::{bytes_be_to_u128, encode_h256_to_field, AssignedH256, EthConfigParams},
+    EthChip, EthConfig, Field,
+};
+#[cfg(feature = "display")]
+use ark_std::{end_timer, start_timer};
+use ethers_core::{types::H256, utils::keccak256};
+use halo2_base::{
+    gates::{range::RangeConfig, GateInstructions, RangeInstructions},
+    AssignedValue, Context, ContextParams,
+    QuantumCell::{Constant, Existing},
+    SKIP_FIRST_PASS,
+};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use snark_verifier_sdk::CircuitExt;
+use std::marker::PhantomData;
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockHashMerkleProof {
+    pub block_hash: H256,
+    /// Position of the block within its mountain. Bit `i` of `index` is 1 if the node at height `i` on the path
+    /// to the root is a right child.
+    pub index: usize,
+    /// Depth of the mountain containing the block
+    pub peak_depth: usize,
+    /// Sibling hashes on the path from the block hash up to the root of its mountain, bottom first.
+    /// Has length `peak_depth`.
+    pub siblings: Vec<H256>,
+}
+
+impl BlockHashMerkleProof {
+    /// Generates the proof that `block_hashes[idx]` is in the merkle mountain range of `block_hashes`,
+    /// as computed by [`get_merkle_mountain_range`](crate::util::get_merkle_mountain_range).
+    pub fn new(block_hashes: &[H256], idx: usize) -> Self {
+        let num_leaves = block_hashes.len();
+        assert!(idx < num_leaves, "block index {idx} out of range");
+        // mountains are ordered largest first, find the one containing `idx`
+        let mut start = 0;
+        let mut peak_depth = num_leaves.ilog2() as usize;
+        loop {
+            if (num_leaves >> peak_depth) & 1 == 1 {
+                if idx < start + (1 << peak_depth) {
+                    break;
+                }
+                start += 1 << peak_depth;
+            }
+            peak_depth -= 1;
+        }
+
+        let index = idx - start;
+        let mut layer = block_hashes[start..start + (1 << peak_depth)].to_vec();
+        let mut siblings = Vec::with_capacity(peak_depth);
+        for height in 0..peak_depth {
+            siblings.push(layer[(index >> height) ^ 1]);
+            layer = layer.chunks(2).map(|pair| hash_pair(&pair[0], &pair[1])).collect();
+        }
+        Self { block_hash: block_hashes[idx], index, peak_depth, siblings }
+    }
+
+    /// The root of the mountain containing the block, computed from the proof
+    pub fn peak(&self) -> H256 {
+        self.siblings.iter().enumerate().fold(self.block_hash, |node, (height, sibling)| {
+            if (self.index >> height) & 1 == 1 {
+                hash_pair(sibling, &node)
+            } else {
+                hash_pair(&node, sibling)
+            }
+        })
+    }
+
+    /// Checks the proof against the merkle mountain range `merkle_roots`, ordered largest mountain first.
+    pub fn verify(&self, merkle_roots: &[H256]) -> bool {
+        self.siblings.len() == self.peak_depth
+            && self.index < 1 << self.peak_depth
+            && self.peak_depth < merkle_roots.len()
+            && merkle_roots[merkle_roots.len() - 1 - self.peak_depth] != H256::zero()
+            && merkle_roots[merkle_roots.len() - 1 - self.peak_depth] == self.peak()
+    }
+
+    /// Assigns the proof as witnesses, with the path padded to `max_depth` using dummy siblings.
+    pub fn assign<'v, F: Field>(
+        &self,
+        ctx: &mut Context<'_, F>,
+        gate: &impl GateInstructions<F>,
+        max_depth: usize,
+    ) -> AssignedBlockHashMerkleProof<'v, F> {
+        assert!(self.peak_depth <= max_depth);
+        let mut load_bytes = |bytes: &[u8]| {
+            gate.assign_witnesses(ctx, bytes.iter().map(|x| Value::known(F::from(*x as u64))))
+        };
+        let block_hash = load_bytes(self.block_hash.as_bytes());
+        let siblings = self
+            .siblings
+            .iter()
+            .copied()
+            .chain(std::iter::repeat(H256::zero()))
+            .take(max_depth)
+            .map(|sibling| load_bytes(sibling.as_bytes()))
+            .collect();
+        let index_bits = gate.assign_witnesses(
+            ctx,
+            (0..max_depth).map(|i| Value::known(F::from(((self.index >> i) & 1) as u64))),
+        );
+        let peak_depth = gate.load_witness(ctx, Value::known(F::from(self.peak_depth as u64)));
+        AssignedBlockHashMerkleProof { block_hash, index_bits, peak_depth, siblings }
+    }
+}
+
+fn hash_pair(left: &H256, right: &H256) -> H256 {
+    H256(keccak256([left.as_bytes(), right.as_bytes()].concat()))
+}
+
+#[derive(Clone, Debug)]
+pub struct AssignedBlockHashMerkleProof<'v, F: Field> {
+    pub block_hash: AssignedBytes<'v, F>,
+    /// Little endian bits of `index`, of length `max_depth`. Only the first `peak_depth` bits are used.
+    pub index_bits: Vec<AssignedValue<'v, F>>,
+    pub peak_depth: AssignedValue<'v, F>,
+    /// Has length `max_depth`. Only the first `peak_depth` siblings are used.
+    pub siblings: Vec<AssignedBytes<'v, F>>,
+}
+
+pub trait EthBlockHashMerkleChip<'v, F: Field> {
+    /// Constrains that `proof.block_hash` is a leaf of the merkle mountain range `merkle_roots`, where `merkle_roots`
+    /// is ordered largest mountain first, has length `max_depth + 1`, and each root is hi-lo (u128, u128) encoded
+    /// with zero for missing mountains, as in the public instances of `EthBlockHeaderChainCircuit`.
+    ///
+    /// Returns the block hash as hi-lo (u128, u128), to be linked to the block hash of e.g. a storage proof.
+    ///
+    /// This MUST be done in `FirstPhase`. The keccak hashes are constrained when the keccak chip is assigned.
+    fn verify_block_hash_merkle_proof(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        proof: &AssignedBlockHashMerkleProof<'v, F>,
+        merkle_roots: &[AssignedH256<'v, F>],
+    ) -> AssignedH256<'v, F>;
+}
+
+impl<'v, F: Field> EthBlockHashMerkleChip<'v, F> for EthChip<'v, F> {
+    fn verify_block_hash_merkle_proof(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        proof: &AssignedBlockHashMerkleProof<'v, F>,
+        merkle_roots: &[AssignedH256<'v, F>],
+    ) -> AssignedH256<'v, F> {
+        verify_block_hash_merkle_proof(
+            ctx,
+            &self.mpt.rlp.range,
+            &mut self.mpt.keccak,
+            proof,
+            merkle_roots,
+        )
+    }
+}
+
+/// Same as [`EthBlockHashMerkleChip::verify_block_hash_merkle_proof`], for circuits that have a keccak chip but not a
+/// full [`EthChip`], such as aggregation circuits.
+pub fn verify_block_hash_merkle_proof<'v, F: Field>(
+    ctx: &mut Context<'v, F>,
+    range: &RangeConfig<F>,
+    keccak: &mut KeccakChip<'v, F>,
+    proof: &AssignedBlockHashMerkleProof<'v, F>,
+    merkle_roots: &[AssignedH256<'v, F>],
+) -> AssignedH256<'v, F> {
+    debug_assert_eq!(ctx.current_phase(), 0);
+    let max_depth = proof.siblings.len();
+    assert_eq!(proof.index_bits.len(), max_depth);
+    assert_eq!(merkle_roots.len(), max_depth + 1);
+
+    for byte in proof.block_hash.iter().chain(proof.siblings.iter().flatten()) {
+        range.range_check(ctx, byte, 8);
+    }
+    for bit in &proof.index_bits {
+        range.gate.assert_bit(ctx, bit);
+    }
+    range.check_less_than_safe(ctx, &proof.peak_depth, max_depth as u64 + 1);
+
+    // nodes[height] is the root of the subtree of height `height` containing the block
+    let mut nodes = Vec::with_capacity(max_depth + 1);
+    nodes.push(proof.block_hash.clone());
+    for (sibling, bit) in proof.siblings.iter().zip(proof.index_bits.iter()) {
+        let node = nodes.last().unwrap();
+        // if `bit` is 1 then `node` is the right child
+        let (left, right): (Vec<_>, Vec<_>) = node
+            .iter()
+            .zip(sibling.iter())
+            .map(|(node_byte, sibling_byte)| {
+                let left = range.gate.select(
+                    ctx,
+                    Existing(sibling_byte),
+                    Existing(node_byte),
+                    Existing(bit),
+                );
+                let right = range.gate.select(
+                    ctx,
+                    Existing(node_byte),
+                    Existing(sibling_byte),
+                    Existing(bit),
+                );
+                (left, right)
+            })
+            .unzip();
+        let query_idx = keccak.keccak_fixed_len(ctx, &range.gate, [left, right].concat(), None);
+        nodes.push(keccak.fixed_len_queries[query_idx].output_assigned.clone());
+    }
+
+    let peak = (0..32)
+        .map(|byte_idx| {
+            range.gate.select_from_idx(
+                ctx,
+                nodes.iter().map(|node| Existing(&node[byte_idx])),
+                Existing(&proof.peak_depth),
+            )
+        })
+        .collect_vec();
+    let peak = bytes_be_to_u128(ctx, &range.gate, &peak);
+    let mountain_idx =
+        range.gate.sub(ctx, Constant(F::from(max_depth as u64)), Existing(&proof.peak_depth));
+    let root = [0, 1].map(|limb| {
+        range.gate.select_from_idx(
+            ctx,
+            merkle_roots.iter().map(|root| Existing(&root[limb])),
+            Existing(&mountain_idx),
+        )
+    });
+    // missing mountains are committed as zero, which is not the root of any mountain
+    let root_is_zero = [0, 1].map(|limb| range.gate.is_zero(ctx, &root[limb]));
+    let root_is_zero = range.gate.and(ctx, Existing(&root_is_zero[0]), Existing(&root_is_zero[1]));
+    range.gate.assert_is_const(ctx, &root_is_zero, F::zero());
+    for (peak, root) in peak.iter().zip(root.iter()) {
+        ctx.constrain_equal(peak, root);
+    }
+
+    let block_hash = bytes_be_to_u128(ctx, &range.gate, &proof.block_hash);
+    block_hash.try_into().unwrap()
+}
+
+/// Proves that `proof.block_hash` is in the merkle mountain range `merkle_roots`.
+///
+/// Public instances: the block hash followed by the `max_depth + 1` merkle roots, with H256 encoded as hi-lo (u128, u128).
+#[derive(Clone, Debug)]
+pub struct EthBlockHashMerkleCircuit<F> {
+    pub proof: BlockHashMerkleProof,
+    pub merkle_roots: Vec<H256>,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> EthBlockHashMerkleCircuit<F> {
+    pub fn new(proof: BlockHashMerkleProof, merkle_roots: Vec<H256>) -> Self {
+        assert!(!merkle_roots.is_empty());
+        Self { proof, merkle_roots, _marker: PhantomData }
+    }
+
+    pub fn max_depth(&self) -> usize {
+        self.merkle_roots.len() - 1
+    }
+
+    pub fn instance(&self) -> Vec<F> {
+        [&self.proof.block_hash]
+            .into_iter()
+            .chain(&self.merkle_roots)
+            .flat_map(encode_h256_to_field)
+            .collect()
+    }
+}
+
+impl<F: Field> Circuit<F> for EthBlockHashMerkleCircuit<F> {
+    type Config = EthConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        self.clone()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        let params = EthConfigParams::get_block_hash_merkle();
+        EthConfig::configure(meta, params, 0)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        #[cfg(feature = "display")]
+        let witness_gen = start_timer!(|| "synthesize");
+
+        let gamma = layouter.get_challenge(config.rlc().gamma);
+        config.range().load_lookup_table(&mut layouter).expect("load range lookup table");
+        config.keccak().load_aux_tables(&mut layouter).expect("load keccak lookup tables");
+
+        let mut first_pass = SKIP_FIRST_PASS;
+        let mut instance = vec![];
+        layouter
+            .assign_region(
+                || "block hash in merkle mountain range",
+                |region| {
+                    if first_pass {
+                        first_pass = false;
+                        return Ok(());
+                    }
+                    let mut chip = EthChip::new(config.clone(), gamma);
+                    let mut aux = Context::new(
+                        region,
+                        ContextParams {
+                            max_rows: chip.gate().max_rows,
+                            num_context_ids: 2,
+                            fixed_columns: chip.gate().constants.clone(),
+                        },
+                    );
+                    let ctx = &mut aux;
+
+                    // ================= FIRST PHASE ================
+                    let proof = self.proof.assign(ctx, chip.gate(), self.max_depth());
+                    let merkle_roots = self
+                        .merkle_roots
+                        .iter()
+                        .map(|root| {
+                            let root = encode_h256_to_field::<F>(root).map(Value::known);
+                            chip.gate().assign_witnesses(ctx, root).try_into().unwrap()
+                        })
+                        .collect_vec();
+                    let block_hash =
+                        chip.verify_block_hash_merkle_proof(ctx, &proof, &merkle_roots);
+                    chip.assign_phase0(ctx);
+                    ctx.next_phase();
+
+                    // ================= SECOND PHASE ================
+                    chip.get_challenge(ctx);
+                    chip.keccak_assign_phase1(ctx);
+                    chip.range().finalize(ctx);
+
+                    instance.extend(
+                        block_hash
+                            .iter()
+                            .chain(merkle_roots.iter().flatten())
+                            .map(|acell| acell.cell().clone()),
+                    );
+
+                    #[cfg(feature = "display")]
+                    ctx.print_stats(&["Range", "RLC"]);
+                    Ok(())
+                },
+            )
+            .unwrap();
+        for (i, cell) in instance.into_iter().enumerate() {
+            layouter.constrain_instance(cell, config.instance, i);
+        }
+        #[cfg(feature = "display")]
+        end_timer!(witness_gen);
+        Ok(())
+    }
+}
+
+impl<F: Field> CircuitExt<F> for EthBlockHashMerkleCircuit<F> {
+    fn num_instance(&self) -> Vec<usize> {
+        vec![2 * (self.max_depth() + 2)]
+    }
+
+    fn instances(&self) -> Vec<Vec<F>> {
+        vec![self.instance()]
+    }
+}
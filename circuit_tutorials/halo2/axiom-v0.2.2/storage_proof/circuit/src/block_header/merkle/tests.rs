@@ -0,0 +1,81 @@
+use super::*;
+use crate::{
+    halo2_proofs::{dev::MockProver, halo2curves::bn256::Fr},
+    util::get_merkle_mountain_range,
+};
+
+const MAX_DEPTH: usize = 4;
+
+fn mock_block_hashes(num_blocks: usize) -> Vec<H256> {
+    (0..num_blocks).map(|i| H256(keccak256((i as u64).to_be_bytes()))).collect()
+}
+
+#[test]
+pub fn test_block_hash_merkle_proof() {
+    for num_blocks in 1..=1 << MAX_DEPTH {
+        let block_hashes = mock_block_hashes(num_blocks);
+        let merkle_roots = get_merkle_mountain_range(&block_hashes, MAX_DEPTH);
+        for idx in 0..num_blocks {
+            let proof = BlockHashMerkleProof::new(&block_hashes, idx);
+            assert_eq!(proof.block_hash, block_hashes[idx]);
+            assert!(proof.verify(&merkle_roots), "num_blocks {num_blocks}, idx {idx}");
+
+            let mut wrong_hash = proof.clone();
+            wrong_hash.block_hash = block_hashes[(idx + 1) % num_blocks];
+            assert!(num_blocks == 1 || !wrong_hash.verify(&merkle_roots));
+        }
+    }
+}
+
+#[test]
+pub fn test_block_hash_merkle_proof_peaks() {
+    // 11 = 0b1011: mountains of depth 3, 1, 0
+    let block_hashes = mock_block_hashes(11);
+    let peaks =
+        [0, 7, 8, 9, 10].map(|idx| BlockHashMerkleProof::new(&block_hashes, idx).peak_depth);
+    assert_eq!(peaks, [3, 3, 1, 1, 0]);
+    let proof = BlockHashMerkleProof::new(&block_hashes, 9);
+    assert_eq!(proof.index, 1);
+    assert_eq!(proof.siblings, vec![block_hashes[8]]);
+}
+
+fn get_test_circuit(num_blocks: usize, idx: usize) -> EthBlockHashMerkleCircuit<Fr> {
+    let block_hashes = mock_block_hashes(num_blocks);
+    let merkle_roots = get_merkle_mountain_range(&block_hashes, MAX_DEPTH);
+    EthBlockHashMerkleCircuit::new(BlockHashMerkleProof::new(&block_hashes, idx), merkle_roots)
+}
+
+#[test]
+pub fn test_mock_block_hash_merkle() {
+    let k = EthConfigParams::get_block_hash_merkle().degree;
+    for (num_blocks, idx) in [(11, 5), (11, 9), (11, 10), (16, 15)] {
+        let circuit = get_test_circuit(num_blocks, idx);
+        MockProver::run(k, &circuit, circuit.instances()).unwrap().assert_satisfied();
+    }
+}
+
+#[test]
+pub fn test_mock_block_hash_merkle_wrong_root() {
+    let k = EthConfigParams::get_block_hash_merkle().degree;
+    let circuit = get_test_circuit(11, 5);
+    let mut instances = circuit.instances();
+    // hi of the merkle root of the depth 3 mountain, which contains block 5
+    instances[0][4] += Fr::one();
+    assert!(MockProver::run(k, &circuit, instances).unwrap().verify().is_err());
+}
+
+#[test]
+pub fn test_mock_block_hash_merkle_missing_mountain() {
+    let k = EthConfigParams::get_block_hash_merkle().degree;
+    // with a single block the depth 0 mountain is the block hash itself; claiming the block is
+    // in the (missing) depth 0 mountain of a range of 2 blocks must fail
+    let block_hashes = mock_block_hashes(2);
+    let mut proof = BlockHashMerkleProof::new(&block_hashes, 1);
+    proof.peak_depth = 0;
+    proof.siblings.clear();
+    proof.index = 0;
+    let merkle_roots = get_merkle_mountain_range(&block_hashes, MAX_DEPTH);
+    assert!(!proof.verify(&merkle_roots));
+    let circuit = EthBlockHashMerkleCircuit::<Fr>::new(proof, merkle_roots);
+    assert!(MockProver::run(k, &circuit, circuit.instances()).unwrap().verify().is_err());
+}
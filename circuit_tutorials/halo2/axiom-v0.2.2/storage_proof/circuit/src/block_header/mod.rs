@@ -7,7 +7,10 @@ use crate::{
         rlc::{RlcFixedTrace, RlcTrace, RLC_PHASE},
         RlpArrayTraceWitness, RlpFieldTrace,
     },
-    util::{bytes_be_var_to_fixed, decode_field_to_h256},
+    util::{
+        bytes_be_to_uint, bytes_be_var_to_fixed, decode_field_to_h256, get_merkle_mountain_range,
+        uint_to_bytes_be, AssignedH256,
+    },
     EthChip, EthConfig,
 };
 #[cfg(feature = "display")]
@@ -16,16 +19,17 @@ use core::{
     iter::{self, once},
     marker::PhantomData,
 };
-use ethers_core::types::H256;
+use ethers_core::{types::H256, utils::keccak256};
 #[cfg(feature = "providers")]
 use ethers_providers::{Http, Provider};
+use fields::{get_header_field_h256, BlockHeaderField};
 use halo2_base::{
-    gates::GateInstructions,
+    gates::{GateInstructions, RangeInstructions},
     halo2_proofs::{
         circuit::{Layouter, SimpleFloorPlanner, Value},
         plonk::{Circuit, ConstraintSystem, Error},
     },
-    utils::PrimeField,
+    utils::{bit_length, PrimeField},
     AssignedValue, Context, ContextParams,
     QuantumCell::{Constant, Existing},
     SKIP_FIRST_PASS,
@@ -35,25 +39,34 @@ use serde::{Deserialize, Serialize};
 
 #[cfg(feature = "aggregation")]
 pub mod aggregation;
+#[cfg(feature = "evm")]
+pub mod evm;
+pub mod fields;
 #[cfg(all(feature = "aggregation", feature = "providers"))]
 pub mod helpers;
+#[cfg(all(feature = "aggregation", feature = "providers"))]
+pub mod journal;
+pub mod merkle;
+#[cfg(all(feature = "aggregation", feature = "providers"))]
+pub mod scheduler;
+pub mod stats;
 #[cfg(test)]
 mod tests;
 
 const MAINNET_EXTRA_DATA_MAX_BYTES: usize = 32;
 const MAINNET_EXTRA_DATA_RLP_MAX_BYTES: usize = MAINNET_EXTRA_DATA_MAX_BYTES + 1;
 pub const MAINNET_BLOCK_HEADER_RLP_MAX_BYTES: usize =
-    1 + 2 + 521 + MAINNET_EXTRA_DATA_RLP_MAX_BYTES;
+    1 + 2 + 638 + MAINNET_EXTRA_DATA_RLP_MAX_BYTES;
 const GOERLI_EXTRA_DATA_MAX_BYTES: usize = 97;
 const GOERLI_EXTRA_DATA_RLP_MAX_BYTES: usize = GOERLI_EXTRA_DATA_MAX_BYTES + 1;
-pub const GOERLI_BLOCK_HEADER_RLP_MAX_BYTES: usize = 1 + 2 + 521 + GOERLI_EXTRA_DATA_RLP_MAX_BYTES;
-const BLOCK_HEADER_RLP_MIN_BYTES: usize = 479;
+pub const GOERLI_BLOCK_HEADER_RLP_MAX_BYTES: usize = 1 + 2 + 638 + GOERLI_EXTRA_DATA_RLP_MAX_BYTES;
+pub(crate) const BLOCK_HEADER_RLP_MIN_BYTES: usize = 479;
 
-const NUM_BLOCK_HEADER_FIELDS: usize = 16;
-const MAINNET_HEADER_FIELDS_MAX_BYTES: [usize; NUM_BLOCK_HEADER_FIELDS] =
-    [32, 32, 20, 32, 32, 32, 256, 7, 4, 4, 4, 4, MAINNET_EXTRA_DATA_MAX_BYTES, 32, 8, 6];
+const NUM_BLOCK_HEADER_FIELDS: usize = 21;
+pub(crate) const MAINNET_HEADER_FIELDS_MAX_BYTES: [usize; NUM_BLOCK_HEADER_FIELDS] =
+    header_fields_max_bytes(MAINNET_EXTRA_DATA_MAX_BYTES);
 const GOERLI_HEADER_FIELDS_MAX_BYTES: [usize; NUM_BLOCK_HEADER_FIELDS] =
-    [32, 32, 20, 32, 32, 32, 256, 7, 4, 4, 4, 4, GOERLI_EXTRA_DATA_MAX_BYTES, 32, 8, 6];
+    header_fields_max_bytes(GOERLI_EXTRA_DATA_MAX_BYTES);
 pub const BLOCK_NUMBER_MAX_BYTES: usize = MAINNET_HEADER_FIELDS_MAX_BYTES[8];
 
 // Field        Type        Size (bytes) RLP size (bytes) RLP size (bits)
@@ -73,6 +86,18 @@ pub const BLOCK_NUMBER_MAX_BYTES: usize = MAINNET_HEADER_FIELDS_MAX_BYTES[8];
 // mixHash	256 bits	32	33	264
 // nonce	64 bits	8	9	72
 // basefee (post-1559)	big int scalar	variable	<= 6	<= 48
+// withdrawalsRoot (post-Shanghai)	256 bits	32	33	264
+// blobGasUsed (post-Cancun)	big int scalar	variable	<= 9	<= 72
+// excessBlobGas (post-Cancun)	big int scalar	variable	<= 9	<= 72
+// parentBeaconBlockRoot (post-Cancun)	256 bits	32	33	264
+// requestsHash (post-Prague)	256 bits	32	33	264
+
+/// Maximum byte lengths of the fields of a block header, see the table above, where `extra_data` is the maximum
+/// byte length of `extraData`
+const fn header_fields_max_bytes(extra_data: usize) -> [usize; NUM_BLOCK_HEADER_FIELDS] {
+    [32, 32, 20, 32, 32, 32, 256, 7, 4, 4, 4, 4, extra_data, 32, 8, 6, 32, 8, 8, 32, 32]
+}
+
 #[allow(dead_code)]
 #[derive(Clone, Debug)]
 pub struct EthBlockHeaderTrace<'v, F: Field> {
@@ -94,6 +119,12 @@ pub struct EthBlockHeaderTrace<'v, F: Field> {
     pub mix_hash: RlpFieldTrace<'v, F>,
     pub nonce: RlpFieldTrace<'v, F>,
     pub basefee: Option<RlpFieldTrace<'v, F>>,
+    // the fields of later forks have length 0 in headers of earlier blocks
+    pub withdrawals_root: RlpFieldTrace<'v, F>,
+    pub blob_gas_used: RlpFieldTrace<'v, F>,
+    pub excess_blob_gas: RlpFieldTrace<'v, F>,
+    pub parent_beacon_block_root: RlpFieldTrace<'v, F>,
+    pub requests_hash: RlpFieldTrace<'v, F>,
 
     pub block_hash: RlcFixedTrace<'v, F>,
 
@@ -196,7 +227,7 @@ impl<'v, F: Field> EthBlockHeaderChip<'v, F> for EthChip<'v, F> {
             block_header.iter().map(|byte| Value::known(F::from(*byte as u64))),
         );
         let rlp_witness =
-            self.rlp().decompose_rlp_array_phase0(ctx, block_header_assigned, max_field_lens, true); // `is_variable_len = true` because RLP can have either 15 or 16 fields, depending on whether block is pre-London or not
+            self.rlp().decompose_rlp_array_phase0(ctx, block_header_assigned, max_field_lens, true); // `is_variable_len = true` because RLP has 15 to 21 fields, depending on the fork of the block
 
         let block_hash_query_idx = self.mpt.keccak.keccak_var_len(
             ctx,
@@ -218,7 +249,9 @@ impl<'v, F: Field> EthBlockHeaderChip<'v, F> for EthChip<'v, F> {
         let mut trace = self.mpt.rlp.decompose_rlp_array_phase1(ctx, witness.rlp_witness, true);
         let block_hash = self.keccak().var_len_rlcs[witness.block_hash_query_idx].1.clone();
 
-        // Base fee per unit gas only after London
+        // Base fee per unit gas only after London, the fields after it from Shanghai to Prague
+        let [withdrawals_root, blob_gas_used, excess_blob_gas, parent_beacon_block_root, requests_hash]: [RlpFieldTrace<F>; 5] =
+            trace.field_trace.split_off(16).try_into().unwrap();
         let basefee = trace.field_trace.pop();
         let [parent_hash, ommers_hash, beneficiary, state_root, transactions_root, receipts_root, logs_bloom, difficulty, number, gas_limit, gas_used, timestamp, extra_data, mix_hash, nonce]: [RlpFieldTrace<F>; 15] =
             trace.field_trace.try_into().unwrap();
@@ -240,6 +273,11 @@ impl<'v, F: Field> EthBlockHeaderChip<'v, F> for EthChip<'v, F> {
             mix_hash,
             nonce,
             basefee,
+            withdrawals_root,
+            blob_gas_used,
+            excess_blob_gas,
+            parent_beacon_block_root,
+            requests_hash,
             block_hash,
             len_trace: trace.len_trace,
         }
@@ -377,7 +415,50 @@ pub fn get_boundary_block_data<'v, F: Field + PrimeField>(
     (prev_block_hash, end_block_hash, block_numbers)
 }
 
-#[derive(Debug, Clone)]
+/// Decomposes the RLP encoded `block_header` (not padded) and constrains that its keccak hash equals `block_hash`,
+/// where `block_hash` is H256 represented as two u128 in big endian.
+///
+/// Returns the block header witness together with the block number as a u32.
+///
+/// This MUST be done in `FirstPhase`. The witness must be finalized with `decompose_block_header_phase1` in `SecondPhase`.
+pub fn decompose_block_header_with_hash_phase0<'v, F: Field>(
+    chip: &mut EthChip<'v, F>,
+    ctx: &mut Context<'v, F>,
+    mut block_header: Vec<u8>,
+    block_hash: &AssignedH256<'v, F>,
+    network: Network,
+) -> (EthBlockHeaderTraceWitness<'v, F>, AssignedValue<'v, F>) {
+    let block_hash_bytes0 =
+        block_hash.iter().map(|u128| uint_to_bytes_be(ctx, chip.range(), u128, 16)).concat();
+    let max_len = match network {
+        Network::Goerli => GOERLI_BLOCK_HEADER_RLP_MAX_BYTES,
+        Network::Mainnet => MAINNET_BLOCK_HEADER_RLP_MAX_BYTES,
+    };
+    block_header.resize(max_len, 0);
+    let block_witness = chip.decompose_block_header_phase0(ctx, &block_header, network);
+
+    let block_hash_bytes1 =
+        &chip.keccak().var_len_queries[block_witness.block_hash_query_idx].output_assigned;
+    for (byte0, byte1) in block_hash_bytes0.iter().zip(block_hash_bytes1.iter()) {
+        ctx.constrain_equal(byte0, byte1);
+    }
+
+    // compute block number from big-endian bytes
+    let block_num_bytes = &block_witness.rlp_witness.field_witness[8].field_cells;
+    let block_num_len = &block_witness.rlp_witness.field_witness[8].field_len;
+    let block_number = bytes_be_var_to_fixed(
+        ctx,
+        chip.gate(),
+        block_num_bytes,
+        block_num_len,
+        BLOCK_NUMBER_MAX_BYTES,
+    );
+    let block_number = bytes_be_to_uint(ctx, chip.gate(), &block_number, BLOCK_NUMBER_MAX_BYTES);
+
+    (block_witness, block_number)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct EthBlockHeaderChainInstance {
     pub prev_hash: H256,
     pub end_hash: H256,
@@ -436,6 +517,10 @@ pub struct EthBlockHeaderChainCircuit<F> {
     pub instance: EthBlockHeaderChainInstance,
     max_depth: usize,
     network: Network,
+    /// (block index, field) of the header fields exposed after `instance`, each as H256 hi-lo.
+    /// Only the header chain instance is read by the aggregation circuits, so this should be empty
+    /// for header chain snarks that are aggregated.
+    field_queries: Vec<(usize, BlockHeaderField)>,
     _marker: PhantomData<F>,
 }
 
@@ -456,6 +541,7 @@ impl<F: Field + PrimeField> Circuit<F> for EthBlockHeaderChainCircuit<F> {
             },
             max_depth: self.max_depth,
             network: self.network,
+            field_queries: self.field_queries.clone(),
             _marker: PhantomData,
         }
     }
@@ -544,6 +630,22 @@ impl<F: Field + PrimeField> Circuit<F> for EthBlockHeaderChainCircuit<F> {
                         })
                         .collect_vec();
 
+                    // the queried blocks must be in the chain, not padding
+                    let num_blocks_bits = bit_length(1 << self.max_depth);
+                    let fields = self
+                        .field_queries
+                        .iter()
+                        .flat_map(|(block_idx, field)| {
+                            chip.range().check_less_than(
+                                ctx,
+                                Constant(F::from(*block_idx as u64)),
+                                Existing(&num_blocks),
+                                num_blocks_bits,
+                            );
+                            block_chain_witness[*block_idx].get_h256(ctx, chip.gate(), *field)
+                        })
+                        .collect_vec();
+
                     chip.assign_phase0(ctx);
                     ctx.next_phase();
 
@@ -579,6 +681,7 @@ impl<F: Field + PrimeField> Circuit<F> for EthBlockHeaderChainCircuit<F> {
                                 &block_numbers,
                             ])
                             .chain(mountain_range.iter())
+                            .chain(fields.iter())
                             .map(|assigned| assigned.cell())
                             .cloned(),
                     );
@@ -623,6 +726,81 @@ impl<F: Field> EthBlockHeaderChainCircuit<F> {
             block_rlp.resize(header_rlp_max_bytes, 0u8);
         }
 
-        Self { inputs: block_rlps, num_blocks, instance, max_depth, network, _marker: PhantomData }
+        Self {
+            inputs: block_rlps,
+            num_blocks,
+            instance,
+            max_depth,
+            network,
+            field_queries: vec![],
+            _marker: PhantomData,
+        }
+    }
+
+    /// The circuit for the chain of RLP encoded block headers `headers`, with the instance computed from the
+    /// headers. The chain is padded to `2^max_depth` headers with copies of the first header.
+    pub fn from_headers(mut headers: Vec<Vec<u8>>, network: Network, max_depth: usize) -> Self {
+        let num_blocks = headers.len();
+        assert!(num_blocks > 0 && num_blocks <= 1 << max_depth, "wrong number of headers");
+        let block_hashes = headers.iter().map(|header| H256(keccak256(header))).collect_vec();
+        let number = |header: &[u8]| {
+            get_header_field_h256(header, BlockHeaderField::Number).to_low_u64_be() as u32
+        };
+        let instance = EthBlockHeaderChainInstance::new(
+            get_header_field_h256(&headers[0], BlockHeaderField::ParentHash),
+            block_hashes[num_blocks - 1],
+            number(&headers[0]),
+            number(&headers[num_blocks - 1]),
+            get_merkle_mountain_range(&block_hashes, max_depth),
+        );
+        let header_rlp_max_bytes = match network {
+            Network::Mainnet => MAINNET_BLOCK_HEADER_RLP_MAX_BYTES,
+            Network::Goerli => GOERLI_BLOCK_HEADER_RLP_MAX_BYTES,
+        };
+        headers.resize(1 << max_depth, headers[0].clone());
+        for header in headers.iter_mut() {
+            assert!(header.len() <= header_rlp_max_bytes, "block header is too long");
+            header.resize(header_rlp_max_bytes, 0);
+        }
+
+        Self {
+            inputs: headers,
+            num_blocks: num_blocks as u32,
+            instance,
+            max_depth,
+            network,
+            field_queries: vec![],
+            _marker: PhantomData,
+        }
+    }
+
+    /// Also exposes `field` of the block at index `block_idx` of the chain for each of `field_queries`,
+    /// after the header chain instance. Each block must be in the chain and each field must fit in
+    /// 32 bytes.
+    pub fn with_field_queries(mut self, field_queries: Vec<(usize, BlockHeaderField)>) -> Self {
+        for (block_idx, field) in &field_queries {
+            assert!(*block_idx < self.num_blocks as usize, "block {block_idx} is not in the chain");
+            assert!(field.is_h256(self.network), "{field:?} does not fit in 32 bytes");
+        }
+        self.field_queries = field_queries;
+        self
+    }
+
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    pub fn field_queries(&self) -> &[(usize, BlockHeaderField)] {
+        &self.field_queries
+    }
+
+    /// The queried header fields, each as H256 hi-lo, see [`Self::with_field_queries`]
+    pub fn field_instance(&self) -> Vec<F> {
+        self.field_queries
+            .iter()
+            .flat_map(|(block_idx, field)| {
+                encode_h256_to_field::<F>(&get_header_field_h256(&self.inputs[*block_idx], *field))
+            })
+            .collect()
     }
 }
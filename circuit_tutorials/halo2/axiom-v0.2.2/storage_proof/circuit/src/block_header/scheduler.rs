@@ -0,0 +1,314 @@
+//! Proves the full aggregation tree of a header chain [`Task`], with independent tasks proved concurrently.
+//!
+//! The dependency tree is built up front and split into layers: every task in a layer has the same
+//! [`CircuitType`] and only depends on tasks in earlier layers. Circuit configurations are passed through
+//! environmental variables (see [`Sequencer::get_params`]), so only tasks within the same layer are proved
+//! at the same time.
+//!
+//! Progress is recorded in a [`Journal`] after every state change, so an interrupted run can continue from
+//! where it stopped with [`Sequencer::resume`].
+use super::{
+    helpers::{CircuitType, Sequencer, Task},
+    journal::{Journal, TaskState},
+};
+use crate::Network;
+use snark_verifier_sdk::Snark;
+use std::{
+    any::Any,
+    cmp::{max, min},
+    collections::{HashSet, VecDeque},
+    fs, io,
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Rough estimate of the peak memory, in bytes per row, used to prove one circuit
+pub const ESTIMATED_PROOF_BYTES_PER_ROW: u64 = 1 << 13;
+
+#[derive(Clone, Copy, Debug)]
+pub struct SchedulerConfig {
+    /// Maximum number of proofs generated at the same time
+    pub num_workers: usize,
+    /// If set, fewer proofs of a circuit with `2^k` rows are generated at the same time so that their estimated
+    /// memory, `ESTIMATED_PROOF_BYTES_PER_ROW << k` each, fits within this many bytes. At least one proof is always run.
+    pub memory_budget: Option<u64>,
+    /// Called whenever a task is skipped, started or finished
+    pub on_progress: fn(&TaskProgress),
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        let num_workers = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        Self { num_workers, memory_budget: None, on_progress: print_progress }
+    }
+}
+
+impl SchedulerConfig {
+    /// The number of proofs of a circuit with `2^k` rows to generate at the same time
+    pub fn max_concurrency(&self, k: u32) -> usize {
+        let num_workers = max(self.num_workers, 1);
+        match self.memory_budget {
+            Some(budget) => {
+                let per_proof = ESTIMATED_PROOF_BYTES_PER_ROW << k;
+                min(num_workers, max((budget / per_proof) as usize, 1))
+            }
+            None => num_workers,
+        }
+    }
+
+    /// Whether one proof of a circuit with `2^k` rows fits within the memory budget, if there is one
+    pub fn fits_in_memory(&self, k: u32) -> bool {
+        self.memory_budget.map_or(true, |budget| ESTIMATED_PROOF_BYTES_PER_ROW << k <= budget)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum TaskStatus {
+    /// The snark was already on disk
+    Cached,
+    /// The task was interrupted or had failed in a previous run, as recorded in the journal, and is queued again
+    Retrying(String),
+    Started,
+    Finished(Duration),
+}
+
+#[derive(Clone, Debug)]
+pub struct TaskProgress {
+    pub task: Task,
+    pub status: TaskStatus,
+    /// Number of tasks cached or finished so far, including this one
+    pub done: usize,
+    pub total: usize,
+}
+
+pub fn print_progress(progress: &TaskProgress) {
+    let TaskProgress { task, status, done, total } = progress;
+    let Task { start, end, circuit_type } = task;
+    let CircuitType { depth, initial_depth, finality, .. } = circuit_type;
+    let name = format!("[{start:06x}, {end:06x}] depth {depth}/{initial_depth} {finality:?}");
+    match status {
+        TaskStatus::Cached => println!("[{done}/{total}] {name}: cached"),
+        TaskStatus::Retrying(reason) => println!("[{done}/{total}] {name}: retrying, {reason}"),
+        TaskStatus::Started => println!("[{done}/{total}] {name}: proving"),
+        TaskStatus::Finished(time) => {
+            println!("[{done}/{total}] {name}: done in {:.2}s", time.as_secs_f64())
+        }
+    }
+}
+
+/// The dependency tree of a task, without duplicate tasks.
+#[derive(Clone, Debug)]
+pub struct TaskDag {
+    /// All tasks in `layers[i]` have the same circuit type and only depend on tasks in `layers[..i]`.
+    /// The last layer is the root task.
+    pub layers: Vec<Vec<Task>>,
+}
+
+impl TaskDag {
+    pub fn new(root: Task) -> Self {
+        let mut layers = vec![vec![root]];
+        loop {
+            let mut seen = HashSet::new();
+            let next = layers
+                .last()
+                .unwrap()
+                .iter()
+                .flat_map(|task| task.dependencies())
+                .filter(|task| seen.insert(*task))
+                .collect::<Vec<_>>();
+            if next.is_empty() {
+                break;
+            }
+            layers.push(next);
+        }
+        layers.reverse();
+        Self { layers }
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.iter().map(|layer| layer.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the tasks of each layer that still have to be proved, and records in `journal` which tasks are
+    /// pending and which are done because their snark is on disk. `on_status` is called with
+    /// `TaskStatus::Cached` for every done task, and with `TaskStatus::Retrying` for every queued task that was
+    /// interrupted or had failed.
+    ///
+    /// A task left in `Proving` may have been interrupted right after its snark was written, so its snark is
+    /// removed and it is proved again to be safe.
+    pub fn queue(
+        self,
+        journal: &mut Journal,
+        network: Network,
+        mut on_status: impl FnMut(Task, TaskStatus),
+    ) -> Vec<VecDeque<Task>> {
+        let mut layers = Vec::with_capacity(self.layers.len());
+        for layer in self.layers {
+            let mut queue = VecDeque::with_capacity(layer.len());
+            for task in layer {
+                let snark_name = task.snark_name(network);
+                let retry_reason = match journal.state(&task, network) {
+                    Some(TaskState::Proving) => {
+                        let _ = fs::remove_file(&snark_name);
+                        Some("interrupted".to_string())
+                    }
+                    Some(TaskState::Failed(error)) => Some(format!("failed with {error}")),
+                    _ => None,
+                };
+                if Path::new(&snark_name).exists() {
+                    journal.set_state(&task, network, TaskState::Done);
+                    on_status(task, TaskStatus::Cached);
+                } else {
+                    journal.set_state(&task, network, TaskState::Pending);
+                    if let Some(reason) = retry_reason {
+                        on_status(task, TaskStatus::Retrying(reason));
+                    }
+                    queue.push_back(task);
+                }
+            }
+            layers.push(queue);
+        }
+        layers
+    }
+}
+
+impl Sequencer {
+    /// Same as [`Sequencer::get_snark`], except the whole dependency tree of `task` is built first and the
+    /// independent tasks of each layer are proved concurrently, as configured by `config`.
+    ///
+    /// The state of every task is recorded in the journal at [`Journal::path`]. If proving a task panics,
+    /// it is marked as failed, no further tasks are started, and the panic is propagated.
+    pub fn get_snark_parallel(&mut self, task: Task, config: &SchedulerConfig) -> Snark {
+        let network = self.network;
+        let journal_path = Journal::path(network);
+        let dag = TaskDag::new(task);
+        let total = dag.len();
+        let done = AtomicUsize::new(0);
+        let report = |task: Task, status: TaskStatus| {
+            let done = match status {
+                TaskStatus::Started | TaskStatus::Retrying(_) => done.load(Ordering::SeqCst),
+                TaskStatus::Cached | TaskStatus::Finished(_) => {
+                    done.fetch_add(1, Ordering::SeqCst) + 1
+                }
+            };
+            (config.on_progress)(&TaskProgress { task, status, done, total });
+        };
+
+        let mut journal = Journal::read_for(&journal_path, task);
+        let layers = dag.queue(&mut journal, network, &report);
+        journal.write(&journal_path).expect("write journal should not fail");
+
+        let journal = Mutex::new(journal);
+        let set_state = |task: &Task, state: TaskState| {
+            let mut journal = journal.lock().unwrap();
+            journal.set_state(task, network, state);
+            journal.write(&journal_path).expect("write journal should not fail");
+        };
+        for queue in layers {
+            let Some(first) = queue.front() else { continue };
+            // sets the environmental vars shared by all tasks in this layer
+            let k = self.get_params(first.circuit_type);
+            let num_workers = min(config.max_concurrency(k), queue.len());
+
+            let sequencer = &*self;
+            let queue = Mutex::new(queue);
+            thread::scope(|s| {
+                for _ in 0..num_workers {
+                    s.spawn(|| loop {
+                        let task = queue.lock().unwrap().pop_front();
+                        let Some(task) = task else { break };
+                        set_state(&task, TaskState::Proving);
+                        report(task, TaskStatus::Started);
+                        let timer = Instant::now();
+                        match panic::catch_unwind(AssertUnwindSafe(|| sequencer.prove_task(task))) {
+                            Ok(_) => {
+                                set_state(&task, TaskState::Done);
+                                report(task, TaskStatus::Finished(timer.elapsed()));
+                            }
+                            Err(payload) => {
+                                queue.lock().unwrap().clear();
+                                set_state(&task, TaskState::Failed(panic_message(&*payload)));
+                                panic::resume_unwind(payload);
+                            }
+                        }
+                    });
+                }
+            });
+        }
+        task.read_snark(network).expect("snark of root task should be on disk")
+    }
+
+    /// Continues the run recorded in the journal at [`Journal::path`], skipping every task whose snark is
+    /// already on disk. Tasks that were interrupted or had failed are proved again, and reported to
+    /// `config.on_progress` with `TaskStatus::Retrying`.
+    ///
+    /// For an `Evm` root task this produces the snark of the root task; call `get_calldata` afterwards to
+    /// generate the EVM proof.
+    pub fn resume(&mut self, config: &SchedulerConfig) -> io::Result<Snark> {
+        let journal = Journal::read(Journal::path(self.network))?;
+        Ok(self.get_snark_parallel(journal.root, config))
+    }
+
+    /// Chooses the largest aggregation fan-in `2^m`, with `m <= max_fan_in_log2`, for which every circuit in the
+    /// aggregation tree of `circuit_type` has a configuration, see [`Sequencer::config_path`], and fits within
+    /// the memory budget of `config`. A larger fan-in means fewer aggregation layers, but bigger aggregation
+    /// circuits.
+    ///
+    /// Falls back to a fan-in of 2.
+    pub fn choose_fan_in(
+        &self,
+        circuit_type: CircuitType,
+        max_fan_in_log2: usize,
+        config: &SchedulerConfig,
+    ) -> CircuitType {
+        let fits = |mut circuit_type: CircuitType| loop {
+            if circuit_type.depth == circuit_type.initial_depth {
+                return true;
+            }
+            let config_path = self.config_path(circuit_type);
+            if !Path::new(&config_path).exists()
+                || !config.fits_in_memory(self.get_degree(circuit_type))
+            {
+                return false;
+            }
+            circuit_type = circuit_type.prev();
+        };
+        (2..=max_fan_in_log2)
+            .rev()
+            .map(|fan_in_log2| circuit_type.with_fan_in_log2(fan_in_log2))
+            .find(|circuit_type| fits(*circuit_type))
+            .unwrap_or_else(|| circuit_type.with_fan_in_log2(1))
+    }
+
+    /// Proves `task`, assuming the snarks of all its dependencies are on disk.
+    fn prove_task(&self, task: Task) -> Snark {
+        let snarks = task
+            .dependencies()
+            .into_iter()
+            .map(|dep| dep.read_snark(self.network).expect("dependency should be proved first"))
+            .collect();
+        let circuit = self.build_circuit(task, snarks);
+        self.load_pk(task.circuit_type, &circuit);
+        self.prove(task, circuit)
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
@@ -0,0 +1,317 @@
+//! Gas statistics over a header chain: the total `gasUsed` and the minimum, maximum and average `baseFeePerGas`
+//! of the blocks in the chain, skipping the padding headers after the last block.
+use super::{
+    fields::{get_header_field_h256, BlockHeaderField},
+    get_boundary_block_data, EthBlockHeaderChainCircuit, EthBlockHeaderChainInstance,
+    EthBlockHeaderChip, EthBlockHeaderTraceWitness,
+};
+use crate::{util::EthConfigParams, EthChip, EthConfig, Field, Network};
+#[cfg(feature = "display")]
+use ark_std::{end_timer, start_timer};
+#[cfg(feature = "providers")]
+use ethers_providers::{Http, Provider};
+use halo2_base::{
+    gates::{range::RangeConfig, GateInstructions, RangeInstructions},
+    halo2_proofs::{
+        circuit::{Layouter, SimpleFloorPlanner, Value},
+        plonk::{Circuit, ConstraintSystem, Error},
+    },
+    utils::{bit_length, PrimeField},
+    AssignedValue, Context, ContextParams,
+    QuantumCell::{Constant, Existing},
+    SKIP_FIRST_PASS,
+};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use snark_verifier_sdk::CircuitExt;
+use std::marker::PhantomData;
+
+/// `baseFeePerGas` is at most 6 bytes
+const BASE_FEE_BITS: usize = 48;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EthBlockHeaderStats {
+    pub total_gas_used: u64,
+    pub min_base_fee: u64,
+    pub max_base_fee: u64,
+    /// Rounded down
+    pub avg_base_fee: u64,
+}
+
+impl EthBlockHeaderStats {
+    /// Statistics of the RLP encoded `headers`, which may be zero padded
+    pub fn new(headers: &[Vec<u8>]) -> Self {
+        assert!(!headers.is_empty());
+        let [gas_used, base_fees] = [BlockHeaderField::GasUsed, BlockHeaderField::BaseFeePerGas]
+            .map(|field| {
+                headers
+                    .iter()
+                    .map(|header| get_header_field_h256(header, field).to_low_u64_be())
+                    .collect_vec()
+            });
+        Self {
+            total_gas_used: gas_used.iter().sum(),
+            min_base_fee: *base_fees.iter().min().unwrap(),
+            max_base_fee: *base_fees.iter().max().unwrap(),
+            avg_base_fee: base_fees.iter().sum::<u64>() / base_fees.len() as u64,
+        }
+    }
+
+    pub fn to_instance<F: Field>(&self) -> Vec<F> {
+        [self.total_gas_used, self.min_base_fee, self.max_base_fee, self.avg_base_fee]
+            .map(F::from)
+            .to_vec()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AssignedEthBlockHeaderStats<'v, F: Field> {
+    pub total_gas_used: AssignedValue<'v, F>,
+    pub min_base_fee: AssignedValue<'v, F>,
+    pub max_base_fee: AssignedValue<'v, F>,
+    pub avg_base_fee: AssignedValue<'v, F>,
+}
+
+/// Computes the statistics of the first `num_blocks` headers of `chain`, ignoring the rest.
+///
+/// Assumes `1 <= num_blocks <= chain.len()`. Blocks before London have base fee 0.
+pub fn get_block_header_stats<'v, F: Field>(
+    ctx: &mut Context<'v, F>,
+    range: &RangeConfig<F>,
+    chain: &[EthBlockHeaderTraceWitness<'v, F>],
+    num_blocks: &AssignedValue<'v, F>,
+) -> AssignedEthBlockHeaderStats<'v, F> {
+    let gate = &range.gate;
+    let num_blocks_bits = bit_length(chain.len() as u64);
+    // both fields are less than 16 bytes, so only the lo limb is nonzero
+    let [gas_used, base_fees] =
+        [BlockHeaderField::GasUsed, BlockHeaderField::BaseFeePerGas].map(|field| {
+            chain.iter().map(|header| header.get_h256(ctx, gate, field)[1].clone()).collect_vec()
+        });
+    // the first block is always in the chain
+    let in_chain = (1..chain.len())
+        .map(|idx| {
+            range.is_less_than(
+                ctx,
+                Constant(F::from(idx as u64)),
+                Existing(num_blocks),
+                num_blocks_bits,
+            )
+        })
+        .collect_vec();
+
+    let total_gas_used = gate.inner_product(
+        ctx,
+        gas_used.iter().map(Existing),
+        [Constant(F::one())].into_iter().chain(in_chain.iter().map(Existing)),
+    );
+    let base_fee_sum = gate.inner_product(
+        ctx,
+        base_fees.iter().map(Existing),
+        [Constant(F::one())].into_iter().chain(in_chain.iter().map(Existing)),
+    );
+    let mut min_base_fee = base_fees[0].clone();
+    let mut max_base_fee = base_fees[0].clone();
+    for (base_fee, in_chain) in base_fees[1..].iter().zip(in_chain.iter()) {
+        let is_less =
+            range.is_less_than(ctx, Existing(base_fee), Existing(&min_base_fee), BASE_FEE_BITS);
+        let is_less = gate.and(ctx, Existing(&is_less), Existing(in_chain));
+        min_base_fee =
+            gate.select(ctx, Existing(base_fee), Existing(&min_base_fee), Existing(&is_less));
+        let is_more =
+            range.is_less_than(ctx, Existing(&max_base_fee), Existing(base_fee), BASE_FEE_BITS);
+        let is_more = gate.and(ctx, Existing(&is_more), Existing(in_chain));
+        max_base_fee =
+            gate.select(ctx, Existing(base_fee), Existing(&max_base_fee), Existing(&is_more));
+    }
+
+    // avg_base_fee * num_blocks + rem = base_fee_sum with rem < num_blocks
+    let (avg, rem) = base_fee_sum
+        .value()
+        .zip(num_blocks.value())
+        .map(|(sum, num_blocks)| {
+            let (sum, num_blocks) = (sum.get_lower_128(), num_blocks.get_lower_128());
+            (F::from_u128(sum / num_blocks), F::from_u128(sum % num_blocks))
+        })
+        .unzip();
+    let avg_base_fee = gate.load_witness(ctx, avg);
+    let rem = gate.load_witness(ctx, rem);
+    range.range_check(ctx, &avg_base_fee, BASE_FEE_BITS);
+    range.range_check(ctx, &rem, num_blocks_bits);
+    range.check_less_than(ctx, Existing(&rem), Existing(num_blocks), num_blocks_bits);
+    let sum = gate.mul_add(ctx, Existing(&avg_base_fee), Existing(num_blocks), Existing(&rem));
+    ctx.constrain_equal(&sum, &base_fee_sum);
+
+    AssignedEthBlockHeaderStats { total_gas_used, min_base_fee, max_base_fee, avg_base_fee }
+}
+
+/// Proves the gas statistics of a header chain, see [`EthBlockHeaderStats`].
+///
+/// Public instances: prevHash, endHash, startBlockNumber || endBlockNumber as in [`EthBlockHeaderChainInstance`],
+/// then totalGasUsed, minBaseFee, maxBaseFee, avgBaseFee.
+#[derive(Clone, Debug)]
+pub struct EthBlockHeaderStatsCircuit<F> {
+    inputs: Vec<Vec<u8>>,
+    num_blocks: u32,
+    pub instance: EthBlockHeaderChainInstance,
+    pub stats: EthBlockHeaderStats,
+    network: Network,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> EthBlockHeaderStatsCircuit<F> {
+    /// The statistics of the blocks of the header chain `chain`
+    pub fn from_chain(chain: EthBlockHeaderChainCircuit<F>) -> Self {
+        let stats = EthBlockHeaderStats::new(&chain.inputs[..chain.num_blocks as usize]);
+        Self {
+            inputs: chain.inputs,
+            num_blocks: chain.num_blocks,
+            instance: chain.instance,
+            stats,
+            network: chain.network,
+            _marker: PhantomData,
+        }
+    }
+
+    #[cfg(feature = "providers")]
+    pub fn from_provider(
+        provider: &Provider<Http>,
+        network: Network,
+        start_block_number: u32,
+        num_blocks: u32,
+        max_depth: usize,
+    ) -> Self {
+        Self::from_chain(EthBlockHeaderChainCircuit::from_provider(
+            provider,
+            network,
+            start_block_number,
+            num_blocks,
+            max_depth,
+        ))
+    }
+
+    pub fn instance(&self) -> Vec<F> {
+        let chain_instance = self.instance.to_instance::<F>();
+        [&chain_instance[..5], &self.stats.to_instance()].concat()
+    }
+}
+
+impl<F: Field + PrimeField> Circuit<F> for EthBlockHeaderStatsCircuit<F> {
+    type Config = EthConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        self.clone()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        let params = EthConfigParams::get_header();
+        EthConfig::configure(meta, params, 0)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        #[cfg(feature = "display")]
+        let witness_gen = start_timer!(|| "synthesize");
+
+        let gamma = layouter.get_challenge(config.rlc().gamma);
+        config.range().load_lookup_table(&mut layouter).expect("load range lookup table");
+        config.keccak().load_aux_tables(&mut layouter).expect("load keccak lookup tables");
+
+        let mut first_pass = SKIP_FIRST_PASS;
+        let mut instance = vec![];
+        layouter
+            .assign_region(
+                || "Eth block header chain gas statistics",
+                |region| {
+                    if first_pass {
+                        first_pass = false;
+                        return Ok(());
+                    }
+                    let mut chip = EthChip::new(config.clone(), gamma);
+                    let mut aux = Context::new(
+                        region,
+                        ContextParams {
+                            max_rows: chip.gate().max_rows,
+                            num_context_ids: 2,
+                            fixed_columns: chip.gate().constants.clone(),
+                        },
+                    );
+                    let ctx = &mut aux;
+
+                    // ================= FIRST PHASE ================
+                    let num_blocks = chip
+                        .gate()
+                        .load_witness(ctx, Value::known(F::from(self.num_blocks as u64)));
+                    let num_blocks_minus_one =
+                        chip.gate().sub(ctx, Existing(&num_blocks), Constant(F::one()));
+                    // `1 <= num_blocks <= 2^max_depth` follows from the public start and end block numbers
+                    let block_chain_witness =
+                        chip.decompose_block_header_chain_phase0(ctx, &self.inputs, self.network);
+                    let stats = get_block_header_stats(
+                        ctx,
+                        chip.range(),
+                        &block_chain_witness,
+                        &num_blocks,
+                    );
+                    chip.assign_phase0(ctx);
+                    ctx.next_phase();
+
+                    // ================= SECOND PHASE ================
+                    chip.get_challenge(ctx);
+                    chip.keccak_assign_phase1(ctx);
+
+                    let block_chain_trace = chip.decompose_block_header_chain_phase1(
+                        ctx,
+                        block_chain_witness,
+                        Some(&num_blocks_minus_one),
+                    );
+                    let (prev_block_hash, end_block_hash, block_numbers) = get_boundary_block_data(
+                        ctx,
+                        chip.gate(),
+                        &block_chain_trace,
+                        &num_blocks_minus_one,
+                    );
+                    chip.range().finalize(ctx);
+
+                    instance.extend(
+                        prev_block_hash
+                            .iter()
+                            .chain(end_block_hash.iter())
+                            .chain([
+                                &block_numbers,
+                                &stats.total_gas_used,
+                                &stats.min_base_fee,
+                                &stats.max_base_fee,
+                                &stats.avg_base_fee,
+                            ])
+                            .map(|acell| acell.cell().clone()),
+                    );
+
+                    #[cfg(feature = "display")]
+                    ctx.print_stats(&["Range", "RLC"]);
+                    Ok(())
+                },
+            )
+            .unwrap();
+        for (i, cell) in instance.into_iter().enumerate() {
+            layouter.constrain_instance(cell, config.instance, i);
+        }
+        #[cfg(feature = "display")]
+        end_timer!(witness_gen);
+        Ok(())
+    }
+}
+
+impl<F: Field + PrimeField> CircuitExt<F> for EthBlockHeaderStatsCircuit<F> {
+    fn num_instance(&self) -> Vec<usize> {
+        vec![9]
+    }
+
+    fn instances(&self) -> Vec<Vec<F>> {
+        vec![self.instance()]
+    }
+}
@@ -237,6 +237,7 @@ fn get_default_goerli_header_chain_circuit() -> EthBlockHeaderChainCircuit<Fr> {
         },
         max_depth,
         network,
+        field_queries: vec![],
         _marker: PhantomData,
     }
 }
@@ -253,6 +254,53 @@ pub fn test_multi_goerli_header_mock() {
     MockProver::run(k, &circuit, vec![instance]).unwrap().assert_satisfied();
 }
 
+#[test]
+pub fn test_multi_goerli_header_fields_mock() {
+    use fields::{get_header_field_h256, BlockHeaderField::*};
+
+    set_var("BLOCK_HEADER_CONFIG", "configs/tests/multi_block.json");
+    let config = EthConfigParams::get_header();
+    let k = config.degree;
+
+    let circuit = get_default_goerli_header_chain_circuit().with_field_queries(vec![
+        (0, Timestamp),
+        (6, BaseFeePerGas),
+        (6, GasUsed),
+        (3, MixHash),
+        (0, Number),
+    ]);
+    let number = get_header_field_h256(&circuit.inputs[0], Number);
+    assert_eq!(number.to_low_u64_be(), circuit.instance.start_block_number as u64);
+    let instance = [circuit.instance.to_instance(), circuit.field_instance()].concat();
+    assert_eq!(instance.len(), EthBlockHeaderChainCircuit::<Fr>::get_num_instance(3) + 10);
+
+    MockProver::run(k, &circuit, vec![instance]).unwrap().assert_satisfied();
+}
+
+#[test]
+pub fn test_multi_goerli_header_stats_mock() {
+    use stats::{EthBlockHeaderStats, EthBlockHeaderStatsCircuit};
+
+    set_var("BLOCK_HEADER_CONFIG", "configs/tests/multi_block.json");
+    let config = EthConfigParams::get_header();
+    let k = config.degree;
+
+    // the chain has 7 blocks padded with a copy of the first block, which must not be counted
+    let circuit =
+        EthBlockHeaderStatsCircuit::<Fr>::from_chain(get_default_goerli_header_chain_circuit());
+    assert_eq!(circuit.stats, EthBlockHeaderStats::new(&circuit.inputs[..7]));
+    let first_gas_used =
+        fields::get_header_field_h256(&circuit.inputs[0], fields::BlockHeaderField::GasUsed);
+    assert_eq!(
+        EthBlockHeaderStats::new(&circuit.inputs).total_gas_used,
+        circuit.stats.total_gas_used + first_gas_used.to_low_u64_be()
+    );
+    assert!(circuit.stats.min_base_fee <= circuit.stats.avg_base_fee);
+    assert!(circuit.stats.avg_base_fee <= circuit.stats.max_base_fee);
+
+    MockProver::run(k, &circuit, vec![circuit.instance()]).unwrap().assert_satisfied();
+}
+
 #[test]
 pub fn test_multi_goerli_header_prover() {
     set_var("BLOCK_HEADER_CONFIG", "configs/tests/multi_block.json");
@@ -304,7 +352,345 @@ pub fn test_multi_goerli_header_prover() {
 #[cfg(all(feature = "aggregation", feature = "providers"))]
 mod aggregation {
     use super::*;
-    use crate::block_header::helpers::{CircuitType, Finality, Sequencer, Task};
+    use crate::block_header::{
+        helpers::{CircuitType, Finality, Sequencer, Task},
+        journal::{Journal, TaskState},
+        scheduler::{SchedulerConfig, TaskDag},
+    };
+    use crate::util::write_atomic;
+    use halo2_base::gates::range::{RangeConfig, RangeStrategy};
+    use snark_verifier_sdk::{gen_pk, CircuitExt};
+    use std::{
+        path::Path,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        thread,
+        time::{Duration, Instant},
+    };
+
+    #[test]
+    fn test_task_dag_layers() {
+        let task = Task::new(0x765fb3, 0x765fb3 + 11, CircuitType::new(5, 3, Finality::Evm(1)));
+        let dag = TaskDag::new(task);
+        let types = dag.layers.iter().map(|layer| layer[0].circuit_type).collect::<Vec<_>>();
+        assert_eq!(
+            types,
+            vec![
+                CircuitType::new(3, 3, Finality::None),
+                CircuitType::new(4, 3, Finality::None),
+                CircuitType::new(5, 3, Finality::Merkle),
+                CircuitType::new(5, 3, Finality::Evm(0)),
+                CircuitType::new(5, 3, Finality::Evm(1)),
+            ]
+        );
+        // 12 blocks: two initial snarks and one at each later layer
+        let layer_lens = dag.layers.iter().map(|layer| layer.len()).collect::<Vec<_>>();
+        assert_eq!(layer_lens, [2, 1, 1, 1, 1]);
+        assert_eq!(dag.len(), 6);
+        for layer in &dag.layers {
+            assert!(layer.iter().all(|task| task.circuit_type == layer[0].circuit_type));
+        }
+    }
+
+    #[test]
+    fn test_task_dependencies_fan_in() {
+        let circuit_type = CircuitType::new(8, 3, Finality::Merkle).with_fan_in_log2(2);
+        let task = Task::new(0x765fb3, 0x765fb3 + 99, circuit_type);
+        let deps = task.dependencies();
+        // 100 blocks in chains of 2^6 blocks
+        assert_eq!(deps.len(), 2);
+        assert_eq!(deps[1].start, 0x765fb3 + 64);
+        assert_eq!(deps[1].end, task.end);
+        assert_eq!(deps[0].circuit_type.fan_in_log2(), 2);
+        // 6 - 3 is not a multiple of 2, so the layer above the initial depth has fan-in 2
+        let deps = deps[0].dependencies();
+        assert_eq!(deps.len(), 4);
+        assert_eq!(deps[0].circuit_type.depth, 4);
+        assert_eq!(deps[0].circuit_type.fan_in_log2(), 1);
+        assert_eq!(deps[0].dependencies().len(), 2);
+
+        let dag = TaskDag::new(task);
+        let depths = dag.layers.iter().map(|layer| layer[0].circuit_type.depth).collect::<Vec<_>>();
+        assert_eq!(depths, [3, 4, 6, 8]);
+        // the snarks of circuits with fan-in 2 are shared with `fan_in_log2 = 1`
+        assert_eq!(
+            deps[0].snark_name(Network::Goerli),
+            Task::new(0x765fb3, 0x765fb3 + 15, CircuitType::new(4, 3, Finality::None))
+                .snark_name(Network::Goerli)
+        );
+        assert_eq!(
+            circuit_type.pkey_name(Network::Goerli),
+            "data/headers/goerli_8_3_fan4_final.pkey"
+        );
+    }
+
+    #[test]
+    fn test_circuit_type_default_fan_in() {
+        let circuit_type: CircuitType =
+            serde_json::from_str(r#"{"depth":5,"initial_depth":3,"finality":{"Evm":0}}"#).unwrap();
+        assert_eq!(circuit_type, CircuitType::new(5, 3, Finality::Evm(0)));
+    }
+
+    #[test]
+    fn test_scheduler_memory_budget() {
+        let config =
+            SchedulerConfig { num_workers: 8, memory_budget: Some(3 << 30), ..Default::default() };
+        // 2^18 rows * 2^13 bytes = 2GiB per proof
+        assert_eq!(config.max_concurrency(18), 1);
+        assert_eq!(config.max_concurrency(16), 6);
+        assert_eq!(config.max_concurrency(10), 8);
+        assert!(config.fits_in_memory(18));
+        assert!(!config.fits_in_memory(19));
+        assert!(SchedulerConfig::default().fits_in_memory(30));
+    }
+
+    #[test]
+    fn test_choose_fan_in_derived_config() {
+        use snark_verifier_sdk::halo2::aggregation::AggregationConfigParams;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut sequencer = Sequencer::with_provider(Network::Goerli, None);
+        sequencer.config_dir = dir.path().join("configs").to_str().unwrap().to_string();
+        sequencer.data_dir = dir.path().join("data").to_str().unwrap().to_string();
+        let circuit_type = CircuitType::new(9, 5, Finality::None);
+        let fan_in_4 = circuit_type.with_fan_in_log2(2);
+        assert_eq!((circuit_type.fan_in(), fan_in_4.fan_in()), (2, 4));
+        let config = SchedulerConfig { memory_budget: Some(3 << 30), ..Default::default() };
+        // without configurations for fan-in 2 there is nothing to derive from
+        assert_eq!(sequencer.choose_fan_in(circuit_type, 2, &config), circuit_type);
+
+        // fan-in 4 aggregates depth 7 into depth 9 and depth 5 into depth 7
+        std::fs::create_dir_all(&sequencer.config_dir).unwrap();
+        for name in ["goerli_9_5", "goerli_7_5"] {
+            let config = r#"{"strategy":"Simple","degree":17,"num_advice":4,"num_lookup_advice":1,"num_fixed":1,"lookup_bits":16,"limb_bits":88,"num_limbs":3}"#;
+            write_atomic(format!("{}/{name}.json", sequencer.config_dir), config).unwrap();
+        }
+        assert_eq!(sequencer.choose_fan_in(circuit_type, 2, &config), fan_in_4);
+        let derived_path = sequencer.config_path(fan_in_4);
+        assert_eq!(derived_path, format!("{}/goerli_9_5_fan4.json", sequencer.data_dir));
+        assert!(Path::new(&format!("{}/goerli_7_5_fan4.json", sequencer.data_dir)).exists());
+        let derived: AggregationConfigParams =
+            serde_json::from_reader(File::open(&derived_path).unwrap()).unwrap();
+        assert_eq!((derived.degree, derived.lookup_bits, derived.num_advice), (18, 17, 4));
+        // 2^18 rows do not fit in 1GiB
+        let small = SchedulerConfig { memory_budget: Some(1 << 30), ..Default::default() };
+        assert_eq!(sequencer.choose_fan_in(circuit_type, 2, &small), circuit_type);
+    }
+
+    const OVERLAP_DEGREE: usize = 9;
+
+    /// Exposes a zero instance. With `wait_for`, witness generation sets `started` and only finishes once
+    /// `wait_for` is set, so the proof can only complete if another proof runs at the same time.
+    #[derive(Clone, Default)]
+    struct OverlapTestCircuit {
+        started: Option<Arc<AtomicBool>>,
+        wait_for: Option<Arc<AtomicBool>>,
+    }
+
+    impl Circuit<Fr> for OverlapTestCircuit {
+        type Config = (RangeConfig<Fr>, Column<Instance>);
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self::default()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+            let mut range = RangeConfig::configure(
+                meta,
+                RangeStrategy::Vertical,
+                &[1],
+                &[1],
+                1,
+                OVERLAP_DEGREE - 1,
+                0,
+                OVERLAP_DEGREE,
+            );
+            range.gate.max_rows = (1 << OVERLAP_DEGREE) - meta.minimum_rows();
+            let instance = meta.instance_column();
+            meta.enable_equality(instance);
+            (range, instance)
+        }
+
+        fn synthesize(
+            &self,
+            (range, instance): Self::Config,
+            mut layouter: impl Layouter<Fr>,
+        ) -> Result<(), Error> {
+            range.load_lookup_table(&mut layouter).expect("load range lookup table");
+            if let (Some(started), Some(wait_for)) = (&self.started, &self.wait_for) {
+                started.store(true, Ordering::SeqCst);
+                let deadline = Instant::now() + Duration::from_secs(60);
+                while !wait_for.load(Ordering::SeqCst) {
+                    assert!(Instant::now() < deadline, "proofs did not overlap");
+                    thread::sleep(Duration::from_millis(10));
+                }
+            }
+            let mut first_pass = SKIP_FIRST_PASS;
+            let mut cell = None;
+            layouter.assign_region(
+                || "Overlap test",
+                |region| {
+                    if first_pass {
+                        first_pass = false;
+                        return Ok(());
+                    }
+                    let mut aux = Context::new(
+                        region,
+                        ContextParams {
+                            max_rows: range.gate.max_rows,
+                            num_context_ids: 1,
+                            fixed_columns: range.gate.constants.clone(),
+                        },
+                    );
+                    let ctx = &mut aux;
+                    let zero = range.gate.load_witness(ctx, Value::known(Fr::zero()));
+                    cell = Some(zero.cell().clone());
+                    range.finalize(ctx);
+                    Ok(())
+                },
+            )?;
+            layouter.constrain_instance(cell.unwrap(), instance, 0);
+            Ok(())
+        }
+    }
+
+    impl CircuitExt<Fr> for OverlapTestCircuit {
+        fn num_instance(&self) -> Vec<usize> {
+            vec![1]
+        }
+
+        fn instances(&self) -> Vec<Vec<Fr>> {
+            vec![vec![Fr::zero()]]
+        }
+    }
+
+    #[test]
+    fn test_prove_same_layer_overlap() {
+        let mut sequencer = Sequencer::with_provider(Network::Goerli, None);
+        let circuit_type = CircuitType::new(3, 3, Finality::None);
+        let params = gen_srs(OVERLAP_DEGREE as u32);
+        let pk = gen_pk(&params, &OverlapTestCircuit::default(), None);
+        sequencer.params.insert(OVERLAP_DEGREE as u32, params);
+        sequencer.params_k.insert(circuit_type, OVERLAP_DEGREE as u32);
+        sequencer.pkeys.write().unwrap().insert(circuit_type, Arc::new(pk));
+
+        let started = Arc::new(AtomicBool::new(false));
+        let second_done = Arc::new(AtomicBool::new(false));
+        let waiting = OverlapTestCircuit {
+            started: Some(Arc::clone(&started)),
+            wait_for: Some(Arc::clone(&second_done)),
+        };
+        let sequencer = &sequencer;
+        thread::scope(|s| {
+            let first = s.spawn(move || sequencer.prove_circuit(circuit_type, waiting));
+            while !started.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(10));
+            }
+            // as a worker loading the proving key of another task of the layer would
+            let pkeys = sequencer.pkeys.try_write().expect("proving keys are locked while proving");
+            drop(pkeys);
+            let snark = sequencer.prove_circuit(circuit_type, OverlapTestCircuit::default());
+            second_done.store(true, Ordering::SeqCst);
+            assert_eq!(snark.instances, [vec![Fr::zero()]]);
+            assert_eq!(first.join().unwrap().instances, [vec![Fr::zero()]]);
+        });
+    }
+
+    #[test]
+    fn test_write_atomic() {
+        let path = std::env::temp_dir().join("test_write_atomic.txt");
+        write_atomic(&path, b"first").unwrap();
+        write_atomic(&path, b"second").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"second");
+        assert!(!std::env::temp_dir().join("test_write_atomic.txt.tmp").exists());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_journal_round_trip() {
+        let root = Task::new(0x765fb3, 0x765fb3 + 11, CircuitType::new(4, 3, Finality::Merkle));
+        let mut journal = Journal::new(root);
+        let [first, second] = TaskDag::new(root).layers[0][..] else { panic!() };
+        journal.set_state(&first, Network::Goerli, TaskState::Done);
+        journal.set_state(&second, Network::Goerli, TaskState::Proving);
+        journal.set_state(&root, Network::Goerli, TaskState::Failed("out of memory".to_string()));
+
+        let path = std::env::temp_dir().join("test_journal_round_trip.json");
+        journal.write(&path).unwrap();
+        let read = Journal::read(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(read, journal);
+        assert_eq!(read.state(&first, Network::Goerli), Some(&TaskState::Done));
+        assert_eq!(read.state(&first, Network::Mainnet), None);
+        let unfinished = read.unfinished().map(|(name, _)| name.clone()).collect::<Vec<_>>();
+        assert_eq!(unfinished.len(), 2);
+        assert!(unfinished.contains(&second.snark_name(Network::Goerli)));
+        assert!(unfinished.contains(&root.snark_name(Network::Goerli)));
+    }
+
+    #[test]
+    fn test_journal_resume_queue() {
+        // a chain far beyond the goerli head, so no real snarks are touched
+        let root = Task::new(0xfff000, 0xfff000 + 11, CircuitType::new(5, 3, Finality::Merkle));
+        let dag = TaskDag::new(root);
+        let [cached, interrupted] = dag.layers[0][..] else { panic!() };
+        let lost = dag.layers[1][0];
+        let network = Network::Goerli;
+        let mut journal = Journal::new(root);
+        journal.set_state(&cached, network, TaskState::Done);
+        journal.set_state(&interrupted, network, TaskState::Proving);
+        // marked done, but the snark is no longer on disk
+        journal.set_state(&lost, network, TaskState::Done);
+        journal.set_state(&root, network, TaskState::Failed("out of memory".to_string()));
+        std::fs::create_dir_all("data/headers").unwrap();
+        for task in [cached, interrupted] {
+            std::fs::write(task.snark_name(network), b"snark").unwrap();
+        }
+        let path = std::env::temp_dir().join("test_journal_resume_queue.json");
+        journal.write(&path).unwrap();
+
+        // a journal for another root is not resumed
+        let other = Task::new(0xfff000, 0xfff000 + 11, CircuitType::new(5, 3, Finality::None));
+        assert_eq!(Journal::read_for(&path, other), Journal::new(other));
+
+        let mut journal = Journal::read_for(&path, root);
+        std::fs::remove_file(path).unwrap();
+        let mut statuses = vec![];
+        let queues = dag.queue(&mut journal, network, |task, status| {
+            statuses.push((task, format!("{status:?}")))
+        });
+        let queued = queues.iter().map(|queue| queue.iter().copied().collect_vec()).collect_vec();
+        assert_eq!(queued, [vec![interrupted], vec![lost], vec![root]]);
+        // the lost task is queued again without having been interrupted
+        assert_eq!(
+            statuses,
+            [
+                (cached, "Cached".to_string()),
+                (interrupted, r#"Retrying("interrupted")"#.to_string()),
+                (root, r#"Retrying("failed with out of memory")"#.to_string()),
+            ]
+        );
+        // the snark of the interrupted task may be incomplete
+        assert!(!Path::new(&interrupted.snark_name(network)).exists());
+        assert_eq!(journal.state(&cached, network), Some(&TaskState::Done));
+        for task in [interrupted, lost, root] {
+            assert_eq!(journal.state(&task, network), Some(&TaskState::Pending));
+        }
+        std::fs::remove_file(cached.snark_name(network)).unwrap();
+    }
+
+    #[test]
+    #[ignore = "requires over 32G memory"]
+    fn test_goerli_header_chain_with_aggregation_parallel() {
+        let mut sequencer = Sequencer::new(Network::Goerli);
+        sequencer.get_snark_parallel(
+            Task::new(0x765fb3, 0x765fb3 + 11, CircuitType::new(4, 3, Finality::None)),
+            &SchedulerConfig { num_workers: 2, ..Default::default() },
+        );
+    }
 
     #[test]
     fn test_goerli_header_chain_provider() {
@@ -337,6 +723,37 @@ mod aggregation {
         ));
     }
 
+    #[test]
+    #[ignore = "requires over 32G memory"]
+    fn test_goerli_header_chain_extension() {
+        use crate::block_header::EthBlockHeaderChainInstance;
+        use snark_verifier_sdk::LIMBS;
+
+        let mut sequencer = Sequencer::new(Network::Goerli);
+        let prev = Task::new(0x765fb3, 0x765fb3 + 7, CircuitType::new(4, 3, Finality::None));
+        let new = Task::new(0x765fb3 + 8, 0x765fb3 + 11, CircuitType::new(3, 3, Finality::None));
+        let snark = sequencer.extend_snark(prev, new, Finality::None);
+        // same chain instance as proving all the blocks at once
+        let expected = sequencer.get_snark(Task::new(
+            0x765fb3,
+            0x765fb3 + 11,
+            CircuitType::new(4, 3, Finality::None),
+        ));
+        let [instance, expected] = [snark, expected].map(|snark| {
+            EthBlockHeaderChainInstance::from_instance(&snark.instances[0][4 * LIMBS..])
+        });
+        assert_eq!(instance, expected);
+
+        // the extension is finalized like the aggregation of all the blocks
+        let snark = sequencer.extend_snark(prev, new, Finality::Merkle);
+        let expected = sequencer.get_snark(Task::new(
+            0x765fb3,
+            0x765fb3 + 11,
+            CircuitType::new(4, 3, Finality::Merkle),
+        ));
+        assert_eq!(snark.instances[0][4 * LIMBS..], expected.instances[0][4 * LIMBS..]);
+    }
+
     #[cfg(feature = "evm")]
     #[test]
     fn test_goerli_header_chain_for_evm() {
@@ -348,3 +765,160 @@ mod aggregation {
         ));
     }
 }
+
+#[cfg(feature = "evm")]
+mod evm {
+    use super::*;
+    use crate::block_header::evm::*;
+    use ethers_core::{
+        abi::{self, ParamType, Token},
+        utils::{id, keccak256},
+    };
+    use snark_verifier::loader::evm::encode_calldata;
+    use snark_verifier_sdk::LIMBS;
+
+    const MAX_DEPTH: usize = 3;
+
+    /// Raw calldata of the Yul verifier with a dummy accumulator and proof
+    fn mock_calldata(chain_instance: &EthBlockHeaderChainInstance) -> Vec<u8> {
+        let accumulator = (0..4 * LIMBS as u64).map(Fr::from);
+        let instances = accumulator.chain(chain_instance.to_instance::<Fr>()).collect_vec();
+        encode_calldata(&[instances], &[0xab; 100])
+    }
+
+    fn mock_chain_instance() -> EthBlockHeaderChainInstance {
+        EthBlockHeaderChainInstance::new(
+            H256::repeat_byte(1),
+            H256::repeat_byte(2),
+            0x765fb3,
+            0x765fb3 + 11,
+            (0..=MAX_DEPTH as u8).map(|i| H256::repeat_byte(0x10 + i)).collect(),
+        )
+    }
+
+    /// Decodes the calldata of `verifyHeaderChain` and checks that it has the arguments of `chain_instance`.
+    /// Returns the `proof` argument.
+    fn assert_verify_header_chain_calldata(
+        calldata: &[u8],
+        chain_instance: &EthBlockHeaderChainInstance,
+    ) -> Vec<u8> {
+        assert_eq!(calldata[..4], id(VERIFY_HEADER_CHAIN_SIGNATURE));
+        let types = [
+            ParamType::FixedBytes(32),
+            ParamType::FixedBytes(32),
+            ParamType::Uint(32),
+            ParamType::Uint(32),
+            ParamType::Array(Box::new(ParamType::FixedBytes(32))),
+            ParamType::Bytes,
+        ];
+        let tokens = abi::decode(&types, &calldata[4..]).unwrap();
+        let hash = |hash: &H256| Token::FixedBytes(hash.as_bytes().to_vec());
+        assert_eq!(tokens[0], hash(&chain_instance.prev_hash));
+        assert_eq!(tokens[1], hash(&chain_instance.end_hash));
+        assert_eq!(tokens[2], Token::Uint(chain_instance.start_block_number.into()));
+        assert_eq!(tokens[3], Token::Uint(chain_instance.end_block_number.into()));
+        assert_eq!(
+            tokens[4],
+            Token::Array(chain_instance.merkle_mountain_range.iter().map(hash).collect())
+        );
+        match &tokens[5] {
+            Token::Bytes(proof) => proof.clone(),
+            _ => panic!("proof should be bytes"),
+        }
+    }
+
+    #[test]
+    fn test_encode_verify_header_chain() {
+        let chain_instance = mock_chain_instance();
+        let calldata = encode_verify_header_chain(&mock_calldata(&chain_instance), MAX_DEPTH);
+        let proof = assert_verify_header_chain_calldata(&calldata, &chain_instance);
+        assert_eq!(proof.len(), 4 * LIMBS * 32 + 100);
+        assert_eq!(proof[4 * LIMBS * 32..], [0xab; 100]);
+    }
+
+    /// The function of the wrapper has the ABI that `encode_verify_header_chain` encodes calldata for
+    #[test]
+    fn test_header_chain_verifier_wrapper_abi() {
+        let wrapper = gen_header_chain_verifier_wrapper(MAX_DEPTH);
+        let params = wrapper.split("function verifyHeaderChain(").nth(1).unwrap();
+        let params = &params[..params.find(')').unwrap()];
+        let types = params
+            .split(',')
+            .map(|param| param.split_whitespace().next().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            format!("verifyHeaderChain({})", types.join(",")),
+            VERIFY_HEADER_CHAIN_SIGNATURE
+        );
+        assert!(wrapper.contains(&format!("MMR_LEN = {};", MAX_DEPTH + 1)));
+        assert!(wrapper.contains(&format!("ACCUMULATOR_BYTES = {};", 4 * LIMBS * 32)));
+    }
+
+    /// Encodes the calldata of a real EVM proof for `verifyHeaderChain` and decodes it back, then checks it with
+    /// the wrapper in a local EVM
+    #[test]
+    #[ignore = "requires solc and over 32G memory"]
+    fn test_goerli_header_chain_calldata_wrapper() {
+        use crate::block_header::helpers::{CircuitType, Finality, Sequencer, Task};
+
+        let mut sequencer = Sequencer::new(Network::Goerli);
+        let task = |finality| Task::new(0x765fb3, 0x765fb3 + 11, CircuitType::new(4, 3, finality));
+        let raw_calldata = sequencer.get_calldata(task(Finality::Evm(0)), false);
+        // the snark for the EVM has the instances of the final snark it aggregates
+        let snark = sequencer.get_snark(task(Finality::Merkle));
+        let chain_instance =
+            EthBlockHeaderChainInstance::from_instance(&snark.instances[0][4 * LIMBS..]);
+        let calldata = encode_verify_header_chain(&raw_calldata, 4);
+        let proof = assert_verify_header_chain_calldata(&calldata, &chain_instance);
+        let num_instance_bytes = 32 * (4 * LIMBS + chain_instance.to_instance::<Fr>().len());
+        assert_eq!(proof[..4 * LIMBS * 32], raw_calldata[..4 * LIMBS * 32]);
+        assert_eq!(proof[4 * LIMBS * 32..], raw_calldata[num_instance_bytes..]);
+
+        let (_, gas) = sequencer.get_calldata_with_wrapper(task(Finality::Evm(0))).unwrap();
+        assert!(gas > 0);
+    }
+
+    /// Checks that the wrapper forwards the raw calldata to the verifier, with a mock verifier that only accepts
+    /// the raw calldata
+    #[test]
+    #[ignore = "requires solc"]
+    fn test_header_chain_verifier_wrapper() {
+        let mock_verifier = r#"// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.0;
+
+contract MockVerifier {
+    bytes32 immutable expected;
+
+    constructor(bytes32 _expected) {
+        expected = _expected;
+    }
+
+    fallback(bytes calldata input) external returns (bytes memory) {
+        require(keccak256(input) == expected);
+        return "";
+    }
+}
+"#;
+        let chain_instance = mock_chain_instance();
+        let raw_calldata = mock_calldata(&chain_instance);
+        let verifier_code = [
+            compile_solidity(mock_verifier, "MockVerifier").unwrap(),
+            keccak256(&raw_calldata).to_vec(),
+        ]
+        .concat();
+        let wrapper = gen_header_chain_verifier_wrapper(MAX_DEPTH);
+        let wrapper_code = compile_solidity(&wrapper, HEADER_CHAIN_VERIFIER_CONTRACT).unwrap();
+
+        let calldata = encode_verify_header_chain(&raw_calldata, MAX_DEPTH);
+        assert!(evm_verify_header_chain(
+            verifier_code.clone(),
+            wrapper_code.clone(),
+            calldata.clone()
+        )
+        .is_some());
+        // a different `endHash`
+        let mut wrong_calldata = calldata;
+        wrong_calldata[4 + 32 + 31] ^= 1;
+        assert!(evm_verify_header_chain(verifier_code, wrapper_code, wrong_calldata).is_none());
+    }
+}
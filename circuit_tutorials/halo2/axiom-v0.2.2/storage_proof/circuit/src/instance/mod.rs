@@ -0,0 +1,310 @@
+//! Typed public instances of the storage and header chain circuits, for consumers of the proofs.
+//!
+//! Each instance struct round-trips with the flat field elements exposed by its circuit through `from_fields` and
+//! `to_fields`, and serializes to JSON with hashes, addresses and `U256` values as hex strings, while block numbers
+//! and nonces are JSON numbers. With the `providers` feature, the instances can also be decoded straight from the
+//! JSON response of a prove request, see [`instances_from_prove_response`].
+use crate::{
+    block_header::{fields::BlockHeaderField, EthBlockHeaderChainInstance},
+    storage::EthAccountFields,
+    util::{
+        decode_field_to_addr, decode_field_to_h256, decode_field_to_u256, encode_addr_to_field,
+        encode_h256_to_field, encode_u256_to_field,
+    },
+    Field,
+};
+use ethers_core::types::{Address, H256, U256};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+#[cfg(test)]
+mod tests;
+
+/// Decodes a field element that is less than 2^64
+pub fn decode_field_to_u64<F: Field>(fe: &F) -> u64 {
+    let repr = fe.to_repr(); // little endian
+    assert!(repr[8..].iter().all(|byte| *byte == 0), "field element does not fit in u64");
+    u64::from_le_bytes(repr[..8].try_into().unwrap())
+}
+
+pub fn decode_field_to_bool<F: Field>(fe: &F) -> bool {
+    match decode_field_to_u64(fe) {
+        0 => false,
+        1 => true,
+        _ => panic!("field element is not a bit"),
+    }
+}
+
+/// Reads the public instances, one vector per instance column, from the JSON response of a prove request.
+///
+/// The instances are under `public`, either directly or under `public.data`, with each field element the base64
+/// encoding of its 32 byte little endian representation.
+#[cfg(feature = "providers")]
+pub fn instances_from_prove_response<F: Field>(response: &serde_json::Value) -> Vec<Vec<F>> {
+    use base64::{engine::general_purpose, Engine as _};
+
+    let public = &response["public"];
+    let public = if public["data"].is_array() { &public["data"] } else { public };
+    public
+        .as_array()
+        .expect("prove response has no public instances")
+        .iter()
+        .map(|column| {
+            column
+                .as_array()
+                .expect("instance column is not an array")
+                .iter()
+                .map(|fe| {
+                    let bytes = general_purpose::STANDARD
+                        .decode(fe.as_str().expect("field element is not a string"))
+                        .expect("field element is not base64");
+                    let repr: [u8; 32] = bytes.try_into().expect("field element is not 32 bytes");
+                    Option::from(F::from_repr(repr)).expect("invalid field element")
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn next_h256<'a, F: Field>(fields: &mut impl Iterator<Item = &'a F>) -> H256 {
+    decode_field_to_h256(&[*fields.next().unwrap(), *fields.next().unwrap()])
+}
+
+fn next_u256<'a, F: Field>(fields: &mut impl Iterator<Item = &'a F>) -> U256 {
+    decode_field_to_u256(&[*fields.next().unwrap(), *fields.next().unwrap()])
+}
+
+/// The shape of the public instances of [`EthBlockStorageCircuit`](crate::storage::EthBlockStorageCircuit),
+/// which depends on the circuit's inputs and is needed to decode them.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageInstanceLayout {
+    pub account_fields: EthAccountFields,
+    /// For each account, for each slot: `None` if the slot is exposed directly, or the number of mapping keys of
+    /// its storage location otherwise
+    pub slots: Vec<Vec<Option<usize>>>,
+}
+
+impl StorageInstanceLayout {
+    pub fn num_instance(&self) -> usize {
+        3 + self
+            .slots
+            .iter()
+            .map(|slots| {
+                2 + self.account_fields.num_instance()
+                    + slots.iter().map(|slot| 3 + 2 * slot.unwrap_or(1)).sum::<usize>()
+            })
+            .sum::<usize>()
+    }
+}
+
+/// How a slot is identified in the public instances
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SlotKey {
+    Slot(H256),
+    /// The mapping keys of the storage location the slot is derived from in-circuit
+    MappingKeys(Vec<H256>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SlotInstance {
+    pub key: SlotKey,
+    /// 0 if the slot does not exist
+    pub value: U256,
+    pub exists: bool,
+}
+
+/// An account and its slots. The account fields are `Some` exactly when they are selected by
+/// [`StorageInstanceLayout::account_fields`], and are 0 if the account does not exist.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountInstance {
+    pub address: Address,
+    pub exists: bool,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub nonce: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub balance: Option<U256>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub storage_root: Option<H256>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub code_hash: Option<H256>,
+    pub slots: Vec<SlotInstance>,
+}
+
+/// Public instances of [`EthBlockStorageCircuit`](crate::storage::EthBlockStorageCircuit)
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageInstance {
+    pub block_hash: H256,
+    pub block_number: u32,
+    pub accounts: Vec<AccountInstance>,
+}
+
+impl StorageInstance {
+    pub fn from_fields<F: Field>(fields: &[F], layout: &StorageInstanceLayout) -> Self {
+        assert_eq!(fields.len(), layout.num_instance(), "instance does not match layout");
+        let mut fields = fields.iter();
+        let block_hash = next_h256(&mut fields);
+        let block_number = decode_field_to_u64(fields.next().unwrap());
+        let block_number = block_number.try_into().expect("block number does not fit in u32");
+        let account_fields = &layout.account_fields;
+        let accounts = layout
+            .slots
+            .iter()
+            .map(|slots| {
+                let address = decode_field_to_addr(fields.next().unwrap());
+                let exists = decode_field_to_bool(fields.next().unwrap());
+                let nonce =
+                    account_fields.nonce.then(|| decode_field_to_u64(fields.next().unwrap()));
+                let balance = account_fields.balance.then(|| next_u256(&mut fields));
+                let storage_root = account_fields.storage_root.then(|| next_h256(&mut fields));
+                let code_hash = account_fields.code_hash.then(|| next_h256(&mut fields));
+                let slots = slots
+                    .iter()
+                    .map(|num_mapping_keys| {
+                        let key = match num_mapping_keys {
+                            Some(num_mapping_keys) => SlotKey::MappingKeys(
+                                (0..*num_mapping_keys).map(|_| next_h256(&mut fields)).collect(),
+                            ),
+                            None => SlotKey::Slot(next_h256(&mut fields)),
+                        };
+                        let value = next_u256(&mut fields);
+                        let exists = decode_field_to_bool(fields.next().unwrap());
+                        SlotInstance { key, value, exists }
+                    })
+                    .collect();
+                AccountInstance { address, exists, nonce, balance, storage_root, code_hash, slots }
+            })
+            .collect();
+        Self { block_hash, block_number, accounts }
+    }
+
+    pub fn to_fields<F: Field>(&self) -> Vec<F> {
+        let mut fields = encode_h256_to_field::<F>(&self.block_hash).to_vec();
+        fields.push(F::from(self.block_number as u64));
+        for account in &self.accounts {
+            fields.push(encode_addr_to_field(&account.address));
+            fields.push(F::from(account.exists));
+            fields.extend(account.nonce.map(F::from));
+            fields.extend(account.balance.iter().flat_map(encode_u256_to_field::<F>));
+            for hash in [&account.storage_root, &account.code_hash].into_iter().flatten() {
+                fields.extend(encode_h256_to_field::<F>(hash));
+            }
+            for slot in &account.slots {
+                match &slot.key {
+                    SlotKey::Slot(slot) => fields.extend(encode_h256_to_field::<F>(slot)),
+                    SlotKey::MappingKeys(keys) => {
+                        fields.extend(keys.iter().flat_map(encode_h256_to_field::<F>))
+                    }
+                }
+                fields.extend(encode_u256_to_field::<F>(&slot.value));
+                fields.push(F::from(slot.exists));
+            }
+        }
+        fields
+    }
+
+    /// The layout of this instance. The account fields are taken from the first account.
+    pub fn layout(&self) -> StorageInstanceLayout {
+        let account_fields =
+            self.accounts.first().map_or_else(EthAccountFields::default, |acct| EthAccountFields {
+                nonce: acct.nonce.is_some(),
+                balance: acct.balance.is_some(),
+                storage_root: acct.storage_root.is_some(),
+                code_hash: acct.code_hash.is_some(),
+            });
+        let slots = self
+            .accounts
+            .iter()
+            .map(|account| {
+                account
+                    .slots
+                    .iter()
+                    .map(|slot| match &slot.key {
+                        SlotKey::Slot(_) => None,
+                        SlotKey::MappingKeys(keys) => Some(keys.len()),
+                    })
+                    .collect()
+            })
+            .collect();
+        StorageInstanceLayout { account_fields, slots }
+    }
+
+    /// Decodes the first instance column of the JSON response of a prove request,
+    /// see [`instances_from_prove_response`].
+    #[cfg(feature = "providers")]
+    pub fn from_prove_response<F: Field>(
+        response: &serde_json::Value,
+        layout: &StorageInstanceLayout,
+    ) -> Self {
+        Self::from_fields(&instances_from_prove_response::<F>(response)[0], layout)
+    }
+}
+
+/// A header field exposed by [`EthBlockHeaderChainCircuit::with_field_queries`](crate::block_header::EthBlockHeaderChainCircuit::with_field_queries)
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeaderFieldInstance {
+    pub block_idx: usize,
+    pub field: BlockHeaderField,
+    /// Integer fields are their value as U256
+    pub value: H256,
+}
+
+/// Public instances of [`EthBlockHeaderChainCircuit`](crate::block_header::EthBlockHeaderChainCircuit)
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeaderChainInstance {
+    #[serde(flatten)]
+    pub chain: EthBlockHeaderChainInstance,
+    pub fields: Vec<HeaderFieldInstance>,
+}
+
+impl HeaderChainInstance {
+    /// Decodes the instance of a header chain circuit of depth `max_depth` with the header field queries
+    /// `field_queries`, as (block index, field)
+    pub fn from_fields<F: Field>(
+        fields: &[F],
+        max_depth: usize,
+        field_queries: &[(usize, BlockHeaderField)],
+    ) -> Self {
+        let chain_len = 5 + 2 * (max_depth + 1);
+        assert_eq!(fields.len(), chain_len + 2 * field_queries.len(), "instance length mismatch");
+        let chain = EthBlockHeaderChainInstance::from_instance(&fields[..chain_len]);
+        let fields = fields[chain_len..]
+            .chunks(2)
+            .zip_eq(field_queries)
+            .map(|(value, (block_idx, field))| HeaderFieldInstance {
+                block_idx: *block_idx,
+                field: *field,
+                value: decode_field_to_h256(value),
+            })
+            .collect();
+        Self { chain, fields }
+    }
+
+    pub fn to_fields<F: Field>(&self) -> Vec<F> {
+        let mut fields = self.chain.to_instance::<F>();
+        fields.extend(self.fields.iter().flat_map(|field| encode_h256_to_field::<F>(&field.value)));
+        fields
+    }
+
+    pub fn max_depth(&self) -> usize {
+        self.chain.merkle_mountain_range.len() - 1
+    }
+
+    pub fn field_queries(&self) -> Vec<(usize, BlockHeaderField)> {
+        self.fields.iter().map(|field| (field.block_idx, field.field)).collect()
+    }
+
+    /// Decodes the first instance column of the JSON response of a prove request,
+    /// see [`instances_from_prove_response`].
+    #[cfg(feature = "providers")]
+    pub fn from_prove_response<F: Field>(
+        response: &serde_json::Value,
+        max_depth: usize,
+        field_queries: &[(usize, BlockHeaderField)],
+    ) -> Self {
+        Self::from_fields(
+            &instances_from_prove_response::<F>(response)[0],
+            max_depth,
+            field_queries,
+        )
+    }
+}
@@ -0,0 +1,156 @@
+use super::*;
+use crate::halo2_proofs::halo2curves::bn256::Fr;
+use std::str::FromStr;
+
+fn h256(s: &str) -> H256 {
+    H256::from_str(s).unwrap()
+}
+
+fn test_storage_instance() -> StorageInstance {
+    let slot = |key, value: u64, exists| SlotInstance { key, value: U256::from(value), exists };
+    StorageInstance {
+        block_hash: h256("0xf152ad7de1411489dd7bd38d958f1c826f3e98b348c77a2141cef101d6e2dbde"),
+        block_number: 16356350,
+        accounts: vec![
+            AccountInstance {
+                address: Address::from_str("0xb47e3cd837dDF8e4c57F05d70Ab865de6e193BBB").unwrap(),
+                exists: true,
+                nonce: Some(1),
+                balance: Some(U256::from_dec_str("1000000000000000000000000").unwrap()),
+                storage_root: Some(h256(
+                    "0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421",
+                )),
+                code_hash: None,
+                slots: vec![
+                    slot(SlotKey::Slot(H256::from_low_u64_be(3)), 0x1234, true),
+                    slot(
+                        SlotKey::MappingKeys(vec![
+                            H256::from_low_u64_be(7),
+                            h256("0x000000000000000000000000b47e3cd837ddf8e4c57f05d70ab865de6e193bbb"),
+                        ]),
+                        0,
+                        false,
+                    ),
+                    slot(SlotKey::MappingKeys(vec![]), u64::MAX, true),
+                ],
+            },
+            AccountInstance {
+                address: Address::zero(),
+                exists: false,
+                nonce: Some(0),
+                balance: Some(U256::zero()),
+                storage_root: Some(H256::zero()),
+                code_hash: None,
+                slots: vec![],
+            },
+        ],
+    }
+}
+
+#[test]
+pub fn test_storage_instance_round_trip() {
+    let instance = test_storage_instance();
+    let layout = instance.layout();
+    assert_eq!(
+        layout.account_fields,
+        EthAccountFields { nonce: true, balance: true, storage_root: true, code_hash: false }
+    );
+    assert_eq!(layout.slots, vec![vec![None, Some(2), Some(0)], vec![]]);
+
+    let fields = instance.to_fields::<Fr>();
+    assert_eq!(fields.len(), layout.num_instance());
+    assert_eq!(StorageInstance::from_fields(&fields, &layout), instance);
+
+    let json = serde_json::to_string(&instance).unwrap();
+    assert!(!json.contains("code_hash"));
+    // block numbers and nonces are JSON numbers, U256 values are hex strings
+    let value = serde_json::from_str::<serde_json::Value>(&json).unwrap();
+    assert_eq!(value["block_number"], instance.block_number);
+    assert_eq!(value["accounts"][1]["nonce"], 0);
+    assert!(value["accounts"][1]["balance"].as_str().unwrap().starts_with("0x"));
+    assert_eq!(serde_json::from_str::<StorageInstance>(&json).unwrap(), instance);
+}
+
+#[test]
+#[should_panic]
+pub fn test_storage_instance_wrong_layout() {
+    let instance = test_storage_instance();
+    let mut layout = instance.layout();
+    layout.account_fields.code_hash = true;
+    StorageInstance::from_fields(&instance.to_fields::<Fr>(), &layout);
+}
+
+#[test]
+pub fn test_header_chain_instance_round_trip() {
+    let max_depth = 3;
+    let instance = HeaderChainInstance {
+        chain: EthBlockHeaderChainInstance::new(
+            h256("0xc0d33af36f33cc4324e40b2e813e2114a8138fb168b8d2f25484d57a00ba0c41"),
+            h256("0xf152ad7de1411489dd7bd38d958f1c826f3e98b348c77a2141cef101d6e2dbde"),
+            16356344,
+            16356350,
+            (0..=max_depth as u64).map(H256::from_low_u64_be).collect(),
+        ),
+        fields: vec![
+            HeaderFieldInstance {
+                block_idx: 0,
+                field: BlockHeaderField::Timestamp,
+                value: H256::from_low_u64_be(1673116823),
+            },
+            HeaderFieldInstance {
+                block_idx: 6,
+                field: BlockHeaderField::StateRoot,
+                value: h256("0xf8bef79a5edec709ff3d26126cbb2a8aa0da5ff80f6e3ffa16ec2c5d88ffab6f"),
+            },
+        ],
+    };
+    let fields = instance.to_fields::<Fr>();
+    assert_eq!(
+        fields.len(),
+        crate::block_header::EthBlockHeaderChainCircuit::<Fr>::get_num_instance(max_depth) + 4
+    );
+    let decoded = HeaderChainInstance::from_fields(&fields, max_depth, &instance.field_queries());
+    assert_eq!(decoded, instance);
+    assert_eq!(decoded.max_depth(), max_depth);
+
+    let json = serde_json::to_value(&instance).unwrap();
+    assert_eq!(json["start_block_number"], 16356344);
+    assert_eq!(serde_json::from_value::<HeaderChainInstance>(json).unwrap(), instance);
+}
+
+#[cfg(feature = "providers")]
+#[test]
+pub fn test_storage_instance_from_circuit() {
+    use crate::storage::EthBlockStorageCircuit;
+    use snark_verifier_sdk::CircuitExt;
+
+    let circuit =
+        EthBlockStorageCircuit::<Fr>::default().with_account_fields(EthAccountFields::all());
+    let layout = circuit.instance_layout();
+    assert_eq!(layout.num_instance(), circuit.num_instance()[0]);
+    let fields = circuit.instance();
+    let instance = StorageInstance::from_fields(&fields, &layout);
+    assert_eq!(instance.block_number, circuit.inputs.block_number);
+    assert_eq!(instance.block_hash, circuit.inputs.block_hash);
+    assert_eq!(instance.accounts.len(), circuit.inputs.storage.len());
+    assert_eq!(instance.to_fields::<Fr>(), fields);
+}
+
+#[cfg(feature = "providers")]
+#[test]
+pub fn test_instances_from_prove_response() {
+    use base64::{engine::general_purpose, Engine as _};
+    use halo2_base::utils::PrimeField;
+
+    let instance = test_storage_instance();
+    let public = instance
+        .to_fields::<Fr>()
+        .iter()
+        .map(|fe| general_purpose::STANDARD.encode(fe.to_repr()))
+        .collect_vec();
+    let response = serde_json::json!({ "proof_id": "0", "public": { "data": [public] } });
+    assert_eq!(StorageInstance::from_prove_response::<Fr>(&response, &instance.layout()), instance);
+
+    let response = serde_json::json!({ "public": [public] });
+    assert_eq!(instances_from_prove_response::<Fr>(&response), vec![instance.to_fields::<Fr>()]);
+}
@@ -21,6 +21,7 @@ use zkevm_keccak::{
     util::{eth_types::Field, NUM_BYTES_TO_SQUEEZE, NUM_ROUNDS, NUM_WORDS_TO_SQUEEZE, RATE},
 };
 
+pub mod planner;
 #[cfg(test)]
 mod tests;
 
@@ -272,15 +273,19 @@ impl<'v, F: Field> KeccakChip<'v, F> {
             .collect()
     }
 
+    /// Number of keccak-f permutations needed by the queries so far
+    pub fn capacity(&self) -> usize {
+        planner::num_keccak_f(
+            self.fixed_len_queries
+                .iter()
+                .map(|q| q.input_assigned.len())
+                .chain(self.var_len_queries.iter().map(|q| q.max_bytes)),
+        )
+    }
+
     /// Do this at the end of `FirstPhase` and then call `assign_phase1` in `SecondPhase`.
     pub fn assign_phase0(&mut self, region: &mut Region<'_, F>) {
-        let capacity: usize = self
-            .fixed_len_queries
-            .iter()
-            .map(|q| q.input_assigned.len())
-            .chain(self.var_len_queries.iter().map(|q| q.max_bytes))
-            .map(get_num_keccak_f)
-            .sum();
+        let capacity = self.capacity();
         let unused_capacity: usize = self
             .var_len_queries
             .iter()
@@ -479,11 +484,8 @@ impl<'v, F: Field> KeccakChip<'v, F> {
 
     #[cfg(feature = "display")]
     pub fn print_stats(ctx: &Context<F>, num_keccak_f: usize) {
-        use zkevm_keccak::util::NUM_WORDS_TO_ABSORB;
-
         println!("Number of keccak_f permutations: {num_keccak_f}");
-        let rows_per_round =
-            ctx.max_rows / (num_keccak_f * (NUM_ROUNDS + 1) + 1 + NUM_WORDS_TO_ABSORB);
+        let rows_per_round = planner::rows_per_round(ctx.max_rows, num_keccak_f);
         println!("Optimal keccak rows per round: {rows_per_round}");
     }
 }
@@ -0,0 +1,158 @@
+//! Picks the circuit degree, `keccak_rows_per_round` and column counts of an [`EthConfigParams`] from the cells
+//! and keccak queries a circuit uses, instead of tuning `KECCAK_ROWS` and `degree` by hand.
+//!
+//! The cell counts of a circuit can be read from `ctx.print_stats` with the `display` feature, and its number of
+//! keccak-f permutations from [`KeccakChip::capacity`](super::KeccakChip::capacity) or [`num_keccak_f`].
+use crate::{
+    halo2_proofs::{halo2curves::bn256::Fr, plonk::ConstraintSystem},
+    mpt::MPTConfig,
+    util::EthConfigParams,
+};
+use serde::{Deserialize, Serialize};
+use std::{cmp::min, env::var};
+use zkevm_keccak::{
+    keccak_packed_multi::get_num_keccak_f,
+    util::{NUM_BYTES_PER_WORD, NUM_ROUNDS, NUM_WORDS_TO_ABSORB},
+};
+
+/// The keccak circuit needs more rows per round than there are bytes in a word
+pub const MIN_KECCAK_ROWS_PER_ROUND: usize = NUM_BYTES_PER_WORD + 1;
+/// Empirically more than 50 rows per round makes the rotation offsets too large
+pub const MAX_KECCAK_ROWS_PER_ROUND: usize = 50;
+
+/// Number of keccak-f permutations of keccak queries on inputs of at most these byte lengths
+pub fn num_keccak_f(query_lens: impl IntoIterator<Item = usize>) -> usize {
+    query_lens.into_iter().map(get_num_keccak_f).sum()
+}
+
+/// The largest number of rows per round such that `num_keccak_f` permutations fit in `max_rows` rows
+pub fn rows_per_round(max_rows: usize, num_keccak_f: usize) -> usize {
+    max_rows / (num_keccak_f * (NUM_ROUNDS + 1) + 1 + NUM_WORDS_TO_ABSORB)
+}
+
+/// The cells used by a circuit
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CircuitStats {
+    pub num_keccak_f: usize,
+    /// Advice cells without lookup in each phase
+    pub advice_cells: Vec<usize>,
+    /// Cells to range check by lookup in each phase
+    pub lookup_cells: Vec<usize>,
+    /// Advice cells in the `SecondPhase` RLC columns
+    pub rlc_cells: usize,
+    pub fixed_cells: usize,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct KeccakPlanner {
+    pub min_degree: u32,
+    pub max_degree: u32,
+    /// Maximum number of advice columns, including the keccak columns
+    pub max_advice_columns: usize,
+    /// Rough costs of one cell (a row of a column) when proving, used for the estimates of a [`KeccakPlan`].
+    /// They depend heavily on the machine: calibrate them with a benchmark such as `bench_keccak`.
+    pub proving_ns_per_cell: f64,
+    pub memory_bytes_per_cell: u64,
+}
+
+impl Default for KeccakPlanner {
+    fn default() -> Self {
+        Self {
+            min_degree: 10,
+            max_degree: 24,
+            max_advice_columns: 128,
+            proving_ns_per_cell: 200.0,
+            memory_bytes_per_cell: 256,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct KeccakPlan {
+    pub params: EthConfigParams,
+    pub num_keccak_advice: usize,
+    /// Total number of advice columns, including the keccak columns
+    pub num_advice: usize,
+    pub estimated_proving_secs: f64,
+    pub estimated_memory_bytes: u64,
+}
+
+impl KeccakPlanner {
+    /// The plan with the smallest degree that fits `stats`, if any.
+    ///
+    /// This configures the circuit for each candidate degree, which sets the `KECCAK_*` and `UNUSABLE_ROWS`
+    /// environment variables as [`MPTConfig::configure`] does.
+    pub fn plan(&self, stats: &CircuitStats) -> Option<KeccakPlan> {
+        (self.min_degree..=self.max_degree).find_map(|degree| self.plan_degree(stats, degree))
+    }
+
+    /// The plan for `stats` with circuit degree `degree`, if it fits.
+    pub fn plan_degree(&self, stats: &CircuitStats, degree: u32) -> Option<KeccakPlan> {
+        // the unusable rows depend on the keccak rows per round, which depend on the unusable rows: start from
+        // 0 and increase them until the configured circuit needs no more
+        let mut unusable_rows = 0;
+        loop {
+            let params = self.params(stats, degree, unusable_rows)?;
+            let mut meta = ConstraintSystem::<Fr>::default();
+            MPTConfig::configure(&mut meta, params.clone(), 0);
+            if meta.minimum_rows() <= unusable_rows {
+                return self.to_plan(params);
+            }
+            unusable_rows = meta.minimum_rows();
+        }
+    }
+
+    fn params(
+        &self,
+        stats: &CircuitStats,
+        degree: u32,
+        unusable_rows: usize,
+    ) -> Option<EthConfigParams> {
+        let max_rows = (1usize << degree).checked_sub(unusable_rows).filter(|rows| *rows > 0)?;
+        let keccak_rows_per_round =
+            min(rows_per_round(max_rows, stats.num_keccak_f), MAX_KECCAK_ROWS_PER_ROUND);
+        if keccak_rows_per_round < MIN_KECCAK_ROWS_PER_ROUND {
+            return None;
+        }
+        let num_columns = |cells: &usize| (cells + max_rows - 1) / max_rows;
+        let per_phase = |cells: &[usize]| {
+            let mut columns = cells.iter().map(num_columns).collect::<Vec<_>>();
+            columns.resize(2, 0);
+            columns
+        };
+        // `FirstPhase` always has range checks
+        let mut num_range_advice = per_phase(&stats.advice_cells);
+        num_range_advice[0] = num_range_advice[0].max(1);
+        let mut num_lookup_advice = per_phase(&stats.lookup_cells);
+        num_lookup_advice[0] = num_lookup_advice[0].max(1);
+        Some(EthConfigParams {
+            degree,
+            num_rlc_columns: num_columns(&stats.rlc_cells).max(1),
+            num_range_advice,
+            num_lookup_advice,
+            num_fixed: num_columns(&stats.fixed_cells).max(1),
+            unusable_rows,
+            keccak_rows_per_round,
+        })
+    }
+
+    fn to_plan(&self, params: EthConfigParams) -> Option<KeccakPlan> {
+        // set by the keccak config
+        let num_keccak_advice: usize = var("KECCAK_ADVICE_COLUMNS").unwrap().parse().unwrap();
+        let num_advice = num_keccak_advice
+            + params.num_rlc_columns
+            + params.num_range_advice.iter().sum::<usize>()
+            + params.num_lookup_advice.iter().sum::<usize>();
+        if num_advice > self.max_advice_columns {
+            return None;
+        }
+        let num_cells = ((num_advice + params.num_fixed) as u64) << params.degree;
+        Some(KeccakPlan {
+            estimated_proving_secs: num_cells as f64 * self.proving_ns_per_cell / 1e9,
+            estimated_memory_bytes: num_cells * self.memory_bytes_per_cell,
+            params,
+            num_keccak_advice,
+            num_advice,
+        })
+    }
+}
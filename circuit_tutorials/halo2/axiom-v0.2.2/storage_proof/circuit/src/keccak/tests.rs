@@ -290,3 +290,57 @@ fn bench_keccak() {
         .unwrap();
     }
 }
+
+mod planner {
+    use super::*;
+    use crate::keccak::planner::*;
+
+    fn test_stats() -> CircuitStats {
+        CircuitStats {
+            num_keccak_f: 100,
+            advice_cells: vec![500_000, 200_000],
+            lookup_cells: vec![50_000, 10_000],
+            rlc_cells: 100_000,
+            fixed_cells: 1_000,
+        }
+    }
+
+    #[test]
+    pub fn test_num_keccak_f() {
+        // the padding takes at least 1 byte of the 136 byte rate
+        assert_eq!(num_keccak_f([0, 135, 136, 200]), 6);
+    }
+
+    #[test]
+    pub fn test_keccak_planner() {
+        let stats = test_stats();
+        let planner = KeccakPlanner::default();
+        let plan = planner.plan(&stats).unwrap();
+        let params = &plan.params;
+        let max_rows = (1 << params.degree) - params.unusable_rows;
+
+        assert!(params.keccak_rows_per_round >= MIN_KECCAK_ROWS_PER_ROUND);
+        assert!(params.keccak_rows_per_round <= MAX_KECCAK_ROWS_PER_ROUND);
+        set_var("KECCAK_ROWS", params.keccak_rows_per_round.to_string());
+        assert!(get_keccak_capacity(max_rows) >= stats.num_keccak_f);
+        for phase in 0..2 {
+            assert!(params.num_range_advice[phase] * max_rows >= stats.advice_cells[phase]);
+            assert!(params.num_lookup_advice[phase] * max_rows >= stats.lookup_cells[phase]);
+        }
+        assert!(params.num_rlc_columns * max_rows >= stats.rlc_cells);
+        assert!(plan.num_advice <= planner.max_advice_columns);
+        assert!(plan.estimated_proving_secs > 0.0 && plan.estimated_memory_bytes > 0);
+
+        // the plan has the smallest degree
+        assert!(planner.plan_degree(&stats, params.degree - 1).is_none());
+        let larger = planner.plan_degree(&stats, params.degree + 1).unwrap();
+        assert!(larger.params.keccak_rows_per_round >= params.keccak_rows_per_round);
+    }
+
+    #[test]
+    pub fn test_keccak_planner_infeasible() {
+        let planner = KeccakPlanner { max_degree: 14, ..Default::default() };
+        // 2^14 rows fit fewer than 2^14 / (25 * 9) keccak-f permutations
+        assert!(planner.plan(&test_stats()).is_none());
+    }
+}
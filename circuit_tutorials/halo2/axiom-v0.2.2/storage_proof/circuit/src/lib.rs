@@ -1,10 +1,15 @@
 #![feature(int_log)]
 
+pub mod beacon;
 pub mod block_header;
+pub mod instance;
 pub mod keccak;
 pub mod mpt;
+pub mod receipt;
 pub mod rlp;
+pub mod sha256;
 pub mod storage;
+pub mod transaction;
 pub mod util;
 
 #[cfg(feature = "providers")]
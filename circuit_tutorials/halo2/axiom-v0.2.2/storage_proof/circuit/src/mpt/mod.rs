@@ -3,16 +3,17 @@ use crate::{
     keccak::{KeccakChip, KeccakConfig},
     rlp::{
         max_rlp_len_len,
-        rlc::{
-            rlc_constrain_equal, rlc_is_equal, rlc_select, rlc_select_from_idx, RlcTrace, RlcVarLen,
-        },
+        rlc::{rlc_is_equal, rlc_select, rlc_select_from_idx, RlcTrace, RlcVarLen},
         RlpChip, RlpConfig, RlpFieldTrace,
     },
     rlp::{rlc::RlcChip, RlpArrayTraceWitness},
     util::EthConfigParams,
     Field,
 };
-use ethers_core::{types::H256, utils::hex::FromHex};
+use ethers_core::{
+    types::H256,
+    utils::{hex::FromHex, keccak256},
+};
 use halo2_base::{
     gates::{flex_gate::FlexGateConfig, range::RangeConfig, GateInstructions, RangeInstructions},
     utils::{bit_length, ScalarField},
@@ -22,10 +23,15 @@ use halo2_base::{
 use itertools::Itertools;
 use lazy_static::lazy_static;
 use rlp::Rlp;
-use std::{cmp::max, env::set_var, iter::once};
+use std::{
+    cmp::{max, min},
+    env::set_var,
+    iter::once,
+};
 
 #[cfg(test)]
 mod tests;
+pub mod trie;
 
 #[derive(Clone, Debug)]
 pub struct LeafTrace<'v, F: Field> {
@@ -99,6 +105,13 @@ pub struct MPTFixedKeyProof<'v, F: Field> {
 
     // proof specification
     pub depth: AssignedValue<'v, F>,
+    /// Boolean indicating whether `key` is *not* in the MPT, in which case this is a proof of exclusion and `value_bytes` is unconstrained
+    pub slot_is_empty: AssignedValue<'v, F>,
+    /// Boolean indicating whether the proof ends in the leaf `leaf_bytes`. This is always the case for inclusion proofs.
+    ///
+    /// An exclusion proof either ends in a leaf whose path diverges from the key, or, when `has_leaf = 0`, in a node `nodes[depth - 1]`
+    /// that is a branch with an empty slot at the next key nibble or an extension whose path diverges from the key.
+    pub has_leaf: AssignedValue<'v, F>,
     /// RLP encoding of the final leaf node
     pub leaf_bytes: AssignedBytes<'v, F>,
     pub nodes: Vec<MPTNode<'v, F>>,
@@ -123,30 +136,19 @@ pub struct MPTFixedKeyProofWitness<'v, F: Field> {
     pub key_hexs: AssignedNibbles<'v, F>,
 }
 
-#[allow(dead_code)]
+/// Same as [`MPTFixedKeyProof`] except that the key has variable length `key_byte_len`.
+///
+/// `proof.key_bytes` is the key right padded with 0s to the maximum key length `proof.key_byte_len`.
 #[derive(Clone, Debug)]
 pub struct MPTVarKeyProof<'v, F: Field> {
-    // claim specification
-    key_bytes: AssignedBytes<'v, F>,
-    key_byte_len: AssignedValue<'v, F>,
-    value_bytes: AssignedBytes<'v, F>,
-    value_byte_len: AssignedValue<'v, F>,
-    root_hash_bytes: AssignedBytes<'v, F>,
-
-    // proof specification
-    leaf_bytes: AssignedBytes<'v, F>,
-    proof_nodes: Vec<AssignedBytes<'v, F>>,
-    node_types: Vec<AssignedValue<'v, F>>, // index 0 = root; 0 = branch, 1 = extension
-    depth: AssignedValue<'v, F>,
-
-    key_frag_hexs: Vec<AssignedNibbles<'v, F>>,
-    // hex_len = 2 * byte_len + is_odd - 2
-    key_frag_is_odd: Vec<AssignedValue<'v, F>>,
-    key_frag_byte_len: Vec<AssignedValue<'v, F>>,
+    pub proof: MPTFixedKeyProof<'v, F>,
+    pub key_byte_len: AssignedValue<'v, F>,
+}
 
-    key_max_byte_len: usize,
-    value_max_byte_len: usize,
-    max_depth: usize,
+#[derive(Clone, Debug)]
+pub struct MPTVarKeyProofWitness<'v, F: Field> {
+    pub witness: MPTFixedKeyProofWitness<'v, F>,
+    pub key_byte_len: AssignedValue<'v, F>,
 }
 
 pub fn max_leaf_lens(max_key_bytes: usize, max_value_bytes: usize) -> (Vec<usize>, usize) {
@@ -453,6 +455,7 @@ impl<'v, F: Field> MPTChip<'v, F> {
          * 0 < depth <= max_depth
          * 0 <= value_byte_len <= value_max_byte_len
          * 0 <= key_frag_byte_len[idx] <= key_byte_len + 1
+         * slot_is_empty, has_leaf in {0, 1} and an inclusion proof has a leaf
          */
         for byte in proof
             .key_bytes
@@ -469,9 +472,13 @@ impl<'v, F: Field> MPTChip<'v, F> {
             .iter()
             .map(|node| &node.node_type)
             .chain(proof.key_frag.iter().map(|frag| &frag.is_odd))
+            .chain([&proof.slot_is_empty, &proof.has_leaf])
         {
             self.gate().assert_bit(ctx, bit);
         }
+        let has_leaf_or_empty =
+            self.gate().or(ctx, Existing(&proof.has_leaf), Existing(&proof.slot_is_empty));
+        self.gate().assert_is_const(ctx, &has_leaf_or_empty, F::one());
         for nibble in proof.key_frag.iter().flat_map(|frag| frag.nibbles.iter()) {
             self.range().range_check(ctx, nibble, 4);
         }
@@ -589,6 +596,51 @@ impl<'v, F: Field> MPTChip<'v, F> {
         &mut self,
         ctx: &mut Context<'v, F>,
         witness: MPTFixedKeyProofWitness<'v, F>,
+    ) {
+        self.parse_mpt_inclusion_phase1(ctx, witness, None);
+    }
+
+    /// Same as `parse_mpt_inclusion_fixed_key_phase0` except the key has variable length `proof.key_byte_len`,
+    /// with `0 < proof.key_byte_len <= key_max_byte_len`.
+    pub fn parse_mpt_inclusion_var_key_phase0(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        proof: MPTVarKeyProof<'v, F>,
+        key_max_byte_len: usize,
+        value_max_byte_len: usize,
+        max_depth: usize,
+    ) -> MPTVarKeyProofWitness<'v, F> {
+        let MPTVarKeyProof { proof, key_byte_len } = proof;
+        self.range().check_less_than_safe(ctx, &key_byte_len, key_max_byte_len as u64 + 1);
+        let witness = self.parse_mpt_inclusion_fixed_key_phase0(
+            ctx,
+            proof,
+            key_max_byte_len,
+            value_max_byte_len,
+            max_depth,
+        );
+        MPTVarKeyProofWitness { witness, key_byte_len }
+    }
+
+    pub fn parse_mpt_inclusion_var_key_phase1(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        witness: MPTVarKeyProofWitness<'v, F>,
+    ) {
+        let key_hex_len = self.gate().mul(
+            ctx,
+            Existing(&witness.key_byte_len),
+            Constant(self.gate().get_field_element(2)),
+        );
+        self.parse_mpt_inclusion_phase1(ctx, witness.witness, Some(key_hex_len));
+    }
+
+    /// If `key_hex_len` is `None`, the key is the full `witness.key_hexs`. Otherwise only the first `key_hex_len` nibbles are the key.
+    fn parse_mpt_inclusion_phase1(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        witness: MPTFixedKeyProofWitness<'v, F>,
+        key_hex_len: Option<AssignedValue<'v, F>>,
     ) {
         debug_assert_eq!(ctx.current_phase(), 1);
         let MPTFixedKeyProof {
@@ -599,6 +651,8 @@ impl<'v, F: Field> MPTChip<'v, F> {
             leaf_bytes: _,
             nodes,
             depth,
+            slot_is_empty,
+            has_leaf,
             key_frag,
             key_byte_len,
             value_max_byte_len: _,
@@ -626,23 +680,58 @@ impl<'v, F: Field> MPTChip<'v, F> {
             .collect();
         let key_hexs = witness.key_hexs;
 
+        // is_last[idx] = (depth == idx + 1)
+        let is_last = (0..max_depth)
+            .map(|idx| {
+                self.gate().is_equal(
+                    ctx,
+                    Existing(&depth),
+                    Constant(self.gate().get_field_element((idx + 1) as u64)),
+                )
+            })
+            .collect_vec();
+        // is_terminal[idx] = 1 iff `nodes[idx]` is the last node of an exclusion proof without a leaf
+        let is_terminal = is_last[..max_depth - 1]
+            .iter()
+            .map(|is_last| self.gate().mul_not(ctx, Existing(&has_leaf), Existing(is_last)))
+            .collect_vec();
+
         // Match fragments to node key
-        for ((ext_parsed, key_frag_ext_byte_rlc), node) in
-            exts_parsed.iter().zip(key_frag_ext_byte_rlcs.iter()).zip(nodes.iter())
+        for (((ext_parsed, key_frag_ext_byte_rlc), node), (frag, is_terminal)) in exts_parsed
+            .iter()
+            .zip(key_frag_ext_byte_rlcs.iter())
+            .zip(nodes.iter())
+            .zip(key_frag.iter().zip(is_terminal.iter()))
         {
             // When node is extension, check node key RLC equals key frag RLC
-            let mut node_key_is_equal = rlc_is_equal(
+            let node_key_is_equal = rlc_is_equal(
                 ctx,
                 self.gate(),
                 &ext_parsed.key_path.field_trace,
                 key_frag_ext_byte_rlc,
             );
+            // unless the node is the terminal extension of an exclusion proof, in which case the node key must
+            // differ from the key fragment of the same hex-prefix shape
+            let is_terminal_ext =
+                self.gate().and(ctx, Existing(is_terminal), Existing(&node.node_type));
+            let not_terminal_ext = self.gate().not(ctx, Existing(&is_terminal_ext));
+            let key_check = self.gate().is_equal(
+                ctx,
+                Existing(&node_key_is_equal),
+                Existing(&not_terminal_ext),
+            );
+            let shape_check = self.path_has_frag_shape(
+                ctx,
+                &ext_parsed.key_path.field_trace,
+                &frag.byte_len,
+                &frag.is_odd,
+            );
+            let key_check = self.gate().and(ctx, Existing(&key_check), Existing(&shape_check));
             // is equal or node not extension
             let is_not_ext = self.gate().not(ctx, Existing(&node.node_type));
-            node_key_is_equal =
-                self.gate().or(ctx, Existing(&node_key_is_equal), Existing(&is_not_ext));
+            let key_check = self.gate().or(ctx, Existing(&key_check), Existing(&is_not_ext));
             // assuming node type is not extension if idx > pf.len() [we don't care what happens for these idx]
-            self.gate().assert_is_const(ctx, &node_key_is_equal, F::one());
+            self.gate().assert_is_const(ctx, &key_check, F::one());
         }
         let depth_minus_one = self.gate().sub(ctx, Existing(&depth), Constant(F::one()));
         // Quiz for auditers: is the following necessary?
@@ -653,31 +742,106 @@ impl<'v, F: Field> MPTChip<'v, F> {
             key_frag_leaf_byte_rlcs.iter().map(|trace| trace.into()).collect(),
             &depth_minus_one,
         );
-        rlc_constrain_equal(ctx, &key_frag_leaf_bytes_rlc, &leaf_parsed.key_path.field_trace);
+        let leaf_frag_is_odd = self.gate().select_from_idx(
+            ctx,
+            key_frag.iter().map(|frag| Existing(&frag.is_odd)),
+            Existing(&depth_minus_one),
+        );
+        // for an inclusion proof the leaf path equals the rest of the key; for an exclusion proof ending in a leaf
+        // it must differ from the rest of the key
+        let leaf_path_is_equal = rlc_is_equal(
+            ctx,
+            self.gate(),
+            &key_frag_leaf_bytes_rlc,
+            &leaf_parsed.key_path.field_trace,
+        );
+        let slot_is_occupied = self.gate().not(ctx, Existing(&slot_is_empty));
+        let leaf_check =
+            self.gate().is_equal(ctx, Existing(&leaf_path_is_equal), Existing(&slot_is_occupied));
+        let shape_check = self.path_has_frag_shape(
+            ctx,
+            &leaf_parsed.key_path.field_trace,
+            &key_frag_leaf_bytes_rlc.len,
+            &leaf_frag_is_odd,
+        );
+        let leaf_check = self.gate().and(ctx, Existing(&leaf_check), Existing(&shape_check));
+        let no_leaf = self.gate().not(ctx, Existing(&has_leaf));
+        let leaf_check = self.gate().or(ctx, Existing(&leaf_check), Existing(&no_leaf));
+        self.gate().assert_is_const(ctx, &leaf_check, F::one());
 
         // Check key fragments concatenate to key using hex RLC
-        let key_hex_rlc = self.rlp.rlc.compute_rlc_fixed_len(ctx, self.gate(), key_hexs);
         let fragment_rlcs = key_frag
-            .into_iter()
             .into_iter()
             .zip(witness.frag_lens.into_iter())
-            .map(|(key_frag, frag_lens)| {
-                self.rlc().compute_rlc(ctx, self.gate(), key_frag.nibbles, frag_lens)
+            .enumerate()
+            .map(|(idx, (key_frag, frag_len))| {
+                // the fragment of a branch is a single nibble, unless the leaf fragment is at this index
+                let frag_len = if idx < max_depth - 1 {
+                    let is_leaf_frag =
+                        self.gate().and(ctx, Existing(&is_last[idx]), Existing(&has_leaf));
+                    let is_not_branch_frag = self.gate().or(
+                        ctx,
+                        Existing(&nodes[idx].node_type),
+                        Existing(&is_leaf_frag),
+                    );
+                    self.gate().select(
+                        ctx,
+                        Existing(&frag_len),
+                        Constant(F::one()),
+                        Existing(&is_not_branch_frag),
+                    )
+                } else {
+                    frag_len
+                };
+                self.rlc().compute_rlc(ctx, self.gate(), key_frag.nibbles, frag_len)
             })
             .collect_vec();
+        // an exclusion proof only shows that the fragments concatenate to a prefix of the key
+        let frag_len_sums =
+            self.gate().sum_with_assignments(ctx, fragment_rlcs.iter().map(|f| Existing(&f.len)));
+        let key_prefix_len = self.gate().select_from_idx(
+            ctx,
+            frag_len_sums.iter().step_by(3).map(Existing),
+            Existing(&depth_minus_one),
+        );
+        self.range().check_less_than_safe(ctx, &key_prefix_len, 2 * key_byte_len as u64 + 1);
+        let key_prefix_rlc =
+            self.rlc().compute_rlc(ctx, self.gate(), key_hexs.clone(), key_prefix_len);
+        let (key_hex_rlc, key_hex_len) = match key_hex_len {
+            Some(key_hex_len) => {
+                let trace = self.rlc().compute_rlc(ctx, self.gate(), key_hexs, key_hex_len);
+                (trace.rlc_val, trace.len)
+            }
+            None => {
+                let trace = self.rlp.rlc.compute_rlc_fixed_len(ctx, self.gate(), key_hexs);
+                let len =
+                    self.gate().load_constant(ctx, self.gate().get_field_element(trace.len as u64));
+                (trace.rlc_val, len)
+            }
+        };
+        let key_hex_rlc = self.gate().select(
+            ctx,
+            Existing(&key_prefix_rlc.rlc_val),
+            Existing(&key_hex_rlc),
+            Existing(&slot_is_empty),
+        );
+        let key_hex_len = self.gate().select(
+            ctx,
+            Existing(&key_prefix_rlc.len),
+            Existing(&key_hex_len),
+            Existing(&slot_is_empty),
+        );
         self.rlp.rlc.load_rlc_cache(
             ctx,
             self.rlp.range.gate(),
             bit_length(2 * key_byte_len as u64),
         );
-        let assigned_len =
-            self.gate().load_constant(ctx, self.gate().get_field_element(key_hex_rlc.len as u64));
 
         self.rlp.rlc.constrain_rlc_concat_var(
             ctx,
             self.gate(),
             fragment_rlcs.iter().map(|f| (&f.rlc_val, &f.len, f.max_len)),
-            (&key_hex_rlc.rlc_val, &assigned_len),
+            (&key_hex_rlc, &key_hex_len),
             &depth,
             max_depth,
             self.rlc().gamma_pow_cached(),
@@ -685,18 +849,23 @@ impl<'v, F: Field> MPTChip<'v, F> {
 
         /* Check value matches. Currently value_bytes is RLC encoded
          * and value_byte_len is the RLC encoding's length
+         * The value is only checked for inclusion proofs
          */
         let value_rlc_trace =
             self.rlp.rlc.compute_rlc(ctx, self.gate(), value_bytes, value_byte_len.clone());
-
-        rlc_constrain_equal(ctx, &value_rlc_trace, &leaf_parsed.value.field_trace);
+        let value_is_equal =
+            rlc_is_equal(ctx, self.gate(), &value_rlc_trace, &leaf_parsed.value.field_trace);
+        let value_check = self.gate().or(ctx, Existing(&value_is_equal), Existing(&slot_is_empty));
+        self.gate().assert_is_const(ctx, &value_check, F::one());
 
         /* Check hash chains
          * hash(node[0]) = root_hash
          * hash(node[idx + 1]) is in node[idx]
-         * hash(leaf_bytes) is in node[depth - 2]
+         * hash(leaf_bytes) is in node[depth - 2] if the proof has a leaf
          */
         let mut matches = Vec::with_capacity(max_depth - 1);
+        // branch_ref_is_null[idx] = 1 iff `nodes[idx]` is an extension or a branch with an empty slot at the next key nibble
+        let mut branch_ref_is_null = Vec::with_capacity(max_depth - 1);
         // assert so later array indexing doesn't do bound check
         assert_eq!(exts_parsed.len(), max_depth - 1);
         assert_eq!(branches_parsed.len(), max_depth - 1);
@@ -712,11 +881,7 @@ impl<'v, F: Field> MPTChip<'v, F> {
                     &branches_parsed[idx].branch_hash_rlc,
                     &nodes[idx].node_type,
                 );
-                let is_leaf = self.gate().is_equal(
-                    ctx,
-                    Existing(&depth),
-                    Constant(self.gate().get_field_element((idx + 1) as u64)),
-                );
+                let is_leaf = self.gate().and(ctx, Existing(&is_last[idx]), Existing(&has_leaf));
                 node_hash_rlc = rlc_select(
                     ctx,
                     self.gate(),
@@ -724,6 +889,10 @@ impl<'v, F: Field> MPTChip<'v, F> {
                     &node_hash_rlc,
                     &is_leaf,
                 );
+            } else {
+                // an exclusion proof without a leaf has no node at depth `max_depth`
+                node_hash_rlc.rlc_val =
+                    self.gate().mul(ctx, Existing(&node_hash_rlc.rlc_val), Existing(&has_leaf));
             }
             if idx == 0 {
                 let root_hash_rlc =
@@ -746,6 +915,12 @@ impl<'v, F: Field> MPTChip<'v, F> {
                         .collect(),
                     &fragment_rlcs[idx - 1].values[0],
                 );
+                let is_null = self.gate().is_zero(ctx, &branch_ref_rlc.len);
+                branch_ref_is_null.push(self.gate().or(
+                    ctx,
+                    Existing(&is_null),
+                    Existing(&nodes[idx - 1].node_type),
+                ));
                 let match_hash_rlc = rlc_select(
                     ctx,
                     self.gate(),
@@ -767,22 +942,33 @@ impl<'v, F: Field> MPTChip<'v, F> {
             Existing(&depth_minus_one),
         );
         ctx.constrain_equal(&match_cnt, &depth_minus_one);
+
+        // An exclusion proof without a leaf must end in a branch with an empty slot at the next key nibble
+        // (extensions were checked to diverge from the key above).
+        // `select_from_idx` returns 0 when `depth = max_depth`, where there is no such node.
+        let terminal_is_null = self.gate().select_from_idx(
+            ctx,
+            branch_ref_is_null.iter().map(Existing),
+            Existing(&depth_minus_one),
+        );
+        let terminal_check = self.gate().or(ctx, Existing(&terminal_is_null), Existing(&has_leaf));
+        self.gate().assert_is_const(ctx, &terminal_check, F::one());
     }
 
-    pub fn parse_mpt_inclusion_var_key(
+    /// Returns 1 iff the hex-prefix encoded `path` has byte length `frag_byte_len` and its parity flag equals `frag_is_odd`,
+    /// i.e., `path` encodes a key fragment with the same number of nibbles as the fragment.
+    fn path_has_frag_shape(
         &self,
-        _ctx: &mut Context<'_, F>,
-        _range: &RangeConfig<F>,
-        proof: &MPTVarKeyProof<F>,
-        key_max_byte_len: usize,
-        value_max_byte_len: usize,
-        max_depth: usize,
-    ) {
-        assert_eq!(proof.key_max_byte_len, key_max_byte_len);
-        assert_eq!(proof.value_max_byte_len, value_max_byte_len);
-        assert_eq!(proof.max_depth, max_depth);
-
-        todo!()
+        ctx: &mut Context<'v, F>,
+        path: &RlcTrace<'v, F>,
+        frag_byte_len: &AssignedValue<'v, F>,
+        frag_is_odd: &AssignedValue<'v, F>,
+    ) -> AssignedValue<'v, F> {
+        let len_is_equal = self.gate().is_equal(ctx, Existing(&path.len), Existing(frag_byte_len));
+        // the first byte of the hex-prefix encoding is `16 * flag + (is_odd ? x_0 : 0)` where bit 0 of `flag` is the parity
+        let bits = self.gate().num_to_bits(ctx, &path.values[0], 8);
+        let is_odd_is_equal = self.gate().is_equal(ctx, Existing(&bits[4]), Existing(frag_is_odd));
+        self.gate().and(ctx, Existing(&len_is_equal), Existing(&is_odd_is_equal))
     }
 }
 
@@ -897,12 +1083,16 @@ pub struct MPTFixedKeyInput {
     pub root_hash: H256,
 
     pub proof: Vec<Vec<u8>>,
+    /// Whether `path` is *not* in the MPT, in which case `proof` is a proof of exclusion and `value` is ignored
+    pub slot_is_empty: bool,
 
     pub value_max_byte_len: usize,
     pub max_depth: usize,
 }
 
 lazy_static! {
+    /// RLP encoding of a dummy leaf `["", 0x0]`, used in place of the leaf by exclusion proofs that do not end in a leaf
+    pub static ref NULL_LEAF: Vec<u8> = Vec::from_hex("c3818000").unwrap();
     static ref DUMMY_BRANCH: Vec<u8> = Vec::from_hex("f1808080808080808080808080808080a0000000000000000000000000000000000000000000000000000000000000000080").unwrap();
     static ref DUMMY_EXT: Vec<u8> = Vec::from_hex(
             "e21ba00000000000000000000000000000000000000000000000000000000000000000").unwrap();
@@ -914,116 +1104,250 @@ impl MPTFixedKeyInput {
         ctx: &mut Context<'_, F>,
         gate: &impl GateInstructions<F>,
     ) -> MPTFixedKeyProof<'v, F> {
-        let Self { path, value, root_hash, proof, value_max_byte_len, max_depth } = self;
-        let depth = proof.len();
-        assert!(depth <= *max_depth);
-        let mut value = value.clone();
-        let mut proof = proof.clone();
-        let value_max_byte_len = *value_max_byte_len;
-        let max_depth = *max_depth;
-        let bytes_to_nibbles = |bytes: &[u8]| {
-            let mut nibbles = Vec::with_capacity(bytes.len() * 2);
-            for byte in bytes {
-                nibbles.push(byte >> 4);
-                nibbles.push(byte & 0xf);
-            }
-            nibbles
-        };
+        let Self { path, value, root_hash, proof, slot_is_empty, value_max_byte_len, max_depth } =
+            self;
+        assign_mpt_proof(
+            ctx,
+            gate,
+            path.as_bytes(),
+            32,
+            value,
+            root_hash,
+            proof,
+            *slot_is_empty,
+            *value_max_byte_len,
+            *max_depth,
+        )
+    }
+}
 
-        let path_nibbles = bytes_to_nibbles(path.as_bytes());
-        let mut path_idx = 0;
+/// Same as [`MPTFixedKeyInput`] except that the key `key` has variable length at most `key_max_byte_len`.
+///
+/// As an example, the transaction trie of a block has `key = rlp(transaction_index) => value = transaction`.
+#[derive(Clone, Debug)]
+pub struct MPTVarKeyInput {
+    pub key: Vec<u8>,
+    pub value: Vec<u8>,
+    pub root_hash: H256,
 
-        // below "key" and "path" are used interchangeably, sorry for confusion
-        const KEY_BYTE_LEN: usize = 32;
-        let mut leaf = proof.pop().unwrap();
-        let (_, max_leaf_bytes) = max_leaf_lens(KEY_BYTE_LEN, value_max_byte_len);
+    pub proof: Vec<Vec<u8>>,
 
-        let (_, max_ext_bytes) = max_ext_lens(KEY_BYTE_LEN);
-        let max_branch_bytes = MAX_BRANCH_LENS.1;
-        let max_node_bytes = max(max_ext_bytes, max_branch_bytes);
-
-        let mut key_frag = Vec::with_capacity(max_depth);
-        let mut nodes = Vec::with_capacity(max_depth - 1);
-        let mut process_node = |node: &[u8]| {
-            let decode = Rlp::new(node);
-            let node_type = decode.item_count().unwrap() == 2;
-            if node_type {
-                let encoded_path = decode.at(0).unwrap().data().unwrap();
-                let byte_len = encoded_path.len();
-                let encoded_nibbles = bytes_to_nibbles(encoded_path);
-                let is_odd = encoded_nibbles[0] == 1u8 || encoded_nibbles[0] == 3u8;
-                let mut frag = encoded_nibbles[2 - usize::from(is_odd)..].to_vec();
-                path_idx += frag.len();
-                frag.resize(2 * KEY_BYTE_LEN, 0);
-                key_frag.push((frag, byte_len, is_odd));
-            } else {
-                let mut frag = vec![0u8; 2 * KEY_BYTE_LEN];
-                frag[0] = path_nibbles[path_idx];
-                key_frag.push((frag, 1, true));
-                path_idx += 1;
-            }
-            node_type
-        };
-        for mut node in proof {
-            let node_type = process_node(&node);
-            node.resize(max_node_bytes, 0);
-            nodes.push((node, node_type));
+    pub key_max_byte_len: usize,
+    pub value_max_byte_len: usize,
+    pub max_depth: usize,
+}
+
+impl MPTVarKeyInput {
+    /// Builds the input for the inclusion proof `proof` of `key`, as a list of RLP encoded nodes from the root to
+    /// the leaf, e.g. from `eth_getProof` or a local trie. The root hash and the value are read from the proof.
+    ///
+    /// The maximum lengths default to those of this proof, see [`Self::with_max_lens`] to prove keys and values
+    /// of other lengths with the same circuit.
+    ///
+    /// Panics if `proof` is not an inclusion proof of `key`.
+    pub fn from_proof(key: Vec<u8>, proof: Vec<Vec<u8>>) -> Self {
+        let value = mpt_proof_value(&key, &proof);
+        let root_hash = H256(keccak256(&proof[0]));
+        Self {
+            key_max_byte_len: key.len(),
+            value_max_byte_len: value.len(),
+            max_depth: proof.len(),
+            key,
+            value,
+            root_hash,
+            proof,
         }
-        let mut dummy_branch = DUMMY_BRANCH.clone();
-        dummy_branch.resize(max_node_bytes, 0);
-        nodes.resize(max_depth - 1, (dummy_branch, false));
+    }
 
-        process_node(&leaf);
-        key_frag.resize(max_depth, (vec![0u8; 2 * KEY_BYTE_LEN], 0, false));
-        leaf.resize(max_leaf_bytes, 0);
-
-        // assign all values
-        let value_byte_len = gate.load_witness(ctx, Value::known(F::from(value.len() as u64)));
-        let depth = gate.load_witness(ctx, Value::known(F::from(depth as u64)));
-        let mut load_bytes = |bytes: &[u8]| {
-            gate.assign_witnesses(ctx, bytes.iter().map(|x| Value::known(F::from(*x as u64))))
-        };
-        let key_bytes = load_bytes(path.as_bytes());
-        value.resize(value_max_byte_len, 0);
-        let value_bytes = load_bytes(&value);
-        let root_hash_bytes = load_bytes(root_hash.as_bytes());
-        let leaf_bytes = load_bytes(&leaf);
-        let nodes = nodes
-            .into_iter()
-            .map(|(node_bytes, node_type)| {
-                let rlp_bytes = gate.assign_witnesses(
-                    ctx,
-                    node_bytes.iter().map(|x| Value::known(F::from(*x as u64))),
-                );
-                let node_type = gate.load_witness(ctx, Value::known(F::from(node_type)));
-                MPTNode { rlp_bytes, node_type }
-            })
-            .collect_vec();
-        let key_frag = key_frag
-            .into_iter()
-            .map(|(nibbles, byte_len, is_odd)| {
-                let nibbles = gate.assign_witnesses(
-                    ctx,
-                    nibbles.iter().map(|x| Value::known(F::from(*x as u64))),
-                );
-                let byte_len = gate.load_witness(ctx, Value::known(F::from(byte_len as u64)));
-                let is_odd = gate.load_witness(ctx, Value::known(F::from(is_odd)));
-                MPTKeyFragment { nibbles, is_odd, byte_len }
-            })
-            .collect_vec();
+    pub fn with_max_lens(
+        mut self,
+        key_max_byte_len: usize,
+        value_max_byte_len: usize,
+        max_depth: usize,
+    ) -> Self {
+        assert!(self.key.len() <= key_max_byte_len, "key is longer than key_max_byte_len");
+        assert!(self.value.len() <= value_max_byte_len, "value is longer than value_max_byte_len");
+        assert!(self.proof.len() <= max_depth, "proof is deeper than max_depth");
+        self.key_max_byte_len = key_max_byte_len;
+        self.value_max_byte_len = value_max_byte_len;
+        self.max_depth = max_depth;
+        self
+    }
 
-        MPTFixedKeyProof {
-            key_bytes,
-            value_bytes,
-            value_byte_len,
-            root_hash_bytes,
-            leaf_bytes,
-            nodes,
-            depth,
-            key_frag,
-            key_byte_len: KEY_BYTE_LEN,
-            value_max_byte_len,
-            max_depth,
+    pub fn assign<'v, F: Field>(
+        &self,
+        ctx: &mut Context<'_, F>,
+        gate: &impl GateInstructions<F>,
+    ) -> MPTVarKeyProof<'v, F> {
+        let Self { key, value, root_hash, proof, key_max_byte_len, value_max_byte_len, max_depth } =
+            self;
+        assert!(!key.is_empty() && key.len() <= *key_max_byte_len);
+        let proof = assign_mpt_proof(
+            ctx,
+            gate,
+            key,
+            *key_max_byte_len,
+            value,
+            root_hash,
+            proof,
+            false,
+            *value_max_byte_len,
+            *max_depth,
+        );
+        let key_byte_len = gate.load_witness(ctx, Value::known(F::from(key.len() as u64)));
+        MPTVarKeyProof { proof, key_byte_len }
+    }
+}
+
+fn bytes_to_nibbles(bytes: &[u8]) -> Vec<u8> {
+    bytes.iter().flat_map(|byte| [byte >> 4, byte & 0xf]).collect()
+}
+
+/// Checks off-circuit that `proof` is an inclusion proof of `key` whose nodes are each referenced by hash in
+/// the previous node, and returns the value in its leaf.
+pub fn mpt_proof_value(key: &[u8], proof: &[Vec<u8>]) -> Vec<u8> {
+    let root_hash = H256(keccak256(proof.first().expect("empty MPT proof")));
+    let value = trie::verify_mpt_proof(&root_hash, key, proof).expect("key is not in the MPT");
+    // the circuit only supports nodes referenced by hash, i.e. whose RLP encoding is at least 32 bytes. A node
+    // inlined in its parent only has inlined children, so this holds exactly when the proof ends in the leaf.
+    assert!(proof[1..].iter().all(|node| node.len() >= 32), "MPT node is inlined in its parent");
+    let leaf = Rlp::new(proof.last().unwrap());
+    let is_leaf = leaf.item_count() == Ok(2)
+        && trie::hex_prefix_decode(leaf.at(0).unwrap().data().unwrap()).1;
+    assert!(is_leaf, "MPT node is inlined in its parent");
+    value
+}
+
+/// Assigns the witnesses of an MPT inclusion proof of `key => value`, where `key` is right padded with 0s to `key_max_byte_len` bytes.
+///
+/// If `slot_is_empty`, `proof` is instead a proof that `key` is not in the MPT, as returned by `eth_getProof`: it ends either in a
+/// leaf whose path diverges from `key`, or in a branch or extension node where the path to `key` stops.
+#[allow(clippy::too_many_arguments)]
+fn assign_mpt_proof<'v, F: Field>(
+    ctx: &mut Context<'_, F>,
+    gate: &impl GateInstructions<F>,
+    key: &[u8],
+    key_max_byte_len: usize,
+    value: &[u8],
+    root_hash: &H256,
+    proof: &[Vec<u8>],
+    slot_is_empty: bool,
+    value_max_byte_len: usize,
+    max_depth: usize,
+) -> MPTFixedKeyProof<'v, F> {
+    let depth = proof.len();
+    // exclusion proofs in the empty trie are not supported
+    assert!(depth > 0 && depth <= max_depth);
+    let mut value = value.to_vec();
+    let mut proof = proof.to_vec();
+
+    let mut key = key.to_vec();
+    key.resize(key_max_byte_len, 0);
+    let path_nibbles = bytes_to_nibbles(&key);
+    let mut path_idx = 0;
+
+    // below "key" and "path" are used interchangeably, sorry for confusion
+    let is_leaf = |node: &[u8]| {
+        let decode = Rlp::new(node);
+        decode.item_count().unwrap() == 2 && decode.at(0).unwrap().data().unwrap()[0] >> 4 >= 2
+    };
+    let has_leaf = !slot_is_empty || is_leaf(proof.last().unwrap());
+    let mut leaf = if has_leaf { proof.pop().unwrap() } else { NULL_LEAF.clone() };
+    if !has_leaf {
+        assert!(depth < max_depth);
+    }
+    let (_, max_leaf_bytes) = max_leaf_lens(key_max_byte_len, value_max_byte_len);
+
+    let (_, max_ext_bytes) = max_ext_lens(key_max_byte_len);
+    let max_branch_bytes = MAX_BRANCH_LENS.1;
+    let max_node_bytes = max(max_ext_bytes, max_branch_bytes);
+
+    let mut key_frag = Vec::with_capacity(max_depth);
+    let mut nodes = Vec::with_capacity(max_depth - 1);
+    let mut process_node = |node: &[u8]| {
+        let decode = Rlp::new(node);
+        let node_type = decode.item_count().unwrap() == 2;
+        if node_type {
+            let encoded_path = decode.at(0).unwrap().data().unwrap();
+            let byte_len = encoded_path.len();
+            let encoded_nibbles = bytes_to_nibbles(encoded_path);
+            let is_odd = encoded_nibbles[0] == 1u8 || encoded_nibbles[0] == 3u8;
+            let frag_len = encoded_nibbles.len() - 2 + usize::from(is_odd);
+            // take the fragment from the key: it equals the node path unless this is the node where an exclusion proof diverges
+            let frag_end = min(path_idx + frag_len, path_nibbles.len());
+            let mut frag = path_nibbles[path_idx..frag_end].to_vec();
+            path_idx += frag_len;
+            frag.resize(2 * key_max_byte_len, 0);
+            key_frag.push((frag, byte_len, is_odd));
+        } else {
+            let mut frag = vec![0u8; 2 * key_max_byte_len];
+            frag[0] = path_nibbles[path_idx];
+            key_frag.push((frag, 1, true));
+            path_idx += 1;
         }
+        node_type
+    };
+    for mut node in proof {
+        let node_type = process_node(&node);
+        node.resize(max_node_bytes, 0);
+        nodes.push((node, node_type));
+    }
+    let mut dummy_branch = DUMMY_BRANCH.clone();
+    dummy_branch.resize(max_node_bytes, 0);
+    nodes.resize(max_depth - 1, (dummy_branch, false));
+
+    if has_leaf {
+        process_node(&leaf);
+    }
+    key_frag.resize(max_depth, (vec![0u8; 2 * key_max_byte_len], 0, false));
+    leaf.resize(max_leaf_bytes, 0);
+
+    // assign all values
+    let value_byte_len = gate.load_witness(ctx, Value::known(F::from(value.len() as u64)));
+    let depth = gate.load_witness(ctx, Value::known(F::from(depth as u64)));
+    let slot_is_empty = gate.load_witness(ctx, Value::known(F::from(slot_is_empty)));
+    let has_leaf = gate.load_witness(ctx, Value::known(F::from(has_leaf)));
+    let mut load_bytes = |bytes: &[u8]| {
+        gate.assign_witnesses(ctx, bytes.iter().map(|x| Value::known(F::from(*x as u64))))
+    };
+    let key_bytes = load_bytes(&key);
+    value.resize(value_max_byte_len, 0);
+    let value_bytes = load_bytes(&value);
+    let root_hash_bytes = load_bytes(root_hash.as_bytes());
+    let leaf_bytes = load_bytes(&leaf);
+    let nodes = nodes
+        .into_iter()
+        .map(|(node_bytes, node_type)| {
+            let rlp_bytes = gate
+                .assign_witnesses(ctx, node_bytes.iter().map(|x| Value::known(F::from(*x as u64))));
+            let node_type = gate.load_witness(ctx, Value::known(F::from(node_type)));
+            MPTNode { rlp_bytes, node_type }
+        })
+        .collect_vec();
+    let key_frag = key_frag
+        .into_iter()
+        .map(|(nibbles, byte_len, is_odd)| {
+            let nibbles = gate
+                .assign_witnesses(ctx, nibbles.iter().map(|x| Value::known(F::from(*x as u64))));
+            let byte_len = gate.load_witness(ctx, Value::known(F::from(byte_len as u64)));
+            let is_odd = gate.load_witness(ctx, Value::known(F::from(is_odd)));
+            MPTKeyFragment { nibbles, is_odd, byte_len }
+        })
+        .collect_vec();
+
+    MPTFixedKeyProof {
+        key_bytes,
+        value_bytes,
+        value_byte_len,
+        root_hash_bytes,
+        leaf_bytes,
+        nodes,
+        depth,
+        slot_is_empty,
+        has_leaf,
+        key_frag,
+        key_byte_len: key_max_byte_len,
+        value_max_byte_len,
+        max_depth,
     }
 }
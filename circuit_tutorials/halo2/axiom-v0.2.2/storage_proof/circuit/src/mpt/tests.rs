@@ -143,6 +143,7 @@ impl<F: ScalarField> Default for MPTCircuit<F> {
                 value,
                 root_hash: H256::from_slice(&Vec::from_hex(&root_hash_str[2..]).unwrap()),
                 proof,
+                slot_is_empty: false,
                 value_max_byte_len,
                 max_depth,
             },
@@ -162,6 +163,509 @@ pub fn test_mock_mpt_inclusion_fixed() {
     MockProver::run(k, &circuit, vec![]).unwrap().assert_satisfied();
 }
 
+fn key_nibbles(key: &H256) -> Vec<u8> {
+    key.as_bytes().iter().flat_map(|byte| [byte >> 4, byte & 0xf]).collect()
+}
+
+/// Returns the leaf for `key => value` whose path is the key nibbles after the first `start`.
+fn mock_leaf(key: &H256, start: usize, value: &[u8]) -> Vec<u8> {
+    mock_leaf_from_nibbles(&key_nibbles(key)[start..], value)
+}
+
+/// Returns the leaf with path `nibbles` and value `value`.
+fn mock_leaf_from_nibbles(nibbles: &[u8], value: &[u8]) -> Vec<u8> {
+    let is_odd = nibbles.len() % 2;
+    // hex-prefix encoding of a leaf path
+    let mut path = vec![0x20 | (0x10 * is_odd as u8) | if is_odd == 1 { nibbles[0] } else { 0 }];
+    path.extend(nibbles[is_odd..].chunks(2).map(|pair| pair[0] << 4 | pair[1]));
+    let mut stream = ::rlp::RlpStream::new_list(2);
+    stream.append(&path).append(&value.to_vec());
+    stream.out().to_vec()
+}
+
+/// Returns a branch with `children[idx] = (nibble, node)` referenced by hash.
+fn mock_branch(children: &[(u8, Vec<u8>)]) -> Vec<u8> {
+    let mut stream = ::rlp::RlpStream::new_list(17);
+    for nibble in 0..16 {
+        match children.iter().find(|(idx, _)| *idx == nibble) {
+            Some((_, node)) => stream.append(&ethers_core::utils::keccak256(node).to_vec()),
+            None => stream.append_empty_data(),
+        };
+    }
+    stream.append_empty_data();
+    stream.out().to_vec()
+}
+
+/// A trie whose root is a branch with two leaves, at keys `0x11..11` and `0x22..22`.
+/// Returns `(root_hash, branch, [leaf_0, leaf_1])`.
+fn mock_two_leaf_trie() -> (H256, Vec<u8>, [Vec<u8>; 2]) {
+    let value = ::rlp::encode(&vec![0x2au8]).to_vec();
+    let leaves = [0x11, 0x22].map(|byte| mock_leaf(&H256::repeat_byte(byte), 1, &value));
+    let branch = mock_branch(&[(1, leaves[0].clone()), (2, leaves[1].clone())]);
+    (H256(ethers_core::utils::keccak256(&branch)), branch, leaves)
+}
+
+fn mock_exclusion_circuit(path: H256, root_hash: H256, proof: Vec<Vec<u8>>) -> MPTCircuit<Fr> {
+    MPTCircuit {
+        inputs: MPTFixedKeyInput {
+            path,
+            value: vec![0x80],
+            root_hash,
+            proof,
+            slot_is_empty: true,
+            value_max_byte_len: 33,
+            max_depth: 8,
+        },
+        _marker: PhantomData,
+    }
+}
+
+#[test]
+pub fn test_mock_mpt_exclusion_branch_terminated() {
+    let params: EthConfigParams =
+        serde_json::from_reader(File::open("configs/tests/mpt.json").unwrap()).unwrap();
+    let (root_hash, branch, _) = mock_two_leaf_trie();
+    // the root branch has no child at the first nibble 3
+    let circuit = mock_exclusion_circuit(H256::repeat_byte(0x33), root_hash, vec![branch]);
+    MockProver::run(params.degree, &circuit, vec![]).unwrap().assert_satisfied();
+}
+
+#[test]
+pub fn test_mock_mpt_exclusion_mismatched_leaf() {
+    let params: EthConfigParams =
+        serde_json::from_reader(File::open("configs/tests/mpt.json").unwrap()).unwrap();
+    let (root_hash, branch, [leaf, _]) = mock_two_leaf_trie();
+    // the path to the key ends at the leaf for `0x11..11`
+    let mut path = H256::repeat_byte(0x44);
+    path.0[0] = 0x11;
+    let circuit = mock_exclusion_circuit(path, root_hash, vec![branch, leaf]);
+    MockProver::run(params.degree, &circuit, vec![]).unwrap().assert_satisfied();
+}
+
+#[test]
+pub fn test_mock_mpt_exclusion_of_existing_key() {
+    let params: EthConfigParams =
+        serde_json::from_reader(File::open("configs/tests/mpt.json").unwrap()).unwrap();
+    let (root_hash, branch, [leaf, _]) = mock_two_leaf_trie();
+    let circuit = mock_exclusion_circuit(H256::repeat_byte(0x11), root_hash, vec![branch, leaf]);
+    assert!(MockProver::run(params.degree, &circuit, vec![]).unwrap().verify().is_err());
+}
+
+/// A trie whose root is an extension with path `[1, 1]` to a branch with two leaves, at keys `0x1111..11` and
+/// `0x1122..22`. Returns `(root_hash, extension, branch)`.
+fn mock_extension_trie() -> (H256, Vec<u8>, Vec<u8>) {
+    let value = ::rlp::encode(&vec![0x2au8]).to_vec();
+    let leaves = [0x11, 0x22].map(|byte| {
+        let mut key = H256::repeat_byte(byte);
+        key.0[0] = 0x11;
+        mock_leaf(&key, 3, &value)
+    });
+    let branch = mock_branch(&[(1, leaves[0].clone()), (2, leaves[1].clone())]);
+    let mut stream = ::rlp::RlpStream::new_list(2);
+    // hex-prefix encoding of the even extension path `[1, 1]`
+    stream.append(&vec![0x00u8, 0x11]).append(&ethers_core::utils::keccak256(&branch).to_vec());
+    let extension = stream.out().to_vec();
+    (H256(ethers_core::utils::keccak256(&extension)), extension, branch)
+}
+
+#[test]
+pub fn test_mock_mpt_exclusion_extension_terminated() {
+    let params: EthConfigParams =
+        serde_json::from_reader(File::open("configs/tests/mpt.json").unwrap()).unwrap();
+    let (root_hash, extension, branch) = mock_extension_trie();
+    // the extension path `[1, 1]` diverges from the key nibbles `[2, 2]`
+    let circuit =
+        mock_exclusion_circuit(H256::repeat_byte(0x22), root_hash, vec![extension.clone()]);
+    MockProver::run(params.degree, &circuit, vec![]).unwrap().assert_satisfied();
+    // the key follows the extension and stops at the branch, which has no child at nibble 3
+    let mut path = H256::repeat_byte(0x33);
+    path.0[0] = 0x11;
+    let circuit = mock_exclusion_circuit(path, root_hash, vec![extension, branch]);
+    MockProver::run(params.degree, &circuit, vec![]).unwrap().assert_satisfied();
+}
+
+#[test]
+pub fn test_mock_mpt_exclusion_extension_not_diverging() {
+    let params: EthConfigParams =
+        serde_json::from_reader(File::open("configs/tests/mpt.json").unwrap()).unwrap();
+    let (root_hash, extension, _) = mock_extension_trie();
+    // the key follows the extension, so the proof cannot stop there
+    let mut path = H256::repeat_byte(0x33);
+    path.0[0] = 0x11;
+    let circuit = mock_exclusion_circuit(path, root_hash, vec![extension]);
+    assert!(MockProver::run(params.degree, &circuit, vec![]).unwrap().verify().is_err());
+}
+
+#[derive(Clone, Debug)]
+pub struct MPTVarKeyCircuit<F> {
+    inputs: MPTVarKeyInput,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> Circuit<F> for MPTVarKeyCircuit<F> {
+    type Config = MPTConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        self.clone()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        let params: EthConfigParams =
+            serde_json::from_reader(File::open("configs/tests/mpt.json").unwrap()).unwrap();
+
+        MPTConfig::configure(meta, params, 0)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        config.rlp.range.load_lookup_table(&mut layouter).expect("load range lookup tables");
+        config.keccak.load_aux_tables(&mut layouter).expect("load keccak lookup tables");
+        let gamma = layouter.get_challenge(config.rlp.rlc.gamma);
+
+        let mut first_pass = SKIP_FIRST_PASS;
+        layouter
+            .assign_region(
+                || "MPT Var Test",
+                |region| {
+                    if first_pass {
+                        first_pass = false;
+                        return Ok(());
+                    }
+                    let mut chip = MPTChip::new(config, gamma);
+                    let mut aux = Context::new(
+                        region,
+                        ContextParams {
+                            num_context_ids: 2,
+                            max_rows: chip.gate().max_rows,
+                            fixed_columns: chip.gate().constants.clone(),
+                        },
+                    );
+                    let ctx = &mut aux;
+
+                    let mpt_proof = self.inputs.assign(ctx, chip.gate());
+                    let mpt_witness = chip.parse_mpt_inclusion_var_key_phase0(
+                        ctx,
+                        mpt_proof,
+                        self.inputs.key_max_byte_len,
+                        self.inputs.value_max_byte_len,
+                        self.inputs.max_depth,
+                    );
+
+                    chip.keccak.assign_phase0(&mut ctx.region);
+                    chip.range().finalize(ctx);
+                    ctx.next_phase();
+
+                    chip.get_challenge(ctx);
+                    chip.keccak.assign_phase1(ctx, &mut chip.rlp.rlc, &chip.rlp.range);
+                    chip.parse_mpt_inclusion_var_key_phase1(ctx, mpt_witness);
+                    chip.range().finalize(ctx);
+                    Ok(())
+                },
+            )
+            .unwrap();
+        Ok(())
+    }
+}
+
+/// A transaction-like trie keyed by `rlp(idx)` for `idx` in `[0, 1, 2, 128]`, i.e. keys
+/// `0x80, 0x01, 0x02, 0x8180`, with values of 40 to 100 bytes so that every node is referenced by hash.
+/// Returns `(key, value, proof)` for each key.
+fn mock_var_key_trie() -> Vec<(Vec<u8>, Vec<u8>, Vec<Vec<u8>>)> {
+    let value = |idx: u32| vec![idx as u8; 40 + 20 * (idx as usize % 4)];
+    let [leaf_0, leaf_1, leaf_2] = [0, 1, 2].map(|idx| mock_leaf_from_nibbles(&[], &value(idx)));
+    let leaf_128 = mock_leaf_from_nibbles(&[8, 0], &value(128));
+    // keys `0x01, 0x02` share the nibble 0, keys `0x80, 0x8180` share the nibble 8
+    let branch_0 = mock_branch(&[(1, leaf_1.clone()), (2, leaf_2.clone())]);
+    let branch_8 = mock_branch(&[(0, leaf_0.clone()), (1, leaf_128.clone())]);
+    let root = mock_branch(&[(0, branch_0.clone()), (8, branch_8.clone())]);
+    [(0, branch_8.clone(), leaf_0), (1, branch_0.clone(), leaf_1), (2, branch_0, leaf_2)]
+        .into_iter()
+        .chain([(128, branch_8, leaf_128)])
+        .map(|(idx, branch, leaf)| {
+            (::rlp::encode(&idx).to_vec(), value(idx), vec![root.clone(), branch, leaf])
+        })
+        .collect()
+}
+
+fn mock_var_key_circuit(key: Vec<u8>, proof: Vec<Vec<u8>>) -> MPTVarKeyCircuit<Fr> {
+    MPTVarKeyCircuit {
+        inputs: MPTVarKeyInput::from_proof(key, proof).with_max_lens(3, 100, 4),
+        _marker: PhantomData,
+    }
+}
+
+#[test]
+pub fn test_mpt_proof_value() {
+    let trie = mock_var_key_trie();
+    for (key, value, proof) in trie.iter().cloned() {
+        let input = MPTVarKeyInput::from_proof(key, proof);
+        assert_eq!(input.value, value);
+        assert_eq!(input.root_hash, H256(ethers_core::utils::keccak256(&trie[0].2[0])));
+        assert_eq!(input.max_depth, 3);
+    }
+    assert_eq!(trie[0].0, vec![0x80]);
+    assert_eq!(trie[3].0, vec![0x81, 0x80]);
+}
+
+#[test]
+#[should_panic(expected = "key is not in the MPT")]
+pub fn test_mpt_proof_value_wrong_key() {
+    let (_, _, proof) = mock_var_key_trie().swap_remove(3);
+    // `rlp(129) = 0x8181` follows the path of `0x8180` down to its leaf, whose path `[8, 0]` diverges
+    mpt_proof_value(&::rlp::encode(&129u32), &proof);
+}
+
+#[test]
+pub fn test_mock_mpt_inclusion_var_key() {
+    let params: EthConfigParams =
+        serde_json::from_reader(File::open("configs/tests/mpt.json").unwrap()).unwrap();
+    for (key, _, proof) in mock_var_key_trie() {
+        let circuit = mock_var_key_circuit(key, proof);
+        MockProver::run(params.degree, &circuit, vec![]).unwrap().assert_satisfied();
+    }
+}
+
+#[test]
+pub fn test_mock_mpt_inclusion_var_key_wrong_value() {
+    let params: EthConfigParams =
+        serde_json::from_reader(File::open("configs/tests/mpt.json").unwrap()).unwrap();
+    let (key, _, proof) = mock_var_key_trie().swap_remove(3);
+    let mut circuit = mock_var_key_circuit(key, proof);
+    circuit.inputs.value[0] ^= 1;
+    assert!(MockProver::run(params.degree, &circuit, vec![]).unwrap().verify().is_err());
+}
+
+/// A trie whose root is a branch with a single leaf at key `0x12`, referenced by hash if `value` is long enough
+/// and inlined in the branch otherwise. Returns `(root_hash, [branch, leaf])`.
+fn mock_one_leaf_trie(value: &[u8]) -> (H256, Vec<Vec<u8>>) {
+    let leaf = mock_leaf_from_nibbles(&[2], value);
+    let mut stream = ::rlp::RlpStream::new_list(17);
+    for nibble in 0..16 {
+        match nibble {
+            1 if leaf.len() < 32 => stream.append_raw(&leaf, 1),
+            1 => stream.append(&ethers_core::utils::keccak256(&leaf).to_vec()),
+            _ => stream.append_empty_data(),
+        };
+    }
+    stream.append_empty_data();
+    let branch = stream.out().to_vec();
+    (H256(ethers_core::utils::keccak256(&branch)), vec![branch, leaf])
+}
+
+#[test]
+pub fn test_mock_mpt_inclusion_var_key_inlined_leaf() {
+    let params: EthConfigParams =
+        serde_json::from_reader(File::open("configs/tests/mpt.json").unwrap()).unwrap();
+    for (value, inlined) in [(vec![0x2a; 40], false), (vec![0x2a], true)] {
+        let (root_hash, proof) = mock_one_leaf_trie(&value);
+        assert_eq!(proof[1].len() < 32, inlined);
+        // `MPTVarKeyInput::from_proof` rejects inlined nodes, so the input is built directly
+        let circuit = MPTVarKeyCircuit::<Fr> {
+            inputs: MPTVarKeyInput {
+                key: vec![0x12],
+                value,
+                root_hash,
+                proof,
+                key_max_byte_len: 3,
+                value_max_byte_len: 100,
+                max_depth: 4,
+            },
+            _marker: PhantomData,
+        };
+        let verified = MockProver::run(params.degree, &circuit, vec![]).unwrap().verify();
+        assert_eq!(verified.is_err(), inlined, "inlined: {inlined}");
+    }
+}
+
+mod trie {
+    use super::*;
+    use crate::mpt::trie::{verify_mpt_proof, PatriciaTrie};
+    use rand::{Rng, SeedableRng};
+
+    fn trie_from(items: &[(&str, &str)]) -> PatriciaTrie {
+        let mut trie = PatriciaTrie::new();
+        for (key, value) in items {
+            trie.insert(key.as_bytes(), value.as_bytes().to_vec());
+        }
+        trie
+    }
+
+    fn h256(s: &str) -> H256 {
+        H256::from_slice(&Vec::from_hex(s).unwrap())
+    }
+
+    // from the trie tests of ethereum/tests: nodes with short paths are inlined and "dog" is a prefix of
+    // "dogglesworth", so its value is in a branch
+    const DOGS: [(&str, &str); 3] =
+        [("doe", "reindeer"), ("dog", "puppy"), ("dogglesworth", "cat")];
+    const PUPPY: [(&str, &str); 4] =
+        [("do", "verb"), ("horse", "stallion"), ("doge", "coin"), ("dog", "puppy")];
+
+    #[test]
+    pub fn test_trie_root_hash() {
+        assert_eq!(
+            PatriciaTrie::new().root_hash(),
+            h256("56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421")
+        );
+        let dogs_root = h256("8aad789dff2f538bca5d8ea56e8abe10f4c7ba3a5dea95fea4cd6e7c3a1168d3");
+        let puppy_root = h256("5991bb8c6514148a29db676a14ac506cd2cd5775ace63c30a4fe457715e9ac84");
+        assert_eq!(trie_from(&DOGS).root_hash(), dogs_root);
+        let mut dogs = DOGS;
+        dogs.reverse();
+        assert_eq!(trie_from(&dogs).root_hash(), dogs_root);
+        assert_eq!(trie_from(&PUPPY).root_hash(), puppy_root);
+
+        // overwriting a value
+        let mut trie = trie_from(&PUPPY);
+        trie.insert(b"horse", b"pony".to_vec());
+        assert_ne!(trie.root_hash(), puppy_root);
+        trie.insert(b"horse", b"stallion".to_vec());
+        assert_eq!(trie.root_hash(), puppy_root);
+    }
+
+    #[test]
+    pub fn test_trie_matches_mock_tries() {
+        let (root_hash, branch, leaves) = mock_two_leaf_trie();
+        let value = ::rlp::encode(&vec![0x2au8]).to_vec();
+        let mut trie = PatriciaTrie::new();
+        for byte in [0x11, 0x22] {
+            trie.insert(H256::repeat_byte(byte).as_bytes(), value.clone());
+        }
+        assert_eq!(trie.root_hash(), root_hash);
+        assert_eq!(trie.proof(&[0x11; 32]), vec![branch, leaves[0].clone()]);
+
+        let mock_trie = mock_var_key_trie();
+        let mut trie = PatriciaTrie::new();
+        for (key, value, _) in &mock_trie {
+            trie.insert(key, value.clone());
+        }
+        for (key, value, proof) in mock_trie {
+            assert_eq!(trie.get(&key), Some(&value[..]));
+            assert_eq!(trie.proof(&key), proof);
+        }
+    }
+
+    #[test]
+    pub fn test_trie_verify_proof() {
+        for items in [&DOGS[..], &PUPPY[..]] {
+            let trie = trie_from(items);
+            let root_hash = trie.root_hash();
+            for (key, value) in items {
+                let proof = trie.proof(key.as_bytes());
+                let proved = verify_mpt_proof(&root_hash, key.as_bytes(), &proof);
+                assert_eq!(proved.as_deref(), Some(value.as_bytes()));
+            }
+            for key in ["", "d", "dogg", "doggy", "horses", "cat"] {
+                assert_eq!(trie.get(key.as_bytes()), None);
+                let proof = trie.proof(key.as_bytes());
+                assert_eq!(verify_mpt_proof(&root_hash, key.as_bytes(), &proof), None);
+            }
+        }
+        let root_hash = PatriciaTrie::new().root_hash();
+        assert_eq!(verify_mpt_proof(&root_hash, b"dog", &PatriciaTrie::new().proof(b"dog")), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "MPT node hash mismatch")]
+    pub fn test_trie_verify_proof_tampered() {
+        let trie = trie_from(&PUPPY);
+        let mut proof = trie.proof(b"horse");
+        let leaf = proof.last_mut().unwrap();
+        *leaf.last_mut().unwrap() ^= 1;
+        verify_mpt_proof(&trie.root_hash(), b"horse", &proof);
+    }
+
+    #[test]
+    #[should_panic(expected = "MPT node is inlined in its parent")]
+    pub fn test_mpt_proof_value_inlined_leaf() {
+        // the leaf of "doge" with path `[5]` and value "coin" is 7 bytes, so it is inlined in the branch of "dog"
+        let trie = trie_from(&PUPPY);
+        let proof = trie.proof(b"doge");
+        assert_eq!(verify_mpt_proof(&trie.root_hash(), b"doge", &proof).unwrap(), b"coin");
+        MPTVarKeyInput::from_proof(b"doge".to_vec(), proof);
+    }
+
+    #[test]
+    pub fn test_mpt_proof_value_random_short_keys() {
+        let mut rng = rand_chacha::ChaChaRng::from_seed([1; 32]);
+        for num_keys in [1u8, 2, 5, 16] {
+            let mut trie = PatriciaTrie::new();
+            // distinct 2 byte keys with 1 byte values
+            let items =
+                (0..num_keys).map(|idx| (vec![idx, rng.gen()], vec![rng.gen()])).collect_vec();
+            for (key, value) in &items {
+                trie.insert(key, value.clone());
+            }
+            for (key, _) in &items {
+                let value = trie.get(key).unwrap().to_vec();
+                let proof = trie.proof(key);
+                assert_eq!(verify_mpt_proof(&trie.root_hash(), key, &proof), Some(value.clone()));
+                // the leaves are at most 6 bytes, so they are inlined unless the leaf is the root
+                let proved = std::panic::catch_unwind(|| mpt_proof_value(key, &proof));
+                if num_keys == 1 {
+                    assert_eq!(proved.unwrap(), value);
+                } else {
+                    assert!(proved.is_err(), "inlined leaf of key {key:?} was accepted");
+                }
+            }
+        }
+    }
+
+    /// A random key whose first `shared_len` bytes are those of `prefix`
+    fn random_key(rng: &mut impl Rng, prefix: &[u8; 32], shared_len: usize) -> H256 {
+        let mut key = H256(rng.gen());
+        key.0[..shared_len].copy_from_slice(&prefix[..shared_len]);
+        key
+    }
+
+    /// A trie with `num_keys` random 32 byte keys sharing a prefix of `prefix_len` bytes, and half of them sharing
+    /// one more byte, so that the trie has extension nodes.
+    ///
+    /// Returns the trie, two keys in the trie and two keys not in the trie.
+    fn random_trie(
+        rng: &mut impl Rng,
+        prefix_len: usize,
+        num_keys: usize,
+    ) -> (PatriciaTrie, Vec<H256>) {
+        let prefix: [u8; 32] = rng.gen();
+        let mut trie = PatriciaTrie::new();
+        let keys = (0..num_keys)
+            .map(|idx| {
+                let key = random_key(rng, &prefix, prefix_len + idx % 2);
+                // RLP encoded slot values, as in storage tries
+                let value: [u8; 32] = rng.gen();
+                let value = &value[rng.gen_range(0..32)..];
+                trie.insert(key.as_bytes(), ::rlp::encode(&value.to_vec()).to_vec());
+                key
+            })
+            .collect_vec();
+        let absent = [random_key(rng, &prefix, 0), random_key(rng, &prefix, prefix_len + 1)];
+        (trie, keys.into_iter().take(2).chain(absent).collect())
+    }
+
+    #[test]
+    pub fn test_mock_mpt_random_tries() {
+        let params: EthConfigParams =
+            serde_json::from_reader(File::open("configs/tests/mpt.json").unwrap()).unwrap();
+        let mut rng = rand_chacha::ChaChaRng::from_seed([0; 32]);
+        for prefix_len in 0..3 {
+            let (trie, keys) = random_trie(&mut rng, prefix_len, 16);
+            for (idx, key) in keys.into_iter().enumerate() {
+                let inputs = trie.fixed_key_input(key, 33, 8);
+                assert_eq!(inputs.slot_is_empty, idx >= 2);
+                let proved = verify_mpt_proof(&inputs.root_hash, key.as_bytes(), &inputs.proof);
+                assert_eq!(proved.is_none(), inputs.slot_is_empty);
+                let circuit = MPTCircuit::<Fr> { inputs, _marker: PhantomData };
+                MockProver::run(params.degree, &circuit, vec![]).unwrap().assert_satisfied();
+            }
+        }
+    }
+}
+
 #[test]
 fn bench_mpt_inclusion_fixed() -> Result<(), Box<dyn std::error::Error>> {
     let bench_params_file = File::open("configs/bench/mpt.json").unwrap();
@@ -0,0 +1,315 @@
+//! An in-memory Merkle-Patricia trie, to generate MPT proofs without chain data, e.g. test vectors for
+//! [`MPTChip`](super::MPTChip), and an off-circuit verifier for such proofs.
+//!
+//! Proofs have the format of `eth_getProof`: the RLP encoded nodes on the path to a key, starting from the root,
+//! where nodes whose encoding is shorter than 32 bytes are inlined in their parent instead of listed.
+use super::{bytes_to_nibbles, MPTFixedKeyInput, MPTVarKeyInput};
+use ethers_core::{types::H256, utils::keccak256};
+use rlp::{Rlp, RlpStream};
+use std::{iter, mem};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+enum Node {
+    #[default]
+    Empty,
+    Leaf {
+        path: Vec<u8>,
+        value: Vec<u8>,
+    },
+    Extension {
+        path: Vec<u8>,
+        child: Box<Node>,
+    },
+    Branch {
+        children: Box<[Node; 16]>,
+        value: Option<Vec<u8>>,
+    },
+}
+
+impl Node {
+    fn empty_branch() -> Self {
+        Self::Branch { children: Default::default(), value: None }
+    }
+
+    /// The node `node` below the path `path`
+    fn with_path(path: &[u8], node: Node) -> Self {
+        if path.is_empty() {
+            node
+        } else {
+            Self::Extension { path: path.to_vec(), child: Box::new(node) }
+        }
+    }
+
+    /// Inserts `value` at the nibbles `path` below this node
+    fn insert(self, path: &[u8], value: Vec<u8>) -> Self {
+        match self {
+            Self::Empty => Self::Leaf { path: path.to_vec(), value },
+            Self::Leaf { path: leaf_path, value: leaf_value } => {
+                if leaf_path == path {
+                    return Self::Leaf { path: leaf_path, value };
+                }
+                let common = common_prefix_len(&leaf_path, path);
+                let branch = Self::empty_branch()
+                    .insert(&leaf_path[common..], leaf_value)
+                    .insert(&path[common..], value);
+                Self::with_path(&path[..common], branch)
+            }
+            Self::Extension { path: ext_path, child } => {
+                let common = common_prefix_len(&ext_path, path);
+                if common == ext_path.len() {
+                    let child = child.insert(&path[common..], value);
+                    return Self::Extension { path: ext_path, child: Box::new(child) };
+                }
+                // split the extension at the first nibble where it diverges from `path`
+                let mut children: Box<[Node; 16]> = Default::default();
+                children[ext_path[common] as usize] =
+                    Self::with_path(&ext_path[common + 1..], *child);
+                let branch = Self::Branch { children, value: None }.insert(&path[common..], value);
+                Self::with_path(&path[..common], branch)
+            }
+            Self::Branch { mut children, value: branch_value } => match path.split_first() {
+                None => Self::Branch { children, value: Some(value) },
+                Some((nibble, path)) => {
+                    let child = &mut children[*nibble as usize];
+                    *child = mem::take(child).insert(path, value);
+                    Self::Branch { children, value: branch_value }
+                }
+            },
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        match self {
+            Self::Empty => vec![0x80],
+            Self::Leaf { path, value } => {
+                let mut stream = RlpStream::new_list(2);
+                stream.append(&hex_prefix_encode(path, true)).append(value);
+                stream.out().to_vec()
+            }
+            Self::Extension { path, child } => {
+                let mut stream = RlpStream::new_list(2);
+                stream.append(&hex_prefix_encode(path, false));
+                child.append_ref(&mut stream);
+                stream.out().to_vec()
+            }
+            Self::Branch { children, value } => {
+                let mut stream = RlpStream::new_list(17);
+                for child in children.iter() {
+                    child.append_ref(&mut stream);
+                }
+                stream.append(&value.clone().unwrap_or_default());
+                stream.out().to_vec()
+            }
+        }
+    }
+
+    /// Appends the reference to this node in its parent: its hash, or the node itself if its encoding is shorter
+    /// than 32 bytes
+    fn append_ref(&self, stream: &mut RlpStream) {
+        if let Self::Empty = self {
+            stream.append_empty_data();
+            return;
+        }
+        let encoding = self.encode();
+        if encoding.len() < 32 {
+            stream.append_raw(&encoding, 1);
+        } else {
+            stream.append(&keccak256(&encoding).to_vec());
+        }
+    }
+}
+
+fn common_prefix_len(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+fn hex_prefix_encode(nibbles: &[u8], is_leaf: bool) -> Vec<u8> {
+    let is_odd = nibbles.len() % 2 == 1;
+    let flag = 2 * u8::from(is_leaf) + u8::from(is_odd);
+    let first = if is_odd { flag << 4 | nibbles[0] } else { flag << 4 };
+    iter::once(first)
+        .chain(nibbles[usize::from(is_odd)..].chunks(2).map(|pair| pair[0] << 4 | pair[1]))
+        .collect()
+}
+
+/// Returns the nibbles of the hex-prefix encoded `path` and whether it is the path of a leaf
+pub(super) fn hex_prefix_decode(path: &[u8]) -> (Vec<u8>, bool) {
+    let nibbles = bytes_to_nibbles(path);
+    let is_odd = nibbles[0] & 1 == 1;
+    (nibbles[2 - usize::from(is_odd)..].to_vec(), nibbles[0] >= 2)
+}
+
+/// An in-memory Merkle-Patricia trie from byte keys to byte values
+#[derive(Clone, Debug, Default)]
+pub struct PatriciaTrie {
+    root: Node,
+}
+
+impl PatriciaTrie {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inserts `key => value`, replacing the previous value of `key`. `value` must be nonempty.
+    pub fn insert(&mut self, key: &[u8], value: Vec<u8>) {
+        assert!(!value.is_empty(), "MPT values are nonempty");
+        self.root = mem::take(&mut self.root).insert(&bytes_to_nibbles(key), value);
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        let key = bytes_to_nibbles(key);
+        let mut path = &key[..];
+        let mut node = &self.root;
+        loop {
+            match node {
+                Node::Empty => return None,
+                Node::Leaf { path: leaf_path, value } => {
+                    return (leaf_path[..] == *path).then_some(&value[..]);
+                }
+                Node::Extension { path: ext_path, child } => {
+                    path = path.strip_prefix(&ext_path[..])?;
+                    node = child.as_ref();
+                }
+                Node::Branch { children, value } => match path.split_first() {
+                    None => return value.as_deref(),
+                    Some((nibble, rest)) => {
+                        path = rest;
+                        node = &children[*nibble as usize];
+                    }
+                },
+            }
+        }
+    }
+
+    pub fn root_hash(&self) -> H256 {
+        H256(keccak256(self.root.encode()))
+    }
+
+    /// Returns the proof of inclusion of `key` if it is in the trie, and the proof of exclusion otherwise.
+    ///
+    /// A proof of exclusion ends in the node where the path to `key` stops: a leaf or extension whose path
+    /// diverges from `key`, or a branch with an empty slot at the next nibble of `key`.
+    pub fn proof(&self, key: &[u8]) -> Vec<Vec<u8>> {
+        let key = bytes_to_nibbles(key);
+        let mut path = &key[..];
+        let mut node = &self.root;
+        let mut proof = vec![];
+        loop {
+            let encoding = node.encode();
+            if proof.is_empty() || encoding.len() >= 32 {
+                proof.push(encoding);
+            }
+            node = match node {
+                Node::Extension { path: ext_path, child } => match path.strip_prefix(&ext_path[..])
+                {
+                    Some(rest) => {
+                        path = rest;
+                        child.as_ref()
+                    }
+                    None => break,
+                },
+                Node::Branch { children, .. } => match path.split_first() {
+                    Some((nibble, rest)) => {
+                        path = rest;
+                        &children[*nibble as usize]
+                    }
+                    None => break,
+                },
+                Node::Empty | Node::Leaf { .. } => break,
+            };
+            if let Node::Empty = node {
+                break;
+            }
+        }
+        proof
+    }
+
+    /// Returns the input for the proof of `path` in [`MPTChip::parse_mpt_inclusion_fixed_key_phase0`](super::MPTChip::parse_mpt_inclusion_fixed_key_phase0),
+    /// of inclusion if `path` is in the trie and of exclusion otherwise.
+    pub fn fixed_key_input(
+        &self,
+        path: H256,
+        value_max_byte_len: usize,
+        max_depth: usize,
+    ) -> MPTFixedKeyInput {
+        let value = self.get(path.as_bytes()).map(|value| value.to_vec());
+        MPTFixedKeyInput {
+            path,
+            slot_is_empty: value.is_none(),
+            value: value.unwrap_or_else(|| vec![0x80]),
+            root_hash: self.root_hash(),
+            proof: self.proof(path.as_bytes()),
+            value_max_byte_len,
+            max_depth,
+        }
+    }
+
+    /// Returns the input for the proof of inclusion of `key`, which must be in the trie.
+    pub fn var_key_input(
+        &self,
+        key: &[u8],
+        key_max_byte_len: usize,
+        value_max_byte_len: usize,
+        max_depth: usize,
+    ) -> MPTVarKeyInput {
+        MPTVarKeyInput::from_proof(key.to_vec(), self.proof(key)).with_max_lens(
+            key_max_byte_len,
+            value_max_byte_len,
+            max_depth,
+        )
+    }
+}
+
+/// Verifies off-circuit that `proof` is a proof of inclusion or exclusion of `key` in the trie with root
+/// `root_hash`, as returned by [`PatriciaTrie::proof`] or `eth_getProof`.
+///
+/// Returns the value of `key` for a proof of inclusion and `None` for a proof of exclusion. Panics if the proof
+/// is invalid.
+pub fn verify_mpt_proof(root_hash: &H256, key: &[u8], proof: &[Vec<u8>]) -> Option<Vec<u8>> {
+    let key = bytes_to_nibbles(key);
+    let mut path_idx = 0;
+    let mut nodes = proof.iter();
+    let mut node = nodes.next().expect("empty MPT proof").clone();
+    assert_eq!(keccak256(&node), root_hash.0, "MPT root hash mismatch");
+    let value = loop {
+        let decode = Rlp::new(&node);
+        // the root of the empty trie
+        if decode.is_empty() {
+            break vec![];
+        }
+        let child = match decode.item_count().expect("MPT node is not an RLP list") {
+            17 => match key.get(path_idx) {
+                Some(nibble) => {
+                    path_idx += 1;
+                    decode.at(*nibble as usize).unwrap()
+                }
+                None => break decode.at(16).unwrap().data().unwrap().to_vec(),
+            },
+            2 => {
+                let (frag, is_leaf) = hex_prefix_decode(decode.at(0).unwrap().data().unwrap());
+                if key.get(path_idx..path_idx + frag.len()) != Some(&frag[..]) {
+                    break vec![];
+                }
+                path_idx += frag.len();
+                if is_leaf {
+                    let value = decode.at(1).unwrap().data().unwrap().to_vec();
+                    break if path_idx == key.len() { value } else { vec![] };
+                }
+                decode.at(1).unwrap()
+            }
+            _ => panic!("invalid MPT node"),
+        };
+        if child.is_empty() {
+            break vec![];
+        }
+        node = if child.is_list() {
+            child.as_raw().to_vec()
+        } else {
+            let next = nodes.next().expect("MPT proof ends before the path to the key");
+            assert_eq!(child.data().unwrap(), keccak256(next), "MPT node hash mismatch");
+            next.clone()
+        };
+    };
+    assert!(nodes.next().is_none(), "MPT proof continues after the path to the key");
+    (!value.is_empty()).then_some(value)
+}
@@ -1,8 +1,8 @@
 #![allow(unused_imports)] // until storage proof is refactored
 use crate::{
     block_header::{
-        EthBlockHeaderChainInstance, GOERLI_BLOCK_HEADER_RLP_MAX_BYTES,
-        MAINNET_BLOCK_HEADER_RLP_MAX_BYTES,
+        merkle::BlockHashMerkleProof, EthBlockHeaderChainInstance,
+        GOERLI_BLOCK_HEADER_RLP_MAX_BYTES, MAINNET_BLOCK_HEADER_RLP_MAX_BYTES,
     },
     mpt::MPTFixedKeyInput,
     storage::{EthBlockStorageInput, EthStorageInput},
@@ -14,7 +14,8 @@ use ethers_core::types::{
     H256, U256,
 };
 use ethers_core::utils::keccak256;
-use ethers_providers::{Http, Middleware, Provider};
+use ethers_providers::{Http, Middleware, Provider, ProviderError};
+use futures::{stream, Future, StreamExt, TryStreamExt};
 // use halo2_mpt::mpt::{max_branch_lens, max_leaf_lens};
 use itertools::Itertools;
 use lazy_static::__Deref;
@@ -27,42 +28,206 @@ use std::{
     io::{Read, Write},
     iter, num,
     path::Path,
+    time::Duration,
 };
-use tokio::runtime::Runtime;
+use tokio::runtime::{Handle, Runtime, RuntimeFlavor};
 
 pub const MAINNET_PROVIDER_URL: &str = "https://mainnet.infura.io/v3/";
 pub const GOERLI_PROVIDER_URL: &str = "https://goerli.infura.io/v3/";
 
+/// Name of the environmental variable holding the JSON-RPC URL of a node for `network`,
+/// e.g. `MAINNET_RPC_URL`
+pub fn rpc_url_var(network: Network) -> String {
+    format!("{}_RPC_URL", network.to_string().to_uppercase())
+}
+
+/// The JSON-RPC URL for `network` from the environment: the `rpc_url_var(network)` variable
+/// if set, otherwise the Infura endpoint if `INFURA_ID` is set.
+pub fn provider_url_from_env(network: Network) -> Option<String> {
+    provider_url_from(
+        std::env::var(rpc_url_var(network)).ok(),
+        std::env::var("INFURA_ID").ok(),
+        network,
+    )
+}
+
+/// The JSON-RPC URL for `network`: `rpc_url` if any, otherwise the Infura endpoint for
+/// `infura_id` if any. See [`provider_url_from_env`].
+pub fn provider_url_from(
+    rpc_url: Option<String>,
+    infura_id: Option<String>,
+    network: Network,
+) -> Option<String> {
+    if rpc_url.is_some() {
+        return rpc_url;
+    }
+    let provider_url = match network {
+        Network::Mainnet => MAINNET_PROVIDER_URL,
+        Network::Goerli => GOERLI_PROVIDER_URL,
+    };
+    infura_id.map(|infura_id| format!("{provider_url}{infura_id}"))
+}
+
 const ACCOUNT_PROOF_VALUE_MAX_BYTE_LEN: usize = 114;
 const STORAGE_PROOF_VALUE_MAX_BYTE_LEN: usize = 33;
 
+/// Default maximum number of JSON-RPC requests in flight when fetching a range of blocks
+pub const DEFAULT_FETCH_CONCURRENCY: usize = 16;
+/// Default number of times a failed JSON-RPC request is retried before giving up
+pub const DEFAULT_FETCH_RETRIES: usize = 5;
+const RETRY_BASE_DELAY_MS: u64 = 250;
+/// The backoff delay stops doubling after this many retries, at ~4 minutes
+const RETRY_MAX_BACKOFF_SHIFT: usize = 10;
+
+/// Runs `future` to completion from synchronous code.
+///
+/// Creating a new [`Runtime`] from inside an existing tokio runtime panics, so if we are
+/// already inside one we block in place on it instead (or, for a current-thread runtime where
+/// blocking in place is not allowed, drive the future on a fresh runtime in a scoped thread).
+pub fn block_on<Fut>(future: Fut) -> Fut::Output
+where
+    Fut: Future + Send,
+    Fut::Output: Send,
+{
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(|| handle.block_on(future))
+        }
+        Ok(_) => std::thread::scope(|s| {
+            s.spawn(|| Runtime::new().unwrap().block_on(future)).join().unwrap()
+        }),
+        Err(_) => Runtime::new().unwrap().block_on(future),
+    }
+}
+
+/// Fetches block `block_number`, retrying with exponential backoff up to `retries` times on
+/// transport errors. Returns an error if the block does not exist.
+pub async fn get_block_with_retry(
+    provider: &Provider<Http>,
+    block_number: u32,
+    retries: usize,
+) -> Result<Block<H256>, ProviderError> {
+    let mut attempt = 0;
+    loop {
+        match provider.get_block(block_number as u64).await {
+            Ok(Some(block)) => return Ok(block),
+            Ok(None) => {
+                return Err(ProviderError::CustomError(format!(
+                    "block {block_number} does not exist"
+                )))
+            }
+            Err(e) if attempt >= retries => return Err(e),
+            Err(_) => {
+                let delay = RETRY_BASE_DELAY_MS << attempt.min(RETRY_MAX_BACKOFF_SHIFT);
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// Fetches blocks `start_block_number..start_block_number + num_blocks` with at most
+/// `concurrency` requests in flight. Blocks are returned in order.
+pub async fn get_blocks(
+    provider: &Provider<Http>,
+    start_block_number: u32,
+    num_blocks: u32,
+    concurrency: usize,
+    retries: usize,
+) -> Result<Vec<Block<H256>>, ProviderError> {
+    assert!(concurrency > 0);
+    stream::iter(start_block_number..start_block_number + num_blocks)
+        .map(|block_number| get_block_with_retry(provider, block_number, retries))
+        .buffered(concurrency)
+        .try_collect()
+        .await
+}
+
+/// Checks that `blocks` form a chain, i.e., each block's `parent_hash` is the hash of the
+/// previous block and block numbers are consecutive.
+pub fn check_parent_hashes(blocks: &[Block<H256>]) -> Result<(), ProviderError> {
+    for (prev, block) in blocks.iter().tuple_windows() {
+        let number = block.number.unwrap();
+        if number != prev.number.unwrap() + 1 || block.parent_hash != prev.hash.unwrap() {
+            return Err(ProviderError::CustomError(format!(
+                "block {number} does not link to block {}: parent_hash {:?} != {:?}",
+                prev.number.unwrap(),
+                block.parent_hash,
+                prev.hash.unwrap()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Fetches the block `block_number` and, for each `(address, slots)` in `queries`, the `eth_getProof` response
+/// for the account `address` and its storage `slots` at that block.
 pub fn get_block_storage_input(
     provider: &Provider<Http>,
     block_number: u32,
-    addr: Address,
-    slots: Vec<H256>,
+    queries: Vec<(Address, Vec<H256>)>,
     acct_pf_max_depth: usize,
     storage_pf_max_depth: usize,
 ) -> EthBlockStorageInput {
-    let rt = Runtime::new().unwrap();
-
-    let block = rt.block_on(provider.get_block(block_number as u64)).unwrap().unwrap();
+    let (block, pfs) = block_on(async {
+        let block = get_block_with_retry(provider, block_number, DEFAULT_FETCH_RETRIES).await?;
+        let pfs: Vec<EIP1186ProofResponse> = stream::iter(queries)
+            .map(|(addr, slots)| {
+                provider.get_proof(addr, slots, Some(Number(BlockNumber::from(block_number))))
+            })
+            .buffered(DEFAULT_FETCH_CONCURRENCY)
+            .try_collect()
+            .await?;
+        Ok::<_, ProviderError>((block, pfs))
+    })
+    .unwrap();
     let block_hash = block.hash.unwrap();
     let block_header = get_block_rlp(&block);
 
-    let pf = rt
-        .block_on(provider.get_proof(addr, slots, Some(Number(BlockNumber::from(block_number)))))
-        .unwrap();
+    let storage = pfs
+        .into_iter()
+        .map(|pf| {
+            get_storage_input(pf, block.state_root, acct_pf_max_depth, storage_pf_max_depth)
+        })
+        .collect();
 
-    for storage_pf in pf.storage_proof.iter() {
-        println!("key: {:?}, is_assigned_slot: {}", storage_pf.key, is_assigned_slot(&storage_pf));
-    }
+    EthBlockStorageInput { block, block_number, block_hash, block_header, storage }
+}
+
+/// The account proof of `addr` at `block_number`, without storage proofs, and the code of the account.
+pub fn get_block_bytecode_input(
+    provider: &Provider<Http>,
+    block_number: u32,
+    addr: Address,
+    acct_pf_max_depth: usize,
+) -> (EthBlockStorageInput, Vec<u8>) {
+    let storage =
+        get_block_storage_input(provider, block_number, vec![(addr, vec![])], acct_pf_max_depth, 1);
+    let code = block_on(provider.get_code(addr, Some(Number(BlockNumber::from(block_number)))))
+        .unwrap();
+    (storage, code.to_vec())
+}
 
+/// Converts the `eth_getProof` response `pf` against the state root `state_root` into circuit inputs.
+pub fn get_storage_input(
+    pf: EIP1186ProofResponse,
+    state_root: H256,
+    acct_pf_max_depth: usize,
+    storage_pf_max_depth: usize,
+) -> EthStorageInput {
+    let addr = pf.address;
+    let acct_exists = is_assigned_account(&pf);
+    // the storage trie of an account that does not exist is empty, and exclusion proofs in the empty trie are not supported
+    assert!(
+        acct_exists || pf.storage_proof.is_empty(),
+        "cannot prove storage of an account that does not exist"
+    );
     let acct_pf = MPTFixedKeyInput {
         path: H256(keccak256(addr)),
         value: get_acct_rlp(&pf),
-        root_hash: block.state_root,
-        proof: pf.account_proof.into_iter().map(|x| x.to_vec()).collect(),
+        root_hash: state_root,
+        proof: pf.account_proof.iter().map(|x| x.to_vec()).collect(),
+        slot_is_empty: !acct_exists,
         value_max_byte_len: ACCOUNT_PROOF_VALUE_MAX_BYTE_LEN,
         max_depth: acct_pf_max_depth,
     };
@@ -71,6 +236,7 @@ pub fn get_block_storage_input(
         .storage_proof
         .into_iter()
         .map(|storage_pf| {
+            let slot_is_empty = !is_assigned_slot(&storage_pf);
             (
                 storage_pf.key,
                 storage_pf.value,
@@ -79,6 +245,7 @@ pub fn get_block_storage_input(
                     value: storage_pf.value.rlp_bytes().to_vec(),
                     root_hash: pf.storage_hash,
                     proof: storage_pf.proof.into_iter().map(|x| x.to_vec()).collect(),
+                    slot_is_empty,
                     value_max_byte_len: STORAGE_PROOF_VALUE_MAX_BYTE_LEN,
                     max_depth: storage_pf_max_depth,
                 },
@@ -86,68 +253,19 @@ pub fn get_block_storage_input(
         })
         .collect();
 
-    EthBlockStorageInput {
-        block,
-        block_number,
-        block_hash,
-        block_header,
-        storage: EthStorageInput { addr, acct_pf, storage_pfs },
-    }
+    let slot_locations = vec![None; storage_pfs.len()];
+    EthStorageInput { addr, acct_pf, storage_pfs, slot_locations }
 }
 
-
 pub fn saved_block_storage_input(
     json_path: &str,
 ) -> EthBlockStorageInput {
-
     let store_str = std::fs::read_to_string(json_path).unwrap();
-    let storage: serde_json::Value = serde_json::from_str(store_str.as_str()).unwrap();
-
-    let block: Block<H256> = serde_json::from_value(storage["block"].clone()).unwrap();
-    let block_hash = block.hash.unwrap();
-    let block_header = get_block_rlp(&block);
-    let block_number: u32 = block.number.unwrap().low_u32();
-
-    let pf: EIP1186ProofResponse = serde_json::from_value(storage["account"].clone()).unwrap(); //serde_json::from_str(pf_str.as_str()).unwrap();
-    let addr = pf.address;
-
-    let acct_pf = MPTFixedKeyInput {
-        path: H256(keccak256(addr)),
-        value: get_acct_rlp(&pf),
-        root_hash: block.state_root,
-        proof: pf.account_proof.into_iter().map(|x| x.to_vec()).collect(),
-        value_max_byte_len: ACCOUNT_PROOF_VALUE_MAX_BYTE_LEN,
-        max_depth: 8,
-    };
-
-    let storage_pfs = pf
-        .storage_proof
-        .into_iter()
-        .map(|storage_pf| {
-            (
-                storage_pf.key,
-                storage_pf.value,
-                MPTFixedKeyInput {
-                    path: H256(keccak256(storage_pf.key)),
-                    value: storage_pf.value.rlp_bytes().to_vec(),
-                    root_hash: pf.storage_hash,
-                    proof: storage_pf.proof.into_iter().map(|x| x.to_vec()).collect(),
-                    value_max_byte_len: STORAGE_PROOF_VALUE_MAX_BYTE_LEN,
-                    max_depth: 8,
-                },
-            )
-        })
-        .collect();
-
-    EthBlockStorageInput {
-        block,
-        block_number,
-        block_hash,
-        block_header,
-        storage: EthStorageInput { addr, acct_pf, storage_pfs },
-    }
+    saved_block_storage_input_fromstr(&store_str)
 }
 
+/// Parses a saved block and `eth_getProof` responses, given either as a single response under `"account"`
+/// or as a list of responses under `"accounts"`.
 pub fn saved_block_storage_input_fromstr(
     json_input: &str,
 ) -> EthBlockStorageInput {
@@ -159,57 +277,39 @@ pub fn saved_block_storage_input_fromstr(
     let block_header = get_block_rlp(&block);
     let block_number: u32 = block.number.unwrap().low_u32();
 
-    let pf: EIP1186ProofResponse = serde_json::from_value(storage["account"].clone()).unwrap(); //serde_json::from_str(pf_str.as_str()).unwrap();
-    let addr = pf.address;
-
-    let acct_pf = MPTFixedKeyInput {
-        path: H256(keccak256(addr)),
-        value: get_acct_rlp(&pf),
-        root_hash: block.state_root,
-        proof: pf.account_proof.into_iter().map(|x| x.to_vec()).collect(),
-        value_max_byte_len: ACCOUNT_PROOF_VALUE_MAX_BYTE_LEN,
-        max_depth: 8,
+    let pfs: Vec<EIP1186ProofResponse> = match storage.get("accounts") {
+        Some(accounts) => serde_json::from_value(accounts.clone()).unwrap(),
+        None => vec![serde_json::from_value(storage["account"].clone()).unwrap()],
     };
-
-    let storage_pfs = pf
-        .storage_proof
+    let storage = pfs
         .into_iter()
-        .map(|storage_pf| {
-            (
-                storage_pf.key,
-                storage_pf.value,
-                MPTFixedKeyInput {
-                    path: H256(keccak256(storage_pf.key)),
-                    value: storage_pf.value.rlp_bytes().to_vec(),
-                    root_hash: pf.storage_hash,
-                    proof: storage_pf.proof.into_iter().map(|x| x.to_vec()).collect(),
-                    value_max_byte_len: STORAGE_PROOF_VALUE_MAX_BYTE_LEN,
-                    max_depth: 8,
-                },
-            )
-        })
+        .map(|pf| get_storage_input(pf, block.state_root, 8, 8))
         .collect();
 
-    EthBlockStorageInput {
-        block,
-        block_number,
-        block_hash,
-        block_header,
-        storage: EthStorageInput { addr, acct_pf, storage_pfs },
-    }
+    EthBlockStorageInput { block, block_number, block_hash, block_header, storage }
 }
 
 pub fn is_assigned_slot(pf: &StorageProof) -> bool {
-    let key = keccak256(pf.key);
+    is_assigned_path(&keccak256(pf.key), &pf.proof)
+}
+
+/// Returns whether `pf` is a proof that the account exists in the state trie.
+pub fn is_assigned_account(pf: &EIP1186ProofResponse) -> bool {
+    is_assigned_path(&keccak256(pf.address), &pf.account_proof)
+}
+
+/// Returns whether the MPT proof `proof` ends in a leaf at `path`, i.e., whether it is an inclusion proof
+/// rather than an exclusion proof.
+pub fn is_assigned_path(path: &[u8; 32], proof: &[impl AsRef<[u8]>]) -> bool {
     let mut key_nibbles = Vec::new();
-    for byte in key {
+    for byte in path {
         key_nibbles.push(byte / 16);
         key_nibbles.push(byte % 16);
     }
     let mut key_frags = Vec::new();
     let mut path_idx = 0;
-    for node in pf.proof.iter() {
-        let rlp = Rlp::new(node);
+    for node in proof.iter() {
+        let rlp = Rlp::new(node.as_ref());
         if rlp.item_count().unwrap() == 2 {
             let path = rlp.at(0).unwrap().data().unwrap();
             let is_odd = (path[0] / 16 == 1u8) || (path[0] / 16 == 3u8);
@@ -225,6 +325,9 @@ pub fn is_assigned_slot(pf: &StorageProof) -> bool {
             }
             key_frags.extend(frag);
         } else {
+            if path_idx >= 64 {
+                return false;
+            }
             key_frags.extend(vec![key_nibbles[path_idx]]);
             path_idx += 1;
         }
@@ -250,9 +353,29 @@ pub fn get_acct_rlp(pf: &EIP1186ProofResponse) -> Vec<u8> {
     rlp.out().into()
 }
 
+/// The RLP encoded header of `block`. The header fields added after London, which `Block` does not
+/// have, are read from the other fields of the JSON-RPC response: each is only present if the
+/// fields of the earlier forks are.
 pub fn get_block_rlp(block: &Block<H256>) -> Vec<u8> {
     let base_fee = block.base_fee_per_gas;
-    let mut rlp = RlpStream::new_list(15 + usize::from(base_fee.is_some()));
+    let h256_field = |key: &str| {
+        let field = block.other.get_deserialized::<H256>(key);
+        field.map(|value| rlp::encode(&value.expect("invalid block header field")))
+    };
+    let u256_field = |key: &str| {
+        let field = block.other.get_deserialized::<U256>(key);
+        field.map(|value| rlp::encode(&value.expect("invalid block header field")))
+    };
+    let later_fields = [
+        h256_field("withdrawalsRoot"),
+        u256_field("blobGasUsed"),
+        u256_field("excessBlobGas"),
+        h256_field("parentBeaconBlockRoot"),
+        h256_field("requestsHash"),
+    ];
+    let later_fields = later_fields.into_iter().map_while(|field| field).collect_vec();
+    let mut rlp =
+        RlpStream::new_list(15 + usize::from(base_fee.is_some()) + later_fields.len());
     rlp.append(&block.parent_hash);
     rlp.append(&block.uncles_hash);
     rlp.append(&block.author.unwrap());
@@ -269,6 +392,9 @@ pub fn get_block_rlp(block: &Block<H256>) -> Vec<u8> {
     rlp.append(&block.mix_hash.unwrap());
     rlp.append(&block.nonce.unwrap());
     base_fee.map(|base_fee| rlp.append(&base_fee));
+    for field in later_fields {
+        rlp.append_raw(&field, 1);
+    }
     rlp.out().into()
 }
 
@@ -294,6 +420,27 @@ pub struct ProcessedBlock {
     pub prev_hash: H256,
 }
 
+impl ProcessedBlock {
+    /// Location of the cache of blocks `start_block_number..=end_block_number` written by
+    /// [`get_blocks_input`]
+    pub fn cache_path(chain_id: U256, start_block_number: u32, end_block_number: u32) -> String {
+        format!(
+            "./data/headers/chainid{chain_id}_{start_block_number:06x}_{end_block_number:06x}.json"
+        )
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let f = File::open(path)?;
+        Ok(serde_json::from_reader(f)?)
+    }
+
+    /// Proof that the hash of the `idx`-th block is in the merkle mountain range of the
+    /// cached blocks
+    pub fn block_hash_merkle_proof(&self, idx: usize) -> BlockHashMerkleProof {
+        BlockHashMerkleProof::new(&self.block_hashes, idx)
+    }
+}
+
 /// returns tuple of:
 ///   * vector of RLP bytes of each block
 ///   * tuple of  
@@ -303,40 +450,53 @@ pub struct ProcessedBlock {
 ///       * endBlockNumber (u32)
 ///       * merkleRoots (Vec<H256>)
 ///   * where merkleRoots is a length `max_depth + 1` vector representing a merkle mountain range, ordered largest mountain first
+///
+/// Safe to call from inside an existing tokio runtime; see [`block_on`].
 pub fn get_blocks_input(
     provider: &Provider<Http>,
     start_block_number: u32,
     num_blocks: u32,
     max_depth: usize,
 ) -> (Vec<Vec<u8>>, EthBlockHeaderChainInstance) {
+    block_on(get_blocks_input_async(
+        provider,
+        start_block_number,
+        num_blocks,
+        max_depth,
+        DEFAULT_FETCH_CONCURRENCY,
+        DEFAULT_FETCH_RETRIES,
+    ))
+    .expect("fetching block headers from provider")
+}
+
+/// Async version of [`get_blocks_input`]. Fetches uncached blocks with at most `concurrency`
+/// requests in flight, retrying each request up to `retries` times. The fetched blocks are
+/// checked to link by `parent_hash` before the [`ProcessedBlock`] cache file is written.
+pub async fn get_blocks_input_async(
+    provider: &Provider<Http>,
+    start_block_number: u32,
+    num_blocks: u32,
+    max_depth: usize,
+    concurrency: usize,
+    retries: usize,
+) -> Result<(Vec<Vec<u8>>, EthBlockHeaderChainInstance), ProviderError> {
     assert!(num_blocks <= (1 << max_depth));
     fs::create_dir_all("./data/headers").unwrap();
     let end_block_number = start_block_number + num_blocks - 1;
-    let rt = Runtime::new().unwrap();
-    let chain_id = rt.block_on(provider.get_chainid()).unwrap();
-    let path = format!(
-        "./data/headers/chainid{chain_id}_{start_block_number:06x}_{end_block_number:06x}.json"
-    );
+    let chain_id = provider.get_chainid().await?;
+    let path = ProcessedBlock::cache_path(chain_id, start_block_number, end_block_number);
 
     let ProcessedBlock { mut block_rlps, block_hashes, prev_hash } =
         if let Ok(f) = File::open(path.as_str()) {
             serde_json::from_reader(f).unwrap()
         } else {
-            let mut block_rlps = Vec::with_capacity(max_depth);
-            let mut block_hashes = Vec::with_capacity(num_blocks as usize);
-            let mut prev_hash = H256::zero();
-
-            for block_number in start_block_number..start_block_number + num_blocks {
-                let block = rt
-                    .block_on(provider.get_block(block_number as u64))
-                    .expect("get_block JSON-RPC call")
-                    .unwrap_or_else(|| panic!("block {block_number} should exist"));
-                if block_number == start_block_number {
-                    prev_hash = block.parent_hash;
-                }
-                block_hashes.push(block.hash.unwrap());
-                block_rlps.push(get_block_rlp(&block));
-            }
+            let blocks =
+                get_blocks(provider, start_block_number, num_blocks, concurrency, retries).await?;
+            check_parent_hashes(&blocks)?;
+
+            let prev_hash = blocks[0].parent_hash;
+            let block_hashes = blocks.iter().map(|block| block.hash.unwrap()).collect();
+            let block_rlps = blocks.iter().map(get_block_rlp).collect();
             // write this to file
             let file = File::create(path.as_str()).unwrap();
             let payload = ProcessedBlock { block_rlps, block_hashes, prev_hash };
@@ -357,7 +517,29 @@ pub fn get_blocks_input(
         end_block_number,
         mmr,
     );
-    (block_rlps, instance)
+    Ok((block_rlps, instance))
+}
+
+/// Proof that block `block_number` is in the merkle mountain range of the header chain
+/// `start_block_number..start_block_number + num_blocks`, as committed by
+/// `EthBlockHeaderChainCircuit`. The blocks are read from the [`ProcessedBlock`] cache,
+/// which is filled from `provider` if needed.
+pub fn get_block_hash_merkle_proof(
+    provider: &Provider<Http>,
+    start_block_number: u32,
+    num_blocks: u32,
+    block_number: u32,
+) -> BlockHashMerkleProof {
+    assert!(start_block_number <= block_number && block_number < start_block_number + num_blocks);
+    let end_block_number = start_block_number + num_blocks - 1;
+    let chain_id = block_on(provider.get_chainid()).expect("fetching chain id from provider");
+    let path = ProcessedBlock::cache_path(chain_id, start_block_number, end_block_number);
+    let processed = ProcessedBlock::read(&path).unwrap_or_else(|_| {
+        let max_depth = num_blocks.next_power_of_two().ilog2() as usize;
+        get_blocks_input(provider, start_block_number, num_blocks, max_depth);
+        ProcessedBlock::read(&path).expect("block cache should be written")
+    });
+    processed.block_hash_merkle_proof((block_number - start_block_number) as usize)
 }
 
 #[cfg(test)]
@@ -373,8 +555,79 @@ mod tests {
         )
         .expect("could not instantiate HTTP Provider");
 
-        let rt = Runtime::new().unwrap();
-        let block = rt.block_on(provider.get_block(0xef0000)).unwrap().unwrap();
+        let block = block_on(provider.get_block(0xef0000)).unwrap().unwrap();
         assert_eq!(hex::encode(get_block_rlp(&block)), "f90201a09ed65266c0958d1ba3e3be4329b41ef541391f2db0f53b99506ae1df5db86ab0a01dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d4934794388c818ca8b9251b393131c08a736a67ccb19297a0771bced6d4acaab391f3996cfb5f6475b6218759efefab7da25f77f01567446aa0339e9acc250d8aa0f041cb9f428dd18ceef89386d0c18a595bf3103caa3a4175a0371131531246fd6b266a67377943fd3ee59d82eb31c0cec6f3d76cc5421c52c2b90100bcfe8a0b973288ca19f84674b03bb7bd6350074141ae9a788099b462dd6e921a92c415f702493ac86038dcb95ab707011310e2bfca23785102478001a07eb45a03d0db880e59b17a6b06acfa006b616804f4cf97a54b164a8e029fc7cd3f9515b3400de03bc76c683d471524493149de2ae00672a27304622034819b9008044ccab685da2b2e911aa44ac8c487904834a66b743917cc267f60f4004660938122bfe1bb83424be44c1ce34af7c501a88a058466e600ebae7391e43947240b80524d52392790f263d9c85a4ae66a3ce7f73a884b4a34df06559084192fc260340a0d33663e4808450412bcbf1363dda86450b89f6f294db842e34518a84b52b4228083ef00008401c9c38083cf055784633a003780a06d81c46262890668551c0a5d37a3ecb03d6e3cc6741a7637a0043c611b3dc8658800000000000000008502615e4790");
     }
+
+    #[test]
+    fn test_block_rlp_later_forks() {
+        use crate::block_header::fields::{get_header_field_h256, BlockHeaderField};
+
+        let london = Block::<H256> {
+            author: Some(Address::repeat_byte(2)),
+            number: Some(19_426_587u64.into()),
+            logs_bloom: Some(Default::default()),
+            mix_hash: Some(H256::repeat_byte(6)),
+            nonce: Some(Default::default()),
+            base_fee_per_gas: Some(7_000_000_000u64.into()),
+            ..Default::default()
+        };
+        assert_eq!(Rlp::new(&get_block_rlp(&london)).item_count().unwrap(), 16);
+
+        let root = H256::repeat_byte(0xbe);
+        let mut json = serde_json::to_value(&london).unwrap();
+        json["withdrawalsRoot"] = serde_json::to_value(H256::repeat_byte(7)).unwrap();
+        json["blobGasUsed"] = "0x20000".into();
+        json["excessBlobGas"] = "0x0".into();
+        json["parentBeaconBlockRoot"] = serde_json::to_value(root).unwrap();
+        let cancun: Block<H256> = serde_json::from_value(json).unwrap();
+        let header = get_block_rlp(&cancun);
+        assert_eq!(Rlp::new(&header).item_count().unwrap(), 20);
+        assert!(header.len() <= MAINNET_BLOCK_HEADER_RLP_MAX_BYTES);
+        assert_eq!(get_header_field_h256(&header, BlockHeaderField::ParentBeaconBlockRoot), root);
+        assert_eq!(
+            get_header_field_h256(&header, BlockHeaderField::BlobGasUsed),
+            H256::from_low_u64_be(0x20000)
+        );
+    }
+
+    #[test]
+    fn test_provider_url_from() {
+        assert_eq!(rpc_url_var(Network::Goerli), "GOERLI_RPC_URL");
+        let rpc_url = Some("http://localhost:8545".to_string());
+        let infura_id = Some("abc".to_string());
+        assert_eq!(
+            provider_url_from(rpc_url.clone(), infura_id.clone(), Network::Goerli),
+            rpc_url
+        );
+        assert_eq!(
+            provider_url_from(None, infura_id, Network::Goerli).unwrap(),
+            format!("{GOERLI_PROVIDER_URL}abc")
+        );
+        assert_eq!(provider_url_from(None, None, Network::Mainnet), None);
+    }
+
+    #[test]
+    fn test_block_on_inside_runtime() {
+        let rt = Runtime::new().unwrap();
+        let x = rt.block_on(rt.spawn(async { block_on(async { 1 + 1 }) })).unwrap();
+        assert_eq!(x, 2);
+        let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        assert_eq!(rt.block_on(async { block_on(async { 3 }) }), 3);
+    }
+
+    #[test]
+    fn test_check_parent_hashes() {
+        let mut blocks = (0..4u64)
+            .map(|i| Block::<H256> {
+                number: Some(i.into()),
+                hash: Some(H256::from_low_u64_be(i + 1)),
+                parent_hash: H256::from_low_u64_be(i),
+                ..Default::default()
+            })
+            .collect_vec();
+        assert!(check_parent_hashes(&blocks).is_ok());
+        blocks[2].parent_hash = H256::repeat_byte(0xff);
+        assert!(check_parent_hashes(&blocks).is_err());
+    }
 }
@@ -0,0 +1,1019 @@
+use crate::{
+    block_header::{
+        decompose_block_header_with_hash_phase0, EthBlockHeaderChip, EthBlockHeaderTrace,
+        EthBlockHeaderTraceWitness,
+    },
+    halo2_proofs::{
+        circuit::{Layouter, SimpleFloorPlanner, Value},
+        plonk::{Circuit, ConstraintSystem, Error},
+    },
+    mpt::{MPTVarKeyInput, MPTVarKeyProof, MPTVarKeyProofWitness},
+    rlp::{max_rlp_len_len, RlpArrayTrace, RlpArrayTraceWitness, RlpFieldTrace, RlpFieldWitness},
+    transaction::{
+        rlp_encode_index, rlp_field_is_list, strip_typed_envelope, NUM_TRANSACTION_TYPES,
+        TRANSACTION_INDEX_MAX_KEY_BYTES,
+    },
+    util::{
+        bytes_be_to_u128, bytes_be_to_uint, bytes_be_var_to_fixed, encode_addr_to_field,
+        encode_h256_to_field, AssignedH256, EthConfigParams,
+    },
+    EthChip, EthConfig, Field, Network,
+};
+#[cfg(feature = "display")]
+use ark_std::{end_timer, start_timer};
+use ethers_core::types::{Address, H256};
+use halo2_base::{
+    gates::{GateInstructions, RangeInstructions},
+    utils::bit_length,
+    AssignedValue, Context, ContextParams,
+    QuantumCell::{Constant, Existing},
+    SKIP_FIRST_PASS,
+};
+use rlp::Rlp;
+use snark_verifier_sdk::CircuitExt;
+use std::marker::PhantomData;
+
+#[cfg(test)]
+mod tests;
+
+// Receipt: [status, cumulativeGasUsed, logsBloom, logs]
+// Only post-Byzantium receipts, where the first field is the status instead of the intermediate state root, are supported.
+pub const RECEIPT_NUM_FIELDS: usize = 4;
+pub const RECEIPT_CUMULATIVE_GAS_MAX_BYTES: usize = 8;
+pub const RECEIPT_LOGS_IDX: usize = 3;
+
+/// Returns the maximum byte length of each field of the receipt RLP list, where the list of logs counts as a single field,
+/// and the maximum byte length of the receipt, including the type byte of typed receipts.
+pub fn max_receipt_lens(max_logs_bytes: usize) -> (Vec<usize>, usize) {
+    let max_field_bytes = vec![1, RECEIPT_CUMULATIVE_GAS_MAX_BYTES, 256, max_logs_bytes];
+    let max_field_rlp_bytes: usize =
+        max_field_bytes.iter().map(|len| 1 + max_rlp_len_len(*len) + len).sum();
+    let max_receipt_bytes = 1 + 1 + max_rlp_len_len(max_field_rlp_bytes) + max_field_rlp_bytes;
+    (max_field_bytes, max_receipt_bytes)
+}
+
+// Log: [address, [topic_0, ..., topic_{n-1}], data] with n <= 4
+pub const LOG_NUM_FIELDS: usize = 3;
+pub const LOG_ADDRESS_BYTES: usize = 20;
+pub const LOG_TOPICS_IDX: usize = 1;
+pub const LOG_DATA_IDX: usize = 2;
+pub const LOG_MAX_TOPICS: usize = 4;
+
+/// Returns the maximum byte length of each field of a log RLP list, where the list of topics counts as a single field,
+/// and the maximum byte length of the payload of the log RLP list.
+pub fn max_log_lens(max_data_bytes: usize) -> (Vec<usize>, usize) {
+    let max_field_bytes = vec![LOG_ADDRESS_BYTES, LOG_MAX_TOPICS * 33, max_data_bytes];
+    let max_log_bytes: usize =
+        max_field_bytes.iter().map(|len| 1 + max_rlp_len_len(*len) + len).sum();
+    (max_field_bytes, max_log_bytes)
+}
+
+/// Returns the `max_logs_bytes` of a receipt with at most `max_num_logs` logs, each with at most `max_data_bytes` bytes of data.
+pub fn max_logs_bytes(max_num_logs: usize, max_data_bytes: usize) -> usize {
+    let (_, max_log_bytes) = max_log_lens(max_data_bytes);
+    max_num_logs * (1 + max_rlp_len_len(max_log_bytes) + max_log_bytes)
+}
+
+#[derive(Clone, Debug)]
+pub struct EthReceiptTrace<'v, F: Field> {
+    pub tx_type: AssignedValue<'v, F>,
+    pub status: RlpFieldTrace<'v, F>,
+    pub cumulative_gas_used: RlpFieldTrace<'v, F>,
+    pub logs_bloom: RlpFieldTrace<'v, F>,
+    /// The payload of the RLP list of logs, i.e., the concatenation of the RLP encoded logs
+    pub logs: RlpFieldTrace<'v, F>,
+}
+
+#[derive(Clone, Debug)]
+pub struct EthReceiptTraceWitness<'v, F: Field> {
+    pub tx_type: AssignedValue<'v, F>,
+    pub array_witness: RlpArrayTraceWitness<'v, F>,
+    mpt_witness: MPTVarKeyProofWitness<'v, F>,
+}
+
+#[derive(Clone, Debug)]
+pub struct EthBlockReceiptTrace<'v, F: Field> {
+    pub block_trace: EthBlockHeaderTrace<'v, F>,
+    pub receipt_trace: EthReceiptTrace<'v, F>,
+    pub digest: EthReceiptDigest<'v, F>,
+}
+
+#[derive(Clone, Debug)]
+pub struct EthBlockReceiptTraceWitness<'v, F: Field> {
+    pub block_witness: EthBlockHeaderTraceWitness<'v, F>,
+    pub receipt_witness: EthReceiptTraceWitness<'v, F>,
+    pub digest: EthReceiptDigest<'v, F>,
+}
+
+#[derive(Clone, Debug)]
+pub struct EthReceiptDigest<'v, F: Field> {
+    pub block_hash: AssignedH256<'v, F>,
+    pub block_number: AssignedValue<'v, F>,
+    pub tx_index: AssignedValue<'v, F>,
+    pub tx_type: AssignedValue<'v, F>,
+    pub status: AssignedValue<'v, F>,
+    pub cumulative_gas_used: AssignedValue<'v, F>,
+}
+
+#[derive(Clone, Debug)]
+pub struct EthReceiptLogTrace<'v, F: Field> {
+    /// Trace of `[address, topics, data]`
+    pub log_trace: RlpArrayTrace<'v, F>,
+    pub topics_trace: RlpArrayTrace<'v, F>,
+}
+
+#[derive(Clone, Debug)]
+pub struct EthReceiptLogTraceWitness<'v, F: Field> {
+    logs_rlp: (Vec<AssignedValue<'v, F>>, AssignedValue<'v, F>),
+    pub logs_witness: RlpArrayTraceWitness<'v, F>,
+    log_rlps: Vec<(Vec<AssignedValue<'v, F>>, AssignedValue<'v, F>)>,
+    pub log_witness: RlpArrayTraceWitness<'v, F>,
+    topics_rlp: (Vec<AssignedValue<'v, F>>, AssignedValue<'v, F>),
+    pub topics_witness: RlpArrayTraceWitness<'v, F>,
+}
+
+#[derive(Clone, Debug)]
+pub struct EthBlockReceiptLogTrace<'v, F: Field> {
+    pub receipt_trace: EthBlockReceiptTrace<'v, F>,
+    pub log_trace: EthReceiptLogTrace<'v, F>,
+    pub digest: EthReceiptLogDigest<'v, F>,
+}
+
+#[derive(Clone, Debug)]
+pub struct EthBlockReceiptLogTraceWitness<'v, F: Field> {
+    pub receipt_witness: EthBlockReceiptTraceWitness<'v, F>,
+    pub log_witness: EthReceiptLogTraceWitness<'v, F>,
+    pub digest: EthReceiptLogDigest<'v, F>,
+}
+
+#[derive(Clone, Debug)]
+pub struct EthReceiptLogDigest<'v, F: Field> {
+    pub block_hash: AssignedH256<'v, F>,
+    pub block_number: AssignedValue<'v, F>,
+    pub tx_index: AssignedValue<'v, F>,
+    pub log_idx: AssignedValue<'v, F>,
+    pub address: AssignedValue<'v, F>,
+    pub num_topics: AssignedValue<'v, F>,
+    /// Always has length `LOG_MAX_TOPICS`, with topics beyond `num_topics` equal to 0
+    pub topics: Vec<AssignedH256<'v, F>>,
+    pub data_word_idx: AssignedValue<'v, F>,
+    /// The 32-byte words `data[32 * data_word_idx..32 * (data_word_idx + data_words.len())]`
+    pub data_words: Vec<AssignedH256<'v, F>>,
+}
+
+pub trait EthReceiptChip<'v, F: Field> {
+    fn parse_receipt_proof_phase0(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        receipts_root_bytes: &[AssignedValue<'v, F>],
+        tx_index: &AssignedValue<'v, F>,
+        proof: MPTVarKeyProof<'v, F>,
+        max_logs_bytes: usize,
+    ) -> EthReceiptTraceWitness<'v, F>;
+
+    fn parse_receipt_proof_phase1(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        witness: EthReceiptTraceWitness<'v, F>,
+    ) -> EthReceiptTrace<'v, F>;
+
+    // block_hash is big-endian 16-byte
+    // inputs have H256 represented in (hi,lo) format as two u128s
+    fn parse_receipt_proof_from_block_phase0(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        input: EthBlockReceiptInputAssigned<'v, F>,
+        network: Network,
+    ) -> EthBlockReceiptTraceWitness<'v, F>
+    where
+        Self: EthBlockHeaderChip<'v, F>;
+
+    fn parse_receipt_proof_from_block_phase1(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        witness: EthBlockReceiptTraceWitness<'v, F>,
+    ) -> EthBlockReceiptTrace<'v, F>
+    where
+        Self: EthBlockHeaderChip<'v, F>;
+
+    /// Decomposes the log at index `log_idx` of the receipt logs, where `logs` is the witness of the logs field of the receipt.
+    /// The receipt must have at most `max_num_logs` logs, each with at most `max_data_bytes` bytes of data.
+    fn parse_receipt_log_phase0(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        logs: &RlpFieldWitness<'v, F>,
+        log_idx: &AssignedValue<'v, F>,
+        max_num_logs: usize,
+        max_data_bytes: usize,
+    ) -> EthReceiptLogTraceWitness<'v, F>;
+
+    /// `logs` is the trace of the logs field of the receipt, from `parse_receipt_proof_phase1`.
+    fn parse_receipt_log_phase1(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        witness: EthReceiptLogTraceWitness<'v, F>,
+        logs: &RlpFieldTrace<'v, F>,
+    ) -> EthReceiptLogTrace<'v, F>;
+
+    fn parse_receipt_log_from_block_phase0(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        input: EthBlockReceiptLogInputAssigned<'v, F>,
+        network: Network,
+    ) -> EthBlockReceiptLogTraceWitness<'v, F>
+    where
+        Self: EthBlockHeaderChip<'v, F>;
+
+    fn parse_receipt_log_from_block_phase1(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        witness: EthBlockReceiptLogTraceWitness<'v, F>,
+    ) -> EthBlockReceiptLogTrace<'v, F>
+    where
+        Self: EthBlockHeaderChip<'v, F>;
+}
+
+impl<'v, F: Field> EthReceiptChip<'v, F> for EthChip<'v, F> {
+    fn parse_receipt_proof_phase0(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        receipts_root_bytes: &[AssignedValue<'v, F>],
+        tx_index: &AssignedValue<'v, F>,
+        proof: MPTVarKeyProof<'v, F>,
+        max_logs_bytes: usize,
+    ) -> EthReceiptTraceWitness<'v, F> {
+        let (max_field_bytes, max_receipt_bytes) = max_receipt_lens(max_logs_bytes);
+        assert_eq!(TRANSACTION_INDEX_MAX_KEY_BYTES, proof.proof.key_byte_len);
+        assert_eq!(max_receipt_bytes, proof.proof.value_max_byte_len);
+
+        // check key is rlp(tx_index)
+        let (key_bytes, key_byte_len) = rlp_encode_index(ctx, self.range(), tx_index);
+        for (byte, key) in key_bytes.iter().zip(proof.proof.key_bytes.iter()) {
+            ctx.constrain_equal(byte, key);
+        }
+        ctx.constrain_equal(&key_byte_len, &proof.key_byte_len);
+
+        // check MPT root is receipts root
+        for (pf_root, root) in proof.proof.root_hash_bytes.iter().zip(receipts_root_bytes.iter()) {
+            ctx.constrain_equal(pf_root, root);
+        }
+
+        // parse value `tx_type || rlp([status, cumulativeGasUsed, logsBloom, logs])`
+        let (tx_type, is_typed, rlp_bytes) =
+            strip_typed_envelope(ctx, self.range(), &proof.proof.value_bytes);
+        self.range().check_less_than_safe(ctx, &tx_type, NUM_TRANSACTION_TYPES as u64);
+        let array_witness =
+            self.mpt.rlp.decompose_rlp_nested_array_phase0(ctx, rlp_bytes, &max_field_bytes, false);
+        let value_byte_len =
+            self.gate().add(ctx, Existing(&array_witness.rlp_len), Existing(&is_typed));
+        ctx.constrain_equal(&value_byte_len, &proof.proof.value_byte_len);
+
+        // check MPT inclusion for:
+        // rlp(tx_index) => tx_type || rlp(receipt)
+        let max_depth = proof.proof.max_depth;
+        let mpt_witness = self.mpt.parse_mpt_inclusion_var_key_phase0(
+            ctx,
+            proof,
+            TRANSACTION_INDEX_MAX_KEY_BYTES,
+            max_receipt_bytes,
+            max_depth,
+        );
+
+        EthReceiptTraceWitness { tx_type, array_witness, mpt_witness }
+    }
+
+    fn parse_receipt_proof_phase1(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        witness: EthReceiptTraceWitness<'v, F>,
+    ) -> EthReceiptTrace<'v, F> {
+        self.mpt.parse_mpt_inclusion_var_key_phase1(ctx, witness.mpt_witness);
+        let field_trace: [_; RECEIPT_NUM_FIELDS] = self
+            .mpt
+            .rlp
+            .decompose_rlp_array_phase1(ctx, witness.array_witness, false)
+            .field_trace
+            .try_into()
+            .unwrap();
+        // only the logs are a list
+        for (idx, field) in field_trace.iter().enumerate() {
+            let is_list = rlp_field_is_list(ctx, self.range(), field);
+            self.gate().assert_is_const(ctx, &is_list, F::from(idx == RECEIPT_LOGS_IDX));
+        }
+        let [status, cumulative_gas_used, logs_bloom, logs] = field_trace;
+        EthReceiptTrace { tx_type: witness.tx_type, status, cumulative_gas_used, logs_bloom, logs }
+    }
+
+    fn parse_receipt_proof_from_block_phase0(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        input: EthBlockReceiptInputAssigned<'v, F>,
+        network: Network,
+    ) -> EthBlockReceiptTraceWitness<'v, F>
+    where
+        Self: EthBlockHeaderChip<'v, F>,
+    {
+        let block_hash = input.block_hash;
+        let (block_witness, block_number) = decompose_block_header_with_hash_phase0(
+            self,
+            ctx,
+            input.block_header,
+            &block_hash,
+            network,
+        );
+        let receipts_root = &block_witness.rlp_witness.field_witness[5].field_cells;
+
+        let EthReceiptInputAssigned { tx_index, proof, max_logs_bytes } = input.receipt;
+        let receipt_witness =
+            self.parse_receipt_proof_phase0(ctx, receipts_root, &tx_index, proof, max_logs_bytes);
+        let tx_type = receipt_witness.tx_type.clone();
+        let fields = &receipt_witness.array_witness.field_witness;
+
+        // status is either 0x80 (failure) or 0x01 (success)
+        let status = self.gate().mul(
+            ctx,
+            Existing(&fields[0].field_cells[0]),
+            Existing(&fields[0].field_len),
+        );
+        self.gate().assert_bit(ctx, &status);
+
+        let cumulative_gas_used = bytes_be_var_to_fixed(
+            ctx,
+            self.gate(),
+            &fields[1].field_cells,
+            &fields[1].field_len,
+            RECEIPT_CUMULATIVE_GAS_MAX_BYTES,
+        );
+        let cumulative_gas_used = bytes_be_to_uint(
+            ctx,
+            self.gate(),
+            &cumulative_gas_used,
+            RECEIPT_CUMULATIVE_GAS_MAX_BYTES,
+        );
+
+        EthBlockReceiptTraceWitness {
+            block_witness,
+            receipt_witness,
+            digest: EthReceiptDigest {
+                block_hash,
+                block_number,
+                tx_index,
+                tx_type,
+                status,
+                cumulative_gas_used,
+            },
+        }
+    }
+
+    fn parse_receipt_proof_from_block_phase1(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        witness: EthBlockReceiptTraceWitness<'v, F>,
+    ) -> EthBlockReceiptTrace<'v, F>
+    where
+        Self: EthBlockHeaderChip<'v, F>,
+    {
+        let block_trace = self.decompose_block_header_phase1(ctx, witness.block_witness);
+        let receipt_trace = self.parse_receipt_proof_phase1(ctx, witness.receipt_witness);
+        EthBlockReceiptTrace { block_trace, receipt_trace, digest: witness.digest }
+    }
+
+    fn parse_receipt_log_phase0(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        logs: &RlpFieldWitness<'v, F>,
+        log_idx: &AssignedValue<'v, F>,
+        max_num_logs: usize,
+        max_data_bytes: usize,
+    ) -> EthReceiptLogTraceWitness<'v, F> {
+        let (max_field_bytes, max_log_bytes) = max_log_lens(max_data_bytes);
+
+        // the logs field only holds the list payload: re-assemble the full list to decompose it into logs
+        let logs_rlp = self.mpt.rlp.witness_rlp_item_phase0(ctx, logs);
+        let logs_witness = self.mpt.rlp.decompose_rlp_nested_array_phase0(
+            ctx,
+            logs_rlp.0.clone(),
+            &vec![max_log_bytes; max_num_logs],
+            true,
+        );
+        ctx.constrain_equal(&logs_witness.rlp_len, &logs_rlp.1);
+
+        // select the RLP encoding of the log at `log_idx`
+        self.range().check_less_than_safe(ctx, log_idx, max_num_logs as u64);
+        let mut log_rlps = Vec::with_capacity(max_num_logs);
+        for log in &logs_witness.field_witness {
+            log_rlps.push(self.mpt.rlp.witness_rlp_item_phase0(ctx, log));
+        }
+        let log_len = self.gate().select_from_idx(
+            ctx,
+            log_rlps.iter().map(|(_, len)| Existing(len)),
+            Existing(log_idx),
+        );
+        // a log is never empty, so `log_len = 0` means `log_idx` is beyond the number of logs
+        let log_is_empty = self.gate().is_zero(ctx, &log_len);
+        self.gate().assert_is_const(ctx, &log_is_empty, F::zero());
+        let log_rlp = (0..log_rlps[0].0.len())
+            .map(|i| {
+                self.gate().select_from_idx(
+                    ctx,
+                    log_rlps.iter().map(|(bytes, _)| Existing(&bytes[i])),
+                    Existing(log_idx),
+                )
+            })
+            .collect();
+        let log_witness =
+            self.mpt.rlp.decompose_rlp_nested_array_phase0(ctx, log_rlp, &max_field_bytes, false);
+        ctx.constrain_equal(&log_witness.rlp_len, &log_len);
+
+        let topics_rlp =
+            self.mpt.rlp.witness_rlp_item_phase0(ctx, &log_witness.field_witness[LOG_TOPICS_IDX]);
+        let topics_witness = self.mpt.rlp.decompose_rlp_array_phase0(
+            ctx,
+            topics_rlp.0.clone(),
+            &[32; LOG_MAX_TOPICS],
+            true,
+        );
+        ctx.constrain_equal(&topics_witness.rlp_len, &topics_rlp.1);
+
+        EthReceiptLogTraceWitness {
+            logs_rlp,
+            logs_witness,
+            log_rlps,
+            log_witness,
+            topics_rlp,
+            topics_witness,
+        }
+    }
+
+    fn parse_receipt_log_phase1(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        witness: EthReceiptLogTraceWitness<'v, F>,
+        logs: &RlpFieldTrace<'v, F>,
+    ) -> EthReceiptLogTrace<'v, F> {
+        let (logs_rlp, logs_rlp_len) = witness.logs_rlp;
+        self.mpt.rlp.constrain_rlp_item_phase1(ctx, logs_rlp, logs_rlp_len, logs);
+        let logs_trace = self.mpt.rlp.decompose_rlp_array_phase1(ctx, witness.logs_witness, true);
+        for ((log_rlp, log_len), log) in witness.log_rlps.into_iter().zip(&logs_trace.field_trace) {
+            self.mpt.rlp.constrain_rlp_item_phase1(ctx, log_rlp, log_len, log);
+        }
+
+        let log_trace = self.mpt.rlp.decompose_rlp_array_phase1(ctx, witness.log_witness, false);
+        // only the topics are a list
+        for (idx, field) in log_trace.field_trace.iter().enumerate() {
+            let is_list = rlp_field_is_list(ctx, self.range(), field);
+            self.gate().assert_is_const(ctx, &is_list, F::from(idx == LOG_TOPICS_IDX));
+        }
+
+        let (topics_rlp, topics_rlp_len) = witness.topics_rlp;
+        self.mpt.rlp.constrain_rlp_item_phase1(
+            ctx,
+            topics_rlp,
+            topics_rlp_len,
+            &log_trace.field_trace[LOG_TOPICS_IDX],
+        );
+        let topics_trace =
+            self.mpt.rlp.decompose_rlp_array_phase1(ctx, witness.topics_witness, true);
+        EthReceiptLogTrace { log_trace, topics_trace }
+    }
+
+    fn parse_receipt_log_from_block_phase0(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        input: EthBlockReceiptLogInputAssigned<'v, F>,
+        network: Network,
+    ) -> EthBlockReceiptLogTraceWitness<'v, F>
+    where
+        Self: EthBlockHeaderChip<'v, F>,
+    {
+        let EthBlockReceiptLogInputAssigned {
+            receipt,
+            log_idx,
+            data_word_idx,
+            num_data_words,
+            max_num_logs,
+            max_data_bytes,
+        } = input;
+        let receipt_witness = self.parse_receipt_proof_from_block_phase0(ctx, receipt, network);
+        let log_witness = self.parse_receipt_log_phase0(
+            ctx,
+            &receipt_witness.receipt_witness.array_witness.field_witness[RECEIPT_LOGS_IDX],
+            &log_idx,
+            max_num_logs,
+            max_data_bytes,
+        );
+        let fields = &log_witness.log_witness.field_witness;
+
+        let address = &fields[0];
+        self.gate().assert_is_const(ctx, &address.field_len, F::from(LOG_ADDRESS_BYTES as u64));
+        let address = bytes_be_to_uint(ctx, self.gate(), &address.field_cells, LOG_ADDRESS_BYTES);
+
+        // every topic is 32 bytes, so the topics list payload has length `33 * num_topics`
+        let topics_witness = &log_witness.topics_witness;
+        let mut topics = Vec::with_capacity(LOG_MAX_TOPICS);
+        let mut topic_flags = Vec::with_capacity(LOG_MAX_TOPICS);
+        for topic in &topics_witness.field_witness {
+            let is_topic =
+                self.gate().is_equal(ctx, Existing(&topic.field_len), Constant(F::from(32)));
+            let topic_len = self.gate().mul(ctx, Existing(&is_topic), Constant(F::from(32)));
+            ctx.constrain_equal(&topic_len, &topic.field_len);
+            let topic =
+                bytes_be_var_to_fixed(ctx, self.gate(), &topic.field_cells, &topic.field_len, 32);
+            topics.push(bytes_be_to_u128(ctx, self.gate(), &topic).try_into().unwrap());
+            topic_flags.push(is_topic);
+        }
+        let num_topics = self.gate().sum(ctx, topic_flags.iter().map(Existing));
+        let topics_payload_len = self.gate().sub(
+            ctx,
+            Existing(&topics_witness.rlp_len),
+            Existing(&topics_witness.len_len),
+        );
+        let topics_payload_len =
+            self.gate().sub(ctx, Existing(&topics_payload_len), Constant(F::one()));
+        let expected_payload_len =
+            self.gate().mul(ctx, Existing(&num_topics), Constant(F::from(33)));
+        ctx.constrain_equal(&topics_payload_len, &expected_payload_len);
+
+        // check `data[32 * data_word_idx..32 * (data_word_idx + num_data_words)]` is within the data
+        let data = &fields[LOG_DATA_IDX];
+        let max_data_words = max_data_bytes / 32;
+        self.range().check_less_than_safe(ctx, &data_word_idx, (max_data_words + 1) as u64);
+        let data_start = self.gate().mul(ctx, Existing(&data_word_idx), Constant(F::from(32)));
+        let data_end = self.gate().add(
+            ctx,
+            Existing(&data_start),
+            Constant(F::from(32 * num_data_words as u64)),
+        );
+        let data_len_plus_one = self.gate().add(ctx, Existing(&data.field_len), Constant(F::one()));
+        self.range().check_less_than(
+            ctx,
+            Existing(&data_end),
+            Existing(&data_len_plus_one),
+            bit_length((32 * (max_data_words + num_data_words) + max_data_bytes + 1) as u64),
+        );
+        let mut data_words = Vec::with_capacity(num_data_words);
+        for word_idx in 0..num_data_words {
+            let mut word = Vec::with_capacity(32);
+            for byte_idx in 0..32 {
+                let idx = self.gate().add(
+                    ctx,
+                    Existing(&data_start),
+                    Constant(F::from((32 * word_idx + byte_idx) as u64)),
+                );
+                word.push(self.gate().select_from_idx(
+                    ctx,
+                    data.field_cells.iter().map(Existing),
+                    Existing(&idx),
+                ));
+            }
+            data_words.push(bytes_be_to_u128(ctx, self.gate(), &word).try_into().unwrap());
+        }
+
+        let EthReceiptDigest { block_hash, block_number, tx_index, .. } =
+            receipt_witness.digest.clone();
+        EthBlockReceiptLogTraceWitness {
+            receipt_witness,
+            log_witness,
+            digest: EthReceiptLogDigest {
+                block_hash,
+                block_number,
+                tx_index,
+                log_idx,
+                address,
+                num_topics,
+                topics,
+                data_word_idx,
+                data_words,
+            },
+        }
+    }
+
+    fn parse_receipt_log_from_block_phase1(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        witness: EthBlockReceiptLogTraceWitness<'v, F>,
+    ) -> EthBlockReceiptLogTrace<'v, F>
+    where
+        Self: EthBlockHeaderChip<'v, F>,
+    {
+        let receipt_trace =
+            self.parse_receipt_proof_from_block_phase1(ctx, witness.receipt_witness);
+        let log_trace = self.parse_receipt_log_phase1(
+            ctx,
+            witness.log_witness,
+            &receipt_trace.receipt_trace.logs,
+        );
+        EthBlockReceiptLogTrace { receipt_trace, log_trace, digest: witness.digest }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct EthReceiptInput {
+    pub tx_index: u32,
+    /// `proof.key = rlp(tx_index)` and `proof.value` is the receipt as stored in the receipt trie:
+    /// `rlp(receipt)` for legacy transactions and `tx_type || rlp(receipt)` for typed transactions
+    pub proof: MPTVarKeyInput,
+    pub max_logs_bytes: usize,
+}
+
+impl EthReceiptInput {
+    /// Decodes `(tx_type, status, cumulative_gas_used)` from the receipt bytes.
+    pub fn decode(&self) -> (u8, u8, u64) {
+        let receipt = &self.proof.value;
+        let tx_type = if receipt[0] > 0xbf { 0 } else { receipt[0] };
+        let rlp = Rlp::new(&receipt[usize::from(tx_type != 0)..]);
+        let status: u8 = rlp.val_at(0).unwrap();
+        let cumulative_gas_used: u64 = rlp.val_at(1).unwrap();
+        (tx_type, status, cumulative_gas_used)
+    }
+
+    pub fn assign<'v, F: Field>(
+        &self,
+        ctx: &mut Context<'_, F>,
+        gate: &impl GateInstructions<F>,
+    ) -> EthReceiptInputAssigned<'v, F> {
+        let (_, max_receipt_bytes) = max_receipt_lens(self.max_logs_bytes);
+        assert_eq!(self.proof.key_max_byte_len, TRANSACTION_INDEX_MAX_KEY_BYTES);
+        assert_eq!(self.proof.value_max_byte_len, max_receipt_bytes);
+        let tx_index = gate.load_witness(ctx, Value::known(F::from(self.tx_index as u64)));
+        let proof = self.proof.assign(ctx, gate);
+        EthReceiptInputAssigned { tx_index, proof, max_logs_bytes: self.max_logs_bytes }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct EthBlockReceiptInput {
+    pub block_number: u32,
+    pub block_hash: H256,
+    pub block_header: Vec<u8>,
+    pub receipt: EthReceiptInput,
+}
+
+impl EthBlockReceiptInput {
+    pub fn assign<'v, F: Field>(
+        &self,
+        ctx: &mut Context<'_, F>,
+        gate: &impl GateInstructions<F>,
+    ) -> EthBlockReceiptInputAssigned<'v, F> {
+        let block_hash = encode_h256_to_field(&self.block_hash);
+        let block_hash =
+            block_hash.map(|block_hash| gate.load_witness(ctx, Value::known(block_hash)));
+        let receipt = self.receipt.assign(ctx, gate);
+        EthBlockReceiptInputAssigned {
+            block_hash,
+            block_header: self.block_header.clone(),
+            receipt,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct EthReceiptInputAssigned<'v, F: Field> {
+    pub tx_index: AssignedValue<'v, F>,
+    pub proof: MPTVarKeyProof<'v, F>,
+    pub max_logs_bytes: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct EthBlockReceiptInputAssigned<'v, F: Field> {
+    pub block_hash: AssignedH256<'v, F>, // H256 as (u128, u128)
+    pub block_header: Vec<u8>,
+    pub receipt: EthReceiptInputAssigned<'v, F>,
+}
+
+#[derive(Clone, Debug)]
+pub struct EthBlockReceiptLogInput {
+    pub receipt: EthBlockReceiptInput,
+    pub log_idx: u32,
+    /// Index of the first 32-byte word of the log data to expose
+    pub data_word_idx: u32,
+    pub num_data_words: usize,
+    pub max_num_logs: usize,
+    /// Maximum byte length of the data of any log in the receipt
+    pub max_data_bytes: usize,
+}
+
+impl EthBlockReceiptLogInput {
+    /// Decodes `(address, topics, data_words)` of the log at `log_idx` from the receipt bytes.
+    pub fn decode(&self) -> (Address, Vec<H256>, Vec<H256>) {
+        let receipt = &self.receipt.receipt.proof.value;
+        let rlp = Rlp::new(&receipt[usize::from(receipt[0] <= 0xbf)..]);
+        let log = rlp.at(RECEIPT_LOGS_IDX).unwrap().at(self.log_idx as usize).unwrap();
+        let address = Address::from_slice(log.at(0).unwrap().data().unwrap());
+        let topics = log
+            .at(LOG_TOPICS_IDX)
+            .unwrap()
+            .iter()
+            .map(|topic| H256::from_slice(topic.data().unwrap()))
+            .collect();
+        let data = log.at(LOG_DATA_IDX).unwrap().data().unwrap();
+        let data_start = 32 * self.data_word_idx as usize;
+        let data_words = data[data_start..data_start + 32 * self.num_data_words]
+            .chunks(32)
+            .map(H256::from_slice)
+            .collect();
+        (address, topics, data_words)
+    }
+
+    pub fn assign<'v, F: Field>(
+        &self,
+        ctx: &mut Context<'_, F>,
+        gate: &impl GateInstructions<F>,
+    ) -> EthBlockReceiptLogInputAssigned<'v, F> {
+        let receipt = self.receipt.assign(ctx, gate);
+        let [log_idx, data_word_idx] = [self.log_idx, self.data_word_idx]
+            .map(|idx| gate.load_witness(ctx, Value::known(F::from(idx as u64))));
+        EthBlockReceiptLogInputAssigned {
+            receipt,
+            log_idx,
+            data_word_idx,
+            num_data_words: self.num_data_words,
+            max_num_logs: self.max_num_logs,
+            max_data_bytes: self.max_data_bytes,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct EthBlockReceiptLogInputAssigned<'v, F: Field> {
+    pub receipt: EthBlockReceiptInputAssigned<'v, F>,
+    pub log_idx: AssignedValue<'v, F>,
+    pub data_word_idx: AssignedValue<'v, F>,
+    pub num_data_words: usize,
+    pub max_num_logs: usize,
+    pub max_data_bytes: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct EthBlockReceiptCircuit<F> {
+    pub inputs: EthBlockReceiptInput,
+    network: Network,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> EthBlockReceiptCircuit<F> {
+    pub fn new(inputs: EthBlockReceiptInput, network: Network) -> Self {
+        Self { inputs, network, _marker: PhantomData }
+    }
+
+    // blockHash, blockNumber, txIndex, txType, status, cumulativeGasUsed
+    // with H256 encoded as hi-lo (u128, u128)
+    pub fn instance(&self) -> Vec<F> {
+        let EthBlockReceiptInput { block_number, block_hash, receipt, .. } = &self.inputs;
+        let (tx_type, status, cumulative_gas_used) = receipt.decode();
+        let mut instance = Vec::with_capacity(7);
+        instance.extend(encode_h256_to_field::<F>(block_hash));
+        instance.push(F::from(*block_number as u64));
+        instance.push(F::from(receipt.tx_index as u64));
+        instance.push(F::from(tx_type as u64));
+        instance.push(F::from(status as u64));
+        instance.push(F::from(cumulative_gas_used));
+        instance
+    }
+}
+
+impl<F: Field> Circuit<F> for EthBlockReceiptCircuit<F> {
+    type Config = EthConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        self.clone()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        let params = EthConfigParams::get_receipt();
+        EthConfig::configure(meta, params, 0)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        #[cfg(feature = "display")]
+        let witness_gen = start_timer!(|| "synthesize");
+
+        let gamma = layouter.get_challenge(config.rlc().gamma);
+        config.range().load_lookup_table(&mut layouter).expect("load range lookup table");
+        config.keccak().load_aux_tables(&mut layouter).expect("load keccak lookup tables");
+
+        let mut first_pass = SKIP_FIRST_PASS;
+        let mut instance = vec![];
+        layouter
+            .assign_region(
+                || "receipt inclusion from blockHash",
+                |region| {
+                    if first_pass {
+                        first_pass = false;
+                        return Ok(());
+                    }
+                    let mut chip = EthChip::new(config.clone(), gamma);
+                    let mut aux = Context::new(
+                        region,
+                        ContextParams {
+                            max_rows: chip.gate().max_rows,
+                            num_context_ids: 2,
+                            fixed_columns: chip.gate().constants.clone(),
+                        },
+                    );
+                    let ctx = &mut aux;
+
+                    // ================= FIRST PHASE ================
+                    let input = self.inputs.assign(ctx, chip.gate());
+                    let witness =
+                        chip.parse_receipt_proof_from_block_phase0(ctx, input, self.network);
+                    chip.assign_phase0(ctx);
+                    ctx.next_phase();
+
+                    // ================= SECOND PHASE ================
+                    chip.get_challenge(ctx);
+                    chip.keccak_assign_phase1(ctx);
+
+                    let trace = chip.parse_receipt_proof_from_block_phase1(ctx, witness);
+                    let EthReceiptDigest {
+                        block_hash,
+                        block_number,
+                        tx_index,
+                        tx_type,
+                        status,
+                        cumulative_gas_used,
+                    } = trace.digest;
+                    chip.range().finalize(ctx);
+
+                    instance.extend(
+                        block_hash
+                            .iter()
+                            .chain(
+                                [block_number, tx_index, tx_type, status, cumulative_gas_used]
+                                    .iter(),
+                            )
+                            .map(|acell| acell.cell().clone()),
+                    );
+
+                    #[cfg(feature = "display")]
+                    ctx.print_stats(&["Range", "RLC"]);
+                    Ok(())
+                },
+            )
+            .unwrap();
+        for (i, cell) in instance.into_iter().enumerate() {
+            layouter.constrain_instance(cell, config.instance, i);
+        }
+        #[cfg(feature = "display")]
+        end_timer!(witness_gen);
+        Ok(())
+    }
+}
+
+impl<F: Field> CircuitExt<F> for EthBlockReceiptCircuit<F> {
+    fn num_instance(&self) -> Vec<usize> {
+        vec![7]
+    }
+
+    fn instances(&self) -> Vec<Vec<F>> {
+        vec![self.instance()]
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct EthBlockReceiptLogCircuit<F> {
+    pub inputs: EthBlockReceiptLogInput,
+    network: Network,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> EthBlockReceiptLogCircuit<F> {
+    pub fn new(inputs: EthBlockReceiptLogInput, network: Network) -> Self {
+        Self { inputs, network, _marker: PhantomData }
+    }
+
+    // blockHash, blockNumber, txIndex, logIdx, address, numTopics, topics[LOG_MAX_TOPICS], dataWordIdx, dataWords
+    // with H256 encoded as hi-lo (u128, u128) and missing topics equal to 0
+    pub fn instance(&self) -> Vec<F> {
+        let EthBlockReceiptLogInput { receipt, log_idx, data_word_idx, num_data_words, .. } =
+            &self.inputs;
+        let (address, topics, data_words) = self.inputs.decode();
+        let mut instance = Vec::with_capacity(16 + 2 * num_data_words);
+        instance.extend(encode_h256_to_field::<F>(&receipt.block_hash));
+        instance.push(F::from(receipt.block_number as u64));
+        instance.push(F::from(receipt.receipt.tx_index as u64));
+        instance.push(F::from(*log_idx as u64));
+        instance.push(encode_addr_to_field(&address));
+        instance.push(F::from(topics.len() as u64));
+        for idx in 0..LOG_MAX_TOPICS {
+            instance
+                .extend(encode_h256_to_field::<F>(&topics.get(idx).copied().unwrap_or_default()));
+        }
+        instance.push(F::from(*data_word_idx as u64));
+        for word in &data_words {
+            instance.extend(encode_h256_to_field::<F>(word));
+        }
+        instance
+    }
+}
+
+impl<F: Field> Circuit<F> for EthBlockReceiptLogCircuit<F> {
+    type Config = EthConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        self.clone()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        let params = EthConfigParams::get_receipt_log();
+        EthConfig::configure(meta, params, 0)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        #[cfg(feature = "display")]
+        let witness_gen = start_timer!(|| "synthesize");
+
+        let gamma = layouter.get_challenge(config.rlc().gamma);
+        config.range().load_lookup_table(&mut layouter).expect("load range lookup table");
+        config.keccak().load_aux_tables(&mut layouter).expect("load keccak lookup tables");
+
+        let mut first_pass = SKIP_FIRST_PASS;
+        let mut instance = vec![];
+        layouter
+            .assign_region(
+                || "receipt log from blockHash",
+                |region| {
+                    if first_pass {
+                        first_pass = false;
+                        return Ok(());
+                    }
+                    let mut chip = EthChip::new(config.clone(), gamma);
+                    let mut aux = Context::new(
+                        region,
+                        ContextParams {
+                            max_rows: chip.gate().max_rows,
+                            num_context_ids: 2,
+                            fixed_columns: chip.gate().constants.clone(),
+                        },
+                    );
+                    let ctx = &mut aux;
+
+                    // ================= FIRST PHASE ================
+                    let input = self.inputs.assign(ctx, chip.gate());
+                    let witness =
+                        chip.parse_receipt_log_from_block_phase0(ctx, input, self.network);
+                    chip.assign_phase0(ctx);
+                    ctx.next_phase();
+
+                    // ================= SECOND PHASE ================
+                    chip.get_challenge(ctx);
+                    chip.keccak_assign_phase1(ctx);
+
+                    let trace = chip.parse_receipt_log_from_block_phase1(ctx, witness);
+                    let EthReceiptLogDigest {
+                        block_hash,
+                        block_number,
+                        tx_index,
+                        log_idx,
+                        address,
+                        num_topics,
+                        topics,
+                        data_word_idx,
+                        data_words,
+                    } = trace.digest;
+                    chip.range().finalize(ctx);
+
+                    instance.extend(
+                        block_hash
+                            .iter()
+                            .chain([block_number, tx_index, log_idx, address, num_topics].iter())
+                            .chain(topics.iter().flatten())
+                            .chain([data_word_idx].iter())
+                            .chain(data_words.iter().flatten())
+                            .map(|acell| acell.cell().clone()),
+                    );
+
+                    #[cfg(feature = "display")]
+                    ctx.print_stats(&["Range", "RLC"]);
+                    Ok(())
+                },
+            )
+            .unwrap();
+        for (i, cell) in instance.into_iter().enumerate() {
+            layouter.constrain_instance(cell, config.instance, i);
+        }
+        #[cfg(feature = "display")]
+        end_timer!(witness_gen);
+        Ok(())
+    }
+}
+
+impl<F: Field> CircuitExt<F> for EthBlockReceiptLogCircuit<F> {
+    fn num_instance(&self) -> Vec<usize> {
+        vec![16 + 2 * self.inputs.num_data_words]
+    }
+
+    fn instances(&self) -> Vec<Vec<F>> {
+        vec![self.instance()]
+    }
+}
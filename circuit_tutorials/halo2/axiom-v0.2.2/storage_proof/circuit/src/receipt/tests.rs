@@ -0,0 +1,133 @@
+use super::*;
+use crate::{
+    halo2_proofs::{dev::MockProver, halo2curves::bn256::Fr},
+    transaction::tests::{mock_block_header, rlp_index, single_leaf_trie},
+};
+use ethers_core::{
+    types::Address,
+    utils::{hex::FromHex, keccak256},
+};
+use rlp::RlpStream;
+
+const MAX_LOGS_BYTES: usize = 512;
+const MAX_DEPTH: usize = 4;
+const MAX_NUM_LOGS: usize = 3;
+const MAX_LOG_DATA_BYTES: usize = 64;
+
+/// An ERC-20 `Transfer(from, to, amount)` log
+pub(crate) fn transfer_log(stream: &mut RlpStream) {
+    stream.begin_list(3).append(&Address::repeat_byte(0xab).as_bytes().to_vec());
+    stream
+        .begin_list(3)
+        .append(
+            &Vec::from_hex("ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef")
+                .unwrap(),
+        )
+        .append(&H256::from(Address::repeat_byte(0x01)).as_bytes().to_vec())
+        .append(&H256::from(Address::repeat_byte(0x02)).as_bytes().to_vec());
+    stream.append(&H256::from_low_u64_be(1000).as_bytes().to_vec());
+}
+
+/// Returns `tx_type || rlp([status, cumulativeGasUsed, logsBloom, logs])` with `num_logs` transfer logs
+pub(crate) fn mock_receipt(tx_type: u8, status: u8, num_logs: usize) -> Vec<u8> {
+    let mut stream = RlpStream::new_list(4);
+    stream.append(&status).append(&121_000u64).append(&vec![0u8; 256]);
+    stream.begin_list(num_logs);
+    for _ in 0..num_logs {
+        transfer_log(&mut stream);
+    }
+    let receipt = stream.out().to_vec();
+    if tx_type == 0 {
+        receipt
+    } else {
+        [vec![tx_type], receipt].concat()
+    }
+}
+
+fn get_test_input(tx_index: u32, receipt: Vec<u8>, max_logs_bytes: usize) -> EthBlockReceiptInput {
+    let key = rlp_index(tx_index);
+    let (root, proof) = single_leaf_trie(&key, &receipt);
+    let block_number = 17_000_000;
+    let block_header = mock_block_header(H256::repeat_byte(0x66), root, block_number);
+    let block_hash = H256(keccak256(&block_header));
+    let (_, max_receipt_bytes) = max_receipt_lens(max_logs_bytes);
+    let receipt = EthReceiptInput {
+        tx_index,
+        proof: MPTVarKeyInput::from_proof(key, proof).with_max_lens(
+            TRANSACTION_INDEX_MAX_KEY_BYTES,
+            max_receipt_bytes,
+            MAX_DEPTH,
+        ),
+        max_logs_bytes,
+    };
+    EthBlockReceiptInput { block_number, block_hash, block_header, receipt }
+}
+
+pub(crate) fn get_test_circuit(tx_index: u32, receipt: Vec<u8>) -> EthBlockReceiptCircuit<Fr> {
+    let inputs = get_test_input(tx_index, receipt, MAX_LOGS_BYTES);
+    EthBlockReceiptCircuit::new(inputs, Network::Mainnet)
+}
+
+fn get_test_log_circuit(
+    log_idx: u32,
+    data_word_idx: u32,
+    receipt: Vec<u8>,
+) -> EthBlockReceiptLogCircuit<Fr> {
+    let receipt = get_test_input(7, receipt, max_logs_bytes(MAX_NUM_LOGS, MAX_LOG_DATA_BYTES));
+    let inputs = EthBlockReceiptLogInput {
+        receipt,
+        log_idx,
+        data_word_idx,
+        num_data_words: 1,
+        max_num_logs: MAX_NUM_LOGS,
+        max_data_bytes: MAX_LOG_DATA_BYTES,
+    };
+    EthBlockReceiptLogCircuit::new(inputs, Network::Mainnet)
+}
+
+#[test]
+pub fn test_mock_receipt_legacy() {
+    let k = EthConfigParams::get_receipt().degree;
+    let circuit = get_test_circuit(3, mock_receipt(0, 1, 1));
+    MockProver::run(k, &circuit, circuit.instances()).unwrap().assert_satisfied();
+}
+
+#[test]
+pub fn test_mock_receipt_eip1559_failed() {
+    let k = EthConfigParams::get_receipt().degree;
+    let circuit = get_test_circuit(130, mock_receipt(2, 0, 0));
+    let instance = circuit.instance();
+    assert_eq!(instance[5], Fr::zero());
+    MockProver::run(k, &circuit, circuit.instances()).unwrap().assert_satisfied();
+}
+
+#[test]
+pub fn test_mock_receipt_wrong_status() {
+    let k = EthConfigParams::get_receipt().degree;
+    let circuit = get_test_circuit(3, mock_receipt(2, 1, 2));
+    let mut instances = circuit.instances();
+    instances[0][5] = Fr::zero();
+    assert!(MockProver::run(k, &circuit, instances).unwrap().verify().is_err());
+}
+
+#[test]
+pub fn test_mock_receipt_log_transfer() {
+    let k = EthConfigParams::get_receipt_log().degree;
+    let circuit = get_test_log_circuit(1, 0, mock_receipt(2, 1, 2));
+    let instance = circuit.instance();
+    // numTopics
+    assert_eq!(instance[6], Fr::from(3));
+    // amount
+    assert_eq!(instance[17], Fr::from(1000));
+    MockProver::run(k, &circuit, circuit.instances()).unwrap().assert_satisfied();
+}
+
+#[test]
+pub fn test_mock_receipt_log_wrong_topic() {
+    let k = EthConfigParams::get_receipt_log().degree;
+    let circuit = get_test_log_circuit(0, 0, mock_receipt(0, 1, 1));
+    let mut instances = circuit.instances();
+    // low half of `to`
+    instances[0][12] += Fr::one();
+    assert!(MockProver::run(k, &circuit, instances).unwrap().verify().is_err());
+}
@@ -0,0 +1,231 @@
+//! In-circuit RLP encoding of assigned byte strings and lists, e.g. to build an MPT leaf and hash it.
+//!
+//! The encoding is witnessed in `FirstPhase`, where its prefix and length bytes are computed and constrained from
+//! the payload length, and its bytes are constrained to be `prefix || len || payload` by RLC in `SecondPhase`.
+use super::{evaluate_byte_array, max_rlp_len_len, rlc::RlcTrace, rlc::RLC_PHASE, RlpChip};
+use halo2_base::{
+    gates::{GateInstructions, RangeInstructions},
+    halo2_proofs::circuit::Value,
+    utils::{bit_length, value_to_option, ScalarField},
+    AssignedValue, Context,
+    QuantumCell::{Constant, Existing, Witness},
+};
+use std::{iter, slice};
+
+#[derive(Clone, Debug)]
+pub struct RlpEncodingWitness<'v, F: ScalarField> {
+    prefix: AssignedValue<'v, F>,
+    prefix_len: AssignedValue<'v, F>,
+    len_len: AssignedValue<'v, F>,
+    len_cells: Vec<AssignedValue<'v, F>>,
+    /// The payload is the concatenation of `bytes[..len]` over these `(bytes, len)`
+    payload: Vec<(Vec<AssignedValue<'v, F>>, AssignedValue<'v, F>)>,
+
+    /// The RLP encoding in the first `rlp_len` bytes. The bytes after it are unconstrained.
+    pub rlp: Vec<AssignedValue<'v, F>>,
+    pub rlp_len: AssignedValue<'v, F>,
+}
+
+impl<'g, F: ScalarField> RlpChip<'g, F> {
+    /// Returns the prefix and the length bytes of the RLP encoding of a payload of length `payload_len`, where
+    /// the prefix is `short_offset + payload_len` for payloads of at most 55 bytes and `big_offset + len_len`
+    /// otherwise. The length bytes are left aligned, and the bytes after the first `len_len` are unconstrained
+    /// beyond being bytes.
+    fn encode_rlp_len_phase0<'v>(
+        &self,
+        ctx: &mut Context<'v, F>,
+        payload_len: &AssignedValue<'v, F>,
+        max_payload_len: usize,
+        short_offset: u64,
+        big_offset: u64,
+    ) -> (AssignedValue<'v, F>, AssignedValue<'v, F>, Vec<AssignedValue<'v, F>>) {
+        let max_len_len = max_rlp_len_len(max_payload_len);
+        self.range.check_less_than_safe(ctx, payload_len, (max_payload_len + 1) as u64);
+        let is_big = if max_len_len == 0 {
+            self.gate().load_zero(ctx)
+        } else {
+            self.range.is_less_than(
+                ctx,
+                Constant(self.gate().get_field_element(55)),
+                Existing(payload_len),
+                bit_length(max_payload_len as u64),
+            )
+        };
+
+        // minimal big endian bytes of `payload_len` if it is big
+        let len_bytes = payload_len.value().map(|len| {
+            let len = len.get_lower_32();
+            if len > 55 {
+                len.to_be_bytes().into_iter().skip_while(|byte| *byte == 0).collect()
+            } else {
+                vec![]
+            }
+        });
+        let len_len = self.gate().load_witness(
+            ctx,
+            len_bytes.as_ref().map(|bytes: &Vec<u8>| F::from(bytes.len() as u64)),
+        );
+        let len_cells = self.gate().assign_witnesses(
+            ctx,
+            (0..max_len_len).map(|idx| {
+                len_bytes.as_ref().map(|bytes| F::from(bytes.get(idx).copied().unwrap_or(0) as u64))
+            }),
+        );
+        for byte in &len_cells {
+            self.range.range_check(ctx, byte, 8);
+        }
+        self.range.check_less_than_safe(ctx, &len_len, (max_len_len + 1) as u64);
+        // the length bytes encode `payload_len` if it is big, and nothing otherwise
+        let len_val = evaluate_byte_array(ctx, self.gate(), &len_cells, &len_len);
+        let expected_len_val = self.gate().mul(ctx, Existing(&is_big), Existing(payload_len));
+        ctx.constrain_equal(&len_val, &expected_len_val);
+        // they have no leading 0s
+        if max_len_len != 0 {
+            let len_len_is_zero = self.gate().is_zero(ctx, &len_len);
+            let first_is_zero = self.gate().is_zero(ctx, &len_cells[0]);
+            let leading_zero =
+                self.gate().mul_not(ctx, Existing(&len_len_is_zero), Existing(&first_is_zero));
+            self.gate().assert_is_const(ctx, &leading_zero, F::zero());
+        }
+
+        let short_prefix =
+            self.gate().add(ctx, Existing(payload_len), Constant(F::from(short_offset)));
+        let big_prefix = self.gate().add(ctx, Existing(&len_len), Constant(F::from(big_offset)));
+        let prefix = self.gate().select(
+            ctx,
+            Existing(&big_prefix),
+            Existing(&short_prefix),
+            Existing(&is_big),
+        );
+        (prefix, len_len, len_cells)
+    }
+
+    /// Witnesses the RLP encoding `prefix || len || payload` with the payload the concatenation of `payload`.
+    fn witness_rlp_encoding<'v>(
+        &self,
+        ctx: &mut Context<'v, F>,
+        prefix: AssignedValue<'v, F>,
+        prefix_len: AssignedValue<'v, F>,
+        len_len: AssignedValue<'v, F>,
+        len_cells: Vec<AssignedValue<'v, F>>,
+        payload: Vec<(Vec<AssignedValue<'v, F>>, AssignedValue<'v, F>)>,
+    ) -> RlpEncodingWitness<'v, F> {
+        let max_len =
+            1 + len_cells.len() + payload.iter().map(|(bytes, _)| bytes.len()).sum::<usize>();
+        let rlp_len = self.gate().sum(
+            ctx,
+            [Existing(&prefix_len), Existing(&len_len)]
+                .into_iter()
+                .chain(payload.iter().map(|(_, len)| Existing(len))),
+        );
+
+        let parts = [(slice::from_ref(&prefix), &prefix_len), (&len_cells[..], &len_len)]
+            .into_iter()
+            .chain(payload.iter().map(|(bytes, len)| (&bytes[..], len)))
+            .map(|(bytes, len)| {
+                value_to_option(len.value()).map(|len| &bytes[..len.get_lower_32() as usize])
+            })
+            .collect::<Option<Vec<_>>>();
+        let rlp = match parts {
+            Some(parts) => self.gate().assign_region(
+                ctx,
+                parts
+                    .into_iter()
+                    .flatten()
+                    .map(|byte| Witness(byte.value().copied()))
+                    .chain(iter::repeat(Witness(Value::known(F::zero()))))
+                    .take(max_len),
+                [],
+            ),
+            None => self.gate().assign_witnesses(ctx, vec![Value::unknown(); max_len]),
+        };
+        RlpEncodingWitness { prefix, prefix_len, len_len, len_cells, payload, rlp, rlp_len }
+    }
+
+    /// Compute and assign witnesses for the RLP encoding of the byte string `bytes[..len]`.
+    ///
+    /// Assumes that `bytes` are range checked to be bytes. The encoding is constrained with
+    /// `encode_rlp_phase1` in `SecondPhase`.
+    pub fn encode_rlp_field_phase0<'v>(
+        &self,
+        ctx: &mut Context<'v, F>,
+        bytes: Vec<AssignedValue<'v, F>>,
+        len: AssignedValue<'v, F>,
+    ) -> RlpEncodingWitness<'v, F> {
+        debug_assert_eq!(ctx.current_phase(), 0);
+        let (prefix, len_len, len_cells) =
+            self.encode_rlp_len_phase0(ctx, &len, bytes.len(), 0x80, 0xb7);
+        // a single byte less than 0x80 is its own encoding
+        let is_literal = if bytes.is_empty() {
+            self.gate().load_zero(ctx)
+        } else {
+            let is_single = self.gate().is_equal(ctx, Existing(&len), Constant(F::one()));
+            let is_small = self.range.is_less_than(
+                ctx,
+                Existing(&bytes[0]),
+                Constant(self.gate().get_field_element(0x80)),
+                8,
+            );
+            self.gate().and(ctx, Existing(&is_single), Existing(&is_small))
+        };
+        let prefix_len = self.gate().not(ctx, Existing(&is_literal));
+        let prefix = self.gate().mul(ctx, Existing(&prefix), Existing(&prefix_len));
+        self.witness_rlp_encoding(ctx, prefix, prefix_len, len_len, len_cells, vec![(bytes, len)])
+    }
+
+    /// Compute and assign witnesses for the RLP encoding of the list whose items have RLP encodings
+    /// `items[i].0[..items[i].1]`, for example the outputs of previous encodings.
+    ///
+    /// The encoding is constrained with `encode_rlp_phase1` in `SecondPhase`.
+    pub fn encode_rlp_list_phase0<'v>(
+        &self,
+        ctx: &mut Context<'v, F>,
+        items: Vec<(Vec<AssignedValue<'v, F>>, AssignedValue<'v, F>)>,
+    ) -> RlpEncodingWitness<'v, F> {
+        debug_assert_eq!(ctx.current_phase(), 0);
+        let max_payload_len = items.iter().map(|(bytes, _)| bytes.len()).sum();
+        let payload_len = if items.is_empty() {
+            self.gate().load_zero(ctx)
+        } else {
+            self.gate().sum(ctx, items.iter().map(|(_, len)| Existing(len)))
+        };
+        let (prefix, len_len, len_cells) =
+            self.encode_rlp_len_phase0(ctx, &payload_len, max_payload_len, 0xc0, 0xf7);
+        let prefix_len = self.gate().load_constant(ctx, F::one());
+        self.witness_rlp_encoding(ctx, prefix, prefix_len, len_len, len_cells, items)
+    }
+
+    /// Use RLC to constrain an RLP encoding witnessed in `FirstPhase`. This MUST be done in `SecondPhase`.
+    ///
+    /// Returns the RLC of the encoding.
+    pub fn encode_rlp_phase1<'v>(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        witness: RlpEncodingWitness<'v, F>,
+    ) -> RlcTrace<'v, F> {
+        debug_assert_eq!(ctx.current_phase(), RLC_PHASE);
+
+        let RlpEncodingWitness { prefix, prefix_len, len_len, len_cells, payload, rlp, rlp_len } =
+            witness;
+        self.rlc.load_rlc_cache(ctx, self.range.gate(), bit_length(rlp.len() as u64));
+
+        let len_trace = self.rlc.compute_rlc(ctx, self.gate(), len_cells, len_len);
+        let payload_trace = payload
+            .into_iter()
+            .map(|(bytes, len)| self.rlc.compute_rlc(ctx, self.gate(), bytes, len))
+            .collect::<Vec<_>>();
+        let rlp_trace = self.rlc.compute_rlc(ctx, self.gate(), rlp, rlp_len);
+
+        self.rlc.constrain_rlc_concat(
+            ctx,
+            self.gate(),
+            [(&prefix, &prefix_len, 1), (&len_trace.rlc_val, &len_trace.len, len_trace.max_len)]
+                .into_iter()
+                .chain(
+                    payload_trace.iter().map(|trace| (&trace.rlc_val, &trace.len, trace.max_len)),
+                ),
+            (&rlp_trace.rlc_val, &rlp_trace.len),
+        );
+        rlp_trace
+    }
+}
@@ -14,7 +14,9 @@ use halo2_base::{
 };
 use std::iter;
 
+pub mod encode;
 pub mod rlc;
+pub mod schema;
 #[cfg(test)]
 mod tests;
 
@@ -295,6 +297,71 @@ impl<'g, F: ScalarField> RlpChip<'g, F> {
         RlpArrayPrefixParsed { /*is_empty,*/ is_big, next_len, len_len }
     }
 
+    /// Parses the prefix of an RLP item which may be either a byte string or a list.
+    ///
+    /// Returns the parsed prefix together with a boolean `is_list`. If the item is a list, `next_len`
+    /// is the length of the list payload (or of the length bytes if the list is big).
+    pub fn parse_rlp_item_prefix<'v>(
+        &self,
+        ctx: &mut Context<'v, F>,
+        prefix: &AssignedValue<'v, F>,
+    ) -> (RlpFieldPrefixParsed<'v, F>, AssignedValue<'v, F>) {
+        let is_not_literal = self.range.is_less_than(
+            ctx,
+            Constant(self.gate().get_field_element(127)),
+            Existing(prefix),
+            8,
+        );
+        let is_list = self.range.is_less_than(
+            ctx,
+            Constant(self.gate().get_field_element(191)),
+            Existing(prefix),
+            8,
+        );
+        let is_big_or_list = self.range.is_less_than(
+            ctx,
+            Constant(self.gate().get_field_element(183)),
+            Existing(prefix),
+            8,
+        );
+        let is_big_list = self.range.is_less_than(
+            ctx,
+            Constant(self.gate().get_field_element(247)),
+            Existing(prefix),
+            8,
+        );
+        // string with prefix in [184, 191]
+        let is_big_field = self.gate().sub(ctx, Existing(&is_big_or_list), Existing(&is_list));
+        let is_big = self.gate().add(ctx, Existing(&is_big_field), Existing(&is_big_list));
+
+        let short_offset = self.gate().select(
+            ctx,
+            Constant(self.gate().get_field_element(192)),
+            Constant(self.gate().get_field_element(128)),
+            Existing(&is_list),
+        );
+        let big_offset = self.gate().select(
+            ctx,
+            Constant(self.gate().get_field_element(247)),
+            Constant(self.gate().get_field_element(183)),
+            Existing(&is_list),
+        );
+        let item_len = self.gate().sub(ctx, Existing(prefix), Existing(&short_offset));
+        let len_len = self.gate().sub(ctx, Existing(prefix), Existing(&big_offset));
+
+        let next_len =
+            self.gate().select(ctx, Existing(&len_len), Existing(&item_len), Existing(&is_big));
+        let next_len = self.gate().select(
+            ctx,
+            Existing(&next_len),
+            Constant(F::one()),
+            Existing(&is_not_literal),
+        );
+        // `is_big = 1` implies `is_not_literal = 1`
+        let len_len = self.gate().mul(ctx, Existing(&len_len), Existing(&is_big));
+        (RlpFieldPrefixParsed { is_not_literal, is_big, next_len, len_len }, is_list)
+    }
+
     fn parse_rlp_len<'v>(
         &self,
         ctx: &mut Context<'v, F>,
@@ -436,6 +503,78 @@ impl<'g, F: ScalarField> RlpChip<'g, F> {
         RlpFieldTrace { prefix, prefix_len, len_trace: len_rlc, field_trace: field_rlc }
     }
 
+    /// Returns the full RLP encoding `prefix || len || payload` of the item parsed in `witness`, right padded with 0s
+    /// to length `1 + max_len_len + max_field_len`, together with its byte length.
+    ///
+    /// This is used to decompose an item of a nested list further. The byte cells are witnessed but _NOT_ constrained:
+    /// this must be done with `constrain_rlp_item_phase1` in `SecondPhase`.
+    pub fn witness_rlp_item_phase0<'v>(
+        &self,
+        ctx: &mut Context<'v, F>,
+        witness: &RlpFieldWitness<'v, F>,
+    ) -> (Vec<AssignedValue<'v, F>>, AssignedValue<'v, F>) {
+        let max_len = 1 + witness.max_len_len + witness.max_field_len;
+        let rlp_len = self.gate().sum(
+            ctx,
+            [
+                Existing(&witness.prefix_len),
+                Existing(&witness.len_len),
+                Existing(&witness.field_len),
+            ],
+        );
+        let mut rlp_item = vec![];
+        witness.prefix_len.value().zip(witness.len_len.value()).zip(witness.field_len.value()).map(
+            |((prefix_len, len_len), field_len)| {
+                let [prefix_len, len_len, field_len] =
+                    [prefix_len, len_len, field_len].map(|fe| fe.get_lower_32() as usize);
+                rlp_item = self.gate().assign_region(
+                    ctx,
+                    iter::once(&witness.prefix)
+                        .take(prefix_len)
+                        .chain(&witness.len_cells[..len_len])
+                        .chain(&witness.field_cells[..field_len])
+                        .map(|a| Witness(a.value().copied()))
+                        .chain(iter::repeat(Witness(Value::known(F::zero()))))
+                        .take(max_len),
+                    [],
+                );
+            },
+        );
+        if rlp_item.is_empty() {
+            rlp_item = self.gate().assign_witnesses(ctx, vec![Value::unknown(); max_len]);
+        }
+        (rlp_item, rlp_len)
+    }
+
+    /// Use RLC to constrain that `rlp_item[..rlp_len]` is the RLP encoding of the item with trace `trace`.
+    /// This MUST be done in `SecondPhase`.
+    pub fn constrain_rlp_item_phase1<'v>(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        rlp_item: Vec<AssignedValue<'v, F>>,
+        rlp_len: AssignedValue<'v, F>,
+        trace: &RlpFieldTrace<'v, F>,
+    ) {
+        debug_assert_eq!(ctx.current_phase(), RLC_PHASE);
+
+        self.rlc.load_rlc_cache(ctx, self.range.gate(), bit_length(rlp_item.len() as u64));
+        let rlp_rlc = self.rlc.compute_rlc(ctx, self.gate(), rlp_item, rlp_len);
+        self.rlc.constrain_rlc_concat(
+            ctx,
+            self.gate(),
+            [
+                (&trace.prefix, &trace.prefix_len, 1),
+                (&trace.len_trace.rlc_val, &trace.len_trace.len, trace.len_trace.values.len()),
+                (
+                    &trace.field_trace.rlc_val,
+                    &trace.field_trace.len,
+                    trace.field_trace.values.len(),
+                ),
+            ],
+            (&rlp_rlc.rlc_val, &rlp_rlc.len),
+        );
+    }
+
     /// Compute and assign witnesses for deserializing an RLP list of byte strings. Does not support nested lists.
     ///
     /// Witnesses MUST be generated in `FirstPhase` to be able to compute RLC of them in `SecondPhase`
@@ -450,6 +589,34 @@ impl<'g, F: ScalarField> RlpChip<'g, F> {
         rlp_array: Vec<AssignedValue<'v, F>>,
         max_field_lens: &[usize],
         is_variable_len: bool,
+    ) -> RlpArrayTraceWitness<'v, F> {
+        self.decompose_rlp_array_phase0_impl(ctx, rlp_array, max_field_lens, is_variable_len, false)
+    }
+
+    /// Same as `decompose_rlp_array_phase0` except that items of the list may themselves be lists.
+    ///
+    /// An item that is a list is not decoded further: its `field_cells` are the payload of the inner list,
+    /// i.e., the concatenation of the RLP encodings of its items. For such an item, `max_field_lens` is the maximum
+    /// byte length of the inner list payload.
+    ///
+    /// The returned witness is constrained with `decompose_rlp_array_phase1` in `SecondPhase`.
+    pub fn decompose_rlp_nested_array_phase0<'v>(
+        &self,
+        ctx: &mut Context<'v, F>,
+        rlp_array: Vec<AssignedValue<'v, F>>,
+        max_field_lens: &[usize],
+        is_variable_len: bool,
+    ) -> RlpArrayTraceWitness<'v, F> {
+        self.decompose_rlp_array_phase0_impl(ctx, rlp_array, max_field_lens, is_variable_len, true)
+    }
+
+    fn decompose_rlp_array_phase0_impl<'v>(
+        &self,
+        ctx: &mut Context<'v, F>,
+        rlp_array: Vec<AssignedValue<'v, F>>,
+        max_field_lens: &[usize],
+        is_variable_len: bool,
+        allow_list_items: bool,
     ) -> RlpArrayTraceWitness<'v, F> {
         let max_rlp_array_len = rlp_array.len();
         let max_len_len = max_rlp_len_len(max_rlp_array_len);
@@ -512,7 +679,11 @@ impl<'g, F: ScalarField> RlpChip<'g, F> {
                 rlp_array.iter().map(Existing).take(running_max_len + 1),
                 Existing(&prefix_idx),
             );
-            let prefix_parsed = self.parse_rlp_field_prefix(ctx, &prefix);
+            let prefix_parsed = if allow_list_items {
+                self.parse_rlp_item_prefix(ctx, &prefix).0
+            } else {
+                self.parse_rlp_field_prefix(ctx, &prefix)
+            };
 
             let mut len_len = prefix_parsed.len_len;
             let max_field_len_len = max_rlp_len_len(max_field_len);
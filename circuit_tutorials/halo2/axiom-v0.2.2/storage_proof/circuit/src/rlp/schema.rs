@@ -0,0 +1,231 @@
+//! Decomposition of nested RLP lists whose shape is described by an [`RlpSchema`], e.g. a transaction with an
+//! access list or a receipt with logs.
+//!
+//! Each list in the schema is decomposed with [`RlpChip::decompose_rlp_nested_array_phase0`]. An item that is
+//! itself a list is re-assembled with [`RlpChip::witness_rlp_item_phase0`] and decomposed further, and the two are
+//! linked by RLC in `SecondPhase`.
+use super::{
+    max_rlp_len_len, rlc::RLC_PHASE, RlpArrayTrace, RlpArrayTraceWitness, RlpChip, RlpFieldTrace,
+    RlpFieldWitness,
+};
+use halo2_base::{
+    gates::{GateInstructions, RangeInstructions},
+    utils::ScalarField,
+    AssignedValue, Context,
+    QuantumCell::{Constant, Existing},
+};
+
+/// The shape of an RLP item, with the maximum byte length of each byte string and the maximum number of items of
+/// each variable length list
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RlpSchema {
+    /// A byte string of at most this many bytes
+    Field(usize),
+    /// A list with exactly these items
+    List(Vec<RlpSchema>),
+    /// A list of at most `max_items` items, all with schema `item`
+    VarList { item: Box<RlpSchema>, max_items: usize },
+}
+
+impl RlpSchema {
+    pub fn var_list(item: RlpSchema, max_items: usize) -> Self {
+        Self::VarList { item: Box::new(item), max_items }
+    }
+
+    pub fn is_list(&self) -> bool {
+        !matches!(self, Self::Field(_))
+    }
+
+    /// Maximum byte length of the payload: the byte string itself, or the concatenated RLP encodings of the items
+    pub fn max_payload_len(&self) -> usize {
+        match self {
+            Self::Field(max_len) => *max_len,
+            Self::List(items) => items.iter().map(|item| item.max_rlp_len()).sum(),
+            Self::VarList { item, max_items } => max_items * item.max_rlp_len(),
+        }
+    }
+
+    /// Maximum byte length of the RLP encoding
+    pub fn max_rlp_len(&self) -> usize {
+        let max_payload_len = self.max_payload_len();
+        1 + max_rlp_len_len(max_payload_len) + max_payload_len
+    }
+
+    /// The schemas of the items of a list
+    fn items(&self) -> Vec<&RlpSchema> {
+        match self {
+            Self::Field(_) => panic!("RLP schema is not a list"),
+            Self::List(items) => items.iter().collect(),
+            Self::VarList { item, max_items } => vec![item.as_ref(); *max_items],
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RlpNestedItemWitness<'v, F: ScalarField> {
+    /// RLP encoding of the item, right padded with 0s, and its length
+    pub rlp_item: Vec<AssignedValue<'v, F>>,
+    pub rlp_len: AssignedValue<'v, F>,
+    pub list: RlpListTraceWitness<'v, F>,
+}
+
+#[derive(Clone, Debug)]
+pub struct RlpListTraceWitness<'v, F: ScalarField> {
+    pub witness: RlpArrayTraceWitness<'v, F>,
+    /// For each item of the list, its decomposition if the schema of the item is a list
+    pub items: Vec<Option<RlpNestedItemWitness<'v, F>>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct RlpListTrace<'v, F: ScalarField> {
+    pub trace: RlpArrayTrace<'v, F>,
+    /// For each item of the list, the trace of its items if the schema of the item is a list
+    pub items: Vec<Option<RlpListTrace<'v, F>>>,
+}
+
+impl<'v, F: ScalarField> RlpListTrace<'v, F> {
+    /// The item at `path`, where each index of `path` except the last selects an item that is a list.
+    ///
+    /// For example `[2, 0, 1]` is the second item of the first item of the third item of this list.
+    pub fn item(&self, path: &[usize]) -> &RlpFieldTrace<'v, F> {
+        let (idx, path) = path.split_first().expect("path must be nonempty");
+        if path.is_empty() {
+            &self.trace.field_trace[*idx]
+        } else {
+            self.items[*idx].as_ref().expect("item is not a list").item(path)
+        }
+    }
+
+    /// The trace of the list at `path`, see [`Self::item`]
+    pub fn list(&self, path: &[usize]) -> &RlpListTrace<'v, F> {
+        path.iter().fold(self, |list, idx| list.items[*idx].as_ref().expect("item is not a list"))
+    }
+}
+
+impl<'g, F: ScalarField> RlpChip<'g, F> {
+    /// Compute and assign witnesses for deserializing the RLP encoded list `rlp`, right padded with 0s, with
+    /// shape `schema`. `schema` must be a list.
+    ///
+    /// Items of variable length lists beyond the number of items in the list have length 0, as in
+    /// [`Self::decompose_rlp_array_phase0`] with `is_variable_len = true`.
+    ///
+    /// Witnesses MUST be generated in `FirstPhase` to be able to compute RLC of them in `SecondPhase`
+    pub fn decompose_rlp_schema_phase0<'v>(
+        &self,
+        ctx: &mut Context<'v, F>,
+        rlp: Vec<AssignedValue<'v, F>>,
+        schema: &RlpSchema,
+    ) -> RlpListTraceWitness<'v, F> {
+        debug_assert_eq!(ctx.current_phase(), 0);
+        self.decompose_rlp_list_phase0(ctx, rlp, schema, None)
+    }
+
+    /// `is_present` is `None` for the outermost list. For a nested list, it is the `prefix_len` of the list in its
+    /// parent, which is 0 iff the list is a dummy item beyond the end of a variable length list. A dummy list is
+    /// decomposed as the empty list.
+    fn decompose_rlp_list_phase0<'v>(
+        &self,
+        ctx: &mut Context<'v, F>,
+        rlp: Vec<AssignedValue<'v, F>>,
+        schema: &RlpSchema,
+        is_present: Option<&AssignedValue<'v, F>>,
+    ) -> RlpListTraceWitness<'v, F> {
+        let items = schema.items();
+        let max_field_lens = items.iter().map(|item| item.max_payload_len()).collect::<Vec<_>>();
+        // nested fixed length lists are parsed as variable length so that dummy lists can be empty,
+        // and we check below that present lists have all their items
+        let is_variable_len = is_present.is_some() || matches!(schema, RlpSchema::VarList { .. });
+        let witness =
+            self.decompose_rlp_nested_array_phase0(ctx, rlp, &max_field_lens, is_variable_len);
+
+        if let (RlpSchema::List(_), Some(is_present)) = (schema, is_present) {
+            for field in &witness.field_witness {
+                // every RLP item has length at least 1
+                let item_len = self.gate().sum(
+                    ctx,
+                    [
+                        Existing(&field.prefix_len),
+                        Existing(&field.len_len),
+                        Existing(&field.field_len),
+                    ],
+                );
+                let is_empty = self.gate().is_zero(ctx, &item_len);
+                let is_missing = self.gate().mul(ctx, Existing(&is_empty), Existing(is_present));
+                self.gate().assert_is_const(ctx, &is_missing, F::zero());
+            }
+        }
+
+        let items = items
+            .into_iter()
+            .zip(witness.field_witness.iter())
+            .map(|(item, field)| {
+                item.is_list().then(|| self.decompose_rlp_nested_item_phase0(ctx, field, item))
+            })
+            .collect();
+        RlpListTraceWitness { witness, items }
+    }
+
+    fn decompose_rlp_nested_item_phase0<'v>(
+        &self,
+        ctx: &mut Context<'v, F>,
+        field: &RlpFieldWitness<'v, F>,
+        schema: &RlpSchema,
+    ) -> RlpNestedItemWitness<'v, F> {
+        let (rlp_item, rlp_len) = self.witness_rlp_item_phase0(ctx, field);
+        // a list item always has a prefix, so `prefix_len = 0` means the item is a dummy: replace it by the
+        // empty list
+        let is_present = &field.prefix_len;
+        let mut list_rlp = rlp_item.clone();
+        list_rlp[0] = self.gate().select(
+            ctx,
+            Existing(&rlp_item[0]),
+            Constant(self.gate().get_field_element(0xc0)),
+            Existing(is_present),
+        );
+        let list = self.decompose_rlp_list_phase0(ctx, list_rlp, schema, Some(is_present));
+        let list_len = self.gate().sum(ctx, [Existing(&rlp_len), Constant(F::one())]);
+        let list_len = self.gate().sub(ctx, Existing(&list_len), Existing(is_present));
+        ctx.constrain_equal(&list.witness.rlp_len, &list_len);
+        RlpNestedItemWitness { rlp_item, rlp_len, list }
+    }
+
+    /// Use RLC to constrain the parsed nested RLP list witness. This MUST be done in `SecondPhase`.
+    ///
+    /// This also constrains that the items with a byte string schema are byte strings and the items with a list
+    /// schema are lists.
+    pub fn decompose_rlp_schema_phase1<'v>(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        witness: RlpListTraceWitness<'v, F>,
+    ) -> RlpListTrace<'v, F> {
+        debug_assert_eq!(ctx.current_phase(), RLC_PHASE);
+
+        let RlpListTraceWitness { witness, items } = witness;
+        let trace = self.decompose_rlp_array_phase1(ctx, witness, true);
+        let items = items
+            .into_iter()
+            .zip(trace.field_trace.iter())
+            .map(|(item, field)| {
+                let is_list = self.range.is_less_than(
+                    ctx,
+                    Constant(self.gate().get_field_element(0xbf)),
+                    Existing(&field.prefix),
+                    8,
+                );
+                match item {
+                    None => {
+                        self.gate().assert_is_const(ctx, &is_list, F::zero());
+                        None
+                    }
+                    Some(RlpNestedItemWitness { rlp_item, rlp_len, list }) => {
+                        // dummy items have prefix 0
+                        ctx.constrain_equal(&is_list, &field.prefix_len);
+                        self.constrain_rlp_item_phase1(ctx, rlp_item, rlp_len, field);
+                        Some(self.decompose_rlp_schema_phase1(ctx, list))
+                    }
+                }
+            })
+            .collect();
+        RlpListTrace { trace, items }
+    }
+}
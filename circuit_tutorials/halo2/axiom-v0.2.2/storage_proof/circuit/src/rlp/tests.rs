@@ -263,6 +263,7 @@ mod rlp {
         max_field_lens: Vec<usize>,
         is_array: bool,
         is_variable_len: bool,
+        is_nested: bool,
         _marker: PhantomData<F>,
     }
 
@@ -316,12 +317,21 @@ mod rlp {
 
                     if self.is_array {
                         // FirstPhase
-                        let witness = chip.decompose_rlp_array_phase0(
-                            ctx,
-                            inputs_assigned,
-                            &self.max_field_lens,
-                            self.is_variable_len,
-                        );
+                        let witness = if self.is_nested {
+                            chip.decompose_rlp_nested_array_phase0(
+                                ctx,
+                                inputs_assigned,
+                                &self.max_field_lens,
+                                self.is_variable_len,
+                            )
+                        } else {
+                            chip.decompose_rlp_array_phase0(
+                                ctx,
+                                inputs_assigned,
+                                &self.max_field_lens,
+                                self.is_variable_len,
+                            )
+                        };
 
                         chip.range.finalize(ctx);
                         ctx.next_phase();
@@ -373,6 +383,32 @@ mod rlp {
                 max_field_lens: vec![15, 9, 11, 10, 17],
                 is_array: true,
                 is_variable_len: true,
+                is_nested: false,
+                _marker: PhantomData,
+            };
+            MockProver::run(k, &circuit, vec![]).unwrap().assert_satisfied();
+        }
+    }
+
+    #[test]
+    pub fn test_mock_rlp_nested_array() {
+        let k = DEGREE;
+        // [ "cat", ["dog", "owl"], [] ]
+        let nested: Vec<u8> = vec![
+            0xce, 0x83, b'c', b'a', b't', 0xc8, 0x83, b'd', b'o', b'g', 0x83, b'o', b'w', b'l',
+            0xc0,
+        ];
+        // [ "cat", "dog" ]: list items are still allowed to be strings
+        let cat_dog: Vec<u8> = vec![0xc8, 0x83, b'c', b'a', b't', 0x83, b'd', b'o', b'g'];
+        for mut test_input in [nested, cat_dog] {
+            test_input.resize(40, 0);
+            let circuit = RlpTestCircuit::<Fr> {
+                inputs: test_input,
+                max_len: 40,
+                max_field_lens: vec![3, 10, 4],
+                is_array: true,
+                is_variable_len: true,
+                is_nested: true,
                 _marker: PhantomData,
             };
             MockProver::run(k, &circuit, vec![]).unwrap().assert_satisfied();
@@ -392,6 +428,7 @@ mod rlp {
             max_field_lens: vec![],
             is_array: false,
             is_variable_len: false,
+            is_nested: false,
             _marker: PhantomData,
         };
         MockProver::run(k, &circuit, vec![]).unwrap().assert_satisfied();
@@ -409,6 +446,7 @@ mod rlp {
             max_field_lens: vec![],
             is_array: false,
             is_variable_len: false,
+            is_nested: false,
             _marker: PhantomData,
         };
         MockProver::run(k, &circuit, vec![]).unwrap().assert_satisfied();
@@ -425,6 +463,7 @@ mod rlp {
             max_field_lens: vec![],
             is_array: false,
             is_variable_len: false,
+            is_nested: false,
             _marker: PhantomData,
         };
         MockProver::run(k, &circuit, vec![]).unwrap().assert_satisfied();
@@ -441,8 +480,340 @@ mod rlp {
             max_field_lens: vec![],
             is_array: false,
             is_variable_len: false,
+            is_nested: false,
             _marker: PhantomData,
         };
         MockProver::run(k, &circuit, vec![]).unwrap().assert_satisfied();
     }
 }
+
+mod schema {
+    use crate::rlp::{schema::*, *};
+    use ::rlp::RlpStream;
+    use halo2_base::{
+        halo2_proofs::{
+            circuit::{Layouter, SimpleFloorPlanner},
+            dev::MockProver,
+            halo2curves::bn256::Fr,
+            plonk::{Circuit, Error},
+        },
+        ContextParams, SKIP_FIRST_PASS,
+    };
+    use std::marker::PhantomData;
+
+    const DEGREE: u32 = 18;
+
+    #[derive(Clone, Debug)]
+    pub struct RlpSchemaTestCircuit<F> {
+        inputs: Vec<u8>,
+        schema: RlpSchema,
+        /// (path, bytes) of items whose value is checked
+        expected: Vec<(Vec<usize>, Vec<u8>)>,
+        _marker: PhantomData<F>,
+    }
+
+    impl<F: ScalarField> Circuit<F> for RlpSchemaTestCircuit<F> {
+        type Config = RlpConfig<F>;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            self.clone()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+            RlpConfig::configure(meta, 1, &[1, 1], &[1, 1], 1, 8, 0, DEGREE as usize)
+        }
+
+        fn synthesize(
+            &self,
+            config: Self::Config,
+            mut layouter: impl Layouter<F>,
+        ) -> Result<(), Error> {
+            config
+                .range
+                .load_lookup_table(&mut layouter)
+                .expect("load lookup table should not fail");
+
+            let gamma = config.rlc.gamma;
+            let mut chip = RlpChip::new(config, layouter.get_challenge(gamma));
+
+            let mut first_pass = SKIP_FIRST_PASS;
+            layouter.assign_region(
+                || "RLP schema test",
+                |region| {
+                    if first_pass {
+                        first_pass = false;
+                        return Ok(());
+                    }
+                    let mut aux = Context::new(
+                        region,
+                        ContextParams {
+                            max_rows: chip.gate().max_rows,
+                            num_context_ids: 2,
+                            fixed_columns: chip.gate().constants.clone(),
+                        },
+                    );
+                    let ctx = &mut aux;
+
+                    let mut inputs = self.inputs.clone();
+                    inputs.resize(self.schema.max_rlp_len(), 0);
+                    let inputs_assigned = chip.gate().assign_witnesses(
+                        ctx,
+                        inputs.iter().map(|x| Value::known(F::from(*x as u64))),
+                    );
+
+                    // FirstPhase
+                    let witness =
+                        chip.decompose_rlp_schema_phase0(ctx, inputs_assigned, &self.schema);
+                    ctx.next_phase();
+
+                    // SecondPhase
+                    chip.get_challenge(ctx);
+                    let trace = chip.decompose_rlp_schema_phase1(ctx, witness);
+                    for (path, bytes) in &self.expected {
+                        let field = &trace.item(path).field_trace;
+                        chip.gate().assert_is_const(ctx, &field.len, F::from(bytes.len() as u64));
+                        for (cell, byte) in field.values.iter().zip(bytes) {
+                            chip.gate().assert_is_const(ctx, cell, F::from(*byte as u64));
+                        }
+                    }
+                    chip.range.finalize(ctx);
+                    Ok(())
+                },
+            )
+        }
+    }
+
+    // a receipt without bloom: [status, cumulativeGas, [[address, [topic, ...], data], ...]]
+    fn receipt_schema() -> RlpSchema {
+        let log = RlpSchema::List(vec![
+            RlpSchema::Field(20),
+            RlpSchema::var_list(RlpSchema::Field(32), 4),
+            RlpSchema::Field(64),
+        ]);
+        RlpSchema::List(vec![RlpSchema::Field(1), RlpSchema::Field(8), RlpSchema::var_list(log, 3)])
+    }
+
+    fn receipt_rlp(logs: &[(Vec<u8>, Vec<Vec<u8>>, Vec<u8>)]) -> Vec<u8> {
+        let mut stream = RlpStream::new_list(3);
+        stream.append(&1u8);
+        stream.append(&0x5208u64);
+        stream.begin_list(logs.len());
+        for (address, topics, data) in logs {
+            stream.begin_list(3);
+            stream.append(address);
+            stream.append_list::<Vec<u8>, _>(topics);
+            stream.append(data);
+        }
+        stream.out().to_vec()
+    }
+
+    fn mock_run(inputs: Vec<u8>, expected: Vec<(Vec<usize>, Vec<u8>)>) -> bool {
+        let circuit = RlpSchemaTestCircuit::<Fr> {
+            inputs,
+            schema: receipt_schema(),
+            expected,
+            _marker: PhantomData,
+        };
+        MockProver::run(DEGREE, &circuit, vec![]).unwrap().verify().is_ok()
+    }
+
+    #[test]
+    pub fn test_mock_rlp_schema() {
+        let address = vec![0x11; 20];
+        let topics = vec![vec![0x22; 32], vec![0x33; 32]];
+        let logs = vec![(address.clone(), topics, vec![0x44; 40]), (address, vec![], vec![])];
+        let expected = vec![
+            (vec![1], vec![0x52, 0x08]),
+            (vec![2, 0, 0], vec![0x11; 20]),
+            (vec![2, 0, 1, 1], vec![0x33; 32]),
+            (vec![2, 0, 2], vec![0x44; 40]),
+            (vec![2, 1, 2], vec![]),
+            (vec![2, 2, 0], vec![]),
+        ];
+        assert!(mock_run(receipt_rlp(&logs), expected));
+        assert!(mock_run(receipt_rlp(&[]), vec![(vec![2, 0, 0], vec![])]));
+    }
+
+    #[test]
+    pub fn test_mock_rlp_schema_invalid() {
+        // topics is a string instead of a list
+        let mut stream = RlpStream::new_list(3);
+        stream.append(&1u8).append(&0x5208u64).begin_list(1).begin_list(3);
+        stream.append(&vec![0x11u8; 20]).append(&vec![0x22u8; 32]).append_empty_data();
+        assert!(!mock_run(stream.out().to_vec(), vec![]));
+
+        // a log with a missing field
+        let mut stream = RlpStream::new_list(3);
+        stream.append(&1u8).append(&0x5208u64).begin_list(1).begin_list(2);
+        stream.append(&vec![0x11u8; 20]).begin_list(0);
+        assert!(!mock_run(stream.out().to_vec(), vec![]));
+    }
+}
+
+mod encode {
+    use crate::rlp::*;
+    use ::rlp::RlpStream;
+    use halo2_base::{
+        halo2_proofs::{
+            circuit::{Layouter, SimpleFloorPlanner},
+            dev::MockProver,
+            halo2curves::bn256::Fr,
+            plonk::{Circuit, Error},
+        },
+        ContextParams, SKIP_FIRST_PASS,
+    };
+    use std::marker::PhantomData;
+
+    const DEGREE: u32 = 14;
+
+    #[derive(Clone, Debug)]
+    pub struct RlpEncodeTestCircuit<F> {
+        /// (bytes, max_len) of each field of the list
+        fields: Vec<(Vec<u8>, usize)>,
+        /// Index of a byte of the list encoding witness to flip
+        tamper: Option<usize>,
+        _marker: PhantomData<F>,
+    }
+
+    impl<F: ScalarField> Circuit<F> for RlpEncodeTestCircuit<F> {
+        type Config = RlpConfig<F>;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            self.clone()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+            RlpConfig::configure(meta, 1, &[1, 1], &[1, 1], 1, 8, 0, DEGREE as usize)
+        }
+
+        fn synthesize(
+            &self,
+            config: Self::Config,
+            mut layouter: impl Layouter<F>,
+        ) -> Result<(), Error> {
+            config
+                .range
+                .load_lookup_table(&mut layouter)
+                .expect("load lookup table should not fail");
+
+            let gamma = config.rlc.gamma;
+            let mut chip = RlpChip::new(config, layouter.get_challenge(gamma));
+
+            let mut first_pass = SKIP_FIRST_PASS;
+            layouter.assign_region(
+                || "RLP encode test",
+                |region| {
+                    if first_pass {
+                        first_pass = false;
+                        return Ok(());
+                    }
+                    let mut aux = Context::new(
+                        region,
+                        ContextParams {
+                            max_rows: chip.gate().max_rows,
+                            num_context_ids: 2,
+                            fixed_columns: chip.gate().constants.clone(),
+                        },
+                    );
+                    let ctx = &mut aux;
+
+                    // FirstPhase
+                    let mut expected = vec![];
+                    let mut witnesses = vec![];
+                    let mut stream = RlpStream::new_list(self.fields.len());
+                    for (bytes, max_len) in &self.fields {
+                        let mut padded = bytes.clone();
+                        padded.resize(*max_len, 0);
+                        let padded = chip.gate().assign_witnesses(
+                            ctx,
+                            padded.iter().map(|x| Value::known(F::from(*x as u64))),
+                        );
+                        let len = chip
+                            .gate()
+                            .load_witness(ctx, Value::known(F::from(bytes.len() as u64)));
+                        witnesses.push(chip.encode_rlp_field_phase0(ctx, padded, len));
+                        expected.push(::rlp::encode(bytes).to_vec());
+                        stream.append(bytes);
+                    }
+                    let items = witnesses
+                        .iter()
+                        .map(|witness| (witness.rlp.clone(), witness.rlp_len.clone()))
+                        .collect();
+                    let mut list_witness = chip.encode_rlp_list_phase0(ctx, items);
+                    let mut list_expected = stream.out().to_vec();
+                    if let Some(idx) = self.tamper {
+                        let byte = list_expected.get(idx).copied().unwrap_or(0) ^ 1;
+                        list_witness.rlp[idx] =
+                            chip.gate().load_witness(ctx, Value::known(F::from(byte as u64)));
+                        if let Some(expected) = list_expected.get_mut(idx) {
+                            *expected = byte;
+                        }
+                    }
+                    witnesses.push(list_witness);
+                    expected.push(list_expected);
+
+                    for (witness, expected) in witnesses.iter().zip(&expected) {
+                        assert!(expected.len() <= witness.rlp.len());
+                        chip.gate().assert_is_const(
+                            ctx,
+                            &witness.rlp_len,
+                            F::from(expected.len() as u64),
+                        );
+                        for (cell, byte) in witness.rlp.iter().zip(expected) {
+                            chip.gate().assert_is_const(ctx, cell, F::from(*byte as u64));
+                        }
+                    }
+                    ctx.next_phase();
+
+                    // SecondPhase
+                    chip.get_challenge(ctx);
+                    for witness in witnesses {
+                        chip.encode_rlp_phase1(ctx, witness);
+                    }
+                    chip.range.finalize(ctx);
+                    Ok(())
+                },
+            )
+        }
+    }
+
+    #[test]
+    pub fn test_mock_rlp_encode() {
+        let long_field = (0..60).collect::<Vec<u8>>();
+        for fields in [
+            // long list
+            vec![
+                (vec![], 4),
+                (vec![0x05], 4),
+                (vec![0x80], 4),
+                (b"dog".to_vec(), 4),
+                (long_field, 64),
+            ],
+            // short list
+            vec![(b"cat".to_vec(), 55), (vec![0x7f], 1)],
+            vec![],
+        ] {
+            let circuit = RlpEncodeTestCircuit::<Fr> { fields, tamper: None, _marker: PhantomData };
+            MockProver::run(DEGREE, &circuit, vec![]).unwrap().assert_satisfied();
+        }
+    }
+
+    #[test]
+    pub fn test_mock_rlp_encode_tampered() {
+        // the list encoding is `0xc5 0x83 d o g 0x05`, witnessed in 11 bytes
+        let fields = vec![(b"dog".to_vec(), 4), (vec![0x05], 4)];
+        let run = |tamper| {
+            let circuit =
+                RlpEncodeTestCircuit::<Fr> { fields: fields.clone(), tamper, _marker: PhantomData };
+            MockProver::run(DEGREE, &circuit, vec![]).unwrap().verify()
+        };
+        // the prefix, a payload byte and the last byte of the encoding
+        for idx in [0, 2, 5] {
+            assert!(run(Some(idx)).is_err(), "tampered byte {idx}");
+        }
+        // the bytes past `rlp_len` are unconstrained
+        assert!(run(Some(8)).is_ok());
+    }
+}
@@ -0,0 +1,504 @@
+//! SHA-256 of assigned bytes, for Bitcoin headers and beacon chain SSZ proofs, with the same query interface as
+//! [`KeccakChip`](crate::keccak::KeccakChip).
+//!
+//! Unlike keccak, which is looked up in the keccak table in `SecondPhase`, SHA-256 is constrained directly with
+//! gates on the bits of 32-bit words in `FirstPhase`: the output bytes of a query are constrained as soon as it is
+//! made. In `SecondPhase`, [`Sha256Chip::compute_all_rlcs`] computes the RLCs of the inputs and outputs of all
+//! queries, to compare them to RLCs from the RLP chips in the same way as keccak queries.
+use crate::{
+    keccak::get_bytes,
+    rlp::rlc::{RlcChip, RlcFixedTrace, RlcTrace},
+};
+use halo2_base::{
+    gates::{GateInstructions, RangeInstructions},
+    utils::{bit_length, value_to_option},
+    AssignedValue, Context,
+    QuantumCell::{Constant, Existing},
+};
+use itertools::Itertools;
+use sha2::{Digest, Sha256};
+use zkevm_keccak::util::eth_types::Field;
+
+pub mod ssz;
+#[cfg(test)]
+mod tests;
+
+pub const SHA256_BLOCK_BYTES: usize = 64;
+pub const SHA256_DIGEST_BYTES: usize = 32;
+
+const SHA256_IV: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// Number of SHA-256 blocks of an input of `num_bytes` bytes, after padding
+pub fn get_num_sha256_blocks(num_bytes: usize) -> usize {
+    // a 0x80 byte and the 8 byte bit length are appended
+    (num_bytes + 8) / SHA256_BLOCK_BYTES + 1
+}
+
+/// A 32-bit word as its little endian bits
+type Word<'v, F> = Vec<AssignedValue<'v, F>>;
+
+#[derive(Clone, Debug)]
+pub struct Sha256FixedLenQuery<'v, F: Field> {
+    pub input_bytes: Vec<u8>,
+    pub input_assigned: Vec<AssignedValue<'v, F>>,
+
+    pub output_bytes: [u8; SHA256_DIGEST_BYTES],
+    pub output_assigned: Vec<AssignedValue<'v, F>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Sha256VarLenQuery<'v, F: Field> {
+    pub min_bytes: usize,
+    pub max_bytes: usize,
+    pub num_bytes: usize,
+    pub length: AssignedValue<'v, F>,
+    pub input_bytes: Vec<u8>,
+    pub input_assigned: Vec<AssignedValue<'v, F>>,
+
+    pub output_bytes: [u8; SHA256_DIGEST_BYTES],
+    pub output_assigned: Vec<AssignedValue<'v, F>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Sha256Chip<'v, F: Field> {
+    // available only in `FirstPhase`
+    pub var_len_queries: Vec<Sha256VarLenQuery<'v, F>>,
+    pub fixed_len_queries: Vec<Sha256FixedLenQuery<'v, F>>,
+    // available only in `SecondPhase`
+    pub fixed_len_rlcs: Vec<(RlcFixedTrace<'v, F>, RlcFixedTrace<'v, F>)>,
+    pub var_len_rlcs: Vec<(RlcTrace<'v, F>, RlcFixedTrace<'v, F>)>,
+    /// Number of SHA-256 compressions constrained so far
+    num_blocks: usize,
+}
+
+impl<'v, F: Field> Default for Sha256Chip<'v, F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'v, F: Field> Sha256Chip<'v, F> {
+    pub fn new() -> Self {
+        Self {
+            var_len_queries: vec![],
+            fixed_len_queries: vec![],
+            fixed_len_rlcs: vec![],
+            var_len_rlcs: vec![],
+            num_blocks: 0,
+        }
+    }
+
+    /// Number of SHA-256 compressions constrained so far. Each uses roughly 300k advice cells.
+    pub fn num_blocks(&self) -> usize {
+        self.num_blocks
+    }
+
+    /// Takes a byte vector of known fixed length and constrains `output_assigned` of the returned query to be the
+    /// SHA-256 digest of `input_assigned`.
+    ///
+    /// Constrains `input_assigned` to be bytes. Assumes that `input_bytes` coincides with the values of
+    /// `input_assigned` as bytes, if provided (`input_bytes` is used for faster witness generation).
+    ///
+    /// Returns the index of the query in `fixed_len_queries`.
+    pub fn sha256_fixed_len(
+        &mut self,
+        ctx: &mut Context<'_, F>,
+        gate: &impl GateInstructions<F>,
+        input_assigned: Vec<AssignedValue<'v, F>>,
+        input_bytes: Option<Vec<u8>>,
+    ) -> usize {
+        let bytes = input_bytes.unwrap_or_else(|| get_bytes(&input_assigned[..]));
+        debug_assert_eq!(bytes.len(), input_assigned.len());
+        let output_bytes: [u8; SHA256_DIGEST_BYTES] = Sha256::digest(&bytes).into();
+
+        let bits = [gate.load_zero(ctx), gate.load_constant(ctx, F::one())];
+        // the padding is known, so only the input bytes need to be decomposed
+        let padding = padding(bytes.len());
+        let mut byte_bits = input_assigned
+            .iter()
+            .map(|byte| gate.num_to_bits(ctx, byte, 8))
+            .chain(
+                padding
+                    .iter()
+                    .map(|byte| (0..8).map(|i| bits[(byte >> i) as usize & 1].clone()).collect()),
+            )
+            .collect_vec();
+
+        let mut state = SHA256_IV.iter().map(|word| constant_word(&bits, *word)).collect_vec();
+        for block in byte_bits.chunks_mut(SHA256_BLOCK_BYTES) {
+            state = self.compress(ctx, gate, &state, block);
+        }
+        let output_assigned = state_to_bytes(ctx, gate, &state);
+
+        self.fixed_len_queries.push(Sha256FixedLenQuery {
+            input_bytes: bytes,
+            input_assigned,
+            output_bytes,
+            output_assigned,
+        });
+        self.fixed_len_queries.len() - 1
+    }
+
+    /// Takes a fixed length byte vector and constrains `output_assigned` of the returned query to be the SHA-256
+    /// digest of `input_assigned[..len]`.
+    ///
+    /// Constrains `min_len <= len <= input_assigned.len()` and `input_assigned[..len]` to be bytes. Assumes that
+    /// `input_bytes` coincides with the values of `input_assigned` as bytes, if provided (`input_bytes` is used
+    /// for faster witness generation).
+    ///
+    /// This constrains the compressions of all blocks of an input of length `input_assigned.len()`, and selects
+    /// the state after the last block of an input of length `len`.
+    ///
+    /// Returns the index of the query in `var_len_queries`.
+    pub fn sha256_var_len(
+        &mut self,
+        ctx: &mut Context<'_, F>,
+        range: &impl RangeInstructions<F>,
+        input_assigned: Vec<AssignedValue<'v, F>>,
+        input_bytes: Option<Vec<u8>>,
+        len: AssignedValue<'v, F>,
+        min_len: usize,
+    ) -> usize {
+        let gate = range.gate();
+        let bytes = input_bytes.unwrap_or_else(|| get_bytes(&input_assigned[..]));
+        let max_len = input_assigned.len();
+
+        range.check_less_than_safe(ctx, &len, (max_len + 1) as u64);
+        if min_len != 0 {
+            range.check_less_than(
+                ctx,
+                Constant(gate.get_field_element((min_len - 1) as u64)),
+                Existing(&len),
+                bit_length((max_len + 1) as u64),
+            );
+        }
+        let num_bytes =
+            value_to_option(len.value()).map(|v| v.get_lower_32() as usize).unwrap_or(min_len);
+        debug_assert!(bytes.len() >= num_bytes);
+        let output_bytes: [u8; SHA256_DIGEST_BYTES] = Sha256::digest(&bytes[..num_bytes]).into();
+
+        let min_blocks = get_num_sha256_blocks(min_len);
+        let max_blocks = get_num_sha256_blocks(max_len);
+        // the index of the last block is (len + 8) / 64
+        let len_plus_8 = gate.add(ctx, Existing(&len), Constant(gate.get_field_element(8)));
+        let (last_block_idx, _) = range.div_mod(
+            ctx,
+            Existing(&len_plus_8),
+            SHA256_BLOCK_BYTES,
+            bit_length((max_len + 9) as u64),
+        );
+        let zero = gate.load_zero(ctx);
+        // the length in bits as 8 big endian bytes
+        let len_bits = gate.num_to_bits(ctx, &len, bit_length((max_len + 1) as u64));
+        let len_bytes = (0..8)
+            .rev()
+            .map(|k| {
+                gate.inner_product(
+                    ctx,
+                    (0..8).map(|i| {
+                        Existing(
+                            (8 * k + i)
+                                .checked_sub(3)
+                                .and_then(|j| len_bits.get(j))
+                                .unwrap_or(&zero),
+                        )
+                    }),
+                    gate.pow_of_two()[..8].iter().map(|pow| Constant(*pow)),
+                )
+            })
+            .collect_vec();
+
+        // padded[j] = input[j] if j < len, 0x80 if j == len, the length bytes at the end of the last block, and 0
+        // otherwise
+        let mut is_past_len = zero.clone();
+        let mut padded = (0..max_blocks * SHA256_BLOCK_BYTES)
+            .map(|j| {
+                if j > max_len {
+                    return zero.clone();
+                }
+                let is_len =
+                    gate.is_equal(ctx, Existing(&len), Constant(gate.get_field_element(j as u64)));
+                is_past_len = gate.add(ctx, Existing(&is_past_len), Existing(&is_len));
+                let pad = gate.mul(ctx, Existing(&is_len), Constant(gate.get_field_element(0x80)));
+                match input_assigned.get(j) {
+                    Some(byte) => {
+                        let data = gate.mul_not(ctx, Existing(&is_past_len), Existing(byte));
+                        gate.add(ctx, Existing(&data), Existing(&pad))
+                    }
+                    None => pad,
+                }
+            })
+            .collect_vec();
+        for block_idx in min_blocks - 1..max_blocks {
+            let is_last = gate.is_equal(
+                ctx,
+                Existing(&last_block_idx),
+                Constant(gate.get_field_element(block_idx as u64)),
+            );
+            let end = (block_idx + 1) * SHA256_BLOCK_BYTES;
+            for (byte, len_byte) in padded[end - 8..end].iter_mut().zip(len_bytes.iter()) {
+                *byte = gate.select(ctx, Existing(len_byte), Existing(byte), Existing(&is_last));
+            }
+        }
+
+        let bits = [zero, gate.load_constant(ctx, F::one())];
+        let mut byte_bits = padded.iter().map(|byte| gate.num_to_bits(ctx, byte, 8)).collect_vec();
+        let mut state = SHA256_IV.iter().map(|word| constant_word(&bits, *word)).collect_vec();
+        let mut outputs = Vec::with_capacity(max_blocks + 1 - min_blocks);
+        for (block_idx, block) in byte_bits.chunks_mut(SHA256_BLOCK_BYTES).enumerate() {
+            state = self.compress(ctx, gate, &state, block);
+            if block_idx + 1 >= min_blocks {
+                outputs.push(state_to_bytes(ctx, gate, &state));
+            }
+        }
+        let output_idx = gate.sub(
+            ctx,
+            Existing(&last_block_idx),
+            Constant(gate.get_field_element(min_blocks as u64 - 1)),
+        );
+        let output_assigned = (0..SHA256_DIGEST_BYTES)
+            .map(|i| {
+                gate.select_from_idx(
+                    ctx,
+                    outputs.iter().map(|output| Existing(&output[i])),
+                    Existing(&output_idx),
+                )
+            })
+            .collect_vec();
+
+        self.var_len_queries.push(Sha256VarLenQuery {
+            min_bytes: min_len,
+            max_bytes: max_len,
+            num_bytes,
+            length: len,
+            input_bytes: bytes,
+            input_assigned,
+            output_bytes,
+            output_assigned,
+        });
+        self.var_len_queries.len() - 1
+    }
+
+    /// Computes the SHA-256 merkle root of a tree with leaves `leaves`, as in the SSZ `hash_tree_root` of a vector
+    /// of 32 byte chunks.
+    ///
+    /// Assumptions:
+    /// - `leaves.len()` is a power of two.
+    /// - Each element of `leaves` is a slice of assigned byte values of length `SHA256_DIGEST_BYTES = 32`.
+    ///
+    /// Returns the merkle tree root as a byte array.
+    pub fn merkle_tree_root(
+        &mut self,
+        ctx: &mut Context<'_, F>,
+        gate: &impl GateInstructions<F>,
+        leaves: &[Vec<AssignedValue<'v, F>>],
+    ) -> Vec<AssignedValue<'v, F>> {
+        let depth = leaves.len().ilog2() as usize;
+        debug_assert_eq!(1 << depth, leaves.len());
+        let mut nodes = leaves.to_vec();
+        for _ in 0..depth {
+            nodes = nodes
+                .chunks(2)
+                .map(|pair| {
+                    let query_idx = self.sha256_fixed_len(ctx, gate, pair.concat(), None);
+                    self.fixed_len_queries[query_idx].output_assigned.clone()
+                })
+                .collect();
+        }
+        nodes.pop().unwrap()
+    }
+
+    /// Constrains one SHA-256 compression of the 64 bytes of `block`, given as their little endian bits, from the
+    /// state `state`. Returns the new state.
+    fn compress(
+        &mut self,
+        ctx: &mut Context<'_, F>,
+        gate: &impl GateInstructions<F>,
+        state: &[Word<'v, F>],
+        block: &mut [Vec<AssignedValue<'v, F>>],
+    ) -> Vec<Word<'v, F>> {
+        debug_assert_eq!(block.len(), SHA256_BLOCK_BYTES);
+        self.num_blocks += 1;
+        // message words are big endian
+        let mut w = block
+            .chunks_mut(4)
+            .map(|bytes| bytes.iter_mut().rev().flat_map(|bits| bits.drain(..)).collect_vec())
+            .collect_vec();
+        for t in 16..64 {
+            let s0 = small_sigma(ctx, gate, &w[t - 15], [7, 18], 3);
+            let s1 = small_sigma(ctx, gate, &w[t - 2], [17, 19], 10);
+            let word = add_words(ctx, gate, &[&s1, &w[t - 7], &s0, &w[t - 16]], 0);
+            w.push(word);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h]: [Word<'v, F>; 8] =
+            state.to_vec().try_into().unwrap();
+        for t in 0..64 {
+            let s1 = big_sigma(ctx, gate, &e, [6, 11, 25]);
+            let ch = (0..32)
+                .map(|i| gate.select(ctx, Existing(&f[i]), Existing(&g[i]), Existing(&e[i])))
+                .collect_vec();
+            let s0 = big_sigma(ctx, gate, &a, [2, 13, 22]);
+            let maj = (0..32)
+                .map(|i| {
+                    let a_xor_b = xor(ctx, gate, &a[i], &b[i]);
+                    gate.select(ctx, Existing(&c[i]), Existing(&a[i]), Existing(&a_xor_b))
+                })
+                .collect_vec();
+            // t1 = h + s1 + ch + K[t] + w[t], e = d + t1, a = t1 + s0 + maj
+            let new_e = add_words(ctx, gate, &[&d, &h, &s1, &ch, &w[t]], SHA256_K[t]);
+            let new_a = add_words(ctx, gate, &[&h, &s1, &ch, &w[t], &s0, &maj], SHA256_K[t]);
+            h = g;
+            g = f;
+            f = e;
+            e = new_e;
+            d = c;
+            c = b;
+            b = a;
+            a = new_a;
+        }
+        state
+            .iter()
+            .zip([a, b, c, d, e, f, g, h])
+            .map(|(prev, word)| add_words(ctx, gate, &[prev, &word], 0))
+            .collect()
+    }
+
+    /// Computes the RLCs of the inputs and outputs of all queries, in `SecondPhase`
+    pub fn compute_all_rlcs(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        rlc: &mut RlcChip<'v, F>,
+        gate: &impl GateInstructions<F>,
+    ) {
+        self.fixed_len_rlcs = self
+            .fixed_len_queries
+            .iter()
+            .map(|q| {
+                let input_rlc = rlc.compute_rlc_fixed_len(ctx, gate, q.input_assigned.clone());
+                let output_rlc = rlc.compute_rlc_fixed_len(ctx, gate, q.output_assigned.clone());
+                (input_rlc, output_rlc)
+            })
+            .collect();
+
+        self.var_len_rlcs = self
+            .var_len_queries
+            .iter()
+            .map(|q| {
+                let input_rlc =
+                    rlc.compute_rlc(ctx, gate, q.input_assigned.clone(), q.length.clone());
+                let output_rlc = rlc.compute_rlc_fixed_len(ctx, gate, q.output_assigned.clone());
+                (input_rlc, output_rlc)
+            })
+            .collect();
+    }
+}
+
+/// The bytes appended to an input of `num_bytes` bytes: 0x80, zeros, and the bit length as 8 big endian bytes
+fn padding(num_bytes: usize) -> Vec<u8> {
+    let padded_len = get_num_sha256_blocks(num_bytes) * SHA256_BLOCK_BYTES;
+    let mut padding = vec![0u8; padded_len - num_bytes];
+    padding[0] = 0x80;
+    let len = padding.len();
+    padding[len - 8..].copy_from_slice(&(8 * num_bytes as u64).to_be_bytes());
+    padding
+}
+
+fn constant_word<'v, F: Field>(bits: &[AssignedValue<'v, F>; 2], word: u32) -> Word<'v, F> {
+    (0..32).map(|i| bits[(word >> i) as usize & 1].clone()).collect()
+}
+
+fn xor<'v, F: Field>(
+    ctx: &mut Context<'_, F>,
+    gate: &impl GateInstructions<F>,
+    a: &AssignedValue<'v, F>,
+    b: &AssignedValue<'v, F>,
+) -> AssignedValue<'v, F> {
+    // a + b - 2ab = b * (1 - 2a) + a
+    let not_2a = gate.mul_add(ctx, Existing(a), Constant(-F::from(2)), Constant(F::one()));
+    gate.mul_add(ctx, Existing(b), Existing(&not_2a), Existing(a))
+}
+
+/// ROTR^r0(x) ^ ROTR^r1(x) ^ ROTR^r2(x)
+fn big_sigma<'v, F: Field>(
+    ctx: &mut Context<'_, F>,
+    gate: &impl GateInstructions<F>,
+    x: &Word<'v, F>,
+    [r0, r1, r2]: [usize; 3],
+) -> Word<'v, F> {
+    (0..32)
+        .map(|i| {
+            let bit = xor(ctx, gate, &x[(i + r0) % 32], &x[(i + r1) % 32]);
+            xor(ctx, gate, &bit, &x[(i + r2) % 32])
+        })
+        .collect()
+}
+
+/// ROTR^r0(x) ^ ROTR^r1(x) ^ SHR^shift(x)
+fn small_sigma<'v, F: Field>(
+    ctx: &mut Context<'_, F>,
+    gate: &impl GateInstructions<F>,
+    x: &Word<'v, F>,
+    [r0, r1]: [usize; 2],
+    shift: usize,
+) -> Word<'v, F> {
+    (0..32)
+        .map(|i| {
+            let bit = xor(ctx, gate, &x[(i + r0) % 32], &x[(i + r1) % 32]);
+            match x.get(i + shift) {
+                Some(shifted) => xor(ctx, gate, &bit, shifted),
+                None => bit,
+            }
+        })
+        .collect()
+}
+
+/// The sum of `words` and `constant` modulo 2^32
+fn add_words<'v, F: Field>(
+    ctx: &mut Context<'_, F>,
+    gate: &impl GateInstructions<F>,
+    words: &[&Word<'v, F>],
+    constant: u32,
+) -> Word<'v, F> {
+    let sum = gate.inner_product(
+        ctx,
+        words.iter().flat_map(|word| word.iter().map(Existing)),
+        words.iter().flat_map(|_| gate.pow_of_two()[..32].iter().map(|pow| Constant(*pow))),
+    );
+    let sum = gate.add(ctx, Existing(&sum), Constant(F::from(constant as u64)));
+    let mut bits = gate.num_to_bits(ctx, &sum, 32 + bit_length(words.len() as u64));
+    bits.truncate(32);
+    bits
+}
+
+/// The state as 32 big endian bytes
+fn state_to_bytes<'v, F: Field>(
+    ctx: &mut Context<'_, F>,
+    gate: &impl GateInstructions<F>,
+    state: &[Word<'v, F>],
+) -> Vec<AssignedValue<'v, F>> {
+    state
+        .iter()
+        .flat_map(|word| word.chunks(8).rev().collect_vec())
+        .map(|bits| {
+            gate.inner_product(
+                ctx,
+                bits.iter().map(Existing),
+                gate.pow_of_two()[..8].iter().map(|pow| Constant(*pow)),
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,111 @@
+//! SSZ merkle proofs with [`Sha256Chip`], e.g. of a field of a beacon block header or of the beacon state
+//! against a beacon block root, and their off-circuit counterparts to generate inputs.
+//!
+//! The leaves of SSZ merkle trees are 32 byte chunks. A proof of the leaf at `index` in a tree of depth `depth`
+//! is its branch: the sibling nodes from the leaf up to the root, as in `is_valid_merkle_branch` of the consensus
+//! specs. The generalized index of the leaf is `2^depth + index`.
+use super::{Sha256Chip, SHA256_DIGEST_BYTES};
+use halo2_base::{
+    gates::GateInstructions,
+    AssignedValue, Context,
+    QuantumCell::{Constant, Existing},
+};
+use itertools::Itertools;
+use sha2::{Digest, Sha256};
+use std::iter;
+use zkevm_keccak::util::eth_types::Field;
+
+/// The SSZ chunk of a `uint64`: its 8 little endian bytes followed by zeros
+pub fn uint64_to_chunk(value: u64) -> [u8; 32] {
+    let mut chunk = [0u8; 32];
+    chunk[..8].copy_from_slice(&value.to_le_bytes());
+    chunk
+}
+
+/// The SHA-256 merkle root of `leaves`, whose number must be a power of two
+pub fn sha256_merkle_root(leaves: &[[u8; 32]]) -> [u8; 32] {
+    assert!(leaves.len().is_power_of_two());
+    let mut nodes = leaves.to_vec();
+    while nodes.len() > 1 {
+        nodes = nodes.chunks(2).map(|pair| Sha256::digest(pair.concat()).into()).collect();
+    }
+    nodes[0]
+}
+
+/// The branch of the leaf at `index` in the SHA-256 merkle tree of `leaves`, from the leaf up to the root
+pub fn sha256_merkle_branch(leaves: &[[u8; 32]], mut index: usize) -> Vec<[u8; 32]> {
+    assert!(leaves.len().is_power_of_two() && index < leaves.len());
+    let mut nodes = leaves.to_vec();
+    let mut branch = vec![];
+    while nodes.len() > 1 {
+        branch.push(nodes[index ^ 1]);
+        nodes = nodes.chunks(2).map(|pair| Sha256::digest(pair.concat()).into()).collect();
+        index >>= 1;
+    }
+    branch
+}
+
+/// Constrains the SSZ chunk of the `uint64` `value`. Also constrains `value` to be less than 2^64.
+pub fn uint64_chunk<'v, F: Field>(
+    ctx: &mut Context<'_, F>,
+    gate: &impl GateInstructions<F>,
+    value: &AssignedValue<'v, F>,
+) -> Vec<AssignedValue<'v, F>> {
+    let bits = gate.num_to_bits(ctx, value, 64);
+    let zero = gate.load_zero(ctx);
+    bits.chunks(8)
+        .map(|byte_bits| {
+            gate.inner_product(
+                ctx,
+                byte_bits.iter().map(Existing),
+                gate.pow_of_two()[..8].iter().map(|pow| Constant(*pow)),
+            )
+        })
+        .chain(iter::repeat(zero).take(24))
+        .collect()
+}
+
+impl<'v, F: Field> Sha256Chip<'v, F> {
+    /// Computes the SSZ merkle root of the tree where `leaf` is at `index` with branch `branch`, from the leaf up
+    /// to the root. Constrains `index < 2^branch.len()`.
+    ///
+    /// Assumes `leaf` and the elements of `branch` are 32 assigned bytes.
+    pub fn merkle_branch_root(
+        &mut self,
+        ctx: &mut Context<'_, F>,
+        gate: &impl GateInstructions<F>,
+        leaf: Vec<AssignedValue<'v, F>>,
+        branch: &[Vec<AssignedValue<'v, F>>],
+        index: &AssignedValue<'v, F>,
+    ) -> Vec<AssignedValue<'v, F>> {
+        let index_bits = gate.num_to_bits(ctx, index, branch.len());
+        branch.iter().zip(index_bits.iter()).fold(leaf, |node, (sibling, is_right)| {
+            debug_assert_eq!(sibling.len(), SHA256_DIGEST_BYTES);
+            // the node is the right child if the bit of `index` at its depth is 1
+            let [left, right] = [(sibling, &node), (&node, sibling)].map(|(a, b)| {
+                a.iter()
+                    .zip(b.iter())
+                    .map(|(a, b)| gate.select(ctx, Existing(a), Existing(b), Existing(is_right)))
+                    .collect_vec()
+            });
+            let query_idx = self.sha256_fixed_len(ctx, gate, [left, right].concat(), None);
+            self.fixed_len_queries[query_idx].output_assigned.clone()
+        })
+    }
+
+    /// Constrains that `leaf` is at `index` with branch `branch` in the SSZ merkle tree with root `root`.
+    pub fn verify_merkle_branch(
+        &mut self,
+        ctx: &mut Context<'_, F>,
+        gate: &impl GateInstructions<F>,
+        leaf: Vec<AssignedValue<'v, F>>,
+        branch: &[Vec<AssignedValue<'v, F>>],
+        index: &AssignedValue<'v, F>,
+        root: &[AssignedValue<'v, F>],
+    ) {
+        let computed_root = self.merkle_branch_root(ctx, gate, leaf, branch, index);
+        for (a, b) in computed_root.iter().zip_eq(root.iter()) {
+            ctx.constrain_equal(a, b);
+        }
+    }
+}
@@ -0,0 +1,310 @@
+use super::{ssz::*, *};
+use crate::{
+    halo2_proofs::{
+        circuit::{Layouter, SimpleFloorPlanner, Value},
+        dev::MockProver,
+        halo2curves::bn256::Fr,
+        plonk::{Circuit, ConstraintSystem, Error},
+    },
+    rlp::rlc::RlcConfig,
+};
+use halo2_base::{
+    gates::range::{RangeConfig, RangeStrategy},
+    ContextParams, SKIP_FIRST_PASS,
+};
+use hex::FromHex;
+use std::env::var;
+
+const ABC: &[u8] = b"abc";
+const ABC_DIGEST: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+const EMPTY_DIGEST: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+/// Two blocks after padding
+const ABCDB: &[u8] = b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq";
+const ABCDB_DIGEST: &str = "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1";
+
+fn digest(hex: &str) -> [u8; 32] {
+    <[u8; 32]>::from_hex(hex).unwrap()
+}
+
+#[derive(Clone, Debug)]
+pub struct TestSha256Config<F: Field> {
+    range: RangeConfig<F>,
+    rlc: RlcConfig<F>,
+}
+
+#[derive(Clone, Debug)]
+enum Sha256Query {
+    FixedLen(Vec<u8>),
+    VarLen {
+        input: Vec<u8>,
+        len: usize,
+        min_len: usize,
+    },
+    /// Double SHA-256, as for Bitcoin block hashes
+    Sha256d(Vec<u8>),
+    MerkleRoot(Vec<[u8; 32]>),
+    /// A leaf `uint64` with its SSZ merkle branch
+    MerkleBranch {
+        value: u64,
+        branch: Vec<[u8; 32]>,
+        index: usize,
+    },
+}
+
+/// Constrains the outputs of `queries` to equal the expected digests
+#[derive(Clone, Debug)]
+pub struct Sha256Circuit {
+    queries: Vec<(Sha256Query, [u8; 32])>,
+}
+
+impl<F: Field> Circuit<F> for Sha256Circuit {
+    type Config = TestSha256Config<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        self.clone()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        let num_advice: usize =
+            var("NUM_ADVICE").unwrap_or_else(|_| "20".to_string()).parse().unwrap();
+        let degree: usize =
+            var("SHA256_DEGREE").unwrap_or_else(|_| "17".to_string()).parse().unwrap();
+        let mut range = RangeConfig::configure(
+            meta,
+            RangeStrategy::Vertical,
+            &[num_advice, 1],
+            &[1, 1],
+            1,
+            8,
+            0,
+            degree,
+        );
+        let rlc = RlcConfig::configure(meta, 1, 1);
+        range.gate.max_rows = (1 << degree) - meta.minimum_rows();
+        TestSha256Config { range, rlc }
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        config.range.load_lookup_table(&mut layouter).expect("load range lookup table");
+        let mut first_pass = SKIP_FIRST_PASS;
+        layouter.assign_region(
+            || "sha256",
+            |region| {
+                if first_pass {
+                    first_pass = false;
+                    return Ok(());
+                }
+                let mut aux = Context::new(
+                    region,
+                    ContextParams {
+                        num_context_ids: 2,
+                        max_rows: config.range.gate.max_rows,
+                        fixed_columns: config.range.gate.constants.clone(),
+                    },
+                );
+                let ctx = &mut aux;
+                let gate = &config.range.gate;
+                let mut rlc_chip = RlcChip::new(config.rlc.clone(), Value::unknown());
+                let mut sha256_chip = Sha256Chip::new();
+
+                let assign_bytes = |ctx: &mut Context<'_, F>, bytes: &[u8]| {
+                    gate.assign_witnesses(
+                        ctx,
+                        bytes.iter().map(|byte| Value::known(F::from(*byte as u64))),
+                    )
+                };
+                for (query, expected) in self.queries.iter() {
+                    let output = match query {
+                        Sha256Query::FixedLen(input) => {
+                            let input_assigned = assign_bytes(ctx, input);
+                            let idx = sha256_chip.sha256_fixed_len(
+                                ctx,
+                                gate,
+                                input_assigned,
+                                Some(input.clone()),
+                            );
+                            sha256_chip.fixed_len_queries[idx].output_assigned.clone()
+                        }
+                        Sha256Query::VarLen { input, len, min_len } => {
+                            let input_assigned = assign_bytes(ctx, input);
+                            let len = gate.load_witness(ctx, Value::known(F::from(*len as u64)));
+                            let idx = sha256_chip.sha256_var_len(
+                                ctx,
+                                &config.range,
+                                input_assigned,
+                                Some(input.clone()),
+                                len,
+                                *min_len,
+                            );
+                            sha256_chip.var_len_queries[idx].output_assigned.clone()
+                        }
+                        Sha256Query::Sha256d(input) => {
+                            let input_assigned = assign_bytes(ctx, input);
+                            let idx = sha256_chip.sha256_fixed_len(ctx, gate, input_assigned, None);
+                            let hash = sha256_chip.fixed_len_queries[idx].output_assigned.clone();
+                            let idx = sha256_chip.sha256_fixed_len(ctx, gate, hash, None);
+                            sha256_chip.fixed_len_queries[idx].output_assigned.clone()
+                        }
+                        Sha256Query::MerkleRoot(leaves) => {
+                            let leaves =
+                                leaves.iter().map(|leaf| assign_bytes(ctx, leaf)).collect_vec();
+                            sha256_chip.merkle_tree_root(ctx, gate, &leaves)
+                        }
+                        Sha256Query::MerkleBranch { value, branch, index } => {
+                            let value = gate.load_witness(ctx, Value::known(F::from(*value)));
+                            let leaf = uint64_chunk(ctx, gate, &value);
+                            let branch =
+                                branch.iter().map(|node| assign_bytes(ctx, node)).collect_vec();
+                            let index =
+                                gate.load_witness(ctx, Value::known(F::from(*index as u64)));
+                            sha256_chip.merkle_branch_root(ctx, gate, leaf, &branch, &index)
+                        }
+                    };
+                    for (byte, expected) in output.iter().zip_eq(expected.iter()) {
+                        gate.assert_is_const(ctx, byte, F::from(*expected as u64));
+                    }
+                }
+                config.range.finalize(ctx);
+                // END OF FIRST PHASE
+                ctx.next_phase();
+
+                // SECOND PHASE
+                rlc_chip.get_challenge(ctx);
+                sha256_chip.compute_all_rlcs(ctx, &mut rlc_chip, gate);
+                config.range.finalize(ctx);
+
+                #[cfg(feature = "display")]
+                {
+                    println!("Number of SHA-256 blocks: {}", sha256_chip.num_blocks());
+                    ctx.print_stats(&["Range", "RLC"]);
+                }
+                Ok(())
+            },
+        )
+    }
+}
+
+fn mock_run_sha256_queries(queries: Vec<(Sha256Query, [u8; 32])>) -> MockProver<Fr> {
+    let k: u32 = var("SHA256_DEGREE").unwrap_or_else(|_| "17".to_string()).parse().unwrap();
+    let circuit = Sha256Circuit { queries };
+    MockProver::<Fr>::run(k, &circuit, vec![]).unwrap()
+}
+
+fn test_sha256_queries(queries: Vec<(Sha256Query, [u8; 32])>) {
+    mock_run_sha256_queries(queries).assert_satisfied();
+}
+
+#[test]
+fn test_padding() {
+    assert_eq!(get_num_sha256_blocks(0), 1);
+    assert_eq!(get_num_sha256_blocks(55), 1);
+    assert_eq!(get_num_sha256_blocks(56), 2);
+    assert_eq!(get_num_sha256_blocks(64), 2);
+    assert_eq!(get_num_sha256_blocks(120), 3);
+    for num_bytes in [0, 3, 55, 56, 64, 119, 120] {
+        let padding = padding(num_bytes);
+        assert_eq!((num_bytes + padding.len()) % SHA256_BLOCK_BYTES, 0);
+        assert_eq!(padding[0], 0x80);
+        assert_eq!(padding[padding.len() - 8..], (8 * num_bytes as u64).to_be_bytes());
+    }
+}
+
+#[test]
+fn test_sha256_fixed_len() {
+    test_sha256_queries(vec![
+        (Sha256Query::FixedLen(vec![]), digest(EMPTY_DIGEST)),
+        (Sha256Query::FixedLen(ABC.to_vec()), digest(ABC_DIGEST)),
+        (Sha256Query::FixedLen(ABCDB.to_vec()), digest(ABCDB_DIGEST)),
+    ]);
+}
+
+#[test]
+fn test_sha256_fixed_len_wrong_digest() {
+    let queries = vec![(Sha256Query::FixedLen(ABC.to_vec()), digest(EMPTY_DIGEST))];
+    assert!(mock_run_sha256_queries(queries).verify().is_err());
+}
+
+#[test]
+fn test_sha256_var_len() {
+    // the input is padded with garbage bytes to 100 bytes, i.e. up to 2 blocks
+    let input = [ABCDB, &[0xff; 44]].concat();
+    test_sha256_queries(vec![
+        (Sha256Query::VarLen { input: input.clone(), len: 3, min_len: 0 }, digest(ABC_DIGEST)),
+        (Sha256Query::VarLen { input, len: ABCDB.len(), min_len: 3 }, digest(ABCDB_DIGEST)),
+    ]);
+}
+
+#[test]
+fn test_sha256_var_len_wrong_digest() {
+    // the digest of the first 3 bytes claimed for the first 4 bytes
+    let input = [ABCDB, &[0xff; 44]].concat();
+    let queries = vec![(Sha256Query::VarLen { input, len: 4, min_len: 0 }, digest(ABC_DIGEST))];
+    assert!(mock_run_sha256_queries(queries).verify().is_err());
+}
+
+#[test]
+fn test_sha256d_bitcoin_genesis() {
+    let header = Vec::from_hex("0100000000000000000000000000000000000000000000000000000000000000000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a29ab5f49ffff001d1dac2b7c").unwrap();
+    // block hashes are displayed in reverse byte order
+    let mut block_hash = digest("000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f");
+    block_hash.reverse();
+    test_sha256_queries(vec![(Sha256Query::Sha256d(header), block_hash)]);
+}
+
+mod ssz {
+    use super::*;
+
+    #[test]
+    fn test_sha256_merkle_branch() {
+        let leaves = (0..8).map(|i| uint64_to_chunk(i * 1000)).collect_vec();
+        let root = sha256_merkle_root(&leaves);
+        for index in 0..8 {
+            let branch = sha256_merkle_branch(&leaves, index);
+            assert_eq!(branch.len(), 3);
+            let computed_root =
+                branch.iter().enumerate().fold(leaves[index], |node, (i, sibling)| {
+                    let pair =
+                        if index >> i & 1 == 1 { [*sibling, node] } else { [node, *sibling] };
+                    Sha256::digest(pair.concat()).into()
+                });
+            assert_eq!(computed_root, root);
+        }
+    }
+
+    #[test]
+    fn test_mock_ssz_merkle_branch() {
+        let leaves = (0..4).map(|i| uint64_to_chunk(i * 1000)).collect_vec();
+        let root = sha256_merkle_root(&leaves);
+        test_sha256_queries(vec![
+            (
+                Sha256Query::MerkleBranch {
+                    value: 2000,
+                    branch: sha256_merkle_branch(&leaves, 2),
+                    index: 2,
+                },
+                root,
+            ),
+            (Sha256Query::MerkleRoot(leaves[..2].to_vec()), sha256_merkle_root(&leaves[..2])),
+        ]);
+    }
+
+    #[test]
+    fn test_mock_ssz_merkle_branch_wrong_index() {
+        let leaves = (0..4).map(|i| uint64_to_chunk(i * 1000)).collect_vec();
+        let root = sha256_merkle_root(&leaves);
+        let queries = vec![(
+            Sha256Query::MerkleBranch {
+                value: 2000,
+                branch: sha256_merkle_branch(&leaves, 2),
+                index: 3,
+            },
+            root,
+        )];
+        assert!(mock_run_sha256_queries(queries).verify().is_err());
+    }
+}
@@ -0,0 +1,253 @@
+//! Links a storage proof at some block to a trusted header chain: aggregates a snark of
+//! [`EthBlockStorageCircuit`](super::EthBlockStorageCircuit) and a snark of the header chain, and checks that
+//! the block hash of the storage proof is in the merkle mountain range of the header chain.
+use crate::{
+    block_header::{
+        aggregation::{AggregationWithKeccakConfig, AggregationWithKeccakConfigParams},
+        merkle::{verify_block_hash_merkle_proof, BlockHashMerkleProof},
+        EthBlockHeaderChainCircuit, EthBlockHeaderChainInstance,
+    },
+    keccak::KeccakChip,
+    rlp::rlc::RlcChip,
+    util::decode_field_to_h256,
+};
+#[cfg(feature = "display")]
+use ark_std::{end_timer, start_timer};
+use halo2_base::{
+    halo2_proofs::{
+        circuit::{Layouter, SimpleFloorPlanner},
+        halo2curves::bn256::{Bn256, Fr},
+        plonk::{Circuit, ConstraintSystem, Error},
+        poly::kzg::commitment::ParamsKZG,
+    },
+    Context, ContextParams,
+};
+use itertools::Itertools;
+use rand::Rng;
+use snark_verifier::pcs::kzg::{Bdfg21, Kzg};
+use snark_verifier_sdk::{
+    halo2::aggregation::{aggregate, flatten_accumulator, AggregationCircuit, Halo2Loader},
+    CircuitExt, Snark, LIMBS,
+};
+use std::{env::var, fs::File};
+
+impl AggregationWithKeccakConfigParams {
+    pub fn get_historical_storage() -> Self {
+        let path = var("HISTORICAL_STORAGE_CONFIG")
+            .unwrap_or_else(|_| "configs/historical_storage.json".to_string());
+        serde_json::from_reader(
+            File::open(&path).unwrap_or_else(|e| panic!("{path} does not exist: {e:?}")),
+        )
+        .unwrap()
+    }
+}
+
+/// Aggregates a snark of `EthBlockStorageCircuit` and a snark of the header chain, either an
+/// [`EthBlockHeaderChainCircuit`] or an `EthBlockHeaderChainFinalAggregationCircuit`, with merkle mountain range
+/// of depth `header_max_depth`.
+///
+/// Public instances, after the accumulator:
+/// - the header chain instances (prev_hash, end_hash, block_numbers, merkle_roots), see [`EthBlockHeaderChainInstance`],
+/// followed by the header fields exposed by the header chain snark, if any
+/// - the block number of the storage proof
+/// - the storage proof instances after the block number: for each account its address, whether it exists, its
+/// selected account fields, and for each slot (slot, value, slot_exists)
+///
+/// The block hash of the storage proof is not exposed: it is only proved to be in the header chain.
+#[derive(Clone)]
+pub struct EthHistoricalStorageAggregationCircuit {
+    aggregation: AggregationCircuit,
+    pub merkle_proof: BlockHashMerkleProof,
+    pub header_max_depth: usize,
+    // index of the first instance of the header chain snark after its accumulator, if any
+    header_start: usize,
+    // instances of the header chain snark without its accumulator
+    header_instance: Vec<Fr>,
+    // instances of the storage snark without the block hash
+    storage_instance: Vec<Fr>,
+}
+
+impl EthHistoricalStorageAggregationCircuit {
+    /// `merkle_proof` is the proof that the block hash of `storage_snark` is in the merkle mountain range of
+    /// `header_snark`, see [`BlockHashMerkleProof::new`].
+    pub fn new(
+        params: &ParamsKZG<Bn256>,
+        storage_snark: Snark,
+        header_snark: Snark,
+        merkle_proof: BlockHashMerkleProof,
+        header_max_depth: usize,
+        rng: &mut (impl Rng + Send),
+    ) -> Self {
+        let header_start = header_instance_start(&header_snark, header_max_depth);
+        let chain_end =
+            header_start + EthBlockHeaderChainCircuit::<Fr>::get_num_instance(header_max_depth);
+        let chain_instance = EthBlockHeaderChainInstance::from_instance(
+            &header_snark.instances[0][header_start..chain_end],
+        );
+        let block_hash = decode_field_to_h256(&storage_snark.instances[0][..2]);
+        assert_eq!(merkle_proof.block_hash, block_hash, "merkle proof is for a different block");
+        assert!(
+            merkle_proof.verify(&chain_instance.merkle_mountain_range),
+            "block is not in the header chain"
+        );
+        let header_instance = header_snark.instances[0][header_start..].to_vec();
+        let storage_instance = storage_snark.instances[0][2..].to_vec();
+        let aggregation = AggregationCircuit::new(params, vec![storage_snark, header_snark], rng);
+        Self {
+            aggregation,
+            merkle_proof,
+            header_max_depth,
+            header_start,
+            header_instance,
+            storage_instance,
+        }
+    }
+
+    pub fn instance(&self) -> Vec<Fr> {
+        [&self.aggregation.instances()[0][..], &self.header_instance, &self.storage_instance]
+            .concat()
+    }
+}
+
+/// Index of the first instance of a header chain snark with merkle mountain range of depth `max_depth` after its
+/// accumulator, if any. The chain instances may be followed by the header fields exposed by
+/// [`EthBlockHeaderChainCircuit::with_field_queries`], 2 instances per field.
+fn header_instance_start(header_snark: &Snark, max_depth: usize) -> usize {
+    let start = if header_snark.protocol.accumulator_indices.is_empty() { 0 } else { 4 * LIMBS };
+    let num_field_instance = header_snark.instances[0]
+        .len()
+        .checked_sub(start + EthBlockHeaderChainCircuit::<Fr>::get_num_instance(max_depth))
+        .unwrap_or_else(|| panic!("not a header chain snark of depth {max_depth}"));
+    assert!(num_field_instance % 2 == 0, "not a header chain snark of depth {max_depth}");
+    assert!(
+        start == 0 || num_field_instance == 0,
+        "only initial header chain snarks expose fields"
+    );
+    start
+}
+
+impl Circuit<Fr> for EthHistoricalStorageAggregationCircuit {
+    type Config = AggregationWithKeccakConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self { aggregation: self.aggregation.without_witnesses(), ..self.clone() }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+        let params = AggregationWithKeccakConfigParams::get_historical_storage();
+        AggregationWithKeccakConfig::configure(meta, params)
+    }
+
+    fn synthesize(
+        &self,
+        config: AggregationWithKeccakConfig,
+        mut layouter: impl Layouter<Fr>,
+    ) -> Result<(), Error> {
+        #[cfg(feature = "display")]
+        let witness_time = start_timer!(|| "synthesize historical storage aggregation");
+        config.range().load_lookup_table(&mut layouter).expect("load range lookup table");
+        config.keccak.load_aux_tables(&mut layouter).expect("load keccak lookup table");
+        let gamma = layouter.get_challenge(config.rlc.gamma);
+        let mut first_pass = halo2_base::SKIP_FIRST_PASS;
+        let mut instances = Vec::new();
+        layouter
+            .assign_region(
+                || "Historical storage aggregation circuit",
+                |region| {
+                    if first_pass {
+                        first_pass = false;
+                        return Ok(());
+                    }
+                    let ctx = Context::new(
+                        region,
+                        ContextParams {
+                            max_rows: config.gate().max_rows,
+                            num_context_ids: 1,
+                            fixed_columns: config.gate().constants.clone(),
+                        },
+                    );
+                    let loader = Halo2Loader::new(config.aggregation.ecc_chip(), ctx);
+                    let (prev_instances, acc) = aggregate::<Kzg<Bn256, Bdfg21>>(
+                        self.aggregation.succinct_verifying_key(),
+                        &loader,
+                        self.aggregation.snarks(),
+                        self.aggregation.as_proof(),
+                    );
+                    let [storage_instances, header_instances]: [_; 2] =
+                        prev_instances.try_into().unwrap();
+                    let header_instances = &header_instances[self.header_start..];
+
+                    let ctx = &mut loader.ctx_mut();
+                    // add RLC context
+                    ctx.advice_alloc.push((0, 0));
+
+                    // ============ FIRST PHASE ============
+                    let mut rlc_chip = RlcChip::new(config.rlc.clone(), gamma);
+                    let mut keccak_chip = KeccakChip::new(config.keccak.clone());
+                    // the merkle roots start after prev_hash, end_hash, block_numbers and are followed by the
+                    // header fields, if any
+                    let num_chain_instance =
+                        EthBlockHeaderChainCircuit::<Fr>::get_num_instance(self.header_max_depth);
+                    let merkle_roots = header_instances[5..num_chain_instance]
+                        .chunks(2)
+                        .map(|root| [root[0].clone(), root[1].clone()])
+                        .collect_vec();
+                    let proof = self.merkle_proof.assign(ctx, config.gate(), self.header_max_depth);
+                    let block_hash = verify_block_hash_merkle_proof(
+                        ctx,
+                        config.range(),
+                        &mut keccak_chip,
+                        &proof,
+                        &merkle_roots,
+                    );
+                    for (a, b) in block_hash.iter().zip(storage_instances[..2].iter()) {
+                        ctx.constrain_equal(a, b);
+                    }
+
+                    instances.extend(
+                        flatten_accumulator(acc)
+                            .iter()
+                            .chain(header_instances.iter())
+                            .chain(storage_instances[2..].iter())
+                            .map(|assigned| assigned.cell())
+                            .cloned(),
+                    );
+                    keccak_chip.assign_phase0(&mut ctx.region);
+                    config.range().finalize(ctx);
+                    ctx.next_phase();
+
+                    // ============ SECOND PHASE ============
+                    rlc_chip.get_challenge(ctx);
+                    keccak_chip.assign_phase1(ctx, &mut rlc_chip, config.range());
+                    config.range().finalize(ctx);
+
+                    #[cfg(feature = "display")]
+                    ctx.print_stats(&["Range", "RLC"]);
+                    Ok(())
+                },
+            )
+            .unwrap();
+
+        for (i, cell) in instances.into_iter().enumerate() {
+            layouter.constrain_instance(cell, config.aggregation.instance, i);
+        }
+        #[cfg(feature = "display")]
+        end_timer!(witness_time);
+        Ok(())
+    }
+}
+
+impl CircuitExt<Fr> for EthHistoricalStorageAggregationCircuit {
+    fn num_instance(&self) -> Vec<usize> {
+        vec![self.instance().len()]
+    }
+
+    fn instances(&self) -> Vec<Vec<Fr>> {
+        vec![self.instance()]
+    }
+
+    fn accumulator_indices() -> Option<Vec<(usize, usize)>> {
+        Some((0..4 * LIMBS).map(|idx| (0, idx)).collect())
+    }
+}
@@ -0,0 +1,397 @@
+//! Proves the bytecode of a contract at a block: the code is a private input whose keccak must equal the code hash
+//! in the account proof of the contract, and a range of 32-byte words of the code are public instances.
+use super::{
+    EthAccountFields, EthBlockAccountStorageTrace, EthBlockAccountStorageTraceWitness,
+    EthBlockStorageInput, EthBlockStorageInputAssigned, EthStorageChip,
+};
+use crate::{
+    block_header::EthBlockHeaderChip,
+    halo2_proofs::{
+        circuit::{Layouter, SimpleFloorPlanner, Value},
+        plonk::{Circuit, ConstraintSystem, Error},
+    },
+    mpt::AssignedBytes,
+    util::{
+        bytes_be_to_u128, encode_addr_to_field, encode_h256_to_field, AssignedH256, EthConfigParams,
+    },
+    EthChip, EthConfig, Field, Network,
+};
+#[cfg(feature = "display")]
+use ark_std::{end_timer, start_timer};
+#[cfg(feature = "providers")]
+use ethers_core::types::Address;
+use ethers_core::{types::H256, utils::keccak256};
+#[cfg(feature = "providers")]
+use ethers_providers::{Http, Provider};
+use halo2_base::{
+    gates::{GateInstructions, RangeInstructions},
+    utils::bit_length,
+    AssignedValue, Context, ContextParams,
+    QuantumCell::{Constant, Existing},
+    SKIP_FIRST_PASS,
+};
+use itertools::Itertools;
+use snark_verifier_sdk::CircuitExt;
+use std::marker::PhantomData;
+
+/// Maximum size of contract code, by EIP-170
+pub const MAX_CODE_SIZE: usize = 24576;
+
+#[derive(Clone, Debug)]
+pub struct EthBlockBytecodeInput {
+    /// Account proof of the contract, without any storage proofs
+    pub storage: EthBlockStorageInput,
+    pub code: Vec<u8>,
+    /// The code is zero padded to `max_code_len` bytes in-circuit
+    pub max_code_len: usize,
+    /// Byte offset of the first public code word. It is a constant of the circuit.
+    pub code_offset: usize,
+    pub num_code_words: usize,
+}
+
+impl EthBlockBytecodeInput {
+    pub fn new(
+        storage: EthBlockStorageInput,
+        code: Vec<u8>,
+        max_code_len: usize,
+        code_offset: usize,
+        num_code_words: usize,
+    ) -> Self {
+        assert_eq!(storage.storage.len(), 1, "bytecode proof is for a single account");
+        assert!(storage.storage[0].storage_pfs.is_empty(), "storage proofs are not used");
+        assert!(code.len() <= max_code_len, "code is longer than max_code_len");
+        Self { storage, code, max_code_len, code_offset, num_code_words }
+    }
+
+    /// `code[code_offset..]` in 32-byte words, zero padded past the end of the code
+    pub fn code_words(&self) -> Vec<H256> {
+        (0..self.num_code_words)
+            .map(|idx| {
+                let start = self.code_offset + 32 * idx;
+                let mut word = [0u8; 32];
+                for (i, byte) in word.iter_mut().enumerate() {
+                    *byte = self.code.get(start + i).copied().unwrap_or(0);
+                }
+                H256(word)
+            })
+            .collect()
+    }
+
+    pub fn assign<'v, F: Field>(
+        &self,
+        ctx: &mut Context<'_, F>,
+        gate: &impl GateInstructions<F>,
+    ) -> EthBlockBytecodeInputAssigned<'v, F> {
+        let storage = self.storage.assign(ctx, gate);
+        let mut code_bytes = self.code.clone();
+        code_bytes.resize(self.max_code_len, 0);
+        let code = gate.assign_witnesses(
+            ctx,
+            code_bytes.iter().map(|byte| Value::known(F::from(*byte as u64))),
+        );
+        let code_len = gate.load_witness(ctx, Value::known(F::from(self.code.len() as u64)));
+        EthBlockBytecodeInputAssigned {
+            storage,
+            code,
+            code_bytes,
+            code_len,
+            code_offset: self.code_offset,
+            num_code_words: self.num_code_words,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct EthBlockBytecodeInputAssigned<'v, F: Field> {
+    pub storage: EthBlockStorageInputAssigned<'v, F>,
+    /// The code zero padded to `max_code_len` bytes
+    pub code: AssignedBytes<'v, F>,
+    pub code_bytes: Vec<u8>,
+    pub code_len: AssignedValue<'v, F>,
+    pub code_offset: usize,
+    pub num_code_words: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct EthBytecodeDigest<'v, F: Field> {
+    pub block_hash: AssignedH256<'v, F>,
+    pub block_number: AssignedValue<'v, F>,
+    pub address: AssignedValue<'v, F>,
+    pub code_hash: AssignedH256<'v, F>,
+    pub code_len: AssignedValue<'v, F>,
+    pub code_words: Vec<AssignedH256<'v, F>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct EthBlockBytecodeTraceWitness<'v, F: Field> {
+    storage_witness: EthBlockAccountStorageTraceWitness<'v, F>,
+    digest: EthBytecodeDigest<'v, F>,
+}
+
+#[derive(Clone, Debug)]
+pub struct EthBlockBytecodeTrace<'v, F: Field> {
+    pub storage_trace: EthBlockAccountStorageTrace<'v, F>,
+    pub digest: EthBytecodeDigest<'v, F>,
+}
+
+pub trait EthBytecodeChip<'v, F: Field> {
+    /// Checks that keccak of the code is the code hash of the account, which exists, and reads the public
+    /// code words.
+    fn parse_bytecode_from_block_phase0(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        input: EthBlockBytecodeInputAssigned<'v, F>,
+        network: Network,
+    ) -> EthBlockBytecodeTraceWitness<'v, F>
+    where
+        Self: EthBlockHeaderChip<'v, F>;
+
+    fn parse_bytecode_from_block_phase1(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        witness: EthBlockBytecodeTraceWitness<'v, F>,
+    ) -> EthBlockBytecodeTrace<'v, F>
+    where
+        Self: EthBlockHeaderChip<'v, F>;
+}
+
+impl<'v, F: Field> EthBytecodeChip<'v, F> for EthChip<'v, F> {
+    fn parse_bytecode_from_block_phase0(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        input: EthBlockBytecodeInputAssigned<'v, F>,
+        network: Network,
+    ) -> EthBlockBytecodeTraceWitness<'v, F>
+    where
+        Self: EthBlockHeaderChip<'v, F>,
+    {
+        let storage_witness =
+            self.parse_eip1186_proofs_from_block_phase0(ctx, input.storage, network);
+        let account = &storage_witness.digest.accounts[0];
+        let fields = self.parse_account_fields_phase0(
+            ctx,
+            &storage_witness.acct_witness[0],
+            &account.account_exists,
+            EthAccountFields { code_hash: true, ..Default::default() },
+        );
+        // the code hash is 0 if the account does not exist, so it cannot equal a keccak
+        let code_hash = fields.code_hash.unwrap();
+
+        let max_code_len = input.code.len();
+        for byte in &input.code {
+            self.range().range_check(ctx, byte, 8);
+        }
+        let query_idx = self.mpt.keccak.keccak_var_len(
+            ctx,
+            &self.mpt.rlp.range,
+            input.code.clone(),
+            Some(input.code_bytes),
+            input.code_len.clone(),
+            0,
+        );
+        let hash_bytes = self.keccak().var_len_queries[query_idx].output_assigned.clone();
+        let hash = bytes_be_to_u128(ctx, self.gate(), &hash_bytes);
+        for (a, b) in hash.iter().zip(code_hash.iter()) {
+            ctx.constrain_equal(a, b);
+        }
+
+        // bytes past the end of the code are not constrained by the keccak, so they are zeroed out
+        let num_bits = bit_length(max_code_len as u64 + 1);
+        let code_words = (0..input.num_code_words)
+            .map(|idx| {
+                let start = input.code_offset + 32 * idx;
+                let word = (start..start + 32)
+                    .map(|pos| {
+                        if pos < max_code_len {
+                            let in_code = self.range().is_less_than(
+                                ctx,
+                                Constant(F::from(pos as u64)),
+                                Existing(&input.code_len),
+                                num_bits,
+                            );
+                            self.gate().mul(ctx, Existing(&input.code[pos]), Existing(&in_code))
+                        } else {
+                            self.gate().load_zero(ctx)
+                        }
+                    })
+                    .collect_vec();
+                bytes_be_to_u128(ctx, self.gate(), &word).try_into().unwrap()
+            })
+            .collect();
+
+        let digest = EthBytecodeDigest {
+            block_hash: storage_witness.digest.block_hash.clone(),
+            block_number: storage_witness.digest.block_number.clone(),
+            address: account.address.clone(),
+            code_hash,
+            code_len: input.code_len,
+            code_words,
+        };
+        EthBlockBytecodeTraceWitness { storage_witness, digest }
+    }
+
+    fn parse_bytecode_from_block_phase1(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        witness: EthBlockBytecodeTraceWitness<'v, F>,
+    ) -> EthBlockBytecodeTrace<'v, F>
+    where
+        Self: EthBlockHeaderChip<'v, F>,
+    {
+        let storage_trace =
+            self.parse_eip1186_proofs_from_block_phase1(ctx, witness.storage_witness);
+        EthBlockBytecodeTrace { storage_trace, digest: witness.digest }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct EthBlockBytecodeCircuit<F> {
+    pub inputs: EthBlockBytecodeInput,
+    network: Network,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> EthBlockBytecodeCircuit<F> {
+    pub fn new(inputs: EthBlockBytecodeInput, network: Network) -> Self {
+        Self { inputs, network, _marker: PhantomData }
+    }
+
+    #[cfg(feature = "providers")]
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_provider(
+        provider: &Provider<Http>,
+        block_number: u32,
+        addr: Address,
+        acct_pf_max_depth: usize,
+        max_code_len: usize,
+        code_offset: usize,
+        num_code_words: usize,
+        network: Network,
+    ) -> Self {
+        use crate::providers::get_block_bytecode_input;
+
+        let (storage, code) =
+            get_block_bytecode_input(provider, block_number, addr, acct_pf_max_depth);
+        let inputs =
+            EthBlockBytecodeInput::new(storage, code, max_code_len, code_offset, num_code_words);
+        Self::new(inputs, network)
+    }
+
+    // blockHash, blockNumber, address, codeHash, codeLen, codeWords
+    // with H256 encoded as hi-lo (u128, u128)
+    pub fn instance(&self) -> Vec<F> {
+        let EthBlockStorageInput { block_number, block_hash, storage, .. } = &self.inputs.storage;
+        let mut instance = Vec::with_capacity(self.num_instance()[0]);
+        instance.extend(encode_h256_to_field::<F>(block_hash));
+        instance.push(F::from(*block_number as u64));
+        instance.push(encode_addr_to_field(&storage[0].addr));
+        instance.extend(encode_h256_to_field::<F>(&H256(keccak256(&self.inputs.code))));
+        instance.push(F::from(self.inputs.code.len() as u64));
+        for word in self.inputs.code_words() {
+            instance.extend(encode_h256_to_field::<F>(&word));
+        }
+        instance
+    }
+}
+
+impl<F: Field> Circuit<F> for EthBlockBytecodeCircuit<F> {
+    type Config = EthConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        self.clone()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        let params = EthConfigParams::get_bytecode();
+        EthConfig::configure(meta, params, 0)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        #[cfg(feature = "display")]
+        let witness_gen = start_timer!(|| "synthesize");
+
+        let gamma = layouter.get_challenge(config.rlc().gamma);
+        config.range().load_lookup_table(&mut layouter).expect("load range lookup table");
+        config.keccak().load_aux_tables(&mut layouter).expect("load keccak lookup tables");
+
+        let mut first_pass = SKIP_FIRST_PASS;
+        let mut instance = vec![];
+        layouter
+            .assign_region(
+                || "contract bytecode from blockHash",
+                |region| {
+                    if first_pass {
+                        first_pass = false;
+                        return Ok(());
+                    }
+                    let mut chip = EthChip::new(config.clone(), gamma);
+                    let mut aux = Context::new(
+                        region,
+                        ContextParams {
+                            max_rows: chip.gate().max_rows,
+                            num_context_ids: 2,
+                            fixed_columns: chip.gate().constants.clone(),
+                        },
+                    );
+                    let ctx = &mut aux;
+
+                    // ================= FIRST PHASE ================
+                    let input = self.inputs.assign(ctx, chip.gate());
+                    let witness = chip.parse_bytecode_from_block_phase0(ctx, input, self.network);
+                    chip.assign_phase0(ctx);
+                    ctx.next_phase();
+
+                    // ================= SECOND PHASE ================
+                    chip.get_challenge(ctx);
+                    chip.keccak_assign_phase1(ctx);
+
+                    let trace = chip.parse_bytecode_from_block_phase1(ctx, witness);
+                    let EthBytecodeDigest {
+                        block_hash,
+                        block_number,
+                        address,
+                        code_hash,
+                        code_len,
+                        code_words,
+                    } = trace.digest;
+                    chip.range().finalize(ctx);
+
+                    instance.extend(
+                        block_hash
+                            .iter()
+                            .chain([block_number, address].iter())
+                            .chain(code_hash.iter())
+                            .chain([code_len].iter())
+                            .chain(code_words.iter().flatten())
+                            .map(|acell| acell.cell().clone()),
+                    );
+
+                    #[cfg(feature = "display")]
+                    ctx.print_stats(&["Range", "RLC"]);
+                    Ok(())
+                },
+            )
+            .unwrap();
+        for (i, cell) in instance.into_iter().enumerate() {
+            layouter.constrain_instance(cell, config.instance, i);
+        }
+        #[cfg(feature = "display")]
+        end_timer!(witness_gen);
+        Ok(())
+    }
+}
+
+impl<F: Field> CircuitExt<F> for EthBlockBytecodeCircuit<F> {
+    fn num_instance(&self) -> Vec<usize> {
+        vec![7 + 2 * self.inputs.num_code_words]
+    }
+
+    fn instances(&self) -> Vec<Vec<F>> {
+        vec![self.instance()]
+    }
+}
@@ -0,0 +1,195 @@
+//! Storage slots of Solidity state variables, following the
+//! [storage layout](https://docs.soliditylang.org/en/latest/internals/layout_in_storage.html) of the compiler.
+//!
+//! A [`StorageLocation`] starts at the slot of a state variable and walks down through mappings, dynamic arrays
+//! and struct fields. Its slot can be derived off-circuit with [`StorageLocation::slot`], or in-circuit with
+//! [`storage_slot_bytes`], in which case the mapping keys are witnesses and everything else is a constant of the
+//! circuit.
+use crate::{
+    keccak::KeccakChip,
+    mpt::AssignedBytes,
+    util::{bytes_be_to_u128, encode_h256_to_field, uint_to_bytes_be, AssignedH256},
+    Field,
+};
+use ethers_core::{
+    types::{H256, U256},
+    utils::keccak256,
+};
+use halo2_base::{
+    gates::{range::RangeConfig, GateInstructions},
+    halo2_proofs::circuit::Value,
+    AssignedValue, Context,
+    QuantumCell::{Constant, Existing},
+};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StorageStep {
+    /// Value of a `mapping` at `key`, left padded to 32 bytes as by `abi.encode`: the slot becomes
+    /// `keccak(key . slot)`
+    MappingKey(H256),
+    /// Element `index` of a dynamic array whose elements take `element_slots` slots each: the slot becomes
+    /// `keccak(slot) + index * element_slots`
+    ArrayIndex { index: U256, element_slots: U256 },
+    /// Field `offset` slots into a struct or a fixed size array: the slot becomes `slot + offset`
+    StructField(U256),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageLocation {
+    /// Slot of the state variable
+    pub base: U256,
+    pub path: Vec<StorageStep>,
+}
+
+impl StorageLocation {
+    /// The state variable at slot `base`
+    pub fn new(base: impl Into<U256>) -> Self {
+        Self { base: base.into(), path: vec![] }
+    }
+
+    /// Value at `key` of the mapping at this location. Address keys are converted with `H256::from(address)`.
+    /// Mappings with `string` or `bytes` keys are not supported.
+    pub fn mapping(mut self, key: H256) -> Self {
+        self.path.push(StorageStep::MappingKey(key));
+        self
+    }
+
+    /// Element `index` of the dynamic array at this location, with elements taking `element_slots` slots each
+    pub fn array_index(mut self, index: impl Into<U256>, element_slots: impl Into<U256>) -> Self {
+        let (index, element_slots) = (index.into(), element_slots.into());
+        self.path.push(StorageStep::ArrayIndex { index, element_slots });
+        self
+    }
+
+    /// Field `offset` slots into the struct at this location
+    pub fn field(mut self, offset: impl Into<U256>) -> Self {
+        self.path.push(StorageStep::StructField(offset.into()));
+        self
+    }
+
+    pub fn mapping_keys(&self) -> impl Iterator<Item = &H256> {
+        self.path.iter().filter_map(|step| match step {
+            StorageStep::MappingKey(key) => Some(key),
+            _ => None,
+        })
+    }
+
+    pub fn num_mapping_keys(&self) -> usize {
+        self.mapping_keys().count()
+    }
+
+    /// The storage slot of this location
+    pub fn slot(&self) -> H256 {
+        let slot = self.path.iter().fold(self.base, |slot, step| match step {
+            StorageStep::MappingKey(key) => {
+                U256::from_big_endian(&keccak256([key.as_bytes(), &u256_to_bytes(slot)].concat()))
+            }
+            StorageStep::ArrayIndex { index, element_slots } => {
+                U256::from_big_endian(&keccak256(u256_to_bytes(slot)))
+                    .overflowing_add(index.overflowing_mul(*element_slots).0)
+                    .0
+            }
+            StorageStep::StructField(offset) => slot.overflowing_add(*offset).0,
+        });
+        H256(u256_to_bytes(slot))
+    }
+
+    /// Loads the mapping keys as witnesses, each as (u128, u128)
+    pub fn assign<'v, F: Field>(
+        &self,
+        ctx: &mut Context<'_, F>,
+        gate: &impl GateInstructions<F>,
+    ) -> AssignedStorageLocation<'v, F> {
+        let mapping_keys = self
+            .mapping_keys()
+            .map(|key| {
+                encode_h256_to_field(key).map(|limb| gate.load_witness(ctx, Value::known(limb)))
+            })
+            .collect();
+        AssignedStorageLocation { location: self.clone(), mapping_keys }
+    }
+}
+
+fn u256_to_bytes(value: U256) -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    bytes
+}
+
+#[derive(Clone, Debug)]
+pub struct AssignedStorageLocation<'v, F: Field> {
+    /// Only the base slot and the steps are used in-circuit; the values of the mapping keys are ignored
+    pub location: StorageLocation,
+    /// Mapping keys of `location`, in order, as (u128, u128)
+    pub mapping_keys: Vec<AssignedH256<'v, F>>,
+}
+
+/// Returns the 32 bytes of the storage slot of `location`, with its base slot, array indices and struct offsets
+/// as constants and its mapping keys as witnesses. The mapping keys are range checked to be H256.
+///
+/// Array offsets `index * element_slots` and struct offsets must be less than 2^128, and adding them to a slot
+/// must not overflow 2^256, which holds for any slot used by the Solidity compiler.
+pub fn storage_slot_bytes<'v, F: Field>(
+    ctx: &mut Context<'v, F>,
+    range: &RangeConfig<F>,
+    keccak: &mut KeccakChip<'v, F>,
+    location: &AssignedStorageLocation<'v, F>,
+) -> AssignedBytes<'v, F> {
+    debug_assert_eq!(ctx.current_phase(), 0);
+    let gate = &range.gate;
+    let mut mapping_keys = location.mapping_keys.iter();
+    let mut slot = u256_to_bytes(location.location.base)
+        .iter()
+        .map(|byte| gate.load_constant(ctx, F::from(*byte as u64)))
+        .collect_vec();
+    for step in &location.location.path {
+        slot = match step {
+            StorageStep::MappingKey(_) => {
+                let key = mapping_keys.next().expect("missing mapping key");
+                let key_bytes =
+                    key.iter().map(|limb| uint_to_bytes_be(ctx, range, limb, 16)).concat();
+                let query_idx =
+                    keccak.keccak_fixed_len(ctx, gate, [key_bytes, slot].concat(), None);
+                keccak.fixed_len_queries[query_idx].output_assigned.clone()
+            }
+            StorageStep::ArrayIndex { index, element_slots } => {
+                let query_idx = keccak.keccak_fixed_len(ctx, gate, slot, None);
+                let slot = keccak.fixed_len_queries[query_idx].output_assigned.clone();
+                let offset = index.checked_mul(*element_slots).expect("array offset overflow");
+                add_offset(ctx, range, &slot, offset)
+            }
+            StorageStep::StructField(offset) => add_offset(ctx, range, &slot, *offset),
+        };
+    }
+    assert!(mapping_keys.next().is_none(), "too many mapping keys");
+    slot
+}
+
+/// Adds the constant `offset < 2^128` to the 32 bytes `slot`, asserting that the sum is less than 2^256.
+fn add_offset<'v, F: Field>(
+    ctx: &mut Context<'v, F>,
+    range: &RangeConfig<F>,
+    slot: &[AssignedValue<'v, F>],
+    offset: U256,
+) -> AssignedBytes<'v, F> {
+    assert!(offset.bits() <= 128, "storage offset must be less than 2^128");
+    if offset.is_zero() {
+        return slot.to_vec();
+    }
+    let offset = offset.as_u128();
+    let gate = &range.gate;
+    let [hi, lo]: [_; 2] = bytes_be_to_u128(ctx, gate, slot).try_into().unwrap();
+    // carry is 1 iff lo + offset >= 2^128
+    let carry = gate.load_witness(
+        ctx,
+        lo.value().map(|lo| F::from(lo.get_lower_128().overflowing_add(offset).1 as u64)),
+    );
+    gate.assert_bit(ctx, &carry);
+    let lo = gate.add(ctx, Existing(&lo), Constant(F::from_u128(offset)));
+    let lo = gate.mul_add(ctx, Existing(&carry), Constant(-gate.pow_of_two()[128]), Existing(&lo));
+    let hi = gate.add(ctx, Existing(&hi), Existing(&carry));
+    // decomposing into 16 bytes each checks that lo < 2^128, so the carry is correct, and that hi < 2^128
+    [hi, lo].iter().map(|limb| uint_to_bytes_be(ctx, range, limb, 16)).concat()
+}
@@ -7,6 +7,7 @@ use crate::{
         circuit::{Layouter, SimpleFloorPlanner, Value},
         plonk::{Circuit, ConstraintSystem, Error},
     },
+    instance::StorageInstanceLayout,
     mpt::{AssignedBytes, MPTFixedKeyInput, MPTFixedKeyProof, MPTFixedKeyProofWitness},
     rlp::{rlc::RlcTrace, RlpArrayTraceWitness, RlpFieldTraceWitness},
     util::{
@@ -21,11 +22,21 @@ use ark_std::{end_timer, start_timer};
 use ethers_core::types::{Address, Block, H256, U256};
 #[cfg(feature = "providers")]
 use ethers_providers::{Http, Provider};
-use halo2_base::{gates::GateInstructions, AssignedValue, Context, ContextParams, SKIP_FIRST_PASS};
+use halo2_base::{
+    gates::GateInstructions, AssignedValue, Context, ContextParams, QuantumCell::Existing,
+    SKIP_FIRST_PASS,
+};
 use itertools::Itertools;
+use location::{storage_slot_bytes, AssignedStorageLocation, StorageLocation};
+use rlp::Rlp;
+use serde::{Deserialize, Serialize};
 use snark_verifier_sdk::CircuitExt;
 use std::marker::PhantomData;
 
+#[cfg(feature = "aggregation")]
+pub mod aggregation;
+pub mod bytecode;
+pub mod location;
 #[cfg(all(test, feature = "providers"))]
 mod tests;
 
@@ -57,16 +68,18 @@ pub struct EthStorageTraceWitness<'v, F: Field> {
 #[derive(Clone, Debug)]
 pub struct EthBlockAccountStorageTrace<'v, F: Field> {
     pub block_trace: EthBlockHeaderTrace<'v, F>,
-    pub acct_trace: EthAccountTrace<'v, F>,
-    pub storage_trace: Vec<EthStorageTrace<'v, F>>,
+    /// One trace per account, in the order of the inputs
+    pub acct_trace: Vec<EthAccountTrace<'v, F>>,
+    /// `storage_trace[i]` are the storage traces of the account `acct_trace[i]`
+    pub storage_trace: Vec<Vec<EthStorageTrace<'v, F>>>,
     pub digest: EIP1186ResponseDigest<'v, F>,
 }
 
 #[derive(Clone, Debug)]
 pub struct EthBlockAccountStorageTraceWitness<'v, F: Field> {
     block_witness: EthBlockHeaderTraceWitness<'v, F>,
-    acct_witness: EthAccountTraceWitness<'v, F>,
-    storage_witness: Vec<EthStorageTraceWitness<'v, F>>,
+    acct_witness: Vec<EthAccountTraceWitness<'v, F>>,
+    storage_witness: Vec<Vec<EthStorageTraceWitness<'v, F>>>,
     digest: EIP1186ResponseDigest<'v, F>,
 }
 
@@ -74,9 +87,92 @@ pub struct EthBlockAccountStorageTraceWitness<'v, F: Field> {
 pub struct EIP1186ResponseDigest<'v, F: Field> {
     pub block_hash: AssignedH256<'v, F>,
     pub block_number: AssignedValue<'v, F>,
+    pub accounts: Vec<EIP1186AccountDigest<'v, F>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct EIP1186AccountDigest<'v, F: Field> {
     pub address: AssignedValue<'v, F>,
+    /// Boolean indicating whether the account exists in the state trie
+    pub account_exists: AssignedValue<'v, F>,
     // the value U256 is interpreted as H256 (padded with 0s on left)
-    pub slots_values: Vec<(AssignedH256<'v, F>, AssignedH256<'v, F>)>,
+    // the last entry is a boolean indicating whether the slot exists in the storage trie; if not, the value is 0
+    pub slots_values: Vec<(AssignedH256<'v, F>, AssignedH256<'v, F>, AssignedValue<'v, F>)>,
+    // for each slot, the mapping keys of its storage location if the slot was derived from one in-circuit
+    pub slot_keys: Vec<Option<Vec<AssignedH256<'v, F>>>>,
+}
+
+/// Selects which fields of an account are public instances of [`EthBlockStorageCircuit`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EthAccountFields {
+    pub nonce: bool,
+    pub balance: bool,
+    pub storage_root: bool,
+    pub code_hash: bool,
+}
+
+impl EthAccountFields {
+    pub fn all() -> Self {
+        Self { nonce: true, balance: true, storage_root: true, code_hash: true }
+    }
+
+    /// Number of instances per account: the nonce is one field element, the others are H256 as hi-lo
+    pub fn num_instance(&self) -> usize {
+        self.nonce as usize + 2 * (self.balance as usize + self.storage_root as usize)
+            + 2 * self.code_hash as usize
+    }
+
+    /// The selected fields of the account with RLP `acct_rlp`, see [`EthAccountFieldsDigest`]
+    pub fn instance<F: Field>(&self, acct_rlp: &[u8], account_exists: bool) -> Vec<F> {
+        let (nonce, balance, storage_root, code_hash): (u64, U256, H256, H256) = if account_exists
+        {
+            let rlp = Rlp::new(acct_rlp);
+            let [nonce, balance, storage_root, code_hash] = [0, 1, 2, 3].map(|idx| rlp.at(idx));
+            (
+                nonce.and_then(|rlp| rlp.as_val()).expect("invalid account nonce"),
+                balance.and_then(|rlp| rlp.as_val()).expect("invalid account balance"),
+                storage_root.and_then(|rlp| rlp.as_val()).expect("invalid account storage root"),
+                code_hash.and_then(|rlp| rlp.as_val()).expect("invalid account code hash"),
+            )
+        } else {
+            Default::default()
+        };
+        let mut instance = Vec::with_capacity(self.num_instance());
+        if self.nonce {
+            instance.push(F::from(nonce));
+        }
+        if self.balance {
+            instance.extend(encode_u256_to_field::<F>(&balance));
+        }
+        if self.storage_root {
+            instance.extend(encode_h256_to_field::<F>(&storage_root));
+        }
+        if self.code_hash {
+            instance.extend(encode_h256_to_field::<F>(&code_hash));
+        }
+        instance
+    }
+}
+
+/// The selected fields of an account, all 0 if the account does not exist.
+/// The balance is a U256 interpreted as H256 (padded with 0s on left).
+#[derive(Clone, Debug)]
+pub struct EthAccountFieldsDigest<'v, F: Field> {
+    pub nonce: Option<AssignedValue<'v, F>>,
+    pub balance: Option<AssignedH256<'v, F>>,
+    pub storage_root: Option<AssignedH256<'v, F>>,
+    pub code_hash: Option<AssignedH256<'v, F>>,
+}
+
+impl<'v, F: Field> EthAccountFieldsDigest<'v, F> {
+    /// The selected fields in instance order: nonce, balance, storage root, code hash
+    pub fn iter(&self) -> impl Iterator<Item = &AssignedValue<'v, F>> {
+        self.nonce
+            .iter()
+            .chain(self.balance.iter().flatten())
+            .chain(self.storage_root.iter().flatten())
+            .chain(self.code_hash.iter().flatten())
+    }
 }
 
 pub trait EthStorageChip<'v, F: Field> {
@@ -94,6 +190,15 @@ pub trait EthStorageChip<'v, F: Field> {
         witness: EthAccountTraceWitness<'v, F>,
     ) -> EthAccountTrace<'v, F>;
 
+    /// Reads the `fields` of the account from its RLP, zeroed out if `account_exists` is 0
+    fn parse_account_fields_phase0(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        witness: &EthAccountTraceWitness<'v, F>,
+        account_exists: &AssignedValue<'v, F>,
+        fields: EthAccountFields,
+    ) -> EthAccountFieldsDigest<'v, F>;
+
     fn parse_storage_proof_phase0(
         &mut self,
         ctx: &mut Context<'v, F>,
@@ -202,6 +307,48 @@ impl<'v, F: Field> EthStorageChip<'v, F> for EthChip<'v, F> {
         EthAccountTrace { nonce_trace, balance_trace, storage_root_trace, code_hash_trace }
     }
 
+    fn parse_account_fields_phase0(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        witness: &EthAccountTraceWitness<'v, F>,
+        account_exists: &AssignedValue<'v, F>,
+        fields: EthAccountFields,
+    ) -> EthAccountFieldsDigest<'v, F> {
+        let field_witness = &witness.array_witness.field_witness;
+        let gate = self.gate();
+        let exists = |ctx: &mut Context<'v, F>, value: AssignedValue<'v, F>| {
+            gate.mul(ctx, Existing(&value), Existing(account_exists))
+        };
+        // the nonce is a uint of at most 8 bytes, by EIP-2681
+        let nonce = fields.nonce.then(|| {
+            let nonce = &field_witness[0];
+            let bytes =
+                bytes_be_var_to_fixed(ctx, gate, &nonce.field_cells, &nonce.field_len, 33);
+            for byte in &bytes[..25] {
+                gate.assert_is_const(ctx, byte, F::zero());
+            }
+            let nonce = bytes_be_to_uint(ctx, gate, &bytes[25..], 8);
+            exists(ctx, nonce)
+        });
+        let balance = fields.balance.then(|| {
+            let balance = &field_witness[1];
+            let bytes =
+                bytes_be_var_to_fixed(ctx, gate, &balance.field_cells, &balance.field_len, 32);
+            let balance: [_; 2] = bytes_be_to_u128(ctx, gate, &bytes).try_into().unwrap();
+            balance.map(|limb| exists(ctx, limb))
+        });
+        // storage root and code hash are always 32 bytes
+        let hash_field = |ctx: &mut Context<'v, F>, idx: usize| {
+            let hash: [_; 2] = bytes_be_to_u128(ctx, gate, &field_witness[idx].field_cells[..32])
+                .try_into()
+                .unwrap();
+            hash.map(|limb| exists(ctx, limb))
+        };
+        let storage_root = fields.storage_root.then(|| hash_field(ctx, 2));
+        let code_hash = fields.code_hash.then(|| hash_field(ctx, 3));
+        EthAccountFieldsDigest { nonce, balance, storage_root, code_hash }
+    }
+
     fn parse_storage_proof_phase0(
         &mut self,
         ctx: &mut Context<'v, F>,
@@ -295,7 +442,6 @@ impl<'v, F: Field> EthStorageChip<'v, F> for EthChip<'v, F> {
         // check block_hash
         // TODO: more optimal to compute the `block_hash` via keccak below and then just constrain the bytes match this (hi,lo) representation
         let block_hash = input.block_hash;
-        let address = input.storage.address;
         let block_hash_bytes0 =
             block_hash.iter().map(|u128| uint_to_bytes_be(ctx, self.range(), u128, 16)).concat();
         let mut block_header = input.block_header;
@@ -320,43 +466,85 @@ impl<'v, F: Field> EthStorageChip<'v, F> for EthChip<'v, F> {
             bytes_be_var_to_fixed(ctx, self.gate(), block_num_bytes, block_num_len, 4);
         let block_number = bytes_be_to_uint(ctx, self.gate(), &block_number, 4);
 
-        // verify account + storage proof
-        let addr_bytes = uint_to_bytes_be(ctx, self.range(), &address, 20);
-        let acct_witness = self.parse_account_proof_phase0(
-            ctx,
-            state_root,
-            addr_bytes.clone(),
-            input.storage.acct_pf,
-        );
-        let storage_root = &acct_witness.array_witness.field_witness[2].field_cells;
-
-        let mut slots_values = Vec::with_capacity(input.storage.storage_pfs.len());
-        let storage_witness = input
-            .storage
-            .storage_pfs
-            .into_iter()
-            .map(|(slot, storage_pf)| {
-                let slot_bytes =
-                    slot.iter().map(|u128| uint_to_bytes_be(ctx, self.range(), u128, 16)).concat();
-                let witness =
-                    self.parse_storage_proof_phase0(ctx, storage_root, slot_bytes, storage_pf);
-                // get value as U256 from RLP decoding, convert to H256, then to hi-lo
-                let value_bytes = &witness.value_witness.witness.field_cells;
-                let value_len = &witness.value_witness.witness.field_len;
-                let value_bytes =
-                    bytes_be_var_to_fixed(ctx, self.gate(), value_bytes, value_len, 32);
-                let value: [_; 2] =
-                    bytes_be_to_u128(ctx, self.gate(), &value_bytes).try_into().unwrap();
-                slots_values.push((slot, value));
-
-                witness
-            })
-            .collect();
+        // verify account + storage proofs, all against the same state root
+        let mut acct_witness = Vec::with_capacity(input.storage.len());
+        let mut storage_witness = Vec::with_capacity(input.storage.len());
+        let mut accounts = Vec::with_capacity(input.storage.len());
+        for storage in input.storage {
+            let address = storage.address;
+            // the account exists iff its proof is an inclusion proof
+            let account_exists = self.gate().not(ctx, Existing(&storage.acct_pf.slot_is_empty));
+            let addr_bytes = uint_to_bytes_be(ctx, self.range(), &address, 20);
+            let witness =
+                self.parse_account_proof_phase0(ctx, state_root, addr_bytes, storage.acct_pf);
+            let storage_root = &witness.array_witness.field_witness[2].field_cells;
+
+            let mut slots_values = Vec::with_capacity(storage.storage_pfs.len());
+            let mut slot_keys = Vec::with_capacity(storage.storage_pfs.len());
+            let witnesses = storage
+                .storage_pfs
+                .into_iter()
+                .zip(storage.slot_locations.into_iter())
+                .map(|((slot, storage_pf), location)| {
+                    let slot_bytes = match location {
+                        Some(location) => {
+                            let slot_bytes = storage_slot_bytes(
+                                ctx,
+                                &self.mpt.rlp.range,
+                                &mut self.mpt.keccak,
+                                &location,
+                            );
+                            let derived = bytes_be_to_u128(ctx, self.gate(), &slot_bytes);
+                            for (a, b) in slot.iter().zip(derived.iter()) {
+                                ctx.constrain_equal(a, b);
+                            }
+                            slot_keys.push(Some(location.mapping_keys));
+                            slot_bytes
+                        }
+                        None => {
+                            slot_keys.push(None);
+                            slot.iter()
+                                .map(|u128| uint_to_bytes_be(ctx, self.range(), u128, 16))
+                                .concat()
+                        }
+                    };
+                    // the storage of an account that does not exist is empty
+                    let slot_exists = self.gate().mul_not(
+                        ctx,
+                        Existing(&storage_pf.slot_is_empty),
+                        Existing(&account_exists),
+                    );
+                    let witness =
+                        self.parse_storage_proof_phase0(ctx, storage_root, slot_bytes, storage_pf);
+                    // get value as U256 from RLP decoding, convert to H256, then to hi-lo
+                    let value_bytes = &witness.value_witness.witness.field_cells;
+                    let value_len = &witness.value_witness.witness.field_len;
+                    let value_bytes =
+                        bytes_be_var_to_fixed(ctx, self.gate(), value_bytes, value_len, 32);
+                    let value: [_; 2] =
+                        bytes_be_to_u128(ctx, self.gate(), &value_bytes).try_into().unwrap();
+                    // the value of a slot that does not exist is 0
+                    let value = value
+                        .map(|limb| self.gate().mul(ctx, Existing(&limb), Existing(&slot_exists)));
+                    slots_values.push((slot, value, slot_exists));
+
+                    witness
+                })
+                .collect();
+            acct_witness.push(witness);
+            storage_witness.push(witnesses);
+            accounts.push(EIP1186AccountDigest {
+                address,
+                account_exists,
+                slots_values,
+                slot_keys,
+            });
+        }
         EthBlockAccountStorageTraceWitness {
             block_witness,
             acct_witness,
             storage_witness,
-            digest: EIP1186ResponseDigest { block_hash, block_number, address, slots_values },
+            digest: EIP1186ResponseDigest { block_hash, block_number, accounts },
         }
     }
 
@@ -369,8 +557,12 @@ impl<'v, F: Field> EthStorageChip<'v, F> for EthChip<'v, F> {
         Self: EthBlockHeaderChip<'v, F>,
     {
         let block_trace = self.decompose_block_header_phase1(ctx, witness.block_witness);
-        let (acct_trace, storage_trace) =
-            self.parse_eip1186_proofs_phase1(ctx, (witness.acct_witness, witness.storage_witness));
+        let (acct_trace, storage_trace) = witness
+            .acct_witness
+            .into_iter()
+            .zip(witness.storage_witness.into_iter())
+            .map(|witness| self.parse_eip1186_proofs_phase1(ctx, witness))
+            .unzip();
         EthBlockAccountStorageTrace {
             block_trace,
             acct_trace,
@@ -385,6 +577,9 @@ pub struct EthStorageInput {
     pub addr: Address,
     pub acct_pf: MPTFixedKeyInput,
     pub storage_pfs: Vec<(H256, U256, MPTFixedKeyInput)>, // (slot, value, proof)
+    /// For each slot, the storage location it is derived from in-circuit, if any.
+    /// The public instances then contain the mapping keys of the location instead of the slot.
+    pub slot_locations: Vec<Option<StorageLocation>>,
 }
 
 #[derive(Clone, Debug)]
@@ -393,7 +588,8 @@ pub struct EthBlockStorageInput {
     pub block_number: u32,
     pub block_hash: H256,
     pub block_header: Vec<u8>,
-    pub storage: EthStorageInput,
+    /// The account and storage proofs of each account, all at this block
+    pub storage: Vec<EthStorageInput>,
 }
 
 impl EthStorageInput {
@@ -415,7 +611,24 @@ impl EthStorageInput {
                 (slot, pf)
             })
             .collect();
-        EthStorageInputAssigned { address, acct_pf, storage_pfs }
+        let slot_locations = self
+            .slot_locations
+            .iter()
+            .map(|location| location.as_ref().map(|location| location.assign(ctx, gate)))
+            .collect();
+        EthStorageInputAssigned { address, acct_pf, storage_pfs, slot_locations }
+    }
+
+    /// Derives each slot from `slot_locations` in-circuit, see [`EthStorageInput::slot_locations`].
+    pub fn with_slot_locations(mut self, slot_locations: Vec<Option<StorageLocation>>) -> Self {
+        assert_eq!(slot_locations.len(), self.storage_pfs.len());
+        for ((slot, _, _), location) in self.storage_pfs.iter().zip(slot_locations.iter()) {
+            if let Some(location) = location {
+                assert_eq!(*slot, location.slot(), "storage location does not match slot");
+            }
+        }
+        self.slot_locations = slot_locations;
+        self
     }
 }
 
@@ -428,7 +641,7 @@ impl EthBlockStorageInput {
         let block_hash = encode_h256_to_field(&self.block_hash);
         let block_hash =
             block_hash.map(|block_hash| gate.load_witness(ctx, Value::known(block_hash)));
-        let storage = self.storage.assign(ctx, gate);
+        let storage = self.storage.iter().map(|storage| storage.assign(ctx, gate)).collect();
         EthBlockStorageInputAssigned {
             block_hash,
             block_header: self.block_header.clone(),
@@ -442,6 +655,7 @@ pub struct EthStorageInputAssigned<'v, F: Field> {
     pub address: AssignedValue<'v, F>, // U160
     pub acct_pf: MPTFixedKeyProof<'v, F>,
     pub storage_pfs: Vec<(AssignedH256<'v, F>, MPTFixedKeyProof<'v, F>)>, // (slot, proof) where slot is H256 as (u128, u128)
+    pub slot_locations: Vec<Option<AssignedStorageLocation<'v, F>>>,
 }
 
 
@@ -449,13 +663,15 @@ pub struct EthStorageInputAssigned<'v, F: Field> {
 pub struct EthBlockStorageInputAssigned<'v, F: Field> {
     pub block_hash: AssignedH256<'v, F>, // H256 as (u128, u128)
     pub block_header: Vec<u8>,
-    pub storage: EthStorageInputAssigned<'v, F>,
+    pub storage: Vec<EthStorageInputAssigned<'v, F>>,
 }
 
 #[derive(Clone, Debug)]
 pub struct EthBlockStorageCircuit<F> {
     pub inputs: EthBlockStorageInput,
     network: Network,
+    /// The account fields that are public instances, for every account
+    pub account_fields: EthAccountFields,
     _marker: PhantomData<F>,
 }
 
@@ -464,8 +680,7 @@ impl<F: Field> EthBlockStorageCircuit<F> {
     pub fn from_provider(
         provider: &Provider<Http>,
         block_number: u32,
-        address: Address,
-        slots: Vec<H256>,
+        queries: Vec<(Address, Vec<H256>)>,
         acct_pf_max_depth: usize,
         storage_pf_max_depth: usize,
         network: Network,
@@ -475,30 +690,97 @@ impl<F: Field> EthBlockStorageCircuit<F> {
         let inputs = get_block_storage_input(
             provider,
             block_number,
-            address,
-            slots,
+            queries,
             acct_pf_max_depth,
             storage_pf_max_depth,
         );
-        Self { inputs, network, _marker: PhantomData }
+        Self { inputs, network, account_fields: EthAccountFields::default(), _marker: PhantomData }
     }
 
-    // blockHash, blockNumber, address, (slot, value)s
-    // with H256 encoded as hi-lo (u128, u128)
+    /// Sets the account fields that are public instances, see [`EthAccountFields`]
+    pub fn with_account_fields(mut self, account_fields: EthAccountFields) -> Self {
+        self.account_fields = account_fields;
+        self
+    }
+
+    /// Same as [`Self::from_provider`], with each slot given by a storage location that is derived in-circuit,
+    /// so that the public instances contain the mapping keys of the location instead of the slot.
+    #[cfg(feature = "providers")]
+    pub fn from_provider_with_locations(
+        provider: &Provider<Http>,
+        block_number: u32,
+        queries: Vec<(Address, Vec<StorageLocation>)>,
+        acct_pf_max_depth: usize,
+        storage_pf_max_depth: usize,
+        network: Network,
+    ) -> Self {
+        let slot_queries = queries
+            .iter()
+            .map(|(addr, locations)| (*addr, locations.iter().map(|loc| loc.slot()).collect()))
+            .collect();
+        let mut circuit = Self::from_provider(
+            provider,
+            block_number,
+            slot_queries,
+            acct_pf_max_depth,
+            storage_pf_max_depth,
+            network,
+        );
+        for (storage, (_, locations)) in circuit.inputs.storage.iter_mut().zip(queries) {
+            let locations = locations.into_iter().map(Some).collect();
+            *storage = storage.clone().with_slot_locations(locations);
+        }
+        circuit
+    }
+
+    // blockHash, blockNumber, then per account: address, accountExists, the selected account fields
+    // (see `EthAccountFields`), (slot, value, slotExists)s
+    // with H256 encoded as hi-lo (u128, u128). If a slot is derived from a storage location, the
+    // mapping keys of the location (possibly none) take the place of the slot.
     pub fn instance(&self) -> Vec<F> {
         let EthBlockStorageInput { block_number, block_hash, storage, .. } = &self.inputs;
-        let EthStorageInput { addr, storage_pfs, .. } = storage;
-        let mut instance = Vec::with_capacity(4 + 4 * storage_pfs.len());
+        let mut instance = Vec::with_capacity(self.num_instance()[0]);
         instance.extend(encode_h256_to_field::<F>(block_hash));
         instance.push(F::from(*block_number as u64));
-        instance.push(encode_addr_to_field(addr));
-        for (slot, value, _) in storage_pfs {
-            instance.extend(encode_h256_to_field::<F>(slot));
-            instance.extend(encode_u256_to_field::<F>(value));
+        for EthStorageInput { addr, acct_pf, storage_pfs, slot_locations } in storage {
+            let account_exists = !acct_pf.slot_is_empty;
+            instance.push(encode_addr_to_field(addr));
+            instance.push(F::from(account_exists));
+            instance.extend(self.account_fields.instance::<F>(&acct_pf.value, account_exists));
+            for ((slot, value, pf), location) in storage_pfs.iter().zip(slot_locations) {
+                let slot_exists = account_exists && !pf.slot_is_empty;
+                let value = if slot_exists { *value } else { U256::zero() };
+                match location {
+                    Some(location) => instance.extend(
+                        location.mapping_keys().flat_map(|key| encode_h256_to_field::<F>(key)),
+                    ),
+                    None => instance.extend(encode_h256_to_field::<F>(slot)),
+                }
+                instance.extend(encode_u256_to_field::<F>(&value));
+                instance.push(F::from(slot_exists));
+            }
         }
         instance
     }
 
+    /// The shape of [`Self::instance`], to decode it as a
+    /// [`StorageInstance`](crate::instance::StorageInstance)
+    pub fn instance_layout(&self) -> StorageInstanceLayout {
+        let slots = self
+            .inputs
+            .storage
+            .iter()
+            .map(|storage| {
+                storage
+                    .slot_locations
+                    .iter()
+                    .map(|location| location.as_ref().map(|location| location.num_mapping_keys()))
+                    .collect()
+            })
+            .collect();
+        StorageInstanceLayout { account_fields: self.account_fields, slots }
+    }
+
     pub fn from_json(
         json_loc: &str,
     ) -> Self {
@@ -507,7 +789,7 @@ impl<F: Field> EthBlockStorageCircuit<F> {
         let inputs = saved_block_storage_input(json_loc);
         let network = Network::Mainnet;
 
-        Self { inputs, network, _marker: PhantomData }
+        Self { inputs, network, account_fields: EthAccountFields::default(), _marker: PhantomData }
     }
 
 }
@@ -778,7 +1060,7 @@ impl<F: Field> Default for EthBlockStorageCircuit<F> {
         let inputs = saved_block_storage_input_fromstr(s);
         let network = Network::Mainnet;
 
-        Self { inputs, network, _marker: PhantomData }
+        Self { inputs, network, account_fields: EthAccountFields::default(), _marker: PhantomData }
     }
 }
 
@@ -843,6 +1125,19 @@ impl<F: Field> Circuit<F> for EthBlockStorageCircuit<F> {
                     let input = self.inputs.assign(ctx, chip.gate());
                     let witness =
                         chip.parse_eip1186_proofs_from_block_phase0(ctx, input, self.network);
+                    let account_fields = witness
+                        .acct_witness
+                        .iter()
+                        .zip(witness.digest.accounts.iter())
+                        .map(|(acct_witness, account)| {
+                            chip.parse_account_fields_phase0(
+                                ctx,
+                                acct_witness,
+                                &account.account_exists,
+                                self.account_fields,
+                            )
+                        })
+                        .collect_vec();
                     chip.assign_phase0(ctx);
                     ctx.next_phase();
 
@@ -851,21 +1146,31 @@ impl<F: Field> Circuit<F> for EthBlockStorageCircuit<F> {
                     chip.keccak_assign_phase1(ctx);
 
                     let trace = chip.parse_eip1186_proofs_from_block_phase1(ctx, witness);
-                    let EIP1186ResponseDigest { block_hash, block_number, address, slots_values } =
+                    let EIP1186ResponseDigest { block_hash, block_number, accounts } =
                         trace.digest;
                     chip.range().finalize(ctx);
 
                     instance.extend(
-                        block_hash
-                            .iter()
-                            .chain([block_number, address].iter())
-                            .chain(
-                                slots_values
-                                    .iter()
-                                    .flat_map(|(slot, value)| slot.iter().chain(value.iter())),
-                            )
-                            .map(|acell| acell.cell().clone()),
+                        block_hash.iter().chain([&block_number]).map(|acell| acell.cell().clone()),
                     );
+                    for (account, fields) in accounts.iter().zip(account_fields.iter()) {
+                        let slots = account.slots_values.iter().zip(&account.slot_keys).flat_map(
+                            |((slot, value, slot_exists), keys)| {
+                                let slot = match keys {
+                                    Some(keys) => keys.iter().flatten().collect_vec(),
+                                    None => slot.iter().collect_vec(),
+                                };
+                                slot.into_iter().chain(value).chain([slot_exists])
+                            },
+                        );
+                        instance.extend(
+                            [&account.address, &account.account_exists]
+                                .into_iter()
+                                .chain(fields.iter())
+                                .chain(slots)
+                                .map(|acell| acell.cell().clone()),
+                        );
+                    }
 
                     #[cfg(feature = "display")]
                     ctx.print_stats(&["Range", "RLC"]);
@@ -884,7 +1189,18 @@ impl<F: Field> Circuit<F> for EthBlockStorageCircuit<F> {
 
 impl<F: Field> CircuitExt<F> for EthBlockStorageCircuit<F> {
     fn num_instance(&self) -> Vec<usize> {
-        vec![4 + 4 * self.inputs.storage.storage_pfs.len()]
+        let storage = &self.inputs.storage;
+        let num_slot_instance = |location: &Option<StorageLocation>| {
+            location.as_ref().map_or(2, |location| 2 * location.num_mapping_keys())
+        };
+        vec![3 + storage
+            .iter()
+            .map(|storage| {
+                2 + self.account_fields.num_instance()
+                    + 3 * storage.storage_pfs.len()
+                    + storage.slot_locations.iter().map(num_slot_instance).sum::<usize>()
+            })
+            .sum::<usize>()]
     }
 
     fn instances(&self) -> Vec<Vec<F>> {
@@ -29,15 +29,19 @@ use std::{
     io::{BufReader, Write},
 };
 
-fn get_test_circuit<F: Field>(network: Network, num_slots: usize) -> EthBlockStorageCircuit<F> {
+/// The Infura provider for `network`, with the Infura ID in `scripts/input_gen/INFURA_ID`
+fn setup_provider(network: Network) -> Provider<Http> {
     let infura_id =
         std::fs::read_to_string("scripts/input_gen/INFURA_ID").expect("Infura ID not found");
     let provider_url = match network {
         Network::Mainnet => format!("{MAINNET_PROVIDER_URL}{infura_id}"),
         Network::Goerli => format!("{GOERLI_PROVIDER_URL}{infura_id}"),
     };
-    let provider = Provider::<Http>::try_from(provider_url.as_str())
-        .expect("could not instantiate HTTP Provider");
+    Provider::<Http>::try_from(provider_url.as_str()).expect("could not instantiate HTTP Provider")
+}
+
+fn get_test_circuit<F: Field>(network: Network, num_slots: usize) -> EthBlockStorageCircuit<F> {
+    let provider = setup_provider(network);
     let addr;
     let block_number;
     match network {
@@ -63,8 +67,7 @@ fn get_test_circuit<F: Field>(network: Network, num_slots: usize) -> EthBlockSto
     EthBlockStorageCircuit::from_provider(
         &provider,
         block_number,
-        addr,
-        slots[..num_slots].to_vec(),
+        vec![(addr, slots[..num_slots].to_vec())],
         8,
         8,
         network,
@@ -83,6 +86,180 @@ pub fn test_mock_single_eip1186() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+#[test]
+pub fn test_mock_multi_account_eip1186() -> Result<(), Box<dyn std::error::Error>> {
+    set_var("STORAGE_CONFIG", "configs/tests/storage.json");
+    let k = EthConfigParams::get_storage().degree;
+
+    let provider = setup_provider(Network::Mainnet);
+    // cryptopunks and WETH
+    let queries = vec![
+        (
+            "0xb47e3cd837dDF8e4c57F05d70Ab865de6e193BBB".parse::<Address>().unwrap(),
+            vec![H256::from_low_u64_be(0), H256::from_low_u64_be(8)],
+        ),
+        (
+            "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2".parse::<Address>().unwrap(),
+            vec![H256::from_low_u64_be(0)],
+        ),
+    ];
+    let mut circuit = EthBlockStorageCircuit::<Fr>::from_provider(
+        &provider,
+        16356350,
+        queries,
+        8,
+        8,
+        Network::Mainnet,
+    );
+    let instance = circuit.instance();
+    assert_eq!(instance.len(), 3 + 2 + 5 * 2 + 2 + 5);
+    MockProver::run(k, &circuit, vec![instance.clone()]).unwrap().assert_satisfied();
+
+    // tamper with a child hash in the root node of the WETH storage proof
+    circuit.inputs.storage[1].storage_pfs[0].2.proof[0][10] ^= 1;
+    assert!(MockProver::run(k, &circuit, vec![instance]).unwrap().verify().is_err());
+    Ok(())
+}
+
+#[test]
+pub fn test_mock_account_fields() -> Result<(), Box<dyn std::error::Error>> {
+    set_var("STORAGE_CONFIG", "configs/tests/storage.json");
+    let k = EthConfigParams::get_storage().degree;
+
+    let provider = setup_provider(Network::Mainnet);
+    // balances and code of cryptopunks and WETH, without any storage slots
+    let queries = vec![
+        ("0xb47e3cd837dDF8e4c57F05d70Ab865de6e193BBB".parse::<Address>().unwrap(), vec![]),
+        ("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2".parse::<Address>().unwrap(), vec![]),
+    ];
+    let circuit = EthBlockStorageCircuit::<Fr>::from_provider(
+        &provider,
+        16356350,
+        queries,
+        8,
+        8,
+        Network::Mainnet,
+    )
+    .with_account_fields(EthAccountFields::all());
+    let instance = circuit.instance();
+    assert_eq!(instance.len(), 3 + 2 * (2 + 7));
+    assert_eq!(instance.len(), circuit.num_instance()[0]);
+    MockProver::run(k, &circuit, vec![instance]).unwrap().assert_satisfied();
+
+    // only the balance
+    let circuit = circuit.with_account_fields(EthAccountFields {
+        balance: true,
+        ..Default::default()
+    });
+    let instance = circuit.instance();
+    assert_eq!(instance.len(), 3 + 2 * (2 + 2));
+    MockProver::run(k, &circuit, vec![instance.clone()]).unwrap().assert_satisfied();
+
+    // a wrong balance of cryptopunks: the low 128 bits come after its address and exists flag
+    let mut instance = instance;
+    instance[6] += Fr::one();
+    assert!(MockProver::run(k, &circuit, vec![instance]).unwrap().verify().is_err());
+    Ok(())
+}
+
+#[test]
+pub fn test_mock_bytecode() -> Result<(), Box<dyn std::error::Error>> {
+    use crate::{
+        providers::get_block_bytecode_input,
+        storage::bytecode::{EthBlockBytecodeCircuit, EthBlockBytecodeInput},
+    };
+
+    let k = EthConfigParams::get_bytecode().degree;
+    let provider = setup_provider(Network::Mainnet);
+    // WETH
+    let addr = "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2".parse::<Address>().unwrap();
+    let (storage, code) = get_block_bytecode_input(&provider, 16356350, addr, 8);
+    assert!(!code.is_empty() && code.len() < 4096);
+
+    // the first word of the code, and a word running past the end of the code
+    for code_offset in [0, code.len() - 16] {
+        let inputs = EthBlockBytecodeInput::new(storage.clone(), code.clone(), 4096, code_offset, 1);
+        let word = inputs.code_words()[0].to_fixed_bytes();
+        let end = code.len().min(code_offset + 32);
+        assert_eq!(&word[..end - code_offset], &code[code_offset..end]);
+        assert!(word[end - code_offset..].iter().all(|byte| *byte == 0));
+
+        let circuit = EthBlockBytecodeCircuit::<Fr>::new(inputs, Network::Mainnet);
+        let instance = circuit.instance();
+        assert_eq!(instance.len(), circuit.num_instance()[0]);
+        MockProver::run(k, &circuit, vec![instance]).unwrap().assert_satisfied();
+    }
+
+    // code that does not hash to the code hash of the account
+    let mut wrong_code = code;
+    wrong_code[0] ^= 1;
+    let inputs = EthBlockBytecodeInput::new(storage, wrong_code, 4096, 0, 1);
+    let circuit = EthBlockBytecodeCircuit::<Fr>::new(inputs, Network::Mainnet);
+    let instance = circuit.instance();
+    assert!(MockProver::run(k, &circuit, vec![instance]).unwrap().verify().is_err());
+    Ok(())
+}
+
+#[test]
+pub fn test_storage_location_slot() {
+    // cryptopunks `punkIndexToAddress` is the mapping at slot 10
+    for punk in 0..4u64 {
+        let mut bytes = [0u8; 64];
+        bytes[31] = punk as u8;
+        bytes[63] = 10;
+        let location = StorageLocation::new(10).mapping(H256::from_low_u64_be(punk));
+        assert_eq!(location.slot(), H256(keccak256(bytes)));
+    }
+    assert_eq!(StorageLocation::new(8).slot(), H256::from_low_u64_be(8));
+
+    // element 2 of a dynamic array at slot 3 with 2-slot elements, then field 1 of that element
+    let location = StorageLocation::new(3).array_index(2, 2).field(1);
+    let start = U256::from_big_endian(&keccak256(H256::from_low_u64_be(3)));
+    let mut slot = [0u8; 32];
+    (start + U256::from(5)).to_big_endian(&mut slot);
+    assert_eq!(location.slot(), H256(slot));
+
+    // nested mappings hash the outer key first
+    let (outer, inner) = (H256::repeat_byte(1), H256::repeat_byte(2));
+    let location = StorageLocation::new(1).mapping(outer).mapping(inner);
+    let outer_slot = keccak256([outer.as_bytes(), H256::from_low_u64_be(1).as_bytes()].concat());
+    assert_eq!(location.slot(), H256(keccak256([inner.as_bytes(), &outer_slot].concat())));
+    assert_eq!(location.mapping_keys().copied().collect_vec(), vec![outer, inner]);
+}
+
+#[test]
+pub fn test_mock_storage_location() -> Result<(), Box<dyn std::error::Error>> {
+    set_var("STORAGE_CONFIG", "configs/tests/storage.json");
+    let k = EthConfigParams::get_storage().degree;
+
+    let provider = setup_provider(Network::Mainnet);
+    // cryptopunks: `punkIndexToAddress[0]`, `punkIndexToAddress[1]` and the state variable at slot 8
+    let locations = vec![
+        StorageLocation::new(10).mapping(H256::from_low_u64_be(0)),
+        StorageLocation::new(10).mapping(H256::from_low_u64_be(1)),
+        StorageLocation::new(8),
+    ];
+    let addr = "0xb47e3cd837dDF8e4c57F05d70Ab865de6e193BBB".parse::<Address>().unwrap();
+    let circuit = EthBlockStorageCircuit::<Fr>::from_provider_with_locations(
+        &provider,
+        16356350,
+        vec![(addr, locations)],
+        8,
+        8,
+        Network::Mainnet,
+    );
+    let instance = circuit.instance();
+    // the mapping keys replace the slots, and the slot without mapping keys is not public
+    assert_eq!(instance.len(), 3 + 2 + 5 * 2 + 3);
+    assert_eq!(instance[5..7], encode_h256_to_field::<Fr>(&H256::from_low_u64_be(0)));
+    MockProver::run(k, &circuit, vec![instance.clone()]).unwrap().assert_satisfied();
+
+    // the value of `punkIndexToAddress[0]` claimed for `punkIndexToAddress[2]`
+    let mut instance = instance;
+    instance[6] = Fr::from(2);
+    assert!(MockProver::run(k, &circuit, vec![instance]).unwrap().verify().is_err());
+    Ok(())
+}
 
 #[derive(Serialize, Deserialize)]
 struct BenchParams(EthConfigParams, usize);
@@ -330,3 +507,206 @@ pub fn test_evm_single_eip1186() {
     evm_verify(deployment_code, instances, proof);
 }
 
+#[cfg(feature = "aggregation")]
+#[test]
+#[ignore = "requires over 32G memory"]
+pub fn test_historical_storage_aggregation() {
+    use crate::{
+        block_header::{
+            aggregation::AggregationWithKeccakConfigParams,
+            helpers::{CircuitType, Finality, Sequencer, Task},
+        },
+        providers::get_block_hash_merkle_proof,
+        storage::aggregation::EthHistoricalStorageAggregationCircuit,
+    };
+    use rand::SeedableRng;
+    use snark_verifier_sdk::{gen_pk, halo2::gen_snark_shplonk, CircuitExt};
+    let mut rng = rand_chacha::ChaChaRng::from_seed([0; 32]);
+
+    set_var("STORAGE_CONFIG", "configs/tests/storage.json");
+    let storage_circuit = get_test_circuit::<Fr>(Network::Mainnet, 1);
+    let block_number = storage_circuit.inputs.block_number;
+    let storage_snark = {
+        let k = EthConfigParams::get_storage().degree;
+        let params = gen_srs(k);
+        let pk = gen_pk(&params, &storage_circuit, None);
+        gen_snark_shplonk(&params, &pk, storage_circuit, &mut rng, None::<&str>)
+    };
+
+    // a header chain of 8 blocks containing the storage block
+    let max_depth = 3;
+    let start = block_number - 5;
+    let mut sequencer = Sequencer::new(Network::Mainnet);
+    let header_snark = sequencer.get_snark(Task::new(
+        start,
+        start + 7,
+        CircuitType::new(max_depth, max_depth, Finality::None),
+    ));
+    let provider = sequencer.provider.as_ref().unwrap();
+    let merkle_proof = get_block_hash_merkle_proof(provider, start, 8, block_number);
+
+    let k = AggregationWithKeccakConfigParams::get_historical_storage().aggregation.degree;
+    let params = gen_srs(k);
+    let circuit = EthHistoricalStorageAggregationCircuit::new(
+        &params,
+        storage_snark,
+        header_snark,
+        merkle_proof,
+        max_depth,
+        &mut rng,
+    );
+    MockProver::run(k, &circuit, circuit.instances()).unwrap().assert_satisfied();
+}
+
+/// Circuit that only exposes `instances`, to make snarks with the public instances of another circuit without
+/// proving it
+#[cfg(feature = "aggregation")]
+#[derive(Clone)]
+struct MockInstanceCircuit {
+    instances: Vec<Fr>,
+}
+
+#[cfg(feature = "aggregation")]
+const MOCK_SNARK_DEGREE: u32 = 10;
+
+#[cfg(feature = "aggregation")]
+impl Circuit<Fr> for MockInstanceCircuit {
+    type Config = (halo2_base::gates::range::RangeConfig<Fr>, Column<Instance>);
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        self.clone()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+        use halo2_base::gates::range::{RangeConfig, RangeStrategy};
+        let degree = MOCK_SNARK_DEGREE as usize;
+        let mut range =
+            RangeConfig::configure(meta, RangeStrategy::Vertical, &[1], &[1], 1, 8, 0, degree);
+        range.gate.max_rows = (1 << degree) - meta.minimum_rows();
+        let instance = meta.instance_column();
+        meta.enable_equality(instance);
+        (range, instance)
+    }
+
+    fn synthesize(
+        &self,
+        (range, instance): Self::Config,
+        mut layouter: impl Layouter<Fr>,
+    ) -> Result<(), Error> {
+        range.load_lookup_table(&mut layouter).expect("load range lookup table");
+        let mut first_pass = SKIP_FIRST_PASS;
+        let mut cells = vec![];
+        layouter.assign_region(
+            || "mock instances",
+            |region| {
+                if first_pass {
+                    first_pass = false;
+                    return Ok(());
+                }
+                let mut aux = Context::new(
+                    region,
+                    ContextParams {
+                        max_rows: range.gate.max_rows,
+                        num_context_ids: 1,
+                        fixed_columns: range.gate.constants.clone(),
+                    },
+                );
+                let ctx = &mut aux;
+                let assigned = range
+                    .gate
+                    .assign_witnesses(ctx, self.instances.iter().map(|value| Value::known(*value)));
+                cells = assigned.iter().map(|assigned| assigned.cell()).cloned().collect();
+                range.finalize(ctx);
+                Ok(())
+            },
+        )?;
+        for (i, cell) in cells.into_iter().enumerate() {
+            layouter.constrain_instance(cell, instance, i);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "aggregation")]
+impl CircuitExt<Fr> for MockInstanceCircuit {
+    fn num_instance(&self) -> Vec<usize> {
+        vec![self.instances.len()]
+    }
+
+    fn instances(&self) -> Vec<Vec<Fr>> {
+        vec![self.instances.clone()]
+    }
+}
+
+#[cfg(feature = "aggregation")]
+fn gen_mock_snark(instances: Vec<Fr>) -> snark_verifier_sdk::Snark {
+    use snark_verifier_sdk::{gen_pk, halo2::gen_snark_shplonk};
+    let params = gen_srs(MOCK_SNARK_DEGREE);
+    let circuit = MockInstanceCircuit { instances };
+    let pk = gen_pk(&params, &circuit, None);
+    gen_snark_shplonk(&params, &pk, circuit, &mut OsRng, None::<&str>)
+}
+
+/// Same as `test_historical_storage_aggregation`, but with snarks of mock circuits with the public instances of a
+/// storage proof and of a header chain over mock block hashes, so nothing is fetched. The header chain also
+/// exposes a header field, which is passed through.
+#[cfg(feature = "aggregation")]
+#[test]
+pub fn test_mock_historical_storage_aggregation() {
+    use crate::{
+        block_header::{
+            aggregation::AggregationWithKeccakConfigParams, merkle::BlockHashMerkleProof,
+            EthBlockHeaderChainInstance,
+        },
+        storage::aggregation::EthHistoricalStorageAggregationCircuit,
+        util::{encode_h256_to_field, get_merkle_mountain_range},
+    };
+
+    // a header chain of 11 blocks with mountains of depth 3, 1, 0
+    let max_depth = 3;
+    let (start, num_blocks, idx) = (0x765fb3, 11, 9);
+    let block_hashes =
+        (0..=num_blocks as u64).map(|i| H256(keccak256(i.to_be_bytes()))).collect_vec();
+    let (prev_hash, block_hashes) = (block_hashes[0], &block_hashes[1..]);
+    let header_instance = EthBlockHeaderChainInstance::new(
+        prev_hash,
+        block_hashes[num_blocks - 1],
+        start,
+        start + num_blocks as u32 - 1,
+        get_merkle_mountain_range(block_hashes, max_depth),
+    );
+    // followed by one header field, as exposed by `EthBlockHeaderChainCircuit::with_field_queries`
+    let header_fields = encode_h256_to_field::<Fr>(&H256::repeat_byte(0x5a));
+    let header_snark =
+        gen_mock_snark([header_instance.to_instance(), header_fields.to_vec()].concat());
+    // block hash, block number, then one account with its address, exists and one slot
+    let storage_instance = [
+        &encode_h256_to_field::<Fr>(&block_hashes[idx])[..],
+        &[Fr::from(start as u64 + idx as u64), Fr::from(0xb47e3cd8u64), Fr::one()],
+        &[Fr::zero(), Fr::from(8), Fr::zero(), Fr::from(0x1234), Fr::one()],
+    ]
+    .concat();
+    let storage_snark = gen_mock_snark(storage_instance.clone());
+
+    let k = AggregationWithKeccakConfigParams::get_historical_storage().aggregation.degree;
+    let params = gen_srs(k);
+    let mut circuit = EthHistoricalStorageAggregationCircuit::new(
+        &params,
+        storage_snark,
+        header_snark,
+        BlockHashMerkleProof::new(block_hashes, idx),
+        max_depth,
+        &mut OsRng,
+    );
+    let instances = circuit.instances();
+    let expected =
+        [header_instance.to_instance(), header_fields.to_vec(), storage_instance[2..].to_vec()]
+            .concat();
+    assert_eq!(instances[0][4 * snark_verifier_sdk::LIMBS..], expected);
+    MockProver::run(k, &circuit, instances.clone()).unwrap().assert_satisfied();
+
+    // the block is not in the header chain
+    circuit.merkle_proof.siblings[0] = block_hashes[idx];
+    assert!(MockProver::run(k, &circuit, instances).unwrap().verify().is_err());
+}
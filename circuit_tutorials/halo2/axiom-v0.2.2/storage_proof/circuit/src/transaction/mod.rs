@@ -0,0 +1,624 @@
+use crate::{
+    block_header::{
+        decompose_block_header_with_hash_phase0, EthBlockHeaderChip, EthBlockHeaderTrace,
+        EthBlockHeaderTraceWitness,
+    },
+    halo2_proofs::{
+        circuit::{Layouter, SimpleFloorPlanner, Value},
+        plonk::{Circuit, ConstraintSystem, Error},
+    },
+    mpt::{AssignedBytes, MPTVarKeyInput, MPTVarKeyProof, MPTVarKeyProofWitness},
+    rlp::{max_rlp_len_len, RlpArrayTraceWitness, RlpFieldTrace, RlpFieldWitness},
+    util::{
+        bytes_be_to_u128, bytes_be_to_uint, bytes_be_var_to_fixed, encode_addr_to_field,
+        encode_h256_to_field, encode_u256_to_field, AssignedH256, EthConfigParams,
+    },
+    EthChip, EthConfig, Field, Network,
+};
+#[cfg(feature = "display")]
+use ark_std::{end_timer, start_timer};
+use ethers_core::types::{Address, H256, U256};
+use halo2_base::{
+    gates::{range::RangeConfig, GateInstructions, RangeInstructions},
+    AssignedValue, Context, ContextParams,
+    QuantumCell::{Constant, Existing},
+    SKIP_FIRST_PASS,
+};
+use rlp::Rlp;
+use snark_verifier_sdk::CircuitExt;
+use std::{cmp::max, marker::PhantomData};
+
+#[cfg(test)]
+pub(crate) mod tests;
+
+/// The key of the transaction trie is `rlp(transaction_index)`, which is at most 3 bytes for `transaction_index < 2^16`.
+pub const TRANSACTION_INDEX_MAX_KEY_BYTES: usize = 3;
+pub const TRANSACTION_MAX_FIELDS: usize = 12;
+const TRANSACTION_FIELD_MAX_BYTES: usize = 32;
+
+// Supported transaction types are 0 (legacy), 1 (EIP-2930) and 2 (EIP-1559).
+// type 0: [nonce, gasPrice, gasLimit, to, value, data, v, r, s]
+// type 1: [chainId, nonce, gasPrice, gasLimit, to, value, data, accessList, signatureYParity, r, s]
+// type 2: [chainId, nonce, maxPriorityFeePerGas, maxFeePerGas, gasLimit, to, value, data, accessList, signatureYParity, r, s]
+pub const NUM_TRANSACTION_TYPES: usize = 3;
+const TRANSACTION_NUM_FIELDS: [usize; NUM_TRANSACTION_TYPES] = [9, 11, 12];
+const TRANSACTION_ACCESS_LIST_IDX: [Option<usize>; NUM_TRANSACTION_TYPES] =
+    [None, Some(7), Some(8)];
+pub const TRANSACTION_TO_IDX: [usize; NUM_TRANSACTION_TYPES] = [3, 4, 5];
+pub const TRANSACTION_VALUE_IDX: [usize; NUM_TRANSACTION_TYPES] = [4, 5, 6];
+pub const TRANSACTION_DATA_IDX: [usize; NUM_TRANSACTION_TYPES] = [5, 6, 7];
+
+/// Returns the maximum byte length of each field of the transaction RLP list, where lists such as the access list
+/// count as a single field, and the maximum byte length of the transaction, including the type byte of typed transactions.
+pub fn max_transaction_lens(
+    max_data_bytes: usize,
+    max_access_list_bytes: usize,
+) -> (Vec<usize>, usize) {
+    let scalar = TRANSACTION_FIELD_MAX_BYTES;
+    let data = max(max_data_bytes, scalar);
+    let mut max_field_bytes = vec![scalar; TRANSACTION_MAX_FIELDS];
+    max_field_bytes[5] = data;
+    max_field_bytes[6] = data;
+    max_field_bytes[7] = max(max_access_list_bytes, data);
+    max_field_bytes[8] = max(max_access_list_bytes, scalar);
+    let max_field_rlp_bytes: usize =
+        max_field_bytes.iter().map(|len| 1 + max_rlp_len_len(*len) + len).sum();
+    let max_tx_bytes = 1 + 1 + max_rlp_len_len(max_field_rlp_bytes) + max_field_rlp_bytes;
+    (max_field_bytes, max_tx_bytes)
+}
+
+/// Returns `rlp(idx)` right padded with 0s to `TRANSACTION_INDEX_MAX_KEY_BYTES` bytes, together with its byte length.
+///
+/// Constrains `0 <= idx < 2^16`.
+pub fn rlp_encode_index<'v, F: Field>(
+    ctx: &mut Context<'v, F>,
+    range: &RangeConfig<F>,
+    idx: &AssignedValue<'v, F>,
+) -> (AssignedBytes<'v, F>, AssignedValue<'v, F>) {
+    let gate = range.gate();
+    let bits = gate.num_to_bits(ctx, idx, 16);
+    let [lo, hi] = [0, 8].map(|start| {
+        gate.inner_product(
+            ctx,
+            bits[start..start + 8].iter().map(Existing),
+            (0..8).map(|i| Constant(gate.pow_of_two()[i])),
+        )
+    });
+    let is_zero = gate.is_zero(ctx, idx);
+    let is_small =
+        range.is_less_than(ctx, Existing(idx), Constant(gate.get_field_element(128)), 16);
+    let hi_is_zero = gate.is_zero(ctx, &hi);
+    let is_two_bytes = gate.not(ctx, Existing(&hi_is_zero));
+
+    // idx = 0: [0x80]; 0 < idx < 128: [idx]; 128 <= idx < 256: [0x81, idx]; 256 <= idx: [0x82, hi, lo]
+    let first_small =
+        gate.select(ctx, Constant(gate.get_field_element(0x80)), Existing(&lo), Existing(&is_zero));
+    let first_big = gate.add(ctx, Constant(gate.get_field_element(0x81)), Existing(&is_two_bytes));
+    let first = gate.select(ctx, Existing(&first_small), Existing(&first_big), Existing(&is_small));
+    let second = gate.select(ctx, Existing(&hi), Existing(&lo), Existing(&is_two_bytes));
+    let second = gate.mul_not(ctx, Existing(&is_small), Existing(&second));
+    let third = gate.mul(ctx, Existing(&lo), Existing(&is_two_bytes));
+
+    let is_big = gate.not(ctx, Existing(&is_small));
+    let len = gate.sum(ctx, [Constant(F::one()), Existing(&is_big), Existing(&is_two_bytes)]);
+    (vec![first, second, third], len)
+}
+
+/// Takes the bytes `value` of an EIP-2718 transaction or receipt as stored in the trie, i.e., either
+/// `rlp(payload)` for a legacy transaction or `tx_type || rlp(payload)` for a typed transaction.
+///
+/// Returns `(tx_type, is_typed, rlp_bytes)` where `tx_type = 0` for legacy transactions and `rlp_bytes` is
+/// `rlp(payload)` right padded with 0s to `value.len()` bytes.
+///
+/// Assumes `value` consists of bytes. Constrains that `tx_type != 0` if the transaction is typed.
+pub fn strip_typed_envelope<'v, F: Field>(
+    ctx: &mut Context<'v, F>,
+    range: &RangeConfig<F>,
+    value: &[AssignedValue<'v, F>],
+) -> (AssignedValue<'v, F>, AssignedValue<'v, F>, AssignedBytes<'v, F>) {
+    let gate = range.gate();
+    // an RLP list has prefix >= 0xc0, while transaction types are in [0, 0x7f]
+    let is_legacy =
+        range.is_less_than(ctx, Constant(gate.get_field_element(0xbf)), Existing(&value[0]), 8);
+    let tx_type = gate.mul_not(ctx, Existing(&is_legacy), Existing(&value[0]));
+    let type_is_zero = gate.is_zero(ctx, &tx_type);
+    ctx.constrain_equal(&type_is_zero, &is_legacy);
+    let is_typed = gate.not(ctx, Existing(&is_legacy));
+
+    let mut rlp_bytes = value
+        .iter()
+        .zip(value.iter().skip(1))
+        .map(|(byte, next)| gate.select(ctx, Existing(next), Existing(byte), Existing(&is_typed)))
+        .collect::<Vec<_>>();
+    rlp_bytes.push(gate.mul(ctx, Existing(value.last().unwrap()), Existing(&is_legacy)));
+    (tx_type, is_typed, rlp_bytes)
+}
+
+/// Selects the field at index `field_idxs[tx_type]` of the RLP list `fields`, returning the first `num_bytes` bytes
+/// of the field together with the field length.
+pub(crate) fn select_field_by_type<'v, F: Field>(
+    ctx: &mut Context<'_, F>,
+    gate: &impl GateInstructions<F>,
+    fields: &[RlpFieldWitness<'v, F>],
+    field_idxs: &[usize],
+    tx_type: &AssignedValue<'v, F>,
+    num_bytes: usize,
+) -> (AssignedBytes<'v, F>, AssignedValue<'v, F>) {
+    let bytes = (0..num_bytes)
+        .map(|idx| {
+            gate.select_from_idx(
+                ctx,
+                field_idxs.iter().map(|i| Existing(&fields[*i].field_cells[idx])),
+                Existing(tx_type),
+            )
+        })
+        .collect();
+    let len = gate.select_from_idx(
+        ctx,
+        field_idxs.iter().map(|i| Existing(&fields[*i].field_len)),
+        Existing(tx_type),
+    );
+    (bytes, len)
+}
+
+/// Returns whether the RLP item with parsed prefix `field.prefix` is a list. Non-existent fields of a
+/// variable length RLP list have prefix 0 and are not lists.
+pub(crate) fn rlp_field_is_list<'v, F: Field>(
+    ctx: &mut Context<'v, F>,
+    range: &RangeConfig<F>,
+    field: &RlpFieldTrace<'v, F>,
+) -> AssignedValue<'v, F> {
+    range.is_less_than(
+        ctx,
+        Constant(range.gate().get_field_element(0xbf)),
+        Existing(&field.prefix),
+        8,
+    )
+}
+
+#[derive(Clone, Debug)]
+pub struct EthTransactionTrace<'v, F: Field> {
+    pub tx_type: AssignedValue<'v, F>,
+    /// The fields of the transaction RLP list. The list has 9, 11, or 12 fields depending on `tx_type`; the remaining fields have length 0.
+    pub field_trace: Vec<RlpFieldTrace<'v, F>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct EthTransactionTraceWitness<'v, F: Field> {
+    pub tx_type: AssignedValue<'v, F>,
+    pub array_witness: RlpArrayTraceWitness<'v, F>,
+    mpt_witness: MPTVarKeyProofWitness<'v, F>,
+}
+
+#[derive(Clone, Debug)]
+pub struct EthBlockTransactionTrace<'v, F: Field> {
+    pub block_trace: EthBlockHeaderTrace<'v, F>,
+    pub tx_trace: EthTransactionTrace<'v, F>,
+    pub digest: EthTransactionDigest<'v, F>,
+}
+
+#[derive(Clone, Debug)]
+pub struct EthBlockTransactionTraceWitness<'v, F: Field> {
+    block_witness: EthBlockHeaderTraceWitness<'v, F>,
+    tx_witness: EthTransactionTraceWitness<'v, F>,
+    digest: EthTransactionDigest<'v, F>,
+}
+
+#[derive(Clone, Debug)]
+pub struct EthTransactionDigest<'v, F: Field> {
+    pub block_hash: AssignedH256<'v, F>,
+    pub block_number: AssignedValue<'v, F>,
+    pub tx_index: AssignedValue<'v, F>,
+    pub tx_type: AssignedValue<'v, F>,
+    // 0 for contract creation
+    pub to: AssignedValue<'v, F>,
+    // the value U256 is interpreted as H256 (padded with 0s on left)
+    pub value: AssignedH256<'v, F>,
+}
+
+pub trait EthTransactionChip<'v, F: Field> {
+    fn parse_transaction_proof_phase0(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        transactions_root_bytes: &[AssignedValue<'v, F>],
+        tx_index: &AssignedValue<'v, F>,
+        proof: MPTVarKeyProof<'v, F>,
+        max_data_bytes: usize,
+        max_access_list_bytes: usize,
+    ) -> EthTransactionTraceWitness<'v, F>;
+
+    fn parse_transaction_proof_phase1(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        witness: EthTransactionTraceWitness<'v, F>,
+    ) -> EthTransactionTrace<'v, F>;
+
+    // block_hash is big-endian 16-byte
+    // inputs have H256 represented in (hi,lo) format as two u128s
+    fn parse_transaction_proof_from_block_phase0(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        input: EthBlockTransactionInputAssigned<'v, F>,
+        network: Network,
+    ) -> EthBlockTransactionTraceWitness<'v, F>
+    where
+        Self: EthBlockHeaderChip<'v, F>;
+
+    fn parse_transaction_proof_from_block_phase1(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        witness: EthBlockTransactionTraceWitness<'v, F>,
+    ) -> EthBlockTransactionTrace<'v, F>
+    where
+        Self: EthBlockHeaderChip<'v, F>;
+}
+
+impl<'v, F: Field> EthTransactionChip<'v, F> for EthChip<'v, F> {
+    fn parse_transaction_proof_phase0(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        transactions_root_bytes: &[AssignedValue<'v, F>],
+        tx_index: &AssignedValue<'v, F>,
+        proof: MPTVarKeyProof<'v, F>,
+        max_data_bytes: usize,
+        max_access_list_bytes: usize,
+    ) -> EthTransactionTraceWitness<'v, F> {
+        let (max_field_bytes, max_tx_bytes) =
+            max_transaction_lens(max_data_bytes, max_access_list_bytes);
+        assert_eq!(TRANSACTION_INDEX_MAX_KEY_BYTES, proof.proof.key_byte_len);
+        assert_eq!(max_tx_bytes, proof.proof.value_max_byte_len);
+
+        // check key is rlp(tx_index)
+        let (key_bytes, key_byte_len) = rlp_encode_index(ctx, self.range(), tx_index);
+        for (byte, key) in key_bytes.iter().zip(proof.proof.key_bytes.iter()) {
+            ctx.constrain_equal(byte, key);
+        }
+        ctx.constrain_equal(&key_byte_len, &proof.key_byte_len);
+
+        // check MPT root is transactions root
+        for (pf_root, root) in
+            proof.proof.root_hash_bytes.iter().zip(transactions_root_bytes.iter())
+        {
+            ctx.constrain_equal(pf_root, root);
+        }
+
+        // parse value `tx_type || rlp([field_0, ..., field_n])`
+        let (tx_type, is_typed, rlp_bytes) =
+            strip_typed_envelope(ctx, self.range(), &proof.proof.value_bytes);
+        self.range().check_less_than_safe(ctx, &tx_type, NUM_TRANSACTION_TYPES as u64);
+        let array_witness =
+            self.mpt.rlp.decompose_rlp_nested_array_phase0(ctx, rlp_bytes, &max_field_bytes, true);
+        let value_byte_len =
+            self.gate().add(ctx, Existing(&array_witness.rlp_len), Existing(&is_typed));
+        ctx.constrain_equal(&value_byte_len, &proof.proof.value_byte_len);
+
+        // check MPT inclusion for:
+        // rlp(tx_index) => tx_type || rlp(transaction)
+        let max_depth = proof.proof.max_depth;
+        let mpt_witness = self.mpt.parse_mpt_inclusion_var_key_phase0(
+            ctx,
+            proof,
+            TRANSACTION_INDEX_MAX_KEY_BYTES,
+            max_tx_bytes,
+            max_depth,
+        );
+
+        EthTransactionTraceWitness { tx_type, array_witness, mpt_witness }
+    }
+
+    fn parse_transaction_proof_phase1(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        witness: EthTransactionTraceWitness<'v, F>,
+    ) -> EthTransactionTrace<'v, F> {
+        self.mpt.parse_mpt_inclusion_var_key_phase1(ctx, witness.mpt_witness);
+        let field_trace =
+            self.mpt.rlp.decompose_rlp_array_phase1(ctx, witness.array_witness, true).field_trace;
+        let tx_type = witness.tx_type;
+
+        // check the list has the number of fields of `tx_type`, and only the access list is itself a list
+        for (idx, field) in field_trace.iter().enumerate() {
+            let rlp_field_len =
+                self.gate().add(ctx, Existing(&field.prefix_len), Existing(&field.field_trace.len));
+            let is_empty = self.gate().is_zero(ctx, &rlp_field_len);
+            let expected_empty = self.gate().select_from_idx(
+                ctx,
+                TRANSACTION_NUM_FIELDS.map(|num_fields| Constant(F::from(idx >= num_fields))),
+                Existing(&tx_type),
+            );
+            ctx.constrain_equal(&is_empty, &expected_empty);
+
+            let is_list = rlp_field_is_list(ctx, self.range(), field);
+            let expected_list = self.gate().select_from_idx(
+                ctx,
+                TRANSACTION_ACCESS_LIST_IDX
+                    .map(|list_idx| Constant(F::from(list_idx == Some(idx)))),
+                Existing(&tx_type),
+            );
+            ctx.constrain_equal(&is_list, &expected_list);
+        }
+
+        EthTransactionTrace { tx_type, field_trace }
+    }
+
+    fn parse_transaction_proof_from_block_phase0(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        input: EthBlockTransactionInputAssigned<'v, F>,
+        network: Network,
+    ) -> EthBlockTransactionTraceWitness<'v, F>
+    where
+        Self: EthBlockHeaderChip<'v, F>,
+    {
+        let block_hash = input.block_hash;
+        let (block_witness, block_number) = decompose_block_header_with_hash_phase0(
+            self,
+            ctx,
+            input.block_header,
+            &block_hash,
+            network,
+        );
+        let transactions_root = &block_witness.rlp_witness.field_witness[4].field_cells;
+
+        let EthTransactionInputAssigned { tx_index, proof, max_data_bytes, max_access_list_bytes } =
+            input.transaction;
+        let tx_witness = self.parse_transaction_proof_phase0(
+            ctx,
+            transactions_root,
+            &tx_index,
+            proof,
+            max_data_bytes,
+            max_access_list_bytes,
+        );
+        let tx_type = tx_witness.tx_type.clone();
+        let fields = &tx_witness.array_witness.field_witness;
+
+        // `to` is either empty (contract creation) or an address
+        let (to_bytes, to_len) =
+            select_field_by_type(ctx, self.gate(), fields, &TRANSACTION_TO_IDX, &tx_type, 20);
+        let to_len_minus_20 =
+            self.gate().sub(ctx, Existing(&to_len), Constant(self.gate().get_field_element(20)));
+        let to_len_check = self.gate().mul(ctx, Existing(&to_len), Existing(&to_len_minus_20));
+        self.gate().assert_is_const(ctx, &to_len_check, F::zero());
+        let to_bytes = bytes_be_var_to_fixed(ctx, self.gate(), &to_bytes, &to_len, 20);
+        let to = bytes_be_to_uint(ctx, self.gate(), &to_bytes, 20);
+
+        // get value as U256 from RLP decoding, convert to H256, then to hi-lo
+        let (value_bytes, value_len) =
+            select_field_by_type(ctx, self.gate(), fields, &TRANSACTION_VALUE_IDX, &tx_type, 32);
+        self.range().check_less_than_safe(ctx, &value_len, 33);
+        let value_bytes = bytes_be_var_to_fixed(ctx, self.gate(), &value_bytes, &value_len, 32);
+        let value: [_; 2] = bytes_be_to_u128(ctx, self.gate(), &value_bytes).try_into().unwrap();
+
+        EthBlockTransactionTraceWitness {
+            block_witness,
+            tx_witness,
+            digest: EthTransactionDigest { block_hash, block_number, tx_index, tx_type, to, value },
+        }
+    }
+
+    fn parse_transaction_proof_from_block_phase1(
+        &mut self,
+        ctx: &mut Context<'v, F>,
+        witness: EthBlockTransactionTraceWitness<'v, F>,
+    ) -> EthBlockTransactionTrace<'v, F>
+    where
+        Self: EthBlockHeaderChip<'v, F>,
+    {
+        let block_trace = self.decompose_block_header_phase1(ctx, witness.block_witness);
+        let tx_trace = self.parse_transaction_proof_phase1(ctx, witness.tx_witness);
+        EthBlockTransactionTrace { block_trace, tx_trace, digest: witness.digest }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct EthTransactionInput {
+    pub tx_index: u32,
+    /// `proof.key = rlp(tx_index)` and `proof.value` is the transaction as stored in the transaction trie:
+    /// `rlp(transaction)` for legacy transactions and `tx_type || rlp(transaction)` for typed transactions
+    pub proof: MPTVarKeyInput,
+    pub max_data_bytes: usize,
+    pub max_access_list_bytes: usize,
+}
+
+impl EthTransactionInput {
+    /// Decodes `(tx_type, to, value)` from the transaction bytes.
+    pub fn decode(&self) -> (u8, Address, U256) {
+        let tx = &self.proof.value;
+        let tx_type = if tx[0] > 0xbf { 0 } else { tx[0] };
+        let rlp = Rlp::new(&tx[usize::from(tx_type != 0)..]);
+        let to: Vec<u8> = rlp.val_at(TRANSACTION_TO_IDX[tx_type as usize]).unwrap();
+        let to = if to.is_empty() { Address::zero() } else { Address::from_slice(&to) };
+        let value: Vec<u8> = rlp.val_at(TRANSACTION_VALUE_IDX[tx_type as usize]).unwrap();
+        (tx_type, to, U256::from_big_endian(&value))
+    }
+
+    pub fn assign<'v, F: Field>(
+        &self,
+        ctx: &mut Context<'_, F>,
+        gate: &impl GateInstructions<F>,
+    ) -> EthTransactionInputAssigned<'v, F> {
+        let (_, max_tx_bytes) =
+            max_transaction_lens(self.max_data_bytes, self.max_access_list_bytes);
+        assert_eq!(self.proof.key_max_byte_len, TRANSACTION_INDEX_MAX_KEY_BYTES);
+        assert_eq!(self.proof.value_max_byte_len, max_tx_bytes);
+        let tx_index = gate.load_witness(ctx, Value::known(F::from(self.tx_index as u64)));
+        let proof = self.proof.assign(ctx, gate);
+        EthTransactionInputAssigned {
+            tx_index,
+            proof,
+            max_data_bytes: self.max_data_bytes,
+            max_access_list_bytes: self.max_access_list_bytes,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct EthBlockTransactionInput {
+    pub block_number: u32,
+    pub block_hash: H256,
+    pub block_header: Vec<u8>,
+    pub transaction: EthTransactionInput,
+}
+
+impl EthBlockTransactionInput {
+    pub fn assign<'v, F: Field>(
+        &self,
+        ctx: &mut Context<'_, F>,
+        gate: &impl GateInstructions<F>,
+    ) -> EthBlockTransactionInputAssigned<'v, F> {
+        let block_hash = encode_h256_to_field(&self.block_hash);
+        let block_hash =
+            block_hash.map(|block_hash| gate.load_witness(ctx, Value::known(block_hash)));
+        let transaction = self.transaction.assign(ctx, gate);
+        EthBlockTransactionInputAssigned {
+            block_hash,
+            block_header: self.block_header.clone(),
+            transaction,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct EthTransactionInputAssigned<'v, F: Field> {
+    pub tx_index: AssignedValue<'v, F>,
+    pub proof: MPTVarKeyProof<'v, F>,
+    pub max_data_bytes: usize,
+    pub max_access_list_bytes: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct EthBlockTransactionInputAssigned<'v, F: Field> {
+    pub block_hash: AssignedH256<'v, F>, // H256 as (u128, u128)
+    pub block_header: Vec<u8>,
+    pub transaction: EthTransactionInputAssigned<'v, F>,
+}
+
+#[derive(Clone, Debug)]
+pub struct EthBlockTransactionCircuit<F> {
+    pub inputs: EthBlockTransactionInput,
+    network: Network,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> EthBlockTransactionCircuit<F> {
+    pub fn new(inputs: EthBlockTransactionInput, network: Network) -> Self {
+        Self { inputs, network, _marker: PhantomData }
+    }
+
+    // blockHash, blockNumber, txIndex, txType, to, value
+    // with H256 encoded as hi-lo (u128, u128)
+    pub fn instance(&self) -> Vec<F> {
+        let EthBlockTransactionInput { block_number, block_hash, transaction, .. } = &self.inputs;
+        let (tx_type, to, value) = transaction.decode();
+        let mut instance = Vec::with_capacity(8);
+        instance.extend(encode_h256_to_field::<F>(block_hash));
+        instance.push(F::from(*block_number as u64));
+        instance.push(F::from(transaction.tx_index as u64));
+        instance.push(F::from(tx_type as u64));
+        instance.push(encode_addr_to_field(&to));
+        instance.extend(encode_u256_to_field::<F>(&value));
+        instance
+    }
+}
+
+impl<F: Field> Circuit<F> for EthBlockTransactionCircuit<F> {
+    type Config = EthConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        self.clone()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        let params = EthConfigParams::get_transaction();
+        EthConfig::configure(meta, params, 0)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        #[cfg(feature = "display")]
+        let witness_gen = start_timer!(|| "synthesize");
+
+        let gamma = layouter.get_challenge(config.rlc().gamma);
+        config.range().load_lookup_table(&mut layouter).expect("load range lookup table");
+        config.keccak().load_aux_tables(&mut layouter).expect("load keccak lookup tables");
+
+        let mut first_pass = SKIP_FIRST_PASS;
+        let mut instance = vec![];
+        layouter
+            .assign_region(
+                || "transaction inclusion from blockHash",
+                |region| {
+                    if first_pass {
+                        first_pass = false;
+                        return Ok(());
+                    }
+                    let mut chip = EthChip::new(config.clone(), gamma);
+                    let mut aux = Context::new(
+                        region,
+                        ContextParams {
+                            max_rows: chip.gate().max_rows,
+                            num_context_ids: 2,
+                            fixed_columns: chip.gate().constants.clone(),
+                        },
+                    );
+                    let ctx = &mut aux;
+
+                    // ================= FIRST PHASE ================
+                    let input = self.inputs.assign(ctx, chip.gate());
+                    let witness =
+                        chip.parse_transaction_proof_from_block_phase0(ctx, input, self.network);
+                    chip.assign_phase0(ctx);
+                    ctx.next_phase();
+
+                    // ================= SECOND PHASE ================
+                    chip.get_challenge(ctx);
+                    chip.keccak_assign_phase1(ctx);
+
+                    let trace = chip.parse_transaction_proof_from_block_phase1(ctx, witness);
+                    let EthTransactionDigest {
+                        block_hash,
+                        block_number,
+                        tx_index,
+                        tx_type,
+                        to,
+                        value,
+                    } = trace.digest;
+                    chip.range().finalize(ctx);
+
+                    instance.extend(
+                        block_hash
+                            .iter()
+                            .chain([block_number, tx_index, tx_type, to].iter())
+                            .chain(value.iter())
+                            .map(|acell| acell.cell().clone()),
+                    );
+
+                    #[cfg(feature = "display")]
+                    ctx.print_stats(&["Range", "RLC"]);
+                    Ok(())
+                },
+            )
+            .unwrap();
+        for (i, cell) in instance.into_iter().enumerate() {
+            layouter.constrain_instance(cell, config.instance, i);
+        }
+        #[cfg(feature = "display")]
+        end_timer!(witness_gen);
+        Ok(())
+    }
+}
+
+impl<F: Field> CircuitExt<F> for EthBlockTransactionCircuit<F> {
+    fn num_instance(&self) -> Vec<usize> {
+        vec![8]
+    }
+
+    fn instances(&self) -> Vec<Vec<F>> {
+        vec![self.instance()]
+    }
+}
@@ -0,0 +1,161 @@
+use super::*;
+use crate::halo2_proofs::{dev::MockProver, halo2curves::bn256::Fr};
+use ethers_core::utils::keccak256;
+use rlp::RlpStream;
+
+const MAX_DATA_BYTES: usize = 128;
+const MAX_ACCESS_LIST_BYTES: usize = 128;
+const MAX_DEPTH: usize = 4;
+
+/// Returns a mainnet block header with the given `transactions_root` and `receipts_root`.
+pub(crate) fn mock_block_header(
+    transactions_root: H256,
+    receipts_root: H256,
+    block_number: u32,
+) -> Vec<u8> {
+    let mut stream = RlpStream::new_list(16);
+    stream
+        .append(&H256::repeat_byte(0x11).as_bytes().to_vec()) // parentHash
+        .append(&H256::repeat_byte(0x22).as_bytes().to_vec()) // ommersHash
+        .append(&Address::repeat_byte(0x33).as_bytes().to_vec()) // beneficiary
+        .append(&H256::repeat_byte(0x44).as_bytes().to_vec()) // stateRoot
+        .append(&transactions_root.as_bytes().to_vec())
+        .append(&receipts_root.as_bytes().to_vec())
+        .append(&vec![0u8; 256]) // logsBloom
+        .append(&0u64) // difficulty
+        .append(&block_number)
+        .append(&30_000_000u64) // gasLimit
+        .append(&1_000_000u64) // gasUsed
+        .append(&1_700_000_000u64) // timestamp
+        .append(&b"test".to_vec()) // extraData
+        .append(&H256::repeat_byte(0x55).as_bytes().to_vec()) // mixHash
+        .append(&vec![0u8; 8]) // nonce
+        .append(&7_000_000_000u64); // basefee
+    stream.out().to_vec()
+}
+
+/// Returns `(root, proof)` for the trie consisting of the single leaf `key => value`.
+pub(crate) fn single_leaf_trie(key: &[u8], value: &[u8]) -> (H256, Vec<Vec<u8>>) {
+    // hex-prefix encoding of a leaf with an even number of nibbles
+    let path = [&[0x20], key].concat();
+    let mut stream = RlpStream::new_list(2);
+    stream.append(&path).append(&value.to_vec());
+    let leaf = stream.out().to_vec();
+    (H256(keccak256(&leaf)), vec![leaf])
+}
+
+pub(crate) fn rlp_index(idx: u32) -> Vec<u8> {
+    rlp::encode(&idx).to_vec()
+}
+
+fn legacy_tx() -> Vec<u8> {
+    let mut stream = RlpStream::new_list(9);
+    stream
+        .append(&1u64) // nonce
+        .append(&20_000_000_000u64) // gasPrice
+        .append(&21_000u64) // gasLimit
+        .append(&Address::repeat_byte(0xab).as_bytes().to_vec()) // to
+        .append(&1_000_000_000_000_000_000u64) // value
+        .append(&Vec::<u8>::new()) // data
+        .append(&37u64) // v
+        .append(&H256::repeat_byte(0x01).as_bytes().to_vec()) // r
+        .append(&H256::repeat_byte(0x02).as_bytes().to_vec()); // s
+    stream.out().to_vec()
+}
+
+/// An EIP-1559 ERC-20 transfer with an empty access list
+fn eip1559_tx() -> Vec<u8> {
+    let mut data = vec![0xa9, 0x05, 0x9c, 0xbb];
+    data.extend(H256::from(Address::repeat_byte(0xcd)).as_bytes());
+    data.extend(H256::from_low_u64_be(1000).as_bytes());
+    let mut stream = RlpStream::new_list(12);
+    stream
+        .append(&1u64) // chainId
+        .append(&7u64) // nonce
+        .append(&1_000_000_000u64) // maxPriorityFeePerGas
+        .append(&30_000_000_000u64) // maxFeePerGas
+        .append(&60_000u64) // gasLimit
+        .append(&Address::repeat_byte(0xab).as_bytes().to_vec()) // to
+        .append(&0u64) // value
+        .append(&data);
+    stream.begin_list(0); // accessList
+    stream
+        .append(&1u64) // signatureYParity
+        .append(&H256::repeat_byte(0x01).as_bytes().to_vec()) // r
+        .append(&H256::repeat_byte(0x02).as_bytes().to_vec()); // s
+    [vec![2u8], stream.out().to_vec()].concat()
+}
+
+/// An EIP-2930 contract creation with a non-empty access list
+fn eip2930_create_tx() -> Vec<u8> {
+    let mut stream = RlpStream::new_list(11);
+    stream
+        .append(&1u64) // chainId
+        .append(&0u64) // nonce
+        .append(&20_000_000_000u64) // gasPrice
+        .append(&100_000u64) // gasLimit
+        .append(&Vec::<u8>::new()) // to
+        .append(&5u64) // value
+        .append(&vec![0x60, 0x00, 0x60, 0x00, 0xf3]); // data
+    stream.begin_list(1); // accessList
+    stream.begin_list(2).append(&Address::repeat_byte(0xef).as_bytes().to_vec());
+    stream.begin_list(1).append(&H256::repeat_byte(0x07).as_bytes().to_vec());
+    stream
+        .append(&0u64) // signatureYParity
+        .append(&H256::repeat_byte(0x01).as_bytes().to_vec()) // r
+        .append(&H256::repeat_byte(0x02).as_bytes().to_vec()); // s
+    [vec![1u8], stream.out().to_vec()].concat()
+}
+
+fn get_test_circuit(tx_index: u32, key_index: u32, tx: Vec<u8>) -> EthBlockTransactionCircuit<Fr> {
+    let key = rlp_index(key_index);
+    let (root, proof) = single_leaf_trie(&key, &tx);
+    let block_number = 17_000_000;
+    let block_header = mock_block_header(root, H256::repeat_byte(0x66), block_number);
+    let block_hash = H256(keccak256(&block_header));
+    let (_, max_tx_bytes) = max_transaction_lens(MAX_DATA_BYTES, MAX_ACCESS_LIST_BYTES);
+    let transaction = EthTransactionInput {
+        tx_index,
+        proof: MPTVarKeyInput::from_proof(key, proof).with_max_lens(
+            TRANSACTION_INDEX_MAX_KEY_BYTES,
+            max_tx_bytes,
+            MAX_DEPTH,
+        ),
+        max_data_bytes: MAX_DATA_BYTES,
+        max_access_list_bytes: MAX_ACCESS_LIST_BYTES,
+    };
+    let inputs = EthBlockTransactionInput { block_number, block_hash, block_header, transaction };
+    EthBlockTransactionCircuit::new(inputs, Network::Mainnet)
+}
+
+#[test]
+pub fn test_mock_transaction_legacy() {
+    let k = EthConfigParams::get_transaction().degree;
+    let circuit = get_test_circuit(0, 0, legacy_tx());
+    MockProver::run(k, &circuit, circuit.instances()).unwrap().assert_satisfied();
+}
+
+#[test]
+pub fn test_mock_transaction_eip1559() {
+    let k = EthConfigParams::get_transaction().degree;
+    let circuit = get_test_circuit(200, 200, eip1559_tx());
+    let instance = circuit.instance();
+    assert_eq!(instance[4], Fr::from(2));
+    MockProver::run(k, &circuit, circuit.instances()).unwrap().assert_satisfied();
+}
+
+#[test]
+pub fn test_mock_transaction_eip2930_create() {
+    let k = EthConfigParams::get_transaction().degree;
+    let circuit = get_test_circuit(1000, 1000, eip2930_create_tx());
+    let instance = circuit.instance();
+    assert_eq!(instance[5], Fr::zero());
+    MockProver::run(k, &circuit, circuit.instances()).unwrap().assert_satisfied();
+}
+
+#[test]
+pub fn test_mock_transaction_wrong_index() {
+    let k = EthConfigParams::get_transaction().degree;
+    let circuit = get_test_circuit(1, 0, legacy_tx());
+    assert!(MockProver::run(k, &circuit, circuit.instances()).unwrap().verify().is_err());
+}
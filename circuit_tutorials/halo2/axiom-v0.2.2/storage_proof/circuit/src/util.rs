@@ -15,7 +15,12 @@ use halo2_base::{
 };
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
-use std::{env::var, fs::File};
+use std::{
+    env::var,
+    fs::{self, File},
+    io::{self, Write},
+    path::Path,
+};
 
 pub(crate) const NUM_BYTES_IN_U128: usize = 16;
 
@@ -50,10 +55,69 @@ impl EthConfigParams {
         )
         .unwrap()
     }
+    pub fn get_transaction() -> Self {
+        let path =
+            var("TRANSACTION_CONFIG").unwrap_or_else(|_| "configs/transaction.json".to_string());
+        serde_json::from_reader(
+            File::open(&path).unwrap_or_else(|e| panic!("{path} does not exist. {e:?}")),
+        )
+        .unwrap()
+    }
+    pub fn get_receipt() -> Self {
+        let path = var("RECEIPT_CONFIG").unwrap_or_else(|_| "configs/receipt.json".to_string());
+        serde_json::from_reader(
+            File::open(&path).unwrap_or_else(|e| panic!("{path} does not exist. {e:?}")),
+        )
+        .unwrap()
+    }
+    pub fn get_block_hash_merkle() -> Self {
+        let path = var("BLOCK_HASH_MERKLE_CONFIG")
+            .unwrap_or_else(|_| "configs/block_hash_merkle.json".to_string());
+        serde_json::from_reader(
+            File::open(&path).unwrap_or_else(|e| panic!("{path} does not exist. {e:?}")),
+        )
+        .unwrap()
+    }
+    pub fn get_bytecode() -> Self {
+        let path = var("BYTECODE_CONFIG").unwrap_or_else(|_| "configs/bytecode.json".to_string());
+        serde_json::from_reader(
+            File::open(&path).unwrap_or_else(|e| panic!("{path} does not exist. {e:?}")),
+        )
+        .unwrap()
+    }
+    pub fn get_receipt_log() -> Self {
+        let path =
+            var("RECEIPT_LOG_CONFIG").unwrap_or_else(|_| "configs/receipt_log.json".to_string());
+        serde_json::from_reader(
+            File::open(&path).unwrap_or_else(|e| panic!("{path} does not exist. {e:?}")),
+        )
+        .unwrap()
+    }
+    pub fn get_beacon_state() -> Self {
+        let path =
+            var("BEACON_STATE_CONFIG").unwrap_or_else(|_| "configs/beacon_state.json".to_string());
+        serde_json::from_reader(
+            File::open(&path).unwrap_or_else(|e| panic!("{path} does not exist. {e:?}")),
+        )
+        .unwrap()
+    }
 }
 
 pub(crate) type AssignedH256<'v, F> = [AssignedValue<'v, F>; 2]; // H256 as hi-lo (u128, u128)
 
+/// Writes `contents` to a temporary file next to `path` and then renames it to `path`, so that a crash
+/// never leaves a partially written file at `path`.
+pub fn write_atomic(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let path = path.as_ref();
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(contents.as_ref())?;
+    file.sync_all()?;
+    fs::rename(tmp_path, path)
+}
+
 pub fn get_merkle_mountain_range(leaves: &[H256], max_depth: usize) -> Vec<H256> {
     let num_leaves = leaves.len();
     let mut merkle_roots = Vec::with_capacity(max_depth + 1);